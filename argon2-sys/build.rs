@@ -0,0 +1,316 @@
+extern crate cc;
+
+#[path = "build/linkage.rs"]
+mod linkage;
+#[path = "build/toolchain.rs"]
+mod toolchain;
+
+use linkage::Linkage;
+use std::env;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Fields of `argon2_context`, in the order of `argon2.h`.
+const CONTEXT_FIELDS: [&str; 18] = [
+    "out", "outlen", "pwd", "pwdlen", "salt", "saltlen", "secret", "secretlen", "ad", "adlen",
+    "t_cost", "m_cost", "lanes", "threads", "version", "allocate_cbk", "free_cbk", "flags",
+];
+
+/// Constants of the enums of `argon2.h`. `src/lib.rs` prefixes them with the name of their enum.
+const ENUM_CONSTANTS: [(&str, &[&str]); 3] = [
+    ("Argon2_ErrorCodes", &[
+        "ARGON2_OK", "ARGON2_OUTPUT_PTR_NULL", "ARGON2_OUTPUT_TOO_SHORT", "ARGON2_OUTPUT_TOO_LONG",
+        "ARGON2_PWD_TOO_SHORT", "ARGON2_PWD_TOO_LONG", "ARGON2_SALT_TOO_SHORT", "ARGON2_SALT_TOO_LONG",
+        "ARGON2_AD_TOO_SHORT", "ARGON2_AD_TOO_LONG", "ARGON2_SECRET_TOO_SHORT", "ARGON2_SECRET_TOO_LONG",
+        "ARGON2_TIME_TOO_SMALL", "ARGON2_TIME_TOO_LARGE", "ARGON2_MEMORY_TOO_LITTLE", "ARGON2_MEMORY_TOO_MUCH",
+        "ARGON2_LANES_TOO_FEW", "ARGON2_LANES_TOO_MANY", "ARGON2_PWD_PTR_MISMATCH", "ARGON2_SALT_PTR_MISMATCH",
+        "ARGON2_SECRET_PTR_MISMATCH", "ARGON2_AD_PTR_MISMATCH", "ARGON2_MEMORY_ALLOCATION_ERROR",
+        "ARGON2_FREE_MEMORY_CBK_NULL", "ARGON2_ALLOCATE_MEMORY_CBK_NULL", "ARGON2_INCORRECT_PARAMETER",
+        "ARGON2_INCORRECT_TYPE", "ARGON2_OUT_PTR_MISMATCH", "ARGON2_THREADS_TOO_FEW", "ARGON2_THREADS_TOO_MANY",
+        "ARGON2_MISSING_ARGS", "ARGON2_ENCODING_FAIL", "ARGON2_DECODING_FAIL", "ARGON2_THREAD_FAIL",
+        "ARGON2_DECODING_LENGTH_FAIL", "ARGON2_VERIFY_MISMATCH",
+    ]),
+    ("Argon2_type", &["Argon2_d", "Argon2_i", "Argon2_id"]),
+    ("Argon2_version", &["ARGON2_VERSION_10", "ARGON2_VERSION_13", "ARGON2_VERSION_NUMBER"]),
+];
+
+pub fn main() {
+    linkage::ENV_VARS.iter()
+        .chain(&toolchain::ENV_VARS)
+        .for_each(|var| println!("cargo:rerun-if-env-changed={}", var));
+    println!("cargo:rustc-check-cfg=cfg(argon2_prebuilt)");
+    println!("cargo:rustc-check-cfg=cfg(argon2_threads)");
+    // `argon2_threads` tells `src/lib.rs` whether the C library can spawn threads. The bundled
+    // sources are built threadless exactly where this is false; a prebuilt or system library is
+    // assumed to have threads on targets that have them.
+    if target_has_threads() {
+        println!("cargo:rustc-cfg=argon2_threads");
+    }
+    match linkage::linkage(cfg!(feature = "pure-rust"), cfg!(feature = "system"), |var| env::var_os(var)) {
+        // The `pure-rust` feature implements the C library in Rust, there is nothing to build or link.
+        Linkage::None => {}
+        Linkage::Bundled => build_bundled(),
+        Linkage::Prebuilt { lib_dir, include_dir, static_lib } => link_prebuilt(&lib_dir, include_dir, static_lib),
+        #[cfg(feature = "system")]
+        Linkage::PkgConfig { include_dir, static_lib } => link_system(include_dir, static_lib),
+        #[cfg(not(feature = "system"))]
+        Linkage::PkgConfig { .. } => unreachable!("pkg-config is only used with the `system` feature"),
+    }
+}
+
+/// Returns a `cc::Build` for C sources, with the compiler of the NDK in `ANDROID_NDK_HOME` for
+/// Android targets unless one is set explicitly (see `build/toolchain.rs`).
+///
+/// iOS needs nothing special: `cc` compiles with the SDK that `xcrun` reports, for
+/// `IPHONEOS_DEPLOYMENT_TARGET`.
+fn c_build() -> cc::Build {
+    let mut build = cc::Build::new();
+    build.cpp(false);
+    let target = env::var("TARGET").expect("TARGET is not set.");
+    let host = env::var("HOST").expect("HOST is not set.");
+    if let Some(ndk) = toolchain::android_toolchain(&target, &host, |var| env::var_os(var)) {
+        build.compiler(ndk.cc).archiver(ndk.ar);
+    }
+    build
+}
+
+/// Whether the target has threads for thread.c; it has none on wasm. Android and iOS have
+/// pthreads, so the sources are built as on other Unix targets.
+fn target_has_threads() -> bool {
+    !env::var("CARGO_CFG_TARGET_FAMILY").unwrap_or_default().split(',').any(|f| f == "wasm")
+}
+
+/// Builds the bundled sources.
+fn build_bundled() {
+    let argon2_root = PathBuf::from(".").join("phc-winner-argon2");
+    let argon2_src = argon2_root.join("src");
+    let blake2_src = argon2_src.join("blake2");
+    let dispatch_src = PathBuf::from(".").join("c").join("dispatch.c");
+    let thread_error_src = PathBuf::from(".").join("c").join("thread_error.c");
+
+    let source_files = [
+        // ARGON2 Source Files:
+        argon2_src.join("argon2.c"),
+        argon2_src.join("core.c"),
+        argon2_src.join("encoding.c"),
+
+        // BLAKE2 Source Files:
+        blake2_src.join("blake2b.c"),
+
+        // Runtime selection of fill_segment:
+        dispatch_src.clone(),
+
+        // argon2_thread_create, which keeps the error of pthread_create:
+        thread_error_src,
+    ];
+
+    let header_dirs = [
+        argon2_root.join("include"),
+        argon2_src.clone(),
+    ];
+
+    // opt.c uses SSE intrinsics, so it is only built for x86, and only with the `opt-core` feature
+    // unless `ref-core` asks for the reference implementation alone. Both it and ref.c define
+    // fill_segment; every copy is built into its own library with the function renamed, and
+    // c/dispatch.c picks one at runtime. src/dispatch.rs of the argon2 crate mirrors this condition.
+    let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let opt = (target_arch == "x86" || target_arch == "x86_64")
+        && cfg!(feature = "opt-core")
+        && !cfg!(feature = "ref-core");
+    let msvc = env::var("CARGO_CFG_TARGET_ENV").as_deref() == Ok("msvc");
+
+    let mut build = c_build();
+    source_files.iter().for_each(|f| { build.file(f); });
+    header_dirs.iter().for_each(|d| { build.include(d); });
+    // There are no threads on wasm, so thread.c is compiled out and the lanes are computed one
+    // after the other. src/types.rs of the argon2 crate rejects more than one thread there.
+    // Everywhere else thread.c is built: on Windows, including MSVC, it takes its `_WIN32` branch,
+    // which starts the lanes with `_beginthreadex` and joins them with `WaitForSingleObject`.
+    if !target_has_threads() {
+        build.define("ARGON2_NO_THREADS", None);
+    }
+    if opt {
+        build.define("ARGON2_HAVE_OPT", None);
+        build.define("ARGON2_HAVE_OPT_AVX2", None);
+    }
+    build.compile("libargon2");
+
+    // thread.c is built on its own with argon2_thread_create renamed, so that the one of
+    // c/thread_error.c wraps it.
+    let thread_src = argon2_src.join("thread.c");
+    let mut build = c_build();
+    build.file(&thread_src);
+    header_dirs.iter().for_each(|d| { build.include(d); });
+    build.define("argon2_thread_create", Some("argon2_sys_thread_create"));
+    if !target_has_threads() {
+        build.define("ARGON2_NO_THREADS", None);
+    }
+    build.compile("libargon2_thread");
+
+    let ref_src = argon2_src.join("ref.c");
+    let opt_src = argon2_src.join("opt.c");
+    build_fill_segment("ref", &ref_src, &header_dirs, None);
+    if opt {
+        build_fill_segment("opt", &opt_src, &header_dirs, None);
+        build_fill_segment("opt_avx2", &opt_src, &header_dirs, Some(if msvc { "/arch:AVX2" } else { "-mavx2" }));
+    }
+
+    #[cfg(feature = "bindgen")]
+    generate_bindings(&header_dirs);
+    build_layout_shim(&header_dirs);
+
+    // `DEP_ARGON2_INCLUDE` for the build scripts of dependents.
+    let include = env::current_dir().expect("Failed to get the current directory.").join(&header_dirs[0]);
+    println!("cargo:include={}", include.display());
+
+    source_files.iter()
+        .chain(&[thread_src, ref_src, opt_src])
+        .map(|p| p.to_str().expect("Failed to convert path to string."))
+        .for_each(|f| println!("cargo:rerun-if-changed={}", f));
+    println!("cargo:rerun-if-env-changed=CC");
+}
+
+/// Links the prebuilt libargon2 in `lib_dir` instead of building the bundled sources, with the
+/// header in `include_dir`, or the bundled header if there is none. The library has no
+/// c/dispatch.c, so `argon2_prebuilt` makes `src/lib.rs` stand in for it.
+fn link_prebuilt(lib_dir: &Path, include_dir: Option<PathBuf>, static_lib: bool) {
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    println!("cargo:rustc-link-lib={}=argon2", if static_lib { "static" } else { "dylib" });
+    println!("cargo:rustc-cfg=argon2_prebuilt");
+    let include_dir = include_dir.unwrap_or_else(|| {
+        env::current_dir().expect("Failed to get the current directory.").join("phc-winner-argon2").join("include")
+    });
+    use_header(&[include_dir]);
+}
+
+/// Links against the libargon2 of the system, found with pkg-config, instead of building the
+/// bundled sources. `include_dir` takes precedence over the header directories of pkg-config.
+#[cfg(feature = "system")]
+fn link_system(include_dir: Option<PathBuf>, static_lib: bool) {
+    let library = pkg_config::Config::new()
+        .statik(static_lib)
+        .probe("libargon2")
+        .unwrap_or_else(|err| panic!("Failed to find libargon2 with pkg-config, set ARGON2_LIB_DIR instead: {}", err));
+    use_header(&include_dir.map_or(library.include_paths, |dir| vec![dir]));
+}
+
+/// Checks the `argon2.h` of a library that isn't built from the bundled sources and generates
+/// what depends on it.
+fn use_header(include_dirs: &[PathBuf]) {
+    check_header(include_dirs);
+    if let Some(include) = include_dirs.iter().find(|d| d.join("argon2.h").exists()).or_else(|| include_dirs.first()) {
+        println!("cargo:include={}", include.display());
+    }
+    #[cfg(feature = "bindgen")]
+    generate_bindings(include_dirs);
+    build_layout_shim(include_dirs);
+}
+
+/// Fails the build if the `argon2.h` of the linked library doesn't declare Argon2id, which was
+/// added in release 20161029.
+fn check_header(include_dirs: &[PathBuf]) {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is not set."));
+    let check = out_dir.join("check_argon2id.c");
+    std::fs::write(&check, "#include <argon2.h>\n\
+                            int argon2_check_argon2id(void) { return argon2id_hash_raw(1, 8, 1, 0, 0, 0, 0, 0, 0) + Argon2_id; }\n")
+        .expect("Failed to write the header check.");
+
+    let mut build = c_build();
+    build.cargo_metadata(false);
+    build.file(&check);
+    include_dirs.iter().for_each(|d| { build.include(d); });
+    if let Err(err) = build.try_compile("argon2_check") {
+        panic!("The argon2.h of the linked library doesn't declare Argon2id: {}", err);
+    }
+}
+
+/// Generates bindings from the `argon2.h` found in `header_dirs` into
+/// `$OUT_DIR/argon2-bindings.rs`. They aren't used for the FFI calls; the tests compare them to
+/// the hand-written `src/lib.rs`. Needs libclang.
+#[cfg(feature = "bindgen")]
+fn generate_bindings(header_dirs: &[PathBuf]) {
+    let header = header_dirs.iter()
+        .map(|d| d.join("argon2.h"))
+        .find(|h| h.exists())
+        .unwrap_or_else(|| PathBuf::from("argon2.h"));
+    let bindings = bindgen::Builder::default()
+        .header(header.to_str().expect("Failed to convert path to string."))
+        .clang_args(header_dirs.iter().map(|d| format!("-I{}", d.display())))
+        .allowlist_type("Argon2_.*")
+        .allowlist_type("argon2_.*")
+        .allowlist_function("argon2.*")
+        .layout_tests(false)
+        .generate()
+        .expect("Failed to generate bindings.");
+
+    let out_path = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is not set."));
+    bindings
+        .write_to_file(out_path.join("argon2-bindings.rs"))
+        .expect("Couldn't write bindings.");
+    println!("cargo:rerun-if-changed={}", header.display());
+}
+
+/// Generates a C shim that reports the size, alignment and field offsets and sizes of
+/// `argon2_context` and the values of the enum constants, as the `argon2.h` in `header_dirs`
+/// defines them, and builds it into its own library. The functions that call it are written to
+/// `$OUT_DIR/argon2-layout.rs`, where they pair each C value with the one of `src/lib.rs` for its
+/// tests. Nothing else calls the shim, so it isn't linked into other binaries.
+fn build_layout_shim(header_dirs: &[PathBuf]) {
+    let mut c = String::from("#include <stddef.h>\n#include <argon2.h>\n\n");
+    c += "struct argon2_sys_align { char c; argon2_context context; };\n";
+    c += "size_t argon2_sys_sizeof_context(void) { return sizeof(argon2_context); }\n";
+    c += "size_t argon2_sys_alignof_context(void) { return offsetof(struct argon2_sys_align, context); }\n";
+    let mut externs = String::from("extern \"C\" {\n");
+    externs += "    fn argon2_sys_sizeof_context() -> usize;\n";
+    externs += "    fn argon2_sys_alignof_context() -> usize;\n";
+    let mut layout = String::from("/// `(what, value in argon2.h, value in src/lib.rs)` for the layout of `Argon2_Context`.\n");
+    layout += "unsafe fn context_layout() -> Vec<(&'static str, usize, usize)> {\n";
+    layout += "    fn size_of_field<T>(_: fn(&Argon2_Context) -> &T) -> usize { std::mem::size_of::<T>() }\n";
+    layout += "    vec![\n";
+    layout += "        (\"size\", argon2_sys_sizeof_context(), std::mem::size_of::<Argon2_Context>()),\n";
+    layout += "        (\"alignment\", argon2_sys_alignof_context(), std::mem::align_of::<Argon2_Context>()),\n";
+    for field in CONTEXT_FIELDS.iter() {
+        writeln!(c, "size_t argon2_sys_offsetof_context_{0}(void) {{ return offsetof(argon2_context, {0}); }}", field).unwrap();
+        writeln!(c, "size_t argon2_sys_sizeof_context_{0}(void) {{ return sizeof(((argon2_context *)0)->{0}); }}", field).unwrap();
+        writeln!(externs, "    fn argon2_sys_offsetof_context_{}() -> usize;", field).unwrap();
+        writeln!(externs, "    fn argon2_sys_sizeof_context_{}() -> usize;", field).unwrap();
+        writeln!(layout, "        (\"offset of {0}\", argon2_sys_offsetof_context_{0}(), std::mem::offset_of!(Argon2_Context, {0})),", field).unwrap();
+        writeln!(layout, "        (\"size of {0}\", argon2_sys_sizeof_context_{0}(), size_of_field(|context| &context.{0})),", field).unwrap();
+    }
+    layout += "    ]\n}\n\n";
+    let mut constants = String::from("/// `(name, value in argon2.h, value in src/lib.rs)` for the enum constants.\n");
+    constants += "unsafe fn constants() -> Vec<(&'static str, i64, i64)> {\n    vec![\n";
+    for (enum_name, names) in ENUM_CONSTANTS.iter() {
+        for name in names.iter() {
+            writeln!(c, "long long argon2_sys_value_{0}(void) {{ return {0}; }}", name).unwrap();
+            writeln!(externs, "    fn argon2_sys_value_{}() -> i64;", name).unwrap();
+            writeln!(constants, "        (\"{0}_{1}\", argon2_sys_value_{1}(), i64::from({0}_{1})),", enum_name, name).unwrap();
+        }
+    }
+    constants += "    ]\n}\n";
+    externs += "}\n\n";
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").expect("OUT_DIR is not set."));
+    let source = out_dir.join("argon2-layout.c");
+    std::fs::write(&source, c).expect("Failed to write the layout shim.");
+    std::fs::write(out_dir.join("argon2-layout.rs"), externs + &layout + &constants)
+        .expect("Failed to write the bindings of the layout shim.");
+
+    let mut build = c_build();
+    build.file(&source);
+    header_dirs.iter().for_each(|d| { build.include(d); });
+    build.compile("libargon2_layout");
+}
+
+/// Builds `source` into its own library with `fill_segment` renamed to
+/// `argon2_<name>_fill_segment`.
+fn build_fill_segment(name: &str, source: &Path, header_dirs: &[PathBuf], flag: Option<&str>) {
+    let mut build = c_build();
+    build.file(source);
+    header_dirs.iter().for_each(|d| { build.include(d); });
+    build.define("fill_segment", Some(format!("argon2_{}_fill_segment", name).as_str()));
+    if let Some(flag) = flag {
+        build.flag(flag);
+    }
+    build.compile(&format!("libargon2_{}", name));
+}
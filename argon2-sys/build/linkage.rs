@@ -0,0 +1,90 @@
+//! Where the build script gets the C library from. Shared with the tests of `src/lib.rs`, since
+//! build scripts can't have tests of their own.
+
+use std::ffi::{OsStr, OsString};
+use std::path::PathBuf;
+
+/// How the C library is built or linked.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Linkage {
+    /// Nothing is built or linked, with the `pure-rust` feature.
+    None,
+    /// The bundled sources are built.
+    Bundled,
+    /// The library in `ARGON2_LIB_DIR` is linked, with its header in `ARGON2_INCLUDE_DIR`.
+    Prebuilt { lib_dir: PathBuf, include_dir: Option<PathBuf>, static_lib: bool },
+    /// The system's libargon2 is found with pkg-config, with the `system` feature. A header in
+    /// `ARGON2_INCLUDE_DIR` takes precedence over the one pkg-config reports.
+    PkgConfig { include_dir: Option<PathBuf>, static_lib: bool },
+}
+
+/// Environment variables that `linkage` reads.
+pub const ENV_VARS: [&str; 3] = ["ARGON2_LIB_DIR", "ARGON2_INCLUDE_DIR", "ARGON2_STATIC"];
+
+/// Decides how to get the C library, from the `pure-rust` and `system` features and the
+/// environment variables that `var` returns:
+///
+/// 1. `pure-rust` needs no C library and ignores the environment.
+/// 2. A non-empty `ARGON2_LIB_DIR` links the library in it, with or without `system`.
+/// 3. `system` finds the library with pkg-config.
+/// 4. Otherwise the bundled sources are built, and `ARGON2_INCLUDE_DIR` and `ARGON2_STATIC` are
+///    ignored.
+///
+/// `ARGON2_STATIC=1` links statically, any other value dynamically. Empty variables count as
+/// unset.
+pub fn linkage(pure_rust: bool, system: bool, var: impl Fn(&str) -> Option<OsString>) -> Linkage {
+    let var = |name| var(name).filter(|value| !value.is_empty());
+    if pure_rust {
+        return Linkage::None;
+    }
+    let include_dir = var("ARGON2_INCLUDE_DIR").map(PathBuf::from);
+    let static_lib = var("ARGON2_STATIC").as_deref() == Some(OsStr::new("1"));
+    match var("ARGON2_LIB_DIR") {
+        Some(lib_dir) => Linkage::Prebuilt { lib_dir: PathBuf::from(lib_dir), include_dir, static_lib },
+        None if system => Linkage::PkgConfig { include_dir, static_lib },
+        None => Linkage::Bundled,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        move |name| vars.iter().find(|&&(n, _)| n == name).map(|&(_, value)| OsString::from(value))
+    }
+
+    #[test]
+    fn test_linkage_precedence() {
+        let all = [("ARGON2_LIB_DIR", "/opt/argon2/lib"), ("ARGON2_INCLUDE_DIR", "/opt/argon2/include"), ("ARGON2_STATIC", "1")];
+        let prebuilt = Linkage::Prebuilt {
+            lib_dir: PathBuf::from("/opt/argon2/lib"),
+            include_dir: Some(PathBuf::from("/opt/argon2/include")),
+            static_lib: true,
+        };
+
+        // `pure-rust` wins over everything.
+        assert_eq!(Linkage::None, linkage(true, false, env(&all)));
+        assert_eq!(Linkage::None, linkage(true, true, env(&all)));
+
+        // `ARGON2_LIB_DIR` wins over `system` and the bundled sources.
+        assert_eq!(prebuilt, linkage(false, false, env(&all)));
+        assert_eq!(prebuilt, linkage(false, true, env(&all)));
+        assert_eq!(Linkage::Prebuilt { lib_dir: PathBuf::from("lib"), include_dir: None, static_lib: false },
+                   linkage(false, false, env(&[("ARGON2_LIB_DIR", "lib")])));
+
+        // Without it, `system` uses pkg-config and the rest builds the bundled sources.
+        assert_eq!(Linkage::PkgConfig { include_dir: Some(PathBuf::from("/opt/argon2/include")), static_lib: true },
+                   linkage(false, true, env(&all[1..])));
+        assert_eq!(Linkage::PkgConfig { include_dir: None, static_lib: false }, linkage(false, true, env(&[])));
+        assert_eq!(Linkage::Bundled, linkage(false, false, env(&all[1..])));
+        assert_eq!(Linkage::Bundled, linkage(false, false, env(&[])));
+
+        // Only `ARGON2_STATIC=1` links statically, and empty variables are unset.
+        for &(value, static_lib) in [("1", true), ("0", false), ("yes", false), ("", false)].iter() {
+            assert_eq!(Linkage::Prebuilt { lib_dir: PathBuf::from("lib"), include_dir: None, static_lib },
+                       linkage(false, false, env(&[("ARGON2_LIB_DIR", "lib"), ("ARGON2_STATIC", value)])));
+        }
+        assert_eq!(Linkage::Bundled, linkage(false, false, env(&[("ARGON2_LIB_DIR", ""), ("ARGON2_INCLUDE_DIR", "")])));
+    }
+}
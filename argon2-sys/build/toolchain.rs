@@ -0,0 +1,104 @@
+//! Which C compiler the build script uses for mobile targets. Shared with the tests of
+//! `src/lib.rs`, since build scripts can't have tests of their own.
+//!
+//! The `cc` crate finds the compiler of most targets on its own, including the Xcode toolchain
+//! for iOS through `xcrun`. For Android it only finds the NDK's clang if it is in `PATH` or set
+//! with `CC_<target>`, so the build script points it to the NDK named by `ANDROID_NDK_HOME` (or
+//! `ANDROID_NDK_ROOT`) unless a compiler is set explicitly.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Environment variables that `android_toolchain` and the `cc` crate read for mobile targets.
+pub const ENV_VARS: [&str; 6] = [
+    "ANDROID_NDK_HOME", "ANDROID_NDK_ROOT", "ANDROID_PLATFORM", "IPHONEOS_DEPLOYMENT_TARGET", "SDKROOT", "TARGET_CC",
+];
+
+/// Android API level used when `ANDROID_PLATFORM` isn't set; the lowest that the NDK supports for
+/// 64 bit targets.
+pub const DEFAULT_ANDROID_API: u32 = 21;
+
+/// Compiler and archiver of the NDK for an Android target.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AndroidToolchain {
+    /// The clang wrapper for the target and API level, e.g. `aarch64-linux-android21-clang`.
+    pub cc: PathBuf,
+    /// `llvm-ar`.
+    pub ar: PathBuf,
+}
+
+/// Returns the NDK toolchain for `target`, or `None` if `target` isn't an Android target, a
+/// compiler is already set with `CC_<target>` or `TARGET_CC`, or no NDK is set.
+///
+/// `host` is the target triple of the machine the build runs on. The API level is taken from
+/// `ANDROID_PLATFORM`, as `android-24` or `24`, and defaults to `DEFAULT_ANDROID_API`.
+pub fn android_toolchain(target: &str, host: &str, var: impl Fn(&str) -> Option<OsString>) -> Option<AndroidToolchain> {
+    let var = |name: &str| var(name).filter(|value| !value.is_empty());
+    if !target.contains("-android") {
+        return None;
+    }
+    if var(&format!("CC_{}", target)).is_some() || var(&format!("CC_{}", target.replace('-', "_"))).is_some()
+        || var("TARGET_CC").is_some() {
+        return None;
+    }
+    let ndk = var("ANDROID_NDK_HOME").or_else(|| var("ANDROID_NDK_ROOT"))?;
+    let api = var("ANDROID_PLATFORM")
+        .and_then(|platform| {
+            let platform = platform.to_str()?;
+            platform.strip_prefix("android-").unwrap_or(platform).parse().ok()
+        })
+        .unwrap_or(DEFAULT_ANDROID_API);
+    let host_tag = if host.contains("-windows") {
+        "windows-x86_64"
+    } else if host.contains("-apple-") {
+        // The NDK only ships x86_64 binaries for macOS, which run on Apple silicon through Rosetta.
+        "darwin-x86_64"
+    } else {
+        "linux-x86_64"
+    };
+    let bin = Path::new(&ndk).join("toolchains").join("llvm").join("prebuilt").join(host_tag).join("bin");
+    // The 32 bit ARM targets of Rust are `armv7-` and `thumbv7neon-linux-androideabi`, the clang
+    // wrappers of the NDK are `armv7a-linux-androideabi<api>-clang`.
+    let triple = match target.split('-').next() {
+        Some("armv7") | Some("thumbv7neon") => "armv7a-linux-androideabi",
+        _ => target,
+    };
+    let suffix = if host.contains("-windows") { ".cmd" } else { "" };
+    Some(AndroidToolchain {
+        cc: bin.join(format!("{}{}-clang{}", triple, api, suffix)),
+        ar: bin.join(if host.contains("-windows") { "llvm-ar.exe" } else { "llvm-ar" }),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn env<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        move |name| vars.iter().find(|&&(n, _)| n == name).map(|&(_, value)| OsString::from(value))
+    }
+
+    #[test]
+    fn test_android_toolchain() {
+        let linux = "x86_64-unknown-linux-gnu";
+        let ndk = [("ANDROID_NDK_HOME", "/opt/ndk")];
+        let bin = Path::new("/opt/ndk/toolchains/llvm/prebuilt/linux-x86_64/bin");
+        assert_eq!(Some(AndroidToolchain { cc: bin.join("aarch64-linux-android21-clang"), ar: bin.join("llvm-ar") }),
+                   android_toolchain("aarch64-linux-android", linux, env(&ndk)));
+        assert_eq!(Some(AndroidToolchain { cc: bin.join("armv7a-linux-androideabi24-clang"), ar: bin.join("llvm-ar") }),
+                   android_toolchain("armv7-linux-androideabi", linux,
+                                     env(&[("ANDROID_NDK_ROOT", "/opt/ndk"), ("ANDROID_PLATFORM", "android-24")])));
+        let bin = Path::new("/opt/ndk/toolchains/llvm/prebuilt/darwin-x86_64/bin");
+        assert_eq!(Some(AndroidToolchain { cc: bin.join("x86_64-linux-android30-clang"), ar: bin.join("llvm-ar") }),
+                   android_toolchain("x86_64-linux-android", "aarch64-apple-darwin",
+                                     env(&[("ANDROID_NDK_HOME", "/opt/ndk"), ("ANDROID_PLATFORM", "30")])));
+
+        // Not Android, no NDK, or an explicit compiler.
+        assert_eq!(None, android_toolchain("aarch64-apple-ios", linux, env(&ndk)));
+        assert_eq!(None, android_toolchain("aarch64-linux-android", linux, env(&[("ANDROID_NDK_HOME", "")])));
+        assert_eq!(None, android_toolchain("aarch64-linux-android", linux,
+                                           env(&[("ANDROID_NDK_HOME", "/opt/ndk"), ("CC_aarch64_linux_android", "clang")])));
+        assert_eq!(None, android_toolchain("aarch64-linux-android", linux,
+                                           env(&[("ANDROID_NDK_HOME", "/opt/ndk"), ("TARGET_CC", "clang")])));
+    }
+}
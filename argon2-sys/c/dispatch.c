@@ -0,0 +1,64 @@
+/*
+ * Runtime selection of the fill_segment implementation.
+ *
+ * ref.c and opt.c both define fill_segment, which core.c calls to fill the memory blocks. The
+ * build script compiles each of them with fill_segment renamed, so that all of them can be linked
+ * into one binary, and this file provides the fill_segment that core.c links against. The Rust
+ * side picks the implementation with argon2_select_fill_segment after detecting the CPU features.
+ */
+
+#include "core.h"
+
+void argon2_ref_fill_segment(const argon2_instance_t *instance, argon2_position_t position);
+#ifdef ARGON2_HAVE_OPT
+void argon2_opt_fill_segment(const argon2_instance_t *instance, argon2_position_t position);
+#endif
+#ifdef ARGON2_HAVE_OPT_AVX2
+void argon2_opt_avx2_fill_segment(const argon2_instance_t *instance, argon2_position_t position);
+#endif
+
+typedef void (*fill_segment_fn)(const argon2_instance_t *instance, argon2_position_t position);
+
+/* The implementation that is used until the Rust side selects one. opt.c is only compiled with
+ * the baseline instruction set of the target, so it is safe to use without detection. */
+#ifdef ARGON2_HAVE_OPT
+static fill_segment_fn selected = argon2_opt_fill_segment;
+#else
+static fill_segment_fn selected = argon2_ref_fill_segment;
+#endif
+
+/* The pointer may be replaced while other threads are hashing; every implementation produces
+ * the same result, so it only has to be read and written atomically. */
+#if defined(_MSC_VER)
+#define LOAD_SELECTED() (*(volatile fill_segment_fn *)&selected)
+#define STORE_SELECTED(fn) (*(volatile fill_segment_fn *)&selected = (fn))
+#else
+#define LOAD_SELECTED() __atomic_load_n(&selected, __ATOMIC_RELAXED)
+#define STORE_SELECTED(fn) __atomic_store_n(&selected, (fn), __ATOMIC_RELAXED)
+#endif
+
+/* Selects the implementation: 0 is ref.c, 1 is opt.c and 2 is opt.c compiled for AVX2. Returns 1
+ * on success and 0 if the implementation isn't compiled in, in which case nothing changes. */
+int argon2_select_fill_segment(int core) {
+    switch (core) {
+    case 0:
+        STORE_SELECTED(argon2_ref_fill_segment);
+        return 1;
+#ifdef ARGON2_HAVE_OPT
+    case 1:
+        STORE_SELECTED(argon2_opt_fill_segment);
+        return 1;
+#endif
+#ifdef ARGON2_HAVE_OPT_AVX2
+    case 2:
+        STORE_SELECTED(argon2_opt_avx2_fill_segment);
+        return 1;
+#endif
+    default:
+        return 0;
+    }
+}
+
+void fill_segment(const argon2_instance_t *instance, argon2_position_t position) {
+    LOAD_SELECTED()(instance, position);
+}
@@ -0,0 +1,48 @@
+/*
+ * The error of the last failed thread creation.
+ *
+ * core.c only reports ARGON2_THREAD_FAIL when argon2_thread_create fails and drops the error that
+ * pthread_create returned, which doesn't set errno either. The build script compiles thread.c with
+ * argon2_thread_create renamed to argon2_sys_thread_create, and this file provides the
+ * argon2_thread_create that core.c links against, which remembers the error of the calling
+ * thread. The Rust side takes it with argon2_sys_take_thread_error after a call fails.
+ */
+
+#include "thread.h"
+
+#if defined(_MSC_VER)
+#define THREAD_LOCAL __declspec(thread)
+#else
+#define THREAD_LOCAL __thread
+#endif
+
+#if defined(_WIN32)
+#include <windows.h>
+#endif
+
+static THREAD_LOCAL int last_error = 0;
+
+#if !defined(ARGON2_NO_THREADS)
+int argon2_sys_thread_create(argon2_thread_handle_t *handle, argon2_thread_func_t func, void *args);
+
+int argon2_thread_create(argon2_thread_handle_t *handle, argon2_thread_func_t func, void *args) {
+    int rc = argon2_sys_thread_create(handle, func, args);
+    if (rc != 0) {
+#if defined(_WIN32)
+        /* thread.c returns -1 if _beginthreadex fails, which leaves the cause of CreateThread. */
+        last_error = (int)GetLastError();
+#else
+        last_error = rc;
+#endif
+    }
+    return rc;
+}
+#endif
+
+/* Returns the OS error of the last failed thread creation on the calling thread and resets it, 0 if
+ * there is none. */
+int argon2_sys_take_thread_error(void) {
+    int error = last_error;
+    last_error = 0;
+    return error;
+}
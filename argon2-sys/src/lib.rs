@@ -0,0 +1,352 @@
+//! Raw FFI bindings to the Argon2 C library, built from the bundled sources or, with the `system`
+//! feature, linked against the system's libargon2. With the `pure-rust` feature, the functions are
+//! implemented in Rust instead and no C library is built or linked.
+//!
+//! Setting `ARGON2_LIB_DIR` links the prebuilt libargon2 in that directory instead, with or
+//! without `system`: statically if `ARGON2_STATIC=1`, dynamically otherwise, with the header in
+//! `ARGON2_INCLUDE_DIR` or the bundled one. `PREBUILT` tells whether it was used.
+//!
+//! The names are those of `argon2.h`, with the enum constants prefixed by the name of their enum.
+//! The build script sets `DEP_ARGON2_INCLUDE` to the directory of `argon2.h` for the build scripts
+//! of dependents, except with `pure-rust`. Use the `argon2` crate (package `just-argon2`) for a
+//! safe interface.
+#![allow(bad_style)]
+
+#[cfg(all(feature = "system", feature = "pure-rust"))]
+compile_error!("The `system` and `pure-rust` features are mutually exclusive.");
+
+#[cfg(feature = "pure-rust")]
+mod pure;
+
+#[cfg(feature = "pure-rust")]
+pub use pure::*;
+
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../build/linkage.rs"]
+mod linkage;
+
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "../build/toolchain.rs"]
+mod toolchain;
+
+use std::os::raw::c_int;
+use std::os::raw::c_void;
+use std::os::raw::c_char;
+
+pub type argon2_context = Argon2_Context;
+pub type argon2_type = Argon2_type;
+
+pub type allocate_fptr = Option<unsafe extern "C" fn(memory: *mut *mut u8, bytes_to_allocate: usize) -> c_int>;
+pub type deallocate_fptr = Option<unsafe extern "C" fn(memory: *mut u8, bytes_to_allocate: usize)>;
+
+pub type Argon2_ErrorCodes  = c_int;
+pub type Argon2_type        = c_int;
+pub type Argon2_version     = c_int;
+
+#[repr(C)]
+pub struct Argon2_Context {
+    pub out:        *mut u8,
+    pub outlen:     u32,
+    pub pwd:        *mut u8,
+    pub pwdlen:     u32,
+    pub salt:       *mut u8,
+    pub saltlen:    u32,
+    pub secret:     *mut u8,
+    pub secretlen:  u32,
+    pub ad:         *mut u8,
+    pub adlen:      u32,
+    pub t_cost:     u32,
+    pub m_cost:     u32,
+    pub lanes:      u32,
+    pub threads:    u32,
+    pub version:    u32,
+    pub allocate_cbk:   allocate_fptr,
+    pub free_cbk:       deallocate_fptr,
+    pub flags:      u32,
+}
+
+pub const Argon2_ErrorCodes_ARGON2_OK: Argon2_ErrorCodes = 0;
+pub const Argon2_ErrorCodes_ARGON2_OUTPUT_PTR_NULL: Argon2_ErrorCodes = -1;
+pub const Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_SHORT: Argon2_ErrorCodes = -2;
+pub const Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_LONG: Argon2_ErrorCodes = -3;
+pub const Argon2_ErrorCodes_ARGON2_PWD_TOO_SHORT: Argon2_ErrorCodes = -4;
+pub const Argon2_ErrorCodes_ARGON2_PWD_TOO_LONG: Argon2_ErrorCodes = -5;
+pub const Argon2_ErrorCodes_ARGON2_SALT_TOO_SHORT: Argon2_ErrorCodes = -6;
+pub const Argon2_ErrorCodes_ARGON2_SALT_TOO_LONG: Argon2_ErrorCodes = -7;
+pub const Argon2_ErrorCodes_ARGON2_AD_TOO_SHORT: Argon2_ErrorCodes = -8;
+pub const Argon2_ErrorCodes_ARGON2_AD_TOO_LONG: Argon2_ErrorCodes = -9;
+pub const Argon2_ErrorCodes_ARGON2_SECRET_TOO_SHORT: Argon2_ErrorCodes = -10;
+pub const Argon2_ErrorCodes_ARGON2_SECRET_TOO_LONG: Argon2_ErrorCodes = -11;
+pub const Argon2_ErrorCodes_ARGON2_TIME_TOO_SMALL: Argon2_ErrorCodes = -12;
+pub const Argon2_ErrorCodes_ARGON2_TIME_TOO_LARGE: Argon2_ErrorCodes = -13;
+pub const Argon2_ErrorCodes_ARGON2_MEMORY_TOO_LITTLE: Argon2_ErrorCodes = -14;
+pub const Argon2_ErrorCodes_ARGON2_MEMORY_TOO_MUCH: Argon2_ErrorCodes = -15;
+pub const Argon2_ErrorCodes_ARGON2_LANES_TOO_FEW: Argon2_ErrorCodes = -16;
+pub const Argon2_ErrorCodes_ARGON2_LANES_TOO_MANY: Argon2_ErrorCodes = -17;
+pub const Argon2_ErrorCodes_ARGON2_PWD_PTR_MISMATCH: Argon2_ErrorCodes = -18;
+pub const Argon2_ErrorCodes_ARGON2_SALT_PTR_MISMATCH: Argon2_ErrorCodes = -19;
+pub const Argon2_ErrorCodes_ARGON2_SECRET_PTR_MISMATCH: Argon2_ErrorCodes = -20;
+pub const Argon2_ErrorCodes_ARGON2_AD_PTR_MISMATCH: Argon2_ErrorCodes = -21;
+pub const Argon2_ErrorCodes_ARGON2_MEMORY_ALLOCATION_ERROR: Argon2_ErrorCodes = -22;
+pub const Argon2_ErrorCodes_ARGON2_FREE_MEMORY_CBK_NULL: Argon2_ErrorCodes = -23;
+pub const Argon2_ErrorCodes_ARGON2_ALLOCATE_MEMORY_CBK_NULL: Argon2_ErrorCodes = -24;
+pub const Argon2_ErrorCodes_ARGON2_INCORRECT_PARAMETER: Argon2_ErrorCodes = -25;
+pub const Argon2_ErrorCodes_ARGON2_INCORRECT_TYPE: Argon2_ErrorCodes = -26;
+pub const Argon2_ErrorCodes_ARGON2_OUT_PTR_MISMATCH: Argon2_ErrorCodes = -27;
+pub const Argon2_ErrorCodes_ARGON2_THREADS_TOO_FEW: Argon2_ErrorCodes = -28;
+pub const Argon2_ErrorCodes_ARGON2_THREADS_TOO_MANY: Argon2_ErrorCodes = -29;
+pub const Argon2_ErrorCodes_ARGON2_MISSING_ARGS: Argon2_ErrorCodes = -30;
+pub const Argon2_ErrorCodes_ARGON2_ENCODING_FAIL: Argon2_ErrorCodes = -31;
+pub const Argon2_ErrorCodes_ARGON2_DECODING_FAIL: Argon2_ErrorCodes = -32;
+pub const Argon2_ErrorCodes_ARGON2_THREAD_FAIL: Argon2_ErrorCodes = -33;
+pub const Argon2_ErrorCodes_ARGON2_DECODING_LENGTH_FAIL: Argon2_ErrorCodes = -34;
+pub const Argon2_ErrorCodes_ARGON2_VERIFY_MISMATCH: Argon2_ErrorCodes = -35;
+
+pub const Argon2_type_Argon2_d: Argon2_type     = 0;
+pub const Argon2_type_Argon2_i: Argon2_type     = 1;
+pub const Argon2_type_Argon2_id: Argon2_type    = 2;
+
+pub const Argon2_version_ARGON2_VERSION_10: Argon2_version = 0x10;
+pub const Argon2_version_ARGON2_VERSION_13: Argon2_version = 0x13;
+pub const Argon2_version_ARGON2_VERSION_NUMBER: Argon2_version = Argon2_version_ARGON2_VERSION_13;
+
+#[cfg(not(feature = "pure-rust"))]
+extern "C" {
+    pub fn argon2_ctx(context: *mut argon2_context, type_: argon2_type) -> c_int;
+
+    pub fn argon2i_hash_encoded(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hashlen: usize, encoded: *mut c_char, encodedlen: usize) -> c_int;
+
+    pub fn argon2i_hash_raw(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hash: *mut c_void, hashlen: usize) -> c_int;
+
+    pub fn argon2d_hash_encoded(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hashlen: usize, encoded: *mut c_char, encodedlen: usize) -> c_int;
+
+    pub fn argon2d_hash_raw(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hash: *mut c_void, hashlen: usize) -> c_int;
+
+    pub fn argon2id_hash_encoded(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hashlen: usize, encoded: *mut c_char, encodedlen: usize) -> c_int;
+
+    pub fn argon2id_hash_raw(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hash: *mut c_void, hashlen: usize) -> c_int;
+
+    pub fn argon2_hash(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hash: *mut c_void, hashlen: usize, encoded: *mut c_char, encodedlen: usize, type_: argon2_type, version: u32) -> c_int;
+
+    pub fn argon2i_verify(encoded: *const c_char, pwd: *const c_void, pwdlen: usize) -> c_int;
+
+    pub fn argon2d_verify(encoded: *const c_char, pwd: *const c_void, pwdlen: usize) -> c_int;
+
+    pub fn argon2id_verify(encoded: *const c_char, pwd: *const c_void, pwdlen: usize) -> c_int;
+
+    pub fn argon2_verify(encoded: *const c_char, pwd: *const c_void, pwdlen: usize, type_: argon2_type) -> c_int;
+
+    pub fn argon2d_ctx(context: *mut argon2_context) -> c_int;
+
+    pub fn argon2i_ctx(context: *mut argon2_context) -> c_int;
+
+    pub fn argon2id_ctx(context: *mut argon2_context) -> c_int;
+
+    pub fn argon2d_verify_ctx(context: *mut argon2_context, hash: *const c_char) -> c_int;
+
+    pub fn argon2i_verify_ctx(context: *mut argon2_context, hash: *const c_char) -> c_int;
+
+    pub fn argon2id_verify_ctx(context: *mut argon2_context, hash: *const c_char) -> c_int;
+
+    pub fn argon2_verify_ctx(context: *mut argon2_context, hash: *const c_char, type_: argon2_type) -> c_int;
+
+    pub fn argon2_error_message( error_code: c_int) -> *const c_char;
+
+    pub fn argon2_encodedlen(t_cost: u32, m_cost: u32, parallelism: u32, saltlen: u32, hashlen: u32, type_: argon2_type) -> usize;
+
+    pub fn argon2_type2string(type_: argon2_type, uppercase: c_int) -> *const c_char;
+
+    // Defined in c/dispatch.c, not part of the argon2 C library.
+    #[cfg(not(any(feature = "system", argon2_prebuilt)))]
+    pub fn argon2_select_fill_segment(core: c_int) -> c_int;
+
+    // Defined in c/thread_error.c, not part of the argon2 C library.
+    #[cfg(not(any(feature = "system", argon2_prebuilt)))]
+    pub fn argon2_sys_take_thread_error() -> c_int;
+
+    // Declared in src/blake2/blake2.h. The system library hides them (`ARGON2_LOCAL`), and a
+    // prebuilt one only has them if it is static.
+    #[cfg(not(feature = "system"))]
+    pub fn blake2b(out: *mut c_void, outlen: usize, in_: *const c_void, inlen: usize, key: *const c_void, keylen: usize) -> c_int;
+
+    #[cfg(not(feature = "system"))]
+    pub fn blake2b_long(out: *mut c_void, outlen: usize, in_: *const c_void, inlen: usize) -> c_int;
+
+    #[cfg(not(feature = "system"))]
+    pub fn blake2b_init(S: *mut blake2b_state, outlen: usize) -> c_int;
+
+    #[cfg(not(feature = "system"))]
+    pub fn blake2b_update(S: *mut blake2b_state, in_: *const c_void, inlen: usize) -> c_int;
+
+    #[cfg(not(feature = "system"))]
+    pub fn blake2b_final(S: *mut blake2b_state, out: *mut c_void, outlen: usize) -> c_int;
+}
+
+/// Whether the library in `ARGON2_LIB_DIR` is linked, instead of the bundled sources or the
+/// library that pkg-config finds.
+pub const PREBUILT: bool = cfg!(argon2_prebuilt);
+
+/// Whether the library accepts more than one thread. Only false on wasm, where the bundled
+/// sources are built with `ARGON2_NO_THREADS`; on Windows, MSVC included, thread.c uses the Win32
+/// threads. The `pure-rust` implementation accepts them but computes the lanes one after the other.
+pub const THREADS: bool = cfg!(argon2_threads);
+
+/// Stand-in for c/dispatch.c, which a prebuilt library doesn't have. It uses the `fill_segment` it
+/// was built with, which the `argon2` crate selects as the reference one (0).
+#[cfg(all(argon2_prebuilt, not(feature = "system")))]
+pub unsafe fn argon2_select_fill_segment(core: c_int) -> c_int {
+    (core == 0) as c_int
+}
+
+/// Stand-in for c/thread_error.c, which only the bundled sources have. Other libraries don't keep
+/// the error of `pthread_create`, so there is never one to take.
+///
+/// # Safety
+///
+/// Always safe to call; it is `unsafe` like the C function it stands in for.
+#[cfg(any(feature = "system", feature = "pure-rust", argon2_prebuilt))]
+pub unsafe fn argon2_sys_take_thread_error() -> c_int {
+    0
+}
+
+// Limits of `blake2b`, from the `blake2b_constant` enum of src/blake2/blake2.h.
+pub const BLAKE2B_BLOCKBYTES: usize = 128;
+pub const BLAKE2B_OUTBYTES: usize = 64;
+pub const BLAKE2B_KEYBYTES: usize = 64;
+
+/// State of the incremental BLAKE2b functions, from src/blake2/blake2.h. Initialized by
+/// `blake2b_init` and only meant to be passed to the other `blake2b_*` functions.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct blake2b_state {
+    pub h: [u64; 8],
+    pub t: [u64; 2],
+    pub f: [u64; 2],
+    pub buf: [u8; BLAKE2B_BLOCKBYTES],
+    pub buflen: u32,
+    pub outlen: u32,
+    pub last_node: u8,
+}
+
+/// Bindings generated from `argon2.h` by the build script, to test this module against.
+#[cfg(all(test, feature = "bindgen", not(feature = "pure-rust")))]
+pub mod generated {
+    include!(concat!(env!("OUT_DIR"), "/argon2-bindings.rs"));
+
+    /// The generated source, to find constants that are missing above.
+    pub const SOURCE: &str = include_str!(concat!(env!("OUT_DIR"), "/argon2-bindings.rs"));
+}
+
+#[cfg(all(test, feature = "bindgen", not(feature = "pure-rust")))]
+mod test {
+    use super::*;
+
+    /// `(name, hand-written value, value in the generated bindings)` of the given constants.
+    macro_rules! sys_constants {
+        ($($name:ident),* $(,)?) => {
+            vec![$((stringify!($name), i64::from($name), i64::from(generated::$name))),*]
+        }
+    }
+
+    /// Compares the hand-written bindings to the bindings that bindgen generates from the
+    /// `argon2.h` that is built or linked against.
+    #[test]
+    fn test_sys_matches_bindgen() {
+        use std::mem::{align_of, offset_of, size_of};
+
+        let constants = sys_constants![
+            Argon2_ErrorCodes_ARGON2_OK,
+            Argon2_ErrorCodes_ARGON2_OUTPUT_PTR_NULL,
+            Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_SHORT,
+            Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_LONG,
+            Argon2_ErrorCodes_ARGON2_PWD_TOO_SHORT,
+            Argon2_ErrorCodes_ARGON2_PWD_TOO_LONG,
+            Argon2_ErrorCodes_ARGON2_SALT_TOO_SHORT,
+            Argon2_ErrorCodes_ARGON2_SALT_TOO_LONG,
+            Argon2_ErrorCodes_ARGON2_AD_TOO_SHORT,
+            Argon2_ErrorCodes_ARGON2_AD_TOO_LONG,
+            Argon2_ErrorCodes_ARGON2_SECRET_TOO_SHORT,
+            Argon2_ErrorCodes_ARGON2_SECRET_TOO_LONG,
+            Argon2_ErrorCodes_ARGON2_TIME_TOO_SMALL,
+            Argon2_ErrorCodes_ARGON2_TIME_TOO_LARGE,
+            Argon2_ErrorCodes_ARGON2_MEMORY_TOO_LITTLE,
+            Argon2_ErrorCodes_ARGON2_MEMORY_TOO_MUCH,
+            Argon2_ErrorCodes_ARGON2_LANES_TOO_FEW,
+            Argon2_ErrorCodes_ARGON2_LANES_TOO_MANY,
+            Argon2_ErrorCodes_ARGON2_PWD_PTR_MISMATCH,
+            Argon2_ErrorCodes_ARGON2_SALT_PTR_MISMATCH,
+            Argon2_ErrorCodes_ARGON2_SECRET_PTR_MISMATCH,
+            Argon2_ErrorCodes_ARGON2_AD_PTR_MISMATCH,
+            Argon2_ErrorCodes_ARGON2_MEMORY_ALLOCATION_ERROR,
+            Argon2_ErrorCodes_ARGON2_FREE_MEMORY_CBK_NULL,
+            Argon2_ErrorCodes_ARGON2_ALLOCATE_MEMORY_CBK_NULL,
+            Argon2_ErrorCodes_ARGON2_INCORRECT_PARAMETER,
+            Argon2_ErrorCodes_ARGON2_INCORRECT_TYPE,
+            Argon2_ErrorCodes_ARGON2_OUT_PTR_MISMATCH,
+            Argon2_ErrorCodes_ARGON2_THREADS_TOO_FEW,
+            Argon2_ErrorCodes_ARGON2_THREADS_TOO_MANY,
+            Argon2_ErrorCodes_ARGON2_MISSING_ARGS,
+            Argon2_ErrorCodes_ARGON2_ENCODING_FAIL,
+            Argon2_ErrorCodes_ARGON2_DECODING_FAIL,
+            Argon2_ErrorCodes_ARGON2_THREAD_FAIL,
+            Argon2_ErrorCodes_ARGON2_DECODING_LENGTH_FAIL,
+            Argon2_ErrorCodes_ARGON2_VERIFY_MISMATCH,
+            Argon2_type_Argon2_d,
+            Argon2_type_Argon2_i,
+            Argon2_type_Argon2_id,
+            Argon2_version_ARGON2_VERSION_10,
+            Argon2_version_ARGON2_VERSION_13,
+            Argon2_version_ARGON2_VERSION_NUMBER,
+        ];
+        for &(name, hand_written, generated) in &constants {
+            assert_eq!(generated, hand_written, "{} differs from argon2.h", name);
+        }
+        // Constants that argon2.h has and src/lib.rs doesn't, e.g. new error codes.
+        let names = generated::SOURCE
+            .split("pub const ")
+            .skip(1)
+            .filter_map(|decl| decl.split(':').next())
+            .map(str::trim)
+            .filter(|name| ["Argon2_ErrorCodes_", "Argon2_type_", "Argon2_version_"].iter().any(|p| name.starts_with(p)));
+        for name in names {
+            assert!(constants.iter().any(|&(n, ..)| n == name), "{} is missing from src/lib.rs", name);
+        }
+
+        assert_eq!(size_of::<generated::Argon2_Context>(), size_of::<Argon2_Context>());
+        assert_eq!(align_of::<generated::Argon2_Context>(), align_of::<Argon2_Context>());
+        macro_rules! assert_offsets {
+            ($($field:ident),*) => {
+                $(assert_eq!(offset_of!(generated::Argon2_Context, $field), offset_of!(Argon2_Context, $field),
+                             "offset of {}", stringify!($field));)*
+            }
+        }
+        assert_offsets!(out, outlen, pwd, pwdlen, salt, saltlen, secret, secretlen, ad, adlen,
+                        t_cost, m_cost, lanes, threads, version, allocate_cbk, free_cbk, flags);
+    }
+}
+
+/// Compares `Argon2_Context` and the enum constants with the `argon2.h` that is built or linked
+/// against, through a C shim that the build script generates. A mismatch would corrupt memory
+/// instead of failing to compile.
+#[cfg(all(test, not(feature = "pure-rust")))]
+mod layout_test {
+    use super::*;
+
+    include!(concat!(env!("OUT_DIR"), "/argon2-layout.rs"));
+
+    #[test]
+    fn test_context_layout_matches_c() {
+        for (what, c, rust) in unsafe { context_layout() } {
+            assert_eq!(c, rust, "{} of Argon2_Context differs from argon2.h", what);
+        }
+    }
+
+    #[test]
+    fn test_constants_match_c() {
+        for (name, c, rust) in unsafe { constants() } {
+            assert_eq!(c, rust, "{} differs from argon2.h", name);
+        }
+    }
+}
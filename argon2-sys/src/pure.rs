@@ -0,0 +1,1232 @@
+//! A Rust implementation of the functions of the C library, with the `pure-rust` feature.
+//!
+//! Each function has the signature of its declaration in the `extern "C"` block and returns the
+//! same error codes as the reference implementation, so the `argon2` crate works the same on top
+//! of either. The lanes are always computed one after the other on the calling thread, and
+//! `blake2b`, `blake2b_long` and `argon2_select_fill_segment` stand in for the bundled C sources.
+
+// The functions keep the signatures of the C library and have the safety requirements that
+// argon2.h documents.
+#![allow(clippy::missing_safety_doc, clippy::too_many_arguments)]
+
+use super::*;
+
+const BLOCK_SIZE: usize = 1024;
+const QWORDS_IN_BLOCK: usize = BLOCK_SIZE / 8;
+const ADDRESSES_IN_BLOCK: u32 = 128;
+const PREHASH_DIGEST_LENGTH: usize = 64;
+const PREHASH_SEED_LENGTH: usize = 72;
+const SYNC_POINTS: u32 = 4;
+
+const MIN_LANES: u32 = 1;
+const MAX_LANES: u32 = 0xFFFFFF;
+const MIN_THREADS: u32 = 1;
+const MAX_THREADS: u32 = 0xFFFFFF;
+const MIN_OUTLEN: u32 = 4;
+const MIN_MEMORY: u32 = 2 * SYNC_POINTS;
+const MIN_TIME: u32 = 1;
+const MIN_SALT_LENGTH: u32 = 8;
+
+// `ARGON2_MAX_MEMORY`, in KiB: the largest `m_cost` whose blocks can be addressed. Only limits
+// `m_cost` with 32 bit pointers.
+#[cfg(target_pointer_width = "64")]
+const MAX_MEMORY: u64 = 0xFFFFFFFF;
+#[cfg(not(target_pointer_width = "64"))]
+const MAX_MEMORY: u64 = 1 << 21;
+
+const FLAG_CLEAR_PASSWORD: u32 = 1 << 0;
+const FLAG_CLEAR_SECRET: u32 = 1 << 1;
+
+const BLAKE2B_IV: [u64; 8] = [
+    0x6a09e667f3bcc908, 0xbb67ae8584caa73b, 0x3c6ef372fe94f82b, 0xa54ff53a5f1d36f1,
+    0x510e527fade682d1, 0x9b05688c2b3e6c1f, 0x1f83d9abfb41bd6b, 0x5be0cd19137e2179,
+];
+
+const BLAKE2B_SIGMA: [[usize; 16]; 12] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+];
+
+struct Blake2b {
+    h: [u64; 8],
+    t: u128,
+    buf: [u8; BLAKE2B_BLOCKBYTES],
+    buflen: usize,
+    outlen: usize,
+}
+
+impl Blake2b {
+    fn new(outlen: usize) -> Blake2b {
+        Blake2b::with_key(outlen, &[])
+    }
+
+    fn with_key(outlen: usize, key: &[u8]) -> Blake2b {
+        let mut h = BLAKE2B_IV;
+        h[0] ^= 0x01010000 ^ ((key.len() as u64) << 8) ^ (outlen as u64);
+        let mut state = Blake2b { h, t: 0, buf: [0; BLAKE2B_BLOCKBYTES], buflen: 0, outlen };
+        if !key.is_empty() {
+            let mut block = [0u8; BLAKE2B_BLOCKBYTES];
+            block[..key.len()].copy_from_slice(key);
+            state.update(&block);
+            secure_wipe(&mut block);
+        }
+        state
+    }
+
+    /// Continues from the state that `store` left in `blake2b_state`.
+    fn load(state: &blake2b_state) -> Blake2b {
+        Blake2b {
+            h: state.h,
+            t: u128::from(state.t[0]) | (u128::from(state.t[1]) << 64),
+            buf: state.buf,
+            buflen: state.buflen as usize,
+            outlen: state.outlen as usize,
+        }
+    }
+
+    fn store(&self, state: &mut blake2b_state) {
+        state.h = self.h;
+        state.t = [self.t as u64, (self.t >> 64) as u64];
+        state.f = [0, 0];
+        state.buf = self.buf;
+        state.buflen = self.buflen as u32;
+        state.outlen = self.outlen as u32;
+        state.last_node = 0;
+    }
+
+    fn compress(&mut self, last: bool) {
+        let mut m = [0u64; 16];
+        for (idx, word) in m.iter_mut().enumerate() {
+            *word = load64(&self.buf[(idx * 8)..]);
+        }
+
+        let mut v = [0u64; 16];
+        v[..8].copy_from_slice(&self.h);
+        v[8..].copy_from_slice(&BLAKE2B_IV);
+        v[12] ^= self.t as u64;
+        v[13] ^= (self.t >> 64) as u64;
+        if last {
+            v[14] = !v[14];
+        }
+
+        for s in BLAKE2B_SIGMA.iter() {
+            blake2b_g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+            blake2b_g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+            blake2b_g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+            blake2b_g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+            blake2b_g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+            blake2b_g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+            blake2b_g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+            blake2b_g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+
+        for idx in 0..8 {
+            self.h[idx] ^= v[idx] ^ v[idx + 8];
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.buflen == BLAKE2B_BLOCKBYTES {
+                self.t += BLAKE2B_BLOCKBYTES as u128;
+                self.compress(false);
+                self.buflen = 0;
+            }
+            let take = std::cmp::min(BLAKE2B_BLOCKBYTES - self.buflen, data.len());
+            self.buf[self.buflen..(self.buflen + take)].copy_from_slice(&data[..take]);
+            self.buflen += take;
+            data = &data[take..];
+        }
+    }
+
+    fn finalize(mut self, out: &mut [u8]) {
+        self.t += self.buflen as u128;
+        for b in self.buf[self.buflen..].iter_mut() {
+            *b = 0;
+        }
+        self.compress(true);
+
+        let mut bytes = [0u8; BLAKE2B_OUTBYTES];
+        for (idx, word) in self.h.iter().enumerate() {
+            bytes[(idx * 8)..(idx * 8 + 8)].copy_from_slice(&word.to_le_bytes());
+        }
+        out[..self.outlen].copy_from_slice(&bytes[..self.outlen]);
+        secure_wipe(&mut bytes);
+        secure_wipe(&mut self.buf);
+    }
+}
+
+#[inline(always)]
+fn blake2b_g(v: &mut [u64; 16], a: usize, b: usize, c: usize, d: usize, x: u64, y: u64) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// Variable-length hash function H' from the Argon2 specification.
+fn blake2b_long_impl(out: &mut [u8], input: &[u8]) {
+    let outlen_bytes = (out.len() as u32).to_le_bytes();
+
+    if out.len() <= BLAKE2B_OUTBYTES {
+        let mut state = Blake2b::new(out.len());
+        state.update(&outlen_bytes);
+        state.update(input);
+        state.finalize(out);
+        return;
+    }
+
+    let mut out_buffer = [0u8; BLAKE2B_OUTBYTES];
+    let mut state = Blake2b::new(BLAKE2B_OUTBYTES);
+    state.update(&outlen_bytes);
+    state.update(input);
+    state.finalize(&mut out_buffer);
+
+    out[..(BLAKE2B_OUTBYTES / 2)].copy_from_slice(&out_buffer[..(BLAKE2B_OUTBYTES / 2)]);
+    let mut offset = BLAKE2B_OUTBYTES / 2;
+    let mut to_produce = out.len() - BLAKE2B_OUTBYTES / 2;
+
+    while to_produce > BLAKE2B_OUTBYTES {
+        let in_buffer = out_buffer;
+        let mut state = Blake2b::new(BLAKE2B_OUTBYTES);
+        state.update(&in_buffer);
+        state.finalize(&mut out_buffer);
+        out[offset..(offset + BLAKE2B_OUTBYTES / 2)].copy_from_slice(&out_buffer[..(BLAKE2B_OUTBYTES / 2)]);
+        offset += BLAKE2B_OUTBYTES / 2;
+        to_produce -= BLAKE2B_OUTBYTES / 2;
+    }
+
+    let in_buffer = out_buffer;
+    let mut state = Blake2b::new(to_produce);
+    state.update(&in_buffer);
+    state.finalize(&mut out_buffer);
+    out[offset..(offset + to_produce)].copy_from_slice(&out_buffer[..to_produce]);
+    secure_wipe(&mut out_buffer);
+}
+
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+struct Block([u64; QWORDS_IN_BLOCK]);
+
+impl Block {
+    const ZERO: Block = Block([0; QWORDS_IN_BLOCK]);
+
+    fn xor_with(&mut self, other: &Block) {
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a ^= *b;
+        }
+    }
+
+    fn load(&mut self, bytes: &[u8]) {
+        for (idx, word) in self.0.iter_mut().enumerate() {
+            *word = load64(&bytes[(idx * 8)..]);
+        }
+    }
+
+    fn store(&self, bytes: &mut [u8]) {
+        for (idx, word) in self.0.iter().enumerate() {
+            bytes[(idx * 8)..(idx * 8 + 8)].copy_from_slice(&word.to_le_bytes());
+        }
+    }
+}
+
+#[inline(always)]
+fn f_bla_mka(x: u64, y: u64) -> u64 {
+    let m = 0xFFFFFFFFu64;
+    let xy = (x & m).wrapping_mul(y & m);
+    x.wrapping_add(y).wrapping_add(xy.wrapping_mul(2))
+}
+
+#[inline(always)]
+fn g(v: &mut [u64; QWORDS_IN_BLOCK], a: usize, b: usize, c: usize, d: usize) {
+    v[a] = f_bla_mka(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = f_bla_mka(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = f_bla_mka(v[a], v[b]);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = f_bla_mka(v[c], v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+#[inline(always)]
+fn blake2_round_nomsg(v: &mut [u64; QWORDS_IN_BLOCK], i: [usize; 16]) {
+    g(v, i[0], i[4], i[8], i[12]);
+    g(v, i[1], i[5], i[9], i[13]);
+    g(v, i[2], i[6], i[10], i[14]);
+    g(v, i[3], i[7], i[11], i[15]);
+    g(v, i[0], i[5], i[10], i[15]);
+    g(v, i[1], i[6], i[11], i[12]);
+    g(v, i[2], i[7], i[8], i[13]);
+    g(v, i[3], i[4], i[9], i[14]);
+}
+
+/// Computes `next = G(prev ^ reference)` (optionally XOR-ing the old contents of `next`).
+fn fill_block(prev: &Block, reference: &Block, next: &mut Block, with_xor: bool) {
+    let mut block_r = *reference;
+    block_r.xor_with(prev);
+    let mut block_tmp = block_r;
+    if with_xor {
+        block_tmp.xor_with(next);
+    }
+
+    let v = &mut block_r.0;
+    for i in 0..8 {
+        let b = 16 * i;
+        blake2_round_nomsg(v, [
+            b, b + 1, b + 2, b + 3, b + 4, b + 5, b + 6, b + 7,
+            b + 8, b + 9, b + 10, b + 11, b + 12, b + 13, b + 14, b + 15,
+        ]);
+    }
+    for i in 0..8 {
+        let b = 2 * i;
+        blake2_round_nomsg(v, [
+            b, b + 1, b + 16, b + 17, b + 32, b + 33, b + 48, b + 49,
+            b + 64, b + 65, b + 80, b + 81, b + 96, b + 97, b + 112, b + 113,
+        ]);
+    }
+
+    *next = block_tmp;
+    next.xor_with(&block_r);
+}
+
+struct Instance<'m> {
+    memory: &'m mut [Block],
+    version: u32,
+    passes: u32,
+    memory_blocks: u32,
+    segment_length: u32,
+    lane_length: u32,
+    lanes: u32,
+    type_: Argon2_type,
+}
+
+struct Position {
+    pass: u32,
+    lane: u32,
+    slice: u32,
+    index: u32,
+}
+
+fn next_addresses(address_block: &mut Block, input_block: &mut Block) {
+    input_block.0[6] = input_block.0[6].wrapping_add(1);
+    fill_block(&Block::ZERO, input_block, address_block, false);
+    let tmp = *address_block;
+    fill_block(&Block::ZERO, &tmp, address_block, false);
+}
+
+fn index_alpha(instance: &Instance, position: &Position, pseudo_rand: u32, same_lane: bool) -> u32 {
+    let reference_area_size: u32 = if position.pass == 0 {
+        if position.slice == 0 {
+            position.index - 1
+        } else if same_lane {
+            position.slice * instance.segment_length + position.index - 1
+        } else if position.index == 0 {
+            position.slice * instance.segment_length - 1
+        } else {
+            position.slice * instance.segment_length
+        }
+    } else if same_lane {
+        instance.lane_length - instance.segment_length + position.index - 1
+    } else if position.index == 0 {
+        instance.lane_length - instance.segment_length - 1
+    } else {
+        instance.lane_length - instance.segment_length
+    };
+
+    let mut relative_position = pseudo_rand as u64;
+    relative_position = (relative_position * relative_position) >> 32;
+    let relative_position = reference_area_size as u64 - 1
+        - ((reference_area_size as u64 * relative_position) >> 32);
+
+    let start_position: u64 = if position.pass != 0 {
+        if position.slice == SYNC_POINTS - 1 {
+            0
+        } else {
+            ((position.slice + 1) * instance.segment_length) as u64
+        }
+    } else {
+        0
+    };
+
+    ((start_position + relative_position) % instance.lane_length as u64) as u32
+}
+
+fn fill_segment(instance: &mut Instance, mut position: Position) {
+    let data_independent_addressing = instance.type_ == Argon2_type_Argon2_i
+        || (instance.type_ == Argon2_type_Argon2_id && position.pass == 0 && position.slice < SYNC_POINTS / 2);
+
+    let mut address_block = Block::ZERO;
+    let mut input_block = Block::ZERO;
+    if data_independent_addressing {
+        input_block.0[0] = position.pass as u64;
+        input_block.0[1] = position.lane as u64;
+        input_block.0[2] = position.slice as u64;
+        input_block.0[3] = instance.memory_blocks as u64;
+        input_block.0[4] = instance.passes as u64;
+        input_block.0[5] = instance.type_ as u64;
+    }
+
+    let mut starting_index = 0;
+    if position.pass == 0 && position.slice == 0 {
+        starting_index = 2;
+        if data_independent_addressing {
+            next_addresses(&mut address_block, &mut input_block);
+        }
+    }
+
+    let segment_offset = position.lane * instance.lane_length + position.slice * instance.segment_length;
+    for i in starting_index..instance.segment_length {
+        let curr_offset = segment_offset + i;
+        // The first block of a lane follows its last one.
+        let prev_offset = if position.slice == 0 && i == 0 {
+            curr_offset + instance.lane_length - 1
+        } else {
+            curr_offset - 1
+        };
+
+        let pseudo_rand: u64 = if data_independent_addressing {
+            if i % ADDRESSES_IN_BLOCK == 0 {
+                next_addresses(&mut address_block, &mut input_block);
+            }
+            address_block.0[(i % ADDRESSES_IN_BLOCK) as usize]
+        } else {
+            instance.memory[prev_offset as usize].0[0]
+        };
+
+        let mut ref_lane = ((pseudo_rand >> 32) % instance.lanes as u64) as u32;
+        if position.pass == 0 && position.slice == 0 {
+            ref_lane = position.lane;
+        }
+
+        position.index = i;
+        let ref_index = index_alpha(instance, &position, pseudo_rand as u32, ref_lane == position.lane);
+
+        let ref_block = instance.memory[(instance.lane_length * ref_lane + ref_index) as usize];
+        let prev_block = instance.memory[prev_offset as usize];
+        let with_xor = instance.version != Argon2_version_ARGON2_VERSION_10 as u32 && position.pass != 0;
+        fill_block(&prev_block, &ref_block, &mut instance.memory[curr_offset as usize], with_xor);
+    }
+}
+
+fn fill_memory_blocks(instance: &mut Instance) {
+    for pass in 0..instance.passes {
+        for slice in 0..SYNC_POINTS {
+            for lane in 0..instance.lanes {
+                fill_segment(instance, Position { pass, lane, slice, index: 0 });
+            }
+        }
+    }
+}
+
+unsafe fn initial_hash(context: &mut Argon2_Context, type_: Argon2_type) -> [u8; PREHASH_DIGEST_LENGTH] {
+    let mut state = Blake2b::new(PREHASH_DIGEST_LENGTH);
+    state.update(&context.lanes.to_le_bytes());
+    state.update(&context.outlen.to_le_bytes());
+    state.update(&context.m_cost.to_le_bytes());
+    state.update(&context.t_cost.to_le_bytes());
+    state.update(&context.version.to_le_bytes());
+    state.update(&(type_ as u32).to_le_bytes());
+
+    state.update(&context.pwdlen.to_le_bytes());
+    if !context.pwd.is_null() {
+        let pwd = std::slice::from_raw_parts_mut(context.pwd, context.pwdlen as usize);
+        state.update(pwd);
+        if context.flags & FLAG_CLEAR_PASSWORD != 0 {
+            secure_wipe(pwd);
+            context.pwdlen = 0;
+        }
+    }
+
+    state.update(&context.saltlen.to_le_bytes());
+    if !context.salt.is_null() {
+        state.update(std::slice::from_raw_parts(context.salt, context.saltlen as usize));
+    }
+
+    state.update(&context.secretlen.to_le_bytes());
+    if !context.secret.is_null() {
+        let secret = std::slice::from_raw_parts_mut(context.secret, context.secretlen as usize);
+        state.update(secret);
+        if context.flags & FLAG_CLEAR_SECRET != 0 {
+            secure_wipe(secret);
+            context.secretlen = 0;
+        }
+    }
+
+    state.update(&context.adlen.to_le_bytes());
+    if !context.ad.is_null() {
+        state.update(std::slice::from_raw_parts(context.ad, context.adlen as usize));
+    }
+
+    let mut out = [0u8; PREHASH_DIGEST_LENGTH];
+    state.finalize(&mut out);
+    out
+}
+
+fn fill_first_blocks(blockhash: &mut [u8; PREHASH_SEED_LENGTH], instance: &mut Instance) {
+    let mut blockhash_bytes = [0u8; BLOCK_SIZE];
+    for lane in 0..instance.lanes {
+        for idx in 0..2u32 {
+            blockhash[PREHASH_DIGEST_LENGTH..(PREHASH_DIGEST_LENGTH + 4)].copy_from_slice(&idx.to_le_bytes());
+            blockhash[(PREHASH_DIGEST_LENGTH + 4)..].copy_from_slice(&lane.to_le_bytes());
+            blake2b_long_impl(&mut blockhash_bytes, &blockhash[..]);
+            instance.memory[(lane * instance.lane_length + idx) as usize].load(&blockhash_bytes);
+        }
+    }
+    secure_wipe(&mut blockhash_bytes);
+}
+
+unsafe fn validate_inputs(context: &Argon2_Context) -> c_int {
+    if context.out.is_null() {
+        return Argon2_ErrorCodes_ARGON2_OUTPUT_PTR_NULL;
+    }
+    if context.outlen < MIN_OUTLEN {
+        return Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_SHORT;
+    }
+
+    if context.pwd.is_null() && context.pwdlen != 0 {
+        return Argon2_ErrorCodes_ARGON2_PWD_PTR_MISMATCH;
+    }
+
+    if context.salt.is_null() && context.saltlen != 0 {
+        return Argon2_ErrorCodes_ARGON2_SALT_PTR_MISMATCH;
+    }
+    if context.saltlen < MIN_SALT_LENGTH {
+        return Argon2_ErrorCodes_ARGON2_SALT_TOO_SHORT;
+    }
+
+    if context.secret.is_null() && context.secretlen != 0 {
+        return Argon2_ErrorCodes_ARGON2_SECRET_PTR_MISMATCH;
+    }
+
+    if context.ad.is_null() && context.adlen != 0 {
+        return Argon2_ErrorCodes_ARGON2_AD_PTR_MISMATCH;
+    }
+
+    if context.m_cost < MIN_MEMORY {
+        return Argon2_ErrorCodes_ARGON2_MEMORY_TOO_LITTLE;
+    }
+    if u64::from(context.m_cost) > MAX_MEMORY {
+        return Argon2_ErrorCodes_ARGON2_MEMORY_TOO_MUCH;
+    }
+    if (context.m_cost as u64) < 8 * context.lanes as u64 {
+        return Argon2_ErrorCodes_ARGON2_MEMORY_TOO_LITTLE;
+    }
+
+    if context.t_cost < MIN_TIME {
+        return Argon2_ErrorCodes_ARGON2_TIME_TOO_SMALL;
+    }
+
+    if context.lanes < MIN_LANES {
+        return Argon2_ErrorCodes_ARGON2_LANES_TOO_FEW;
+    }
+    if context.lanes > MAX_LANES {
+        return Argon2_ErrorCodes_ARGON2_LANES_TOO_MANY;
+    }
+
+    if context.threads < MIN_THREADS {
+        return Argon2_ErrorCodes_ARGON2_THREADS_TOO_FEW;
+    }
+    if context.threads > MAX_THREADS {
+        return Argon2_ErrorCodes_ARGON2_THREADS_TOO_MANY;
+    }
+
+    if context.allocate_cbk.is_some() && context.free_cbk.is_none() {
+        return Argon2_ErrorCodes_ARGON2_FREE_MEMORY_CBK_NULL;
+    }
+    if context.allocate_cbk.is_none() && context.free_cbk.is_some() {
+        return Argon2_ErrorCodes_ARGON2_ALLOCATE_MEMORY_CBK_NULL;
+    }
+
+    Argon2_ErrorCodes_ARGON2_OK
+}
+
+/// Working memory for a single hash, either owned or obtained from the context's callbacks.
+enum Memory {
+    Owned(Vec<Block>),
+    Callback { ptr: *mut u8, bytes: usize, free: deallocate_fptr },
+}
+
+impl Memory {
+    unsafe fn allocate(context: &Argon2_Context, blocks: usize) -> Option<Memory> {
+        let bytes = blocks.checked_mul(BLOCK_SIZE)?;
+        match context.allocate_cbk {
+            Some(allocate) => {
+                let mut ptr: *mut u8 = std::ptr::null_mut();
+                allocate(&mut ptr, bytes);
+                if ptr.is_null() {
+                    return None;
+                }
+                std::ptr::write_bytes(ptr, 0, bytes);
+                Some(Memory::Callback { ptr, bytes, free: context.free_cbk })
+            }
+            None => {
+                // Like `malloc` in the C library, report running out of memory instead of aborting.
+                let mut memory = Vec::new();
+                memory.try_reserve_exact(blocks).ok()?;
+                memory.resize(blocks, Block::ZERO);
+                Some(Memory::Owned(memory))
+            }
+        }
+    }
+
+    fn blocks(&mut self) -> &mut [Block] {
+        match self {
+            Memory::Owned(v) => &mut v[..],
+            Memory::Callback { ptr, bytes, .. } => unsafe {
+                std::slice::from_raw_parts_mut(*ptr as *mut Block, *bytes / BLOCK_SIZE)
+            },
+        }
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        for block in self.blocks().iter_mut() {
+            secure_wipe_words(&mut block.0);
+        }
+        if let Memory::Callback { ptr, bytes, free: Some(free) } = *self {
+            unsafe { free(ptr, bytes) };
+        }
+    }
+}
+
+pub unsafe fn argon2_ctx(context: *mut argon2_context, type_: argon2_type) -> c_int {
+    let context = match context.as_mut() {
+        Some(context) => context,
+        None => return Argon2_ErrorCodes_ARGON2_INCORRECT_PARAMETER,
+    };
+
+    let result = validate_inputs(context);
+    if result != Argon2_ErrorCodes_ARGON2_OK {
+        return result;
+    }
+
+    if type_ != Argon2_type_Argon2_d && type_ != Argon2_type_Argon2_i && type_ != Argon2_type_Argon2_id {
+        return Argon2_ErrorCodes_ARGON2_INCORRECT_TYPE;
+    }
+
+    let mut memory_blocks = context.m_cost;
+    if memory_blocks < 2 * SYNC_POINTS * context.lanes {
+        memory_blocks = 2 * SYNC_POINTS * context.lanes;
+    }
+    let segment_length = memory_blocks / (context.lanes * SYNC_POINTS);
+    let memory_blocks = segment_length * (context.lanes * SYNC_POINTS);
+
+    let mut memory = match Memory::allocate(context, memory_blocks as usize) {
+        Some(memory) => memory,
+        None => return Argon2_ErrorCodes_ARGON2_MEMORY_ALLOCATION_ERROR,
+    };
+
+    let mut instance = Instance {
+        memory: memory.blocks(),
+        version: context.version,
+        passes: context.t_cost,
+        memory_blocks,
+        segment_length,
+        lane_length: segment_length * SYNC_POINTS,
+        lanes: context.lanes,
+        type_,
+    };
+
+    let mut blockhash = [0u8; PREHASH_SEED_LENGTH];
+    blockhash[..PREHASH_DIGEST_LENGTH].copy_from_slice(&initial_hash(context, type_));
+    fill_first_blocks(&mut blockhash, &mut instance);
+    secure_wipe(&mut blockhash);
+
+    fill_memory_blocks(&mut instance);
+
+    let mut final_block = instance.memory[(instance.lane_length - 1) as usize];
+    for lane in 1..instance.lanes {
+        let last_block_in_lane = lane * instance.lane_length + (instance.lane_length - 1);
+        final_block.xor_with(&instance.memory[last_block_in_lane as usize]);
+    }
+    let mut final_bytes = [0u8; BLOCK_SIZE];
+    final_block.store(&mut final_bytes);
+    let out = std::slice::from_raw_parts_mut(context.out, context.outlen as usize);
+    blake2b_long_impl(out, &final_bytes);
+    secure_wipe(&mut final_bytes);
+    secure_wipe_words(&mut final_block.0);
+
+    Argon2_ErrorCodes_ARGON2_OK
+}
+
+fn b64_byte_to_char(x: u32) -> u8 {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    ALPHABET[(x & 0x3F) as usize]
+}
+
+fn b64_char_to_byte(c: u8) -> Option<u32> {
+    match c {
+        b'A'..=b'Z' => Some((c - b'A') as u32),
+        b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+        b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+fn b64len(len: u32) -> usize {
+    let mut olen = (len as usize / 3) << 2;
+    match len % 3 {
+        2 => olen += 3,
+        1 => olen += 2,
+        _ => {}
+    }
+    olen
+}
+
+fn numlen(mut num: u32) -> usize {
+    let mut len = 1;
+    while num >= 10 {
+        len += 1;
+        num /= 10;
+    }
+    len
+}
+
+fn to_base64(dst: &mut Vec<u8>, src: &[u8]) {
+    let mut acc: u32 = 0;
+    let mut acc_len = 0;
+    for b in src.iter() {
+        acc = (acc << 8) + *b as u32;
+        acc_len += 8;
+        while acc_len >= 6 {
+            acc_len -= 6;
+            dst.push(b64_byte_to_char(acc >> acc_len));
+        }
+    }
+    if acc_len > 0 {
+        dst.push(b64_byte_to_char(acc << (6 - acc_len)));
+    }
+}
+
+/// Decodes unpadded base64 from the front of `src`, returning the bytes and the remaining input.
+fn from_base64(src: &[u8], max_len: usize) -> Option<(Vec<u8>, &[u8])> {
+    let mut out = Vec::new();
+    let mut acc: u32 = 0;
+    let mut acc_len = 0;
+    let mut consumed = 0;
+    for c in src.iter() {
+        let d = match b64_char_to_byte(*c) {
+            Some(d) => d,
+            None => break,
+        };
+        consumed += 1;
+        acc = (acc << 6) + d;
+        acc_len += 6;
+        if acc_len >= 8 {
+            acc_len -= 8;
+            if out.len() >= max_len {
+                return None;
+            }
+            out.push((acc >> acc_len) as u8);
+        }
+    }
+    if acc_len > 4 || (acc & ((1u32 << acc_len) - 1)) != 0 {
+        return None;
+    }
+    Some((out, &src[consumed..]))
+}
+
+fn decode_decimal(src: &[u8]) -> Option<(u64, &[u8])> {
+    let mut acc: u64 = 0;
+    let mut len = 0;
+    for c in src.iter() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        acc = acc.checked_mul(10)?.checked_add((*c - b'0') as u64)?;
+        len += 1;
+    }
+    if len == 0 || (src[0] == b'0' && len != 1) {
+        return None;
+    }
+    Some((acc, &src[len..]))
+}
+
+fn type_name(type_: argon2_type) -> Option<&'static str> {
+    match type_ {
+        Argon2_type_Argon2_d => Some("argon2d"),
+        Argon2_type_Argon2_i => Some("argon2i"),
+        Argon2_type_Argon2_id => Some("argon2id"),
+        _ => None,
+    }
+}
+
+struct Decoded {
+    version: u32,
+    m_cost: u32,
+    t_cost: u32,
+    lanes: u32,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+fn decode_string(encoded: &[u8], type_: argon2_type) -> Result<Decoded, c_int> {
+    const FAIL: c_int = Argon2_ErrorCodes_ARGON2_DECODING_FAIL;
+
+    fn cc<'a>(s: &'a [u8], prefix: &str) -> Result<&'a [u8], c_int> {
+        if s.starts_with(prefix.as_bytes()) {
+            Ok(&s[prefix.len()..])
+        } else {
+            Err(FAIL)
+        }
+    }
+
+    fn decimal_u32(s: &[u8]) -> Result<(u32, &[u8]), c_int> {
+        match decode_decimal(s) {
+            Some((v, rest)) if v <= u32::MAX as u64 => Ok((v as u32, rest)),
+            _ => Err(FAIL),
+        }
+    }
+
+    let max_field_len = encoded.len();
+    let type_string = type_name(type_).ok_or(Argon2_ErrorCodes_ARGON2_INCORRECT_TYPE)?;
+
+    let s = cc(encoded, "$")?;
+    let mut s = cc(s, type_string)?;
+
+    let mut version = Argon2_version_ARGON2_VERSION_10 as u32;
+    if s.starts_with(b"$v=") {
+        let (v, rest) = decimal_u32(&s[3..])?;
+        version = v;
+        s = rest;
+    }
+
+    let s = cc(s, "$m=")?;
+    let (m_cost, s) = decimal_u32(s)?;
+    let s = cc(s, ",t=")?;
+    let (t_cost, s) = decimal_u32(s)?;
+    let s = cc(s, ",p=")?;
+    let (lanes, s) = decimal_u32(s)?;
+
+    let s = cc(s, "$")?;
+    let (salt, s) = from_base64(s, max_field_len).ok_or(FAIL)?;
+    let s = cc(s, "$")?;
+    let (hash, s) = from_base64(s, max_field_len).ok_or(FAIL)?;
+
+    let decoded = Decoded { version, m_cost, t_cost, lanes, salt, hash };
+
+    let mut dummy = 0u8;
+    let check = Argon2_Context {
+        out: &mut dummy,
+        outlen: decoded.hash.len() as u32,
+        pwd: std::ptr::null_mut(),
+        pwdlen: 0,
+        salt: &mut dummy,
+        saltlen: decoded.salt.len() as u32,
+        secret: std::ptr::null_mut(),
+        secretlen: 0,
+        ad: std::ptr::null_mut(),
+        adlen: 0,
+        t_cost,
+        m_cost,
+        lanes,
+        threads: lanes,
+        version,
+        allocate_cbk: None,
+        free_cbk: None,
+        flags: 0,
+    };
+    let validation = unsafe { validate_inputs(&check) };
+    if validation != Argon2_ErrorCodes_ARGON2_OK {
+        return Err(validation);
+    }
+
+    if s.is_empty() {
+        Ok(decoded)
+    } else {
+        Err(FAIL)
+    }
+}
+
+fn encode_string(dst: &mut [u8], context: &Argon2_Context, type_: argon2_type, hash: &[u8], salt: &[u8]) -> c_int {
+    let type_string = match type_name(type_) {
+        Some(s) => s,
+        None => return Argon2_ErrorCodes_ARGON2_ENCODING_FAIL,
+    };
+
+    let mut encoded = Vec::with_capacity(dst.len());
+    encoded.extend_from_slice(b"$");
+    encoded.extend_from_slice(type_string.as_bytes());
+    encoded.extend_from_slice(format!("$v={}$m={},t={},p={}$", context.version, context.m_cost, context.t_cost, context.lanes).as_bytes());
+    to_base64(&mut encoded, salt);
+    encoded.push(b'$');
+    to_base64(&mut encoded, hash);
+
+    if encoded.len() >= dst.len() {
+        secure_wipe(&mut encoded);
+        return Argon2_ErrorCodes_ARGON2_ENCODING_FAIL;
+    }
+    dst[..encoded.len()].copy_from_slice(&encoded);
+    dst[encoded.len()] = 0;
+    secure_wipe(&mut encoded);
+    Argon2_ErrorCodes_ARGON2_OK
+}
+
+unsafe fn slice_or_empty<'a>(ptr: *const c_void, len: usize) -> &'a [u8] {
+    if ptr.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(ptr as *const u8, len)
+    }
+}
+
+pub unsafe fn argon2_hash(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hash: *mut c_void, hashlen: usize, encoded: *mut c_char, encodedlen: usize, type_: argon2_type, version: u32) -> c_int {
+    if pwdlen > u32::MAX as usize {
+        return Argon2_ErrorCodes_ARGON2_PWD_TOO_LONG;
+    }
+    if saltlen > u32::MAX as usize {
+        return Argon2_ErrorCodes_ARGON2_SALT_TOO_LONG;
+    }
+    if hashlen > u32::MAX as usize {
+        return Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_LONG;
+    }
+    if hashlen < MIN_OUTLEN as usize {
+        return Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_SHORT;
+    }
+
+    let mut out = vec![0u8; hashlen];
+    let mut context = Argon2_Context {
+        out: out.as_mut_ptr(),
+        outlen: hashlen as u32,
+        pwd: pwd as *mut u8,
+        pwdlen: pwdlen as u32,
+        salt: salt as *mut u8,
+        saltlen: saltlen as u32,
+        secret: std::ptr::null_mut(),
+        secretlen: 0,
+        ad: std::ptr::null_mut(),
+        adlen: 0,
+        t_cost,
+        m_cost,
+        lanes: parallelism,
+        threads: parallelism,
+        version,
+        allocate_cbk: None,
+        free_cbk: None,
+        flags: 0,
+    };
+
+    let result = argon2_ctx(&mut context, type_);
+    if result != Argon2_ErrorCodes_ARGON2_OK {
+        secure_wipe(&mut out);
+        return result;
+    }
+
+    if !hash.is_null() {
+        std::ptr::copy_nonoverlapping(out.as_ptr(), hash as *mut u8, hashlen);
+    }
+
+    if !encoded.is_null() && encodedlen != 0 {
+        let dst = std::slice::from_raw_parts_mut(encoded as *mut u8, encodedlen);
+        let salt = slice_or_empty(salt, saltlen);
+        if encode_string(dst, &context, type_, &out, salt) != Argon2_ErrorCodes_ARGON2_OK {
+            secure_wipe(&mut out);
+            secure_wipe(dst);
+            return Argon2_ErrorCodes_ARGON2_ENCODING_FAIL;
+        }
+    }
+
+    secure_wipe(&mut out);
+    Argon2_ErrorCodes_ARGON2_OK
+}
+
+pub unsafe fn argon2i_hash_encoded(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hashlen: usize, encoded: *mut c_char, encodedlen: usize) -> c_int {
+    argon2_hash(t_cost, m_cost, parallelism, pwd, pwdlen, salt, saltlen, std::ptr::null_mut(), hashlen, encoded, encodedlen, Argon2_type_Argon2_i, Argon2_version_ARGON2_VERSION_NUMBER as u32)
+}
+
+pub unsafe fn argon2i_hash_raw(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hash: *mut c_void, hashlen: usize) -> c_int {
+    argon2_hash(t_cost, m_cost, parallelism, pwd, pwdlen, salt, saltlen, hash, hashlen, std::ptr::null_mut(), 0, Argon2_type_Argon2_i, Argon2_version_ARGON2_VERSION_NUMBER as u32)
+}
+
+pub unsafe fn argon2d_hash_encoded(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hashlen: usize, encoded: *mut c_char, encodedlen: usize) -> c_int {
+    argon2_hash(t_cost, m_cost, parallelism, pwd, pwdlen, salt, saltlen, std::ptr::null_mut(), hashlen, encoded, encodedlen, Argon2_type_Argon2_d, Argon2_version_ARGON2_VERSION_NUMBER as u32)
+}
+
+pub unsafe fn argon2d_hash_raw(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hash: *mut c_void, hashlen: usize) -> c_int {
+    argon2_hash(t_cost, m_cost, parallelism, pwd, pwdlen, salt, saltlen, hash, hashlen, std::ptr::null_mut(), 0, Argon2_type_Argon2_d, Argon2_version_ARGON2_VERSION_NUMBER as u32)
+}
+
+pub unsafe fn argon2id_hash_encoded(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hashlen: usize, encoded: *mut c_char, encodedlen: usize) -> c_int {
+    argon2_hash(t_cost, m_cost, parallelism, pwd, pwdlen, salt, saltlen, std::ptr::null_mut(), hashlen, encoded, encodedlen, Argon2_type_Argon2_id, Argon2_version_ARGON2_VERSION_NUMBER as u32)
+}
+
+pub unsafe fn argon2id_hash_raw(t_cost: u32, m_cost: u32, parallelism: u32, pwd: *const c_void, pwdlen: usize, salt: *const c_void, saltlen: usize, hash: *mut c_void, hashlen: usize) -> c_int {
+    argon2_hash(t_cost, m_cost, parallelism, pwd, pwdlen, salt, saltlen, hash, hashlen, std::ptr::null_mut(), 0, Argon2_type_Argon2_id, Argon2_version_ARGON2_VERSION_NUMBER as u32)
+}
+
+pub unsafe fn argon2_verify(encoded: *const c_char, pwd: *const c_void, pwdlen: usize, type_: argon2_type) -> c_int {
+    if pwdlen > u32::MAX as usize {
+        return Argon2_ErrorCodes_ARGON2_PWD_TOO_LONG;
+    }
+    if encoded.is_null() {
+        return Argon2_ErrorCodes_ARGON2_DECODING_FAIL;
+    }
+    let encoded = std::ffi::CStr::from_ptr(encoded).to_bytes();
+    if encoded.len() > u32::MAX as usize {
+        return Argon2_ErrorCodes_ARGON2_DECODING_FAIL;
+    }
+
+    let mut decoded = match decode_string(encoded, type_) {
+        Ok(decoded) => decoded,
+        Err(code) => return code,
+    };
+
+    let mut out = vec![0u8; decoded.hash.len()];
+    let mut context = Argon2_Context {
+        out: out.as_mut_ptr(),
+        outlen: out.len() as u32,
+        pwd: pwd as *mut u8,
+        pwdlen: pwdlen as u32,
+        salt: decoded.salt.as_mut_ptr(),
+        saltlen: decoded.salt.len() as u32,
+        secret: std::ptr::null_mut(),
+        secretlen: 0,
+        ad: std::ptr::null_mut(),
+        adlen: 0,
+        t_cost: decoded.t_cost,
+        m_cost: decoded.m_cost,
+        lanes: decoded.lanes,
+        threads: decoded.lanes,
+        version: decoded.version,
+        allocate_cbk: None,
+        free_cbk: None,
+        flags: 0,
+    };
+
+    let result = argon2_verify_ctx(&mut context, decoded.hash.as_ptr() as *const c_char, type_);
+    secure_wipe(&mut out);
+    secure_wipe(&mut decoded.hash);
+    result
+}
+
+pub unsafe fn argon2i_verify(encoded: *const c_char, pwd: *const c_void, pwdlen: usize) -> c_int {
+    argon2_verify(encoded, pwd, pwdlen, Argon2_type_Argon2_i)
+}
+
+pub unsafe fn argon2d_verify(encoded: *const c_char, pwd: *const c_void, pwdlen: usize) -> c_int {
+    argon2_verify(encoded, pwd, pwdlen, Argon2_type_Argon2_d)
+}
+
+pub unsafe fn argon2id_verify(encoded: *const c_char, pwd: *const c_void, pwdlen: usize) -> c_int {
+    argon2_verify(encoded, pwd, pwdlen, Argon2_type_Argon2_id)
+}
+
+pub unsafe fn argon2d_ctx(context: *mut argon2_context) -> c_int {
+    argon2_ctx(context, Argon2_type_Argon2_d)
+}
+
+pub unsafe fn argon2i_ctx(context: *mut argon2_context) -> c_int {
+    argon2_ctx(context, Argon2_type_Argon2_i)
+}
+
+pub unsafe fn argon2id_ctx(context: *mut argon2_context) -> c_int {
+    argon2_ctx(context, Argon2_type_Argon2_id)
+}
+
+pub unsafe fn argon2_verify_ctx(context: *mut argon2_context, hash: *const c_char, type_: argon2_type) -> c_int {
+    let result = argon2_ctx(context, type_);
+    if result != Argon2_ErrorCodes_ARGON2_OK {
+        return result;
+    }
+
+    let context = &*context;
+    let expected = std::slice::from_raw_parts(hash as *const u8, context.outlen as usize);
+    let actual = std::slice::from_raw_parts(context.out, context.outlen as usize);
+    let mut diff = 0u8;
+    for (a, b) in expected.iter().zip(actual.iter()) {
+        diff |= a ^ b;
+    }
+    if diff != 0 {
+        Argon2_ErrorCodes_ARGON2_VERIFY_MISMATCH
+    } else {
+        Argon2_ErrorCodes_ARGON2_OK
+    }
+}
+
+pub unsafe fn argon2d_verify_ctx(context: *mut argon2_context, hash: *const c_char) -> c_int {
+    argon2_verify_ctx(context, hash, Argon2_type_Argon2_d)
+}
+
+pub unsafe fn argon2i_verify_ctx(context: *mut argon2_context, hash: *const c_char) -> c_int {
+    argon2_verify_ctx(context, hash, Argon2_type_Argon2_i)
+}
+
+pub unsafe fn argon2id_verify_ctx(context: *mut argon2_context, hash: *const c_char) -> c_int {
+    argon2_verify_ctx(context, hash, Argon2_type_Argon2_id)
+}
+
+pub unsafe fn argon2_error_message(error_code: c_int) -> *const c_char {
+    let message: &'static [u8] = match error_code {
+        Argon2_ErrorCodes_ARGON2_OK => b"OK\0",
+        Argon2_ErrorCodes_ARGON2_OUTPUT_PTR_NULL => b"Output pointer is NULL\0",
+        Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_SHORT => b"Output is too short\0",
+        Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_LONG => b"Output is too long\0",
+        Argon2_ErrorCodes_ARGON2_PWD_TOO_SHORT => b"Password is too short\0",
+        Argon2_ErrorCodes_ARGON2_PWD_TOO_LONG => b"Password is too long\0",
+        Argon2_ErrorCodes_ARGON2_SALT_TOO_SHORT => b"Salt is too short\0",
+        Argon2_ErrorCodes_ARGON2_SALT_TOO_LONG => b"Salt is too long\0",
+        Argon2_ErrorCodes_ARGON2_AD_TOO_SHORT => b"Associated data is too short\0",
+        Argon2_ErrorCodes_ARGON2_AD_TOO_LONG => b"Associated data is too long\0",
+        Argon2_ErrorCodes_ARGON2_SECRET_TOO_SHORT => b"Secret is too short\0",
+        Argon2_ErrorCodes_ARGON2_SECRET_TOO_LONG => b"Secret is too long\0",
+        Argon2_ErrorCodes_ARGON2_TIME_TOO_SMALL => b"Time cost is too small\0",
+        Argon2_ErrorCodes_ARGON2_TIME_TOO_LARGE => b"Time cost is too large\0",
+        Argon2_ErrorCodes_ARGON2_MEMORY_TOO_LITTLE => b"Memory cost is too small\0",
+        Argon2_ErrorCodes_ARGON2_MEMORY_TOO_MUCH => b"Memory cost is too large\0",
+        Argon2_ErrorCodes_ARGON2_LANES_TOO_FEW => b"Too few lanes\0",
+        Argon2_ErrorCodes_ARGON2_LANES_TOO_MANY => b"Too many lanes\0",
+        Argon2_ErrorCodes_ARGON2_PWD_PTR_MISMATCH => b"Password pointer is NULL, but password length is not 0\0",
+        Argon2_ErrorCodes_ARGON2_SALT_PTR_MISMATCH => b"Salt pointer is NULL, but salt length is not 0\0",
+        Argon2_ErrorCodes_ARGON2_SECRET_PTR_MISMATCH => b"Secret pointer is NULL, but secret length is not 0\0",
+        Argon2_ErrorCodes_ARGON2_AD_PTR_MISMATCH => b"Associated data pointer is NULL, but ad length is not 0\0",
+        Argon2_ErrorCodes_ARGON2_MEMORY_ALLOCATION_ERROR => b"Memory allocation error\0",
+        Argon2_ErrorCodes_ARGON2_FREE_MEMORY_CBK_NULL => b"The free memory callback is NULL\0",
+        Argon2_ErrorCodes_ARGON2_ALLOCATE_MEMORY_CBK_NULL => b"The allocate memory callback is NULL\0",
+        Argon2_ErrorCodes_ARGON2_INCORRECT_PARAMETER => b"Argon2_Context context is NULL\0",
+        Argon2_ErrorCodes_ARGON2_INCORRECT_TYPE => b"There is no such version of Argon2\0",
+        Argon2_ErrorCodes_ARGON2_OUT_PTR_MISMATCH => b"Output pointer mismatch\0",
+        Argon2_ErrorCodes_ARGON2_THREADS_TOO_FEW => b"Not enough threads\0",
+        Argon2_ErrorCodes_ARGON2_THREADS_TOO_MANY => b"Too many threads\0",
+        Argon2_ErrorCodes_ARGON2_MISSING_ARGS => b"Missing arguments\0",
+        Argon2_ErrorCodes_ARGON2_ENCODING_FAIL => b"Encoding failed\0",
+        Argon2_ErrorCodes_ARGON2_DECODING_FAIL => b"Decoding failed\0",
+        Argon2_ErrorCodes_ARGON2_THREAD_FAIL => b"Threading failure\0",
+        Argon2_ErrorCodes_ARGON2_DECODING_LENGTH_FAIL => b"Some of encoded parameters are too long or too short\0",
+        Argon2_ErrorCodes_ARGON2_VERIFY_MISMATCH => b"The password does not match the supplied hash\0",
+        _ => b"Unknown error code\0",
+    };
+    message.as_ptr() as *const c_char
+}
+
+pub unsafe fn argon2_encodedlen(t_cost: u32, m_cost: u32, parallelism: u32, saltlen: u32, hashlen: u32, type_: argon2_type) -> usize {
+    let type_len = type_name(type_).map(|s| s.len()).unwrap_or(0);
+    "$$v=$m=,t=,p=$$".len() + type_len
+        + numlen(t_cost) + numlen(m_cost) + numlen(parallelism)
+        + b64len(saltlen) + b64len(hashlen)
+        + numlen(Argon2_version_ARGON2_VERSION_NUMBER as u32) + 1
+}
+
+pub unsafe fn argon2_type2string(type_: argon2_type, uppercase: c_int) -> *const c_char {
+    let name: &'static [u8] = match (type_, uppercase != 0) {
+        (Argon2_type_Argon2_d, false) => b"argon2d\0",
+        (Argon2_type_Argon2_d, true) => b"Argon2d\0",
+        (Argon2_type_Argon2_i, false) => b"argon2i\0",
+        (Argon2_type_Argon2_i, true) => b"Argon2i\0",
+        (Argon2_type_Argon2_id, false) => b"argon2id\0",
+        (Argon2_type_Argon2_id, true) => b"Argon2id\0",
+        _ => return std::ptr::null(),
+    };
+    name.as_ptr() as *const c_char
+}
+
+pub unsafe fn blake2b(out: *mut c_void, outlen: usize, input: *const c_void, inlen: usize, key: *const c_void, keylen: usize) -> c_int {
+    if out.is_null() || outlen == 0 || outlen > BLAKE2B_OUTBYTES {
+        return -1;
+    }
+    if (input.is_null() && inlen > 0) || (key.is_null() && keylen > 0) || keylen > BLAKE2B_OUTBYTES {
+        return -1;
+    }
+    let key = slice_or_empty(key, keylen);
+    let mut state = Blake2b::with_key(outlen, key);
+    state.update(slice_or_empty(input, inlen));
+    state.finalize(std::slice::from_raw_parts_mut(out as *mut u8, outlen));
+    0
+}
+
+pub unsafe fn blake2b_long(out: *mut c_void, outlen: usize, input: *const c_void, inlen: usize) -> c_int {
+    if out.is_null() || outlen == 0 || outlen > u32::MAX as usize || (input.is_null() && inlen > 0) {
+        return -1;
+    }
+    blake2b_long_impl(std::slice::from_raw_parts_mut(out as *mut u8, outlen), slice_or_empty(input, inlen));
+    0
+}
+
+pub unsafe fn blake2b_init(S: *mut blake2b_state, outlen: usize) -> c_int {
+    if S.is_null() {
+        return -1;
+    }
+    if outlen == 0 || outlen > BLAKE2B_OUTBYTES {
+        invalidate_blake2b_state(&mut *S);
+        return -1;
+    }
+    Blake2b::new(outlen).store(&mut *S);
+    0
+}
+
+pub unsafe fn blake2b_update(S: *mut blake2b_state, in_: *const c_void, inlen: usize) -> c_int {
+    if inlen == 0 {
+        return 0;
+    }
+    if S.is_null() || in_.is_null() || (*S).f[0] != 0 {
+        return -1;
+    }
+    let mut state = Blake2b::load(&*S);
+    state.update(std::slice::from_raw_parts(in_ as *const u8, inlen));
+    state.store(&mut *S);
+    secure_wipe(&mut state.buf);
+    0
+}
+
+pub unsafe fn blake2b_final(S: *mut blake2b_state, out: *mut c_void, outlen: usize) -> c_int {
+    if S.is_null() || out.is_null() || outlen < (*S).outlen as usize || (*S).f[0] != 0 {
+        return -1;
+    }
+    let state = Blake2b::load(&*S);
+    let len = state.outlen;
+    state.finalize(std::slice::from_raw_parts_mut(out as *mut u8, len));
+    invalidate_blake2b_state(&mut *S);
+    0
+}
+
+/// Clears the state and marks it as finalized, so that `blake2b_update` and `blake2b_final`
+/// reject it like the C library does.
+fn invalidate_blake2b_state(state: &mut blake2b_state) {
+    secure_wipe_words(&mut state.h);
+    secure_wipe(&mut state.buf);
+    state.f[0] = u64::MAX;
+}
+
+#[inline(always)]
+fn load64(bytes: &[u8]) -> u64 {
+    let mut word = [0u8; 8];
+    word.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(word)
+}
+
+fn secure_wipe(buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+        unsafe { std::ptr::write_volatile(b, 0) };
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+fn secure_wipe_words(buf: &mut [u64]) {
+    for w in buf.iter_mut() {
+        unsafe { std::ptr::write_volatile(w, 0) };
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Stand-in for c/dispatch.c. There is only the one implementation of `fill_segment` above, which
+/// the `argon2` crate selects as the reference one (0).
+pub unsafe fn argon2_select_fill_segment(core: c_int) -> c_int {
+    (core == 0) as c_int
+}
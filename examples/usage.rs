@@ -33,7 +33,7 @@ fn main() {
         m_cost:     m_cost,
         lanes:      parallelism,
         threads:    parallelism,
-        version:    argon2::Version::Version13,
+        version:    argon2::Version::DEFAULT,
         flags:      argon2::Flags::DEFAULT,
     };
     argon2::i_ctx(&mut context).expect("Error hashing using low-level API.");
@@ -0,0 +1,59 @@
+//! `EncodedHash::parse` on arbitrary strings. Accepted hashes have to write back an encoding that
+//! parses into the same fields and is written back unchanged.
+
+#![no_main]
+
+use argon2::{EncodedHash, ParseOptions};
+use libfuzzer_sys::fuzz_target;
+
+fn canonical(hash: &EncodedHash) -> EncodedHash {
+    EncodedHash::new(
+        hash.variant(),
+        hash.version(),
+        hash.m_cost(),
+        hash.t_cost(),
+        hash.parallelism(),
+        hash.salt().to_vec(),
+        hash.hash().to_vec())
+}
+
+fuzz_target!(|data: &[u8]| {
+    let encoded = match std::str::from_utf8(data) {
+        Ok(encoded) => encoded,
+        Err(_) => return,
+    };
+    // Everything that parses strictly parses the same with padding allowed; padded strings are
+    // never canonical.
+    let padded = match EncodedHash::parse_with(encoded, ParseOptions { allow_padding: true }) {
+        Ok(padded) => padded,
+        Err(_) => return,
+    };
+    assert_eq!(encoded, padded.to_string());
+    let hash = match EncodedHash::parse(encoded) {
+        Ok(hash) => hash,
+        Err(_) => {
+            assert!(!padded.is_canonical());
+            let canonical = EncodedHash::parse(padded.canonicalize().as_str()).expect("canonical encoding doesn't parse");
+            assert_eq!((padded.salt(), padded.hash()), (canonical.salt(), canonical.hash()));
+            return;
+        }
+    };
+    assert_eq!((hash.salt(), hash.hash()), (padded.salt(), padded.hash()));
+    assert_eq!(encoded, hash.to_string());
+
+    let written = canonical(&hash);
+    let parsed = EncodedHash::parse(written.as_str()).expect("canonical encoding doesn't parse");
+    assert_eq!(hash.version().to_int(), parsed.version().to_int());
+    assert!(parsed.has_version());
+    assert_eq!(hash.variant().to_string(), parsed.variant().to_string());
+    assert_eq!(
+        (hash.m_cost(), hash.t_cost(), hash.parallelism(), hash.salt(), hash.hash()),
+        (parsed.m_cost(), parsed.t_cost(), parsed.parallelism(), parsed.salt(), parsed.hash()));
+    assert_eq!(written.as_str(), canonical(&parsed).as_str());
+    assert_eq!(written.as_str(), hash.canonicalize().as_str());
+    assert_eq!(encoded == written.as_str(), hash.is_canonical());
+    // Leading zeros are the only other spelling of a field.
+    if hash.has_version() && !encoded.contains("=0") {
+        assert_eq!(encoded, written.as_str());
+    }
+});
@@ -0,0 +1,38 @@
+//! `verify_bytes` on arbitrary bytes, with and without a terminating NUL, for every variant.
+//!
+//! The memory cost is capped at 64 KiB and hashes with more than 4 passes are skipped, so that
+//! inputs the C library accepts are still verified quickly.
+
+#![no_main]
+
+use argon2::Variant;
+use libfuzzer_sys::fuzz_target;
+use std::sync::Once;
+
+const MAX_M_COST: u32 = 64;
+const MAX_T_COST: u64 = 4;
+
+static CAP: Once = Once::new();
+
+/// Returns the `t=` field, which the C library only accepts after `,` and before `,p=`.
+fn t_cost(data: &[u8]) -> Option<u64> {
+    let start = data.windows(3).position(|w| w == b",t=")? + 3;
+    let digits = data[start..].iter().take_while(|b| b.is_ascii_digit()).take(20);
+    Some(digits.fold(0u64, |n, &b| n.saturating_mul(10).saturating_add(u64::from(b - b'0'))))
+}
+
+fuzz_target!(|data: &[u8]| {
+    CAP.call_once(|| {
+        argon2::set_max_m_cost(MAX_M_COST).expect("m_cost cap already set");
+    });
+    if t_cost(data).map_or(false, |t| t > MAX_T_COST) {
+        return;
+    }
+
+    let mut terminated = data.to_vec();
+    terminated.push(0);
+    for variant in Variant::iter() {
+        let _ = argon2::verify_bytes(data, Some(b"password"), variant);
+        let _ = argon2::verify_bytes(&terminated, Some(b"password"), variant);
+    }
+});
@@ -0,0 +1 @@
+//! Tests of the `sqlx` and `diesel` support of `EncodedHash` against SQLite, in `tests/sql.rs`.
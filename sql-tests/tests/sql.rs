@@ -0,0 +1,105 @@
+//! `EncodedHash` stored in SQLite through `sqlx` and `diesel`.
+
+use argon2::{c_str_cow, verify, EncodedHash, Error, ErrorCode, Variant};
+
+const ENCODED_VECTORS: [&str; 6] = [
+    "$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ",
+    "$argon2i$m=256,t=2,p=2$c29tZXNhbHQ$tsEVYKap1h6scGt5ovl9aLRGOqOth+AMB+KwHpDFZPs",
+    "$argon2i$m=65536,t=2,p=1$ZGlmZnNhbHQ$eaEDuQ/orvhXDLMfyLIiWXeJFvgza3vaw4kladTxxJc",
+    "$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweHKfbftvj+qf+YFY4NeBbtA",
+    "$argon2i$v=19$m=1048576,t=2,p=1$c29tZXNhbHQ$0Vh6ygkiw7XWqD7asxvuPE667zQu1hJ6VdGbI1GtH0E",
+    "$argon2id$v=19$m=512,t=2,p=4$c29tZXNhbHRzb21lc2FsdA$mskTs1Hwo3MDziPqDyxw3REpP3VytJOsFPrikRqe1MVYU8PGsxKyIJL6JnjpPLl8oOyO0eoeX6zjCtOokVJlUg",
+];
+
+/// Hashes with Argon2id, `m=256,t=2,p=1` and a 32 byte hash.
+fn hash_encoded(pwd: &[u8], salt: &[u8]) -> String {
+    let mut encoded = [0u8; 128];
+    argon2::id_hash_encoded(2, 256, 1, Some(pwd), Some(salt), 32, &mut encoded).unwrap();
+    argon2::c_str(&encoded).unwrap().to_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn test_sqlx() {
+    use sqlx::{Connection, SqliteConnection};
+
+    let mut conn = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+    sqlx::query("CREATE TABLE users (name TEXT NOT NULL, hash TEXT NOT NULL)").execute(&mut conn).await.unwrap();
+    for (i, vector) in ENCODED_VECTORS.iter().enumerate() {
+        sqlx::query("INSERT INTO users VALUES (?, ?)")
+            .bind(i.to_string())
+            .bind(EncodedHash::parse(vector).unwrap())
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+
+    let rows: Vec<(String, EncodedHash)> = sqlx::query_as("SELECT name, hash FROM users ORDER BY name")
+        .fetch_all(&mut conn)
+        .await
+        .unwrap();
+    assert_eq!(ENCODED_VECTORS.len(), rows.len());
+    for (i, (name, hash)) in rows.iter().enumerate() {
+        assert_eq!(i.to_string(), *name);
+        assert_eq!(ENCODED_VECTORS[i], hash.to_string());
+    }
+    let (stored,): (String,) = sqlx::query_as("SELECT hash FROM users WHERE name = '0'").fetch_one(&mut conn).await.unwrap();
+    assert_eq!(ENCODED_VECTORS[0], stored);
+
+    sqlx::query("INSERT INTO users VALUES ('invalid', 'not a hash')").execute(&mut conn).await.unwrap();
+    let err = sqlx::query_as::<_, (EncodedHash,)>("SELECT hash FROM users WHERE name = 'invalid'")
+        .fetch_one(&mut conn)
+        .await
+        .unwrap_err();
+    match err {
+        sqlx::Error::ColumnDecode { source, .. } => {
+            assert_eq!(Some(&Error::Code(ErrorCode::DecodingFail)), source.downcast_ref::<Error>());
+        }
+        err => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn test_diesel() {
+    use diesel::prelude::*;
+
+    diesel::table! {
+        users (name) {
+            name -> Text,
+            hash -> Text,
+        }
+    }
+
+    #[derive(Queryable, Insertable)]
+    #[diesel(table_name = users)]
+    struct User {
+        name: String,
+        hash: EncodedHash,
+    }
+
+    let mut conn = SqliteConnection::establish(":memory:").unwrap();
+    diesel::sql_query("CREATE TABLE users (name TEXT PRIMARY KEY NOT NULL, hash TEXT NOT NULL)")
+        .execute(&mut conn)
+        .unwrap();
+
+    let encoded = hash_encoded(b"password", b"somesalt");
+    let user = User { name: "alice".to_owned(), hash: EncodedHash::parse(&encoded).unwrap() };
+    diesel::insert_into(users::table).values(&user).execute(&mut conn).unwrap();
+    let stored: String = users::table.find("alice").select(users::hash).first(&mut conn).unwrap();
+    assert_eq!(encoded, stored);
+
+    let user: User = users::table.find("alice").first(&mut conn).unwrap();
+    assert_eq!("alice", user.name);
+    assert_eq!(encoded, user.hash.as_str());
+    verify(&c_str_cow(user.hash.as_str().as_bytes()), Some(b"password"), Variant::ID).unwrap();
+    assert_eq!(Err(Error::Code(ErrorCode::VerifyMismatch)),
+               verify(&c_str_cow(user.hash.as_str().as_bytes()), Some(b"passwore"), Variant::ID));
+
+    diesel::sql_query("INSERT INTO users VALUES ('mallory', 'not a hash')").execute(&mut conn).unwrap();
+    let err = users::table.find("mallory").first::<User>(&mut conn).map(|_| ()).unwrap_err();
+    match err {
+        diesel::result::Error::DeserializationError(source) => {
+            assert_eq!(Some(&Error::Code(ErrorCode::DecodingFail)), source.downcast_ref::<Error>());
+        }
+        err => panic!("unexpected error: {}", err),
+    }
+}
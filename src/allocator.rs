@@ -0,0 +1,757 @@
+use super::sys;
+use super::types::{Error, ErrorCode};
+use std::alloc::{self, Layout};
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::fmt;
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Allocator for the memory blocks used while hashing.
+///
+/// Pass an implementation to `ctx_with_allocator` or `verify_ctx_with_allocator` to have the C
+/// library allocate its working memory through it. The C library allocates the whole matrix with a
+/// single call before hashing and frees it with a single call afterwards, both on the calling
+/// thread. The C library wipes the memory before it is handed to `deallocate`.
+///
+/// # Safety
+///
+/// `allocate` must return a pointer to `bytes` writable bytes that are aligned for `u64` and stay
+/// valid until they are passed to `deallocate`.
+pub unsafe trait MemoryAllocator {
+    /// Allocate `bytes` bytes. Errors are reported to the caller of the hash function as
+    /// `Error::AllocationFailed`.
+    fn allocate(&self, bytes: usize) -> Result<NonNull<u8>, Error>;
+
+    /// Free memory returned by `allocate`. `bytes` is the size that was requested.
+    fn deallocate(&self, ptr: NonNull<u8>, bytes: usize);
+}
+
+/// `MemoryAllocator` that passes allocations through to the global Rust allocator.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemAllocator;
+
+impl SystemAllocator {
+    /// Alignment of the allocations; the size of a cache line.
+    const ALIGN: usize = 64;
+
+    fn layout(bytes: usize) -> Option<Layout> {
+        Layout::from_size_align(bytes, Self::ALIGN).ok()
+    }
+}
+
+unsafe impl MemoryAllocator for SystemAllocator {
+    fn allocate(&self, bytes: usize) -> Result<NonNull<u8>, Error> {
+        let failed = Error::AllocationFailed { requested_bytes: bytes as u64 };
+        match Self::layout(bytes) {
+            Some(layout) if bytes != 0 => {
+                NonNull::new(unsafe { alloc::alloc(layout) }).ok_or(failed)
+            }
+            _ => Err(failed),
+        }
+    }
+
+    fn deallocate(&self, ptr: NonNull<u8>, bytes: usize) {
+        if let Some(layout) = Self::layout(bytes) {
+            unsafe { alloc::dealloc(ptr.as_ptr(), layout) }
+        }
+    }
+}
+
+/// `MemoryAllocator` that keeps the working memory of a hash with a fixed `m_cost` and hands the
+/// same region to every following hash with that `m_cost`, instead of allocating it anew each time.
+///
+/// The region is allocated from the backing allocator on first use and freed when the arena is
+/// dropped. It is wiped before it is reused. Requests of a different size, and requests while the
+/// region is used by another hash, are passed on to the backing allocator.
+///
+/// The C library rounds the memory down to a multiple of `4 * lanes` blocks of 1 KiB, so `m_cost`
+/// should be such a multiple to match the requests of hashes with that `m_cost`.
+///
+/// Pass it to `ctx_with_allocator`, or to `PhcHasher::with_arena` to use it for every hash of a
+/// hasher.
+pub struct MemoryArena<A: MemoryAllocator = SystemAllocator> {
+    bytes: usize,
+    allocator: A,
+    region: std::sync::Mutex<Region>,
+}
+
+#[derive(Debug)]
+struct Region {
+    ptr: Option<NonNull<u8>>,
+    in_use: bool,
+}
+
+// The region is only accessed while the mutex is held, or by the hash that has it marked in use.
+unsafe impl<A: MemoryAllocator + Send> Send for MemoryArena<A> {}
+unsafe impl<A: MemoryAllocator + Sync> Sync for MemoryArena<A> {}
+
+impl MemoryArena {
+    /// Creates an arena for hashes with `m_cost` KiB of memory, backed by `SystemAllocator`.
+    ///
+    /// Returns `ErrorCode::MemoryTooMuch` if `m_cost` KiB don't fit into the address space.
+    pub fn new(m_cost: u32) -> Result<MemoryArena, Error> {
+        MemoryArena::with_allocator(m_cost, SystemAllocator)
+    }
+}
+
+impl<A: MemoryAllocator> MemoryArena<A> {
+    /// Creates an arena for hashes with `m_cost` KiB of memory, backed by `allocator`.
+    ///
+    /// Returns `ErrorCode::MemoryTooMuch` if `m_cost` KiB don't fit into the address space.
+    pub fn with_allocator(m_cost: u32, allocator: A) -> Result<MemoryArena<A>, Error> {
+        let bytes = usize::try_from(m_cost)
+            .ok()
+            .and_then(|m_cost| m_cost.checked_mul(1024))
+            .ok_or(Error::Code(ErrorCode::MemoryTooMuch))?;
+        Ok(MemoryArena {
+            bytes,
+            allocator,
+            region: std::sync::Mutex::new(Region { ptr: None, in_use: false }),
+        })
+    }
+
+    /// The backing allocator.
+    pub fn allocator(&self) -> &A {
+        &self.allocator
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Region> {
+        self.region.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+unsafe impl<A: MemoryAllocator> MemoryAllocator for MemoryArena<A> {
+    fn allocate(&self, bytes: usize) -> Result<NonNull<u8>, Error> {
+        let mut region = self.lock();
+        if bytes != self.bytes || region.in_use {
+            drop(region);
+            return self.allocator.allocate(bytes);
+        }
+
+        let ptr = match region.ptr {
+            Some(ptr) => ptr,
+            None => *region.ptr.insert(self.allocator.allocate(bytes)?),
+        };
+        region.in_use = true;
+        Ok(ptr)
+    }
+
+    fn deallocate(&self, ptr: NonNull<u8>, bytes: usize) {
+        let mut region = self.lock();
+        if region.ptr == Some(ptr) {
+            super::types::secure_wipe(unsafe { std::slice::from_raw_parts_mut(ptr.as_ptr(), bytes) });
+            region.in_use = false;
+        } else {
+            drop(region);
+            self.allocator.deallocate(ptr, bytes);
+        }
+    }
+}
+
+impl<A: MemoryAllocator> Drop for MemoryArena<A> {
+    fn drop(&mut self) {
+        let bytes = self.bytes;
+        if let Some(ptr) = self.lock().ptr.take() {
+            self.allocator.deallocate(ptr, bytes);
+        }
+    }
+}
+
+impl<A: MemoryAllocator> fmt::Debug for MemoryArena<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let region = self.lock();
+        f.debug_struct("MemoryArena")
+            .field("bytes", &self.bytes)
+            .field("allocated", &region.ptr.is_some())
+            .field("in_use", &region.in_use)
+            .finish()
+    }
+}
+
+/// What the C library allocated for one hash, as recorded by `InstrumentedAllocator`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AllocStats {
+    /// Bytes requested by all allocations. For a hash this is `m_cost` KiB, rounded down to a
+    /// multiple of `4 * lanes` KiB (but at least `8 * lanes` KiB).
+    pub total_bytes: u64,
+    /// Most bytes that were allocated at the same time.
+    pub peak_bytes: u64,
+    /// Number of allocations.
+    pub allocations: u64,
+}
+
+/// `MemoryAllocator` that passes allocations through to a backing allocator and records how much
+/// memory each hash allocated, e.g. for capacity planning.
+///
+/// A hash starts with the first allocation while nothing is allocated, and ends when all of its
+/// memory is freed. Its `AllocStats` are then available from `last_alloc_stats` and passed to the
+/// callback of `with_callback`, if any. Recording costs four atomic operations per allocation.
+///
+/// Hashes that run at the same time with the same allocator are recorded as one; give every thread
+/// its own allocator to keep them apart.
+pub struct InstrumentedAllocator<A: MemoryAllocator = SystemAllocator> {
+    allocator: A,
+    total: AtomicU64,
+    peak: AtomicU64,
+    outstanding: AtomicU64,
+    allocations: AtomicU64,
+    last: std::sync::Mutex<Option<AllocStats>>,
+    callback: Option<StatsCallback>,
+}
+
+type StatsCallback = Box<dyn Fn(&AllocStats) + Send + Sync>;
+
+impl InstrumentedAllocator {
+    /// Creates an allocator backed by `SystemAllocator`.
+    pub fn new() -> InstrumentedAllocator {
+        InstrumentedAllocator::with_allocator(SystemAllocator)
+    }
+}
+
+impl Default for InstrumentedAllocator {
+    fn default() -> InstrumentedAllocator {
+        InstrumentedAllocator::new()
+    }
+}
+
+impl<A: MemoryAllocator> InstrumentedAllocator<A> {
+    /// Creates an allocator backed by `allocator`.
+    pub fn with_allocator(allocator: A) -> InstrumentedAllocator<A> {
+        InstrumentedAllocator {
+            allocator,
+            total: AtomicU64::new(0),
+            peak: AtomicU64::new(0),
+            outstanding: AtomicU64::new(0),
+            allocations: AtomicU64::new(0),
+            last: std::sync::Mutex::new(None),
+            callback: None,
+        }
+    }
+
+    /// Calls `callback` with the statistics of every hash when it ends, on the thread that frees
+    /// its memory.
+    pub fn with_callback(mut self, callback: impl Fn(&AllocStats) + Send + Sync + 'static) -> InstrumentedAllocator<A> {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// The statistics of the last hash that ended, or `None` if none has.
+    pub fn last_alloc_stats(&self) -> Option<AllocStats> {
+        *self.last.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// The backing allocator.
+    pub fn allocator(&self) -> &A {
+        &self.allocator
+    }
+}
+
+unsafe impl<A: MemoryAllocator> MemoryAllocator for InstrumentedAllocator<A> {
+    fn allocate(&self, bytes: usize) -> Result<NonNull<u8>, Error> {
+        let ptr = self.allocator.allocate(bytes)?;
+        let bytes = bytes as u64;
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(bytes, Ordering::Relaxed);
+        let outstanding = self.outstanding.fetch_add(bytes, Ordering::AcqRel) + bytes;
+        self.peak.fetch_max(outstanding, Ordering::Relaxed);
+        Ok(ptr)
+    }
+
+    fn deallocate(&self, ptr: NonNull<u8>, bytes: usize) {
+        self.allocator.deallocate(ptr, bytes);
+        if self.outstanding.fetch_sub(bytes as u64, Ordering::AcqRel) != bytes as u64 {
+            return;
+        }
+        // All memory is freed, the hash has ended.
+        let stats = AllocStats {
+            total_bytes: self.total.swap(0, Ordering::Relaxed),
+            peak_bytes: self.peak.swap(0, Ordering::Relaxed),
+            allocations: self.allocations.swap(0, Ordering::Relaxed),
+        };
+        *self.last.lock().unwrap_or_else(std::sync::PoisonError::into_inner) = Some(stats);
+        if let Some(callback) = &self.callback {
+            callback(&stats);
+        }
+    }
+}
+
+impl<A: MemoryAllocator + fmt::Debug> fmt::Debug for InstrumentedAllocator<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InstrumentedAllocator")
+            .field("allocator", &self.allocator)
+            .field("last", &self.last_alloc_stats())
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+type AllocatorPtr = *const (dyn MemoryAllocator + 'static);
+
+thread_local! {
+    // The C callbacks carry no user data, so the allocator of the running hash is looked up here.
+    static CURRENT: Cell<Option<AllocatorPtr>> = Cell::new(None);
+}
+
+/// Runs `f` with `allocator` installed for the trampolines on this thread.
+pub(crate) fn with_allocator<R>(allocator: &dyn MemoryAllocator, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<AllocatorPtr>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT.with(|current| current.set(self.0));
+        }
+    }
+
+    // The pointer is only dereferenced by the trampolines while `f` runs, and is removed again
+    // before `allocator` can go out of scope.
+    let allocator: AllocatorPtr = unsafe { std::mem::transmute(allocator) };
+    let _restore = Restore(CURRENT.with(|current| current.replace(Some(allocator))));
+    f()
+}
+
+/// Sets the callbacks of `context` to the trampolines. Only valid inside `with_allocator`.
+pub(crate) fn install(context: &mut sys::Argon2_Context) {
+    context.allocate_cbk = Some(allocate_trampoline);
+    context.free_cbk = Some(free_trampoline);
+}
+
+fn current() -> Option<&'static dyn MemoryAllocator> {
+    CURRENT.with(|current| current.get()).map(|allocator| unsafe { &*allocator })
+}
+
+unsafe extern "C" fn allocate_trampoline(memory: *mut *mut u8, bytes: usize) -> c_int {
+    // A panic is reported like a failed allocation; the C library checks `*memory` for NULL.
+    let allocated = panic::catch_unwind(AssertUnwindSafe(|| {
+        current().and_then(|allocator| allocator.allocate(bytes).ok())
+    }));
+    match allocated {
+        Ok(Some(ptr)) => {
+            *memory = ptr.as_ptr();
+            0
+        }
+        _ => {
+            *memory = ptr::null_mut();
+            -1
+        }
+    }
+}
+
+unsafe extern "C" fn free_trampoline(memory: *mut u8, bytes: usize) {
+    // There is no way to report a failure from here, so a panic aborts instead of unwinding into C.
+    let freed = panic::catch_unwind(AssertUnwindSafe(|| {
+        if let (Some(allocator), Some(ptr)) = (current(), NonNull::new(memory)) {
+            allocator.deallocate(ptr, bytes);
+        }
+    }));
+    if freed.is_err() {
+        std::process::abort();
+    }
+}
+
+/// What `SecureAllocator` does when the memory can't be locked, e.g. because `RLIMIT_MEMLOCK` is
+/// too low or the working set quota can't be raised on Windows.
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockFailure {
+    /// Fail the allocation, the hash function returns `Error::AllocationFailed`.
+    Error,
+    /// Use the memory unlocked. The number of such allocations is counted in
+    /// `SecureAllocator::unlocked_allocations`.
+    Continue,
+}
+
+/// What `SecureAllocator` does when a `madvise` call requested by `MemoryProtection` fails.
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdviceFailure {
+    /// Use the memory without the advice.
+    Ignore,
+    /// Use the memory without the advice and count the failure in
+    /// `SecureAllocator::failed_advice`.
+    Warn,
+    /// Fail the allocation, the hash function returns `Error::AllocationFailed`.
+    Error,
+}
+
+/// Additional protection of the memory mapped by `SecureAllocator`. All options are off by default
+/// and are no-ops on platforms other than Linux.
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryProtection {
+    /// Exclude the memory from core dumps (`MADV_DONTDUMP`).
+    pub dont_dump: bool,
+    /// Zero the memory in forked children (`MADV_WIPEONFORK`, Linux 4.14 and later).
+    pub wipe_on_fork: bool,
+    /// What to do if one of the `madvise` calls fails.
+    pub on_failure: AdviceFailure,
+}
+
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+impl Default for MemoryProtection {
+    fn default() -> MemoryProtection {
+        MemoryProtection {
+            dont_dump: false,
+            wipe_on_fork: false,
+            on_failure: AdviceFailure::Error,
+        }
+    }
+}
+
+/// Whether `SecureAllocator` backs the memory with huge pages, which reduces TLB misses while
+/// hashing with a large `m_cost`. Huge pages are only used on Linux; elsewhere all policies behave
+/// like `Off`.
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HugePagePolicy {
+    /// Use normal pages.
+    Off,
+    /// Ask for transparent huge pages with `MADV_HUGEPAGE`. The kernel backs as much of the memory
+    /// with huge pages as it can and uses normal pages for the rest.
+    Transparent,
+    /// Take the memory from the reserved huge page pool (`MAP_HUGETLB`), rounding the mapping up
+    /// to a multiple of the huge page size. Falls back to `Transparent` if the pool doesn't have
+    /// enough free pages.
+    Reserved,
+}
+
+/// Options for the memory mapped by `SecureAllocator`.
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryOptions {
+    /// Whether to use huge pages. Defaults to `HugePagePolicy::Off`.
+    pub huge_pages: HugePagePolicy,
+}
+
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+impl Default for MemoryOptions {
+    fn default() -> MemoryOptions {
+        MemoryOptions { huge_pages: HugePagePolicy::Off }
+    }
+}
+
+/// `MemoryAllocator` that locks the memory into RAM, so that blocks derived from the password are
+/// never written to swap. The memory is wiped before it is released.
+///
+/// On unix the memory is mapped with `mmap` and locked with `mlock`. On Windows it is allocated
+/// with `VirtualAlloc` and locked with `VirtualLock`; if the working set quota is too small, it is
+/// raised by the size of the allocation and the lock is retried.
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+#[derive(Debug)]
+pub struct SecureAllocator {
+    on_lock_failure: LockFailure,
+    protection: MemoryProtection,
+    options: MemoryOptions,
+    unlocked: std::sync::atomic::AtomicUsize,
+    failed_advice: std::sync::atomic::AtomicUsize,
+    huge_pages: std::sync::atomic::AtomicUsize,
+}
+
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+impl SecureAllocator {
+    /// Creates an allocator that handles failures to lock memory according to `on_lock_failure`.
+    pub fn new(on_lock_failure: LockFailure) -> SecureAllocator {
+        SecureAllocator {
+            on_lock_failure,
+            protection: MemoryProtection::default(),
+            options: MemoryOptions::default(),
+            unlocked: std::sync::atomic::AtomicUsize::new(0),
+            failed_advice: std::sync::atomic::AtomicUsize::new(0),
+            huge_pages: std::sync::atomic::AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the additional protection applied to every allocation.
+    pub fn with_protection(mut self, protection: MemoryProtection) -> SecureAllocator {
+        self.protection = protection;
+        self
+    }
+
+    /// Sets the options for the memory of every allocation.
+    pub fn with_options(mut self, options: MemoryOptions) -> SecureAllocator {
+        self.options = options;
+        self
+    }
+
+    /// Number of allocations that were taken from the reserved huge page pool with
+    /// `HugePagePolicy::Reserved`.
+    pub fn huge_page_allocations(&self) -> usize {
+        self.huge_pages.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Size of the mapping for an allocation of `bytes`.
+    fn mapped_len(&self, bytes: usize) -> usize {
+        match self.options.huge_pages {
+            HugePagePolicy::Reserved => os::huge_page_size().map_or(bytes, |size| {
+                bytes.checked_add(size - 1).map_or(bytes, |bytes| bytes / size * size)
+            }),
+            HugePagePolicy::Off | HugePagePolicy::Transparent => bytes,
+        }
+    }
+
+    /// Maps `len` bytes according to the huge page policy.
+    unsafe fn map(&self, len: usize) -> Option<NonNull<u8>> {
+        if self.options.huge_pages == HugePagePolicy::Reserved && len != 0 {
+            if let Some(memory) = os::map_huge(len) {
+                self.huge_pages.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                return Some(memory);
+            }
+        }
+        let memory = os::map(len)?;
+        if self.options.huge_pages != HugePagePolicy::Off {
+            // Without transparent huge pages the memory simply stays in normal pages.
+            os::advise_huge(memory, len);
+        }
+        Some(memory)
+    }
+
+    /// Number of allocations that could not be locked and were used anyway.
+    pub fn unlocked_allocations(&self) -> usize {
+        self.unlocked.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Number of allocations for which `madvise` failed with `AdviceFailure::Warn`.
+    pub fn failed_advice(&self) -> usize {
+        self.failed_advice.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+impl Default for SecureAllocator {
+    fn default() -> SecureAllocator {
+        SecureAllocator::new(LockFailure::Error)
+    }
+}
+
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+unsafe impl MemoryAllocator for SecureAllocator {
+    fn allocate(&self, bytes: usize) -> Result<NonNull<u8>, Error> {
+        let failed = || Error::AllocationFailed { requested_bytes: bytes as u64 };
+        if bytes == 0 {
+            return Err(failed());
+        }
+
+        let mapped = self.mapped_len(bytes);
+        unsafe {
+            let memory = self.map(mapped).ok_or_else(failed)?;
+
+            if !os::lock(memory, bytes) {
+                match self.on_lock_failure {
+                    LockFailure::Error => {
+                        os::unmap(memory, mapped);
+                        return Err(failed());
+                    }
+                    LockFailure::Continue => {
+                        self.unlocked.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                }
+            }
+
+            if !os::advise(&self.protection, memory, bytes) {
+                match self.protection.on_failure {
+                    AdviceFailure::Ignore => {}
+                    AdviceFailure::Warn => {
+                        self.failed_advice.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                    AdviceFailure::Error => {
+                        os::unlock(memory, bytes);
+                        os::unmap(memory, mapped);
+                        return Err(failed());
+                    }
+                }
+            }
+
+            Ok(memory)
+        }
+    }
+
+    fn deallocate(&self, ptr: NonNull<u8>, bytes: usize) {
+        unsafe {
+            super::types::secure_wipe(std::slice::from_raw_parts_mut(ptr.as_ptr(), bytes));
+
+            // Unlocking memory that was never locked is harmless.
+            os::unlock(ptr, bytes);
+            os::unmap(ptr, self.mapped_len(bytes));
+        }
+    }
+}
+
+#[cfg(all(feature = "mlock", unix))]
+mod os {
+    use super::MemoryProtection;
+    use std::ptr::{self, NonNull};
+
+    pub unsafe fn map(bytes: usize) -> Option<NonNull<u8>> {
+        let memory = libc::mmap(
+            ptr::null_mut(),
+            bytes,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANON,
+            -1,
+            0,
+        );
+        if memory == libc::MAP_FAILED {
+            None
+        } else {
+            NonNull::new(memory as *mut u8)
+        }
+    }
+
+    /// Maps `bytes` from the reserved huge page pool. `bytes` must be a multiple of
+    /// `huge_page_size`.
+    #[cfg(target_os = "linux")]
+    pub unsafe fn map_huge(bytes: usize) -> Option<NonNull<u8>> {
+        let memory = libc::mmap(
+            ptr::null_mut(),
+            bytes,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANON | libc::MAP_HUGETLB,
+            -1,
+            0,
+        );
+        if memory == libc::MAP_FAILED {
+            None
+        } else {
+            NonNull::new(memory as *mut u8)
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub unsafe fn map_huge(_bytes: usize) -> Option<NonNull<u8>> {
+        None
+    }
+
+    /// The default huge page size, read once from `/proc/meminfo`.
+    #[cfg(target_os = "linux")]
+    pub fn huge_page_size() -> Option<usize> {
+        use std::convert::TryFrom;
+
+        static SIZE: std::sync::OnceLock<Option<usize>> = std::sync::OnceLock::new();
+        *SIZE.get_or_init(|| {
+            let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+            let size = super::super::memory::meminfo_field(&meminfo, "Hugepagesize")?;
+            usize::try_from(size).ok().filter(|&size| size != 0)
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn huge_page_size() -> Option<usize> {
+        None
+    }
+
+    #[cfg(target_os = "linux")]
+    pub unsafe fn advise_huge(memory: NonNull<u8>, bytes: usize) {
+        libc::madvise(memory.as_ptr() as *mut libc::c_void, bytes, libc::MADV_HUGEPAGE);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub unsafe fn advise_huge(_memory: NonNull<u8>, _bytes: usize) {}
+
+    pub unsafe fn unmap(memory: NonNull<u8>, bytes: usize) {
+        libc::munmap(memory.as_ptr() as *mut libc::c_void, bytes);
+    }
+
+    pub unsafe fn lock(memory: NonNull<u8>, bytes: usize) -> bool {
+        libc::mlock(memory.as_ptr() as *const libc::c_void, bytes) == 0
+    }
+
+    pub unsafe fn unlock(memory: NonNull<u8>, bytes: usize) {
+        libc::munlock(memory.as_ptr() as *const libc::c_void, bytes);
+    }
+
+    /// Applies the advice to the mapping. Returns `false` if any of the calls failed.
+    #[cfg(target_os = "linux")]
+    pub unsafe fn advise(protection: &MemoryProtection, memory: NonNull<u8>, bytes: usize) -> bool {
+        let memory = memory.as_ptr() as *mut libc::c_void;
+        let mut ok = true;
+        if protection.dont_dump {
+            ok &= libc::madvise(memory, bytes, libc::MADV_DONTDUMP) == 0;
+        }
+        if protection.wipe_on_fork {
+            ok &= libc::madvise(memory, bytes, libc::MADV_WIPEONFORK) == 0;
+        }
+        ok
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub unsafe fn advise(_protection: &MemoryProtection, _memory: NonNull<u8>, _bytes: usize) -> bool {
+        true
+    }
+}
+
+#[cfg(all(feature = "mlock", windows))]
+#[allow(clippy::upper_case_acronyms)]
+mod os {
+    use super::MemoryProtection;
+    use std::ptr::{self, NonNull};
+
+    type BOOL = i32;
+    type DWORD = u32;
+    type HANDLE = *mut std::ffi::c_void;
+    type LPVOID = *mut std::ffi::c_void;
+
+    const MEM_COMMIT: DWORD = 0x1000;
+    const MEM_RESERVE: DWORD = 0x2000;
+    const MEM_RELEASE: DWORD = 0x8000;
+    const PAGE_READWRITE: DWORD = 0x04;
+    const ERROR_WORKING_SET_QUOTA: DWORD = 1453;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn VirtualAlloc(address: LPVOID, size: usize, allocation_type: DWORD, protect: DWORD) -> LPVOID;
+        fn VirtualFree(address: LPVOID, size: usize, free_type: DWORD) -> BOOL;
+        fn VirtualLock(address: LPVOID, size: usize) -> BOOL;
+        fn VirtualUnlock(address: LPVOID, size: usize) -> BOOL;
+        fn GetCurrentProcess() -> HANDLE;
+        fn GetProcessWorkingSetSize(process: HANDLE, minimum: *mut usize, maximum: *mut usize) -> BOOL;
+        fn SetProcessWorkingSetSize(process: HANDLE, minimum: usize, maximum: usize) -> BOOL;
+        fn GetLastError() -> DWORD;
+    }
+
+    pub unsafe fn map(bytes: usize) -> Option<NonNull<u8>> {
+        NonNull::new(VirtualAlloc(ptr::null_mut(), bytes, MEM_COMMIT | MEM_RESERVE, PAGE_READWRITE) as *mut u8)
+    }
+
+    pub unsafe fn map_huge(_bytes: usize) -> Option<NonNull<u8>> {
+        None
+    }
+
+    pub fn huge_page_size() -> Option<usize> {
+        None
+    }
+
+    pub unsafe fn advise_huge(_memory: NonNull<u8>, _bytes: usize) {}
+
+    pub unsafe fn unmap(memory: NonNull<u8>, _bytes: usize) {
+        VirtualFree(memory.as_ptr() as LPVOID, 0, MEM_RELEASE);
+    }
+
+    pub unsafe fn lock(memory: NonNull<u8>, bytes: usize) -> bool {
+        let memory = memory.as_ptr() as LPVOID;
+        if VirtualLock(memory, bytes) != 0 {
+            return true;
+        }
+        if GetLastError() != ERROR_WORKING_SET_QUOTA {
+            return false;
+        }
+
+        let process = GetCurrentProcess();
+        let (mut minimum, mut maximum) = (0, 0);
+        if GetProcessWorkingSetSize(process, &mut minimum, &mut maximum) == 0 {
+            return false;
+        }
+        let minimum = minimum.saturating_add(bytes);
+        let maximum = maximum.saturating_add(bytes);
+        SetProcessWorkingSetSize(process, minimum, maximum) != 0 && VirtualLock(memory, bytes) != 0
+    }
+
+    pub unsafe fn unlock(memory: NonNull<u8>, bytes: usize) {
+        VirtualUnlock(memory.as_ptr() as LPVOID, bytes);
+    }
+
+    pub unsafe fn advise(_protection: &MemoryProtection, _memory: NonNull<u8>, _bytes: usize) -> bool {
+        true
+    }
+}
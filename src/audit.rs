@@ -0,0 +1,118 @@
+//! An audit trail of verification attempts, for investigating incidents.
+//!
+//! Install a hook with `set_audit_hook` and verify with `verify_audited` or
+//! `EncodedHash::verify_audited`. Each of those calls passes exactly one `AuditEvent` to the hook,
+//! with the outcome, the parameters of the stored hash, the time and a correlation ID of the
+//! caller, e.g. a request ID. The other `*verify*` functions don't report to the hook.
+//!
+//! Events never contain the password, salt or hash. The correlation ID is passed through as it
+//! is, so it shouldn't contain secrets either.
+
+use super::encoded::EncodedHash;
+use super::types::{Error, Variant, Version};
+use std::sync::{PoisonError, RwLock};
+use std::time::SystemTime;
+
+/// Outcome of an audited verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AuditOutcome {
+    /// The password matches.
+    Match,
+    /// The password doesn't match.
+    Mismatch,
+    /// The stored hash can't be decoded, or names a variant that is compiled out.
+    Malformed,
+    /// Verifying failed for another reason, e.g. the memory cost exceeds the cap of
+    /// `set_max_m_cost` or the memory couldn't be allocated.
+    Error,
+}
+
+/// The parameters of a stored hash.
+#[derive(Debug, Clone, Copy)]
+pub struct AuditParams {
+    /// The variant.
+    pub variant: Variant,
+    /// The version; 0x10 if the hash has no version field.
+    pub version: Version,
+    /// Memory cost in kibibytes.
+    pub m_cost: u32,
+    /// Number of passes.
+    pub t_cost: u32,
+    /// Number of lanes.
+    pub parallelism: u32,
+    /// Length of the hash in bytes.
+    pub hash_len: usize,
+}
+
+/// A verification attempt, as passed to the audit hook.
+#[derive(Debug, Clone, Copy)]
+pub struct AuditEvent<'a> {
+    /// The outcome.
+    pub outcome: AuditOutcome,
+    /// The parameters of the stored hash, or `None` if it is malformed.
+    pub params: Option<AuditParams>,
+    /// When the verification finished.
+    pub timestamp: SystemTime,
+    /// The correlation ID passed by the caller.
+    pub correlation: &'a str,
+}
+
+static HOOK: RwLock<Option<fn(&AuditEvent)>> = RwLock::new(None);
+
+/// Installs the process-wide audit hook, replacing the previous one, if any.
+///
+/// The hook is called on the verifying thread and should return quickly, e.g. by sending the
+/// event to a log. A panic in the hook is caught and ignored, so it can't fail a verification;
+/// the panic hook of the process still runs and may print the message.
+pub fn set_audit_hook(hook: fn(&AuditEvent)) {
+    *HOOK.write().unwrap_or_else(PoisonError::into_inner) = Some(hook);
+}
+
+/// Verifies a password against an encoded hash like `verify_bytes`, with the variant taken from
+/// the hash, and reports the attempt to the audit hook with `correlation`.
+pub fn verify_audited(encoded: &str, pwd: Option<&[u8]>, correlation: &str) -> Result<(), Error> {
+    match EncodedHash::parse(encoded) {
+        Ok(hash) => hash.verify_audited(pwd, correlation),
+        Err(err) => {
+            report(outcome(Some(&err)), None, correlation);
+            Err(err)
+        }
+    }
+}
+
+impl EncodedHash {
+    /// Verifies a password against the hash like `verify`, and reports the attempt to the audit
+    /// hook with `correlation`.
+    pub fn verify_audited(&self, pwd: Option<&[u8]>, correlation: &str) -> Result<(), Error> {
+        let result = self.verify(pwd);
+        let params = AuditParams {
+            variant: self.variant(),
+            version: self.version(),
+            m_cost: self.m_cost(),
+            t_cost: self.t_cost(),
+            parallelism: self.parallelism(),
+            hash_len: self.hash().len(),
+        };
+        report(outcome(result.as_ref().err()), Some(params), correlation);
+        result
+    }
+}
+
+/// Classifies the error of a verification, `None` if it succeeded.
+fn outcome(err: Option<&Error>) -> AuditOutcome {
+    match err {
+        None => AuditOutcome::Match,
+        Some(err) if err.is_verify_mismatch() => AuditOutcome::Mismatch,
+        Some(err) if err.is_decoding_error() || matches!(err, Error::UnknownAlgorithm(_)) => AuditOutcome::Malformed,
+        Some(_) => AuditOutcome::Error,
+    }
+}
+
+fn report(outcome: AuditOutcome, params: Option<AuditParams>, correlation: &str) {
+    let hook = match *HOOK.read().unwrap_or_else(PoisonError::into_inner) {
+        Some(hook) => hook,
+        None => return,
+    };
+    let event = AuditEvent { outcome, params, timestamp: SystemTime::now(), correlation };
+    let _ = std::panic::catch_unwind(|| hook(&event));
+}
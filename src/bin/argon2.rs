@@ -0,0 +1,288 @@
+//! Command-line utility compatible with the `argon2` utility of the reference implementation.
+//!
+//! The arguments, the output and the exit codes are the same, so scripts written against the
+//! reference utility can use this binary instead:
+//!
+//! ```text
+//! echo -n "password" | argon2 somesalt -id -t 2 -m 16 -p 1 -l 32
+//! ```
+
+use argon2::{Error, Variant, Version};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::process;
+use std::time::Instant;
+
+const T_COST_DEF: u32 = 3;
+const LOG_M_COST_DEF: u32 = 12;
+const THREADS_DEF: u32 = 1;
+const OUTLEN_DEF: u32 = 32;
+// Passwords that fill the whole buffer are rejected, so they can be at most 127 bytes long.
+const MAX_PASS_LEN: usize = 128;
+
+const MAX_MEMORY_BITS: u64 = if usize::BITS - 11 < 32 { (usize::BITS - 11) as u64 } else { 32 };
+const MAX_MEMORY: u64 = if MAX_MEMORY_BITS < 32 { 1 << MAX_MEMORY_BITS } else { 0xFFFFFFFF };
+const MAX_THREADS: u64 = 0xFFFFFF;
+
+// Exit code of the reference utility when no salt is given; `main` returns `ARGON2_MISSING_ARGS`.
+const EXIT_MISSING_ARGS: i32 = 226;
+
+fn usage(cmd: &str) {
+    let types = if cfg!(feature = "no-argon2d") { "-i|-id" } else { "-i|-d|-id" };
+    println!("Usage:  {} [-h] salt [{}] [-t iterations] \
+              [-m log2(memory in KiB) | -k memory in KiB] [-p parallelism] \
+              [-l hash length] [-e|-r] [-v (10|13)]", cmd, types);
+    println!("\tPassword is read from stdin");
+    println!("Parameters:");
+    println!("\tsalt\t\tThe salt to use, at least 8 characters");
+    println!("\t-i\t\tUse Argon2i (this is the default)");
+    #[cfg(not(feature = "no-argon2d"))]
+    println!("\t-d\t\tUse Argon2d instead of Argon2i");
+    println!("\t-id\t\tUse Argon2id instead of Argon2i");
+    println!("\t-t N\t\tSets the number of iterations to N (default = {})", T_COST_DEF);
+    println!("\t-m N\t\tSets the memory usage of 2^N KiB (default {})", LOG_M_COST_DEF);
+    println!("\t-k N\t\tSets the memory usage of N KiB (default {})", 1u32 << LOG_M_COST_DEF);
+    println!("\t-p N\t\tSets parallelism to N threads (default {})", THREADS_DEF);
+    println!("\t-l N\t\tSets hash output length to N bytes (default {})", OUTLEN_DEF);
+    println!("\t-e\t\tOutput only encoded hash");
+    println!("\t-r\t\tOutput only the raw bytes of the hash");
+    println!("\t-v (10|13)\tArgon2 version (defaults to the most recent version, currently {:x})",
+             Version::Version13.to_int());
+    println!("\t-h\t\tPrint {} usage", cmd);
+}
+
+fn fatal(error: &str) -> ! {
+    // Flush what was printed so far, like the C library's `exit` does.
+    let _ = io::stdout().flush();
+    eprintln!("Error: {}", error);
+    process::exit(1);
+}
+
+fn fatal_error(err: Error) -> ! {
+    match err {
+        Error::Code(code) => fatal(code.message()),
+        err => fatal(&err.to_string()),
+    }
+}
+
+/// Parses a number like `strtoul` does: leading whitespace and a sign are skipped, parsing stops
+/// at the first non-digit, a missing number is 0 and an overflow saturates to `u64::MAX`.
+fn strtoul(s: &str) -> u64 {
+    let s = s.trim_start();
+    let (negative, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let mut n = 0u64;
+    for digit in s.bytes().take_while(u8::is_ascii_digit) {
+        n = match n.checked_mul(10).and_then(|n| n.checked_add(u64::from(digit - b'0'))) {
+            Some(n) => n,
+            None => return u64::MAX,
+        };
+    }
+    if negative { n.wrapping_neg() } else { n }
+}
+
+/// Parses the value of an option, or fails with `missing` if it is the last argument.
+fn numeric_value<'a>(args: &mut impl Iterator<Item = &'a String>, missing: &str) -> u64 {
+    match args.next() {
+        Some(value) => strtoul(value),
+        None => fatal(missing),
+    }
+}
+
+fn print_hex(bytes: &[u8]) {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    println!("{}", hex);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run(
+    outlen: u32,
+    pwd: &mut [u8],
+    salt: &str,
+    t_cost: u32,
+    m_cost: u32,
+    lanes: u32,
+    variant: Variant,
+    encoded_only: bool,
+    raw_only: bool,
+    version: Version) {
+    let start = Instant::now();
+
+    let saltlen = match u32::try_from(salt.len()) {
+        Ok(saltlen) => saltlen,
+        Err(_) => fatal("salt is too long"),
+    };
+
+    let mut out = vec![0u8; outlen as usize];
+    let mut encoded = vec![0u8; argon2::encodedlen(t_cost, m_cost, lanes, saltlen, outlen, variant)];
+    if let Err(err) = argon2::hash(
+        t_cost,
+        m_cost,
+        lanes,
+        Some(pwd),
+        Some(salt.as_bytes()),
+        Some(&mut out),
+        Some(&mut encoded),
+        variant,
+        version) {
+        argon2::secure_wipe(pwd);
+        fatal_error(err);
+    }
+    let elapsed = start.elapsed();
+    let encoded_len = encoded.iter().position(|&b| b == 0).unwrap_or(encoded.len());
+
+    if encoded_only {
+        println!("{}", String::from_utf8_lossy(&encoded[..encoded_len]));
+    }
+    if raw_only {
+        print_hex(&out);
+    }
+    if encoded_only || raw_only {
+        argon2::secure_wipe(pwd);
+        return;
+    }
+
+    print!("Hash:\t\t");
+    print_hex(&out);
+    println!("Encoded:\t{}", String::from_utf8_lossy(&encoded[..encoded_len]));
+    println!("{:2.3} seconds", elapsed.as_secs_f64());
+
+    let result = argon2::verify_bytes(&encoded[..encoded_len], Some(pwd), variant);
+    argon2::secure_wipe(pwd);
+    if let Err(err) = result {
+        fatal_error(err);
+    }
+    println!("Verification ok");
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args_os().map(|arg| arg.to_string_lossy().into_owned()).collect();
+    let cmd = args.first().map(String::as_str).unwrap_or("argon2");
+
+    if args.len() < 2 {
+        usage(cmd);
+        process::exit(EXIT_MISSING_ARGS);
+    } else if args[1] == "-h" {
+        usage(cmd);
+        process::exit(1);
+    }
+
+    let mut outlen = OUTLEN_DEF;
+    let mut m_cost = 1 << LOG_M_COST_DEF;
+    let mut t_cost = T_COST_DEF;
+    let mut lanes = THREADS_DEF;
+    let mut variant = Variant::I;
+    let mut version = Version::Version13;
+    let mut encoded_only = false;
+    let mut raw_only = false;
+    let mut types_specified = 0;
+    let mut m_cost_specified = false;
+
+    // Read the password first, like the reference utility.
+    let mut pwd = Vec::with_capacity(MAX_PASS_LEN);
+    if io::stdin().take(MAX_PASS_LEN as u64).read_to_end(&mut pwd).is_err() || pwd.is_empty() {
+        fatal("no password read");
+    }
+    if pwd.len() == MAX_PASS_LEN {
+        argon2::secure_wipe(&mut pwd);
+        fatal("Provided password longer than supported in command line utility");
+    }
+
+    let salt = &args[1];
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-h" => {
+                usage(cmd);
+                process::exit(1);
+            }
+            "-m" => {
+                if m_cost_specified {
+                    fatal("-m or -k can only be used once");
+                }
+                m_cost_specified = true;
+                let input = numeric_value(&mut rest, "missing -m argument");
+                if input == 0 || input == u64::MAX || input > MAX_MEMORY_BITS {
+                    fatal("bad numeric input for -m");
+                }
+                m_cost = (1u64 << input).min(0xFFFFFFFF);
+                if m_cost > MAX_MEMORY {
+                    fatal("m_cost overflow");
+                }
+            }
+            "-k" => {
+                if m_cost_specified {
+                    fatal("-m or -k can only be used once");
+                }
+                m_cost_specified = true;
+                let input = numeric_value(&mut rest, "missing -k argument");
+                if input == 0 || input == u64::MAX {
+                    fatal("bad numeric input for -k");
+                }
+                m_cost = input.min(0xFFFFFFFF);
+                if m_cost > MAX_MEMORY {
+                    fatal("m_cost overflow");
+                }
+            }
+            "-t" => {
+                let input = numeric_value(&mut rest, "missing -t argument");
+                if input == 0 || input == u64::MAX || input > u64::from(u32::MAX) {
+                    fatal("bad numeric input for -t");
+                }
+                t_cost = input as u32;
+            }
+            "-p" => {
+                let input = numeric_value(&mut rest, "missing -p argument");
+                if input == 0 || input == u64::MAX || input > MAX_THREADS {
+                    fatal("bad numeric input for -p");
+                }
+                lanes = input as u32;
+            }
+            "-l" => {
+                // Truncated like the `uint32_t` of the reference utility.
+                outlen = numeric_value(&mut rest, "missing -l argument") as u32;
+            }
+            "-i" => {
+                variant = Variant::I;
+                types_specified += 1;
+            }
+            #[cfg(not(feature = "no-argon2d"))]
+            "-d" => {
+                variant = Variant::D;
+                types_specified += 1;
+            }
+            "-id" => {
+                variant = Variant::ID;
+                types_specified += 1;
+            }
+            "-e" => encoded_only = true,
+            "-r" => raw_only = true,
+            "-v" => match rest.next().map(String::as_str) {
+                Some("10") => version = Version::Version10,
+                Some("13") => version = Version::Version13,
+                Some(_) => fatal("invalid Argon2 version"),
+                None => fatal("missing -v argument"),
+            },
+            _ => fatal("unknown argument"),
+        }
+    }
+
+    if types_specified > 1 {
+        fatal("cannot specify multiple Argon2 types");
+    }
+    if encoded_only && raw_only {
+        fatal("cannot provide both -e and -r");
+    }
+
+    if !encoded_only && !raw_only {
+        println!("Type:\t\t{}", argon2::type2string(variant, true).unwrap_or_else(|err| fatal_error(err)));
+        println!("Iterations:\t{}", t_cost);
+        println!("Memory:\t\t{} KiB", m_cost);
+        println!("Parallelism:\t{}", lanes);
+    }
+
+    run(outlen, &mut pwd, salt, t_cost, m_cost as u32, lanes, variant, encoded_only, raw_only, version);
+}
@@ -0,0 +1,103 @@
+//! BLAKE2b, as bundled with the C library for Argon2's own use.
+//!
+//! `blake2b` is plain (optionally keyed) BLAKE2b with up to 64 bytes of output, `blake2b_long` is
+//! the variable-length hash function H' that Argon2 builds from it (RFC 9106, section 3.3).
+//! Both call the C implementation that hashes the passwords, so this module isn't available
+//! together with the `system` feature: the system's libargon2 doesn't export them.
+
+use super::sys;
+use super::types::Error;
+use std::convert::TryFrom;
+use std::os::raw::{c_int, c_void};
+
+/// Largest output of `blake2b` in bytes.
+pub const MAX_OUTPUT_LEN: usize = sys::BLAKE2B_OUTBYTES;
+
+/// Largest key of `blake2b` in bytes.
+pub const MAX_KEY_LEN: usize = sys::BLAKE2B_KEYBYTES;
+
+/// Hashes `data` into `out_len` bytes with BLAKE2b, keyed with `key` unless it is empty.
+///
+/// Returns `Error::InvalidParameter` for `"out_len"` unless it is between 1 and `MAX_OUTPUT_LEN`,
+/// and for `"key.len"` if the key is longer than `MAX_KEY_LEN`.
+pub fn blake2b(out_len: usize, key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    check_len("out_len", out_len, 1, MAX_OUTPUT_LEN)?;
+    check_len("key.len", key.len(), 0, MAX_KEY_LEN)?;
+    let mut out = vec![0u8; out_len];
+    let code = unsafe {
+        sys::blake2b(
+            out.as_mut_ptr() as *mut c_void,
+            out.len(),
+            data.as_ptr() as *const c_void,
+            data.len(),
+            key.as_ptr() as *const c_void,
+            key.len())
+    };
+    check_blake2(code, "blake2b")?;
+    Ok(out)
+}
+
+/// Hashes `data` into `out_len` bytes with H', the variable-length hash function of Argon2. Up to
+/// 64 bytes, this is BLAKE2b of the output length (as 32 bit little endian) followed by `data`;
+/// longer outputs chain BLAKE2b-512.
+///
+/// Returns `Error::InvalidParameter` for `"out_len"` unless it is between 1 and `u32::MAX`.
+pub fn blake2b_long(out_len: usize, data: &[u8]) -> Result<Vec<u8>, Error> {
+    check_len("out_len", out_len, 1, usize::try_from(u32::MAX).unwrap_or(usize::MAX))?;
+    let mut out = vec![0u8; out_len];
+    let code = unsafe {
+        sys::blake2b_long(out.as_mut_ptr() as *mut c_void, out.len(), data.as_ptr() as *const c_void, data.len())
+    };
+    check_blake2(code, "blake2b_long")?;
+    Ok(out)
+}
+
+/// Incremental BLAKE2b, for inputs that are read in chunks.
+pub(crate) struct Blake2bState(sys::blake2b_state);
+
+impl Blake2bState {
+    /// Starts an unkeyed hash of `out_len` bytes.
+    pub(crate) fn new(out_len: usize) -> Result<Blake2bState, Error> {
+        check_len("out_len", out_len, 1, MAX_OUTPUT_LEN)?;
+        // All zeros is a valid state; `blake2b_init` overwrites it.
+        let mut state = Blake2bState(unsafe { std::mem::zeroed() });
+        check_blake2(unsafe { sys::blake2b_init(&mut state.0, out_len) }, "blake2b_init")?;
+        Ok(state)
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) -> Result<(), Error> {
+        let code = unsafe { sys::blake2b_update(&mut self.0, data.as_ptr() as *const c_void, data.len()) };
+        check_blake2(code, "blake2b_update")
+    }
+
+    /// Returns the hash. The state is cleared by the C library.
+    pub(crate) fn finalize(mut self) -> Result<Vec<u8>, Error> {
+        let mut out = vec![0u8; self.0.outlen as usize];
+        let code = unsafe { sys::blake2b_final(&mut self.0, out.as_mut_ptr() as *mut c_void, out.len()) };
+        check_blake2(code, "blake2b_final")?;
+        Ok(out)
+    }
+}
+
+impl Drop for Blake2bState {
+    /// Clears the state of an unfinished hash, which is derived from the input.
+    fn drop(&mut self) {
+        unsafe { std::ptr::write_volatile(&mut self.0, std::mem::zeroed()) };
+    }
+}
+
+fn check_len(name: &'static str, len: usize, min: usize, max: usize) -> Result<(), Error> {
+    if len < min || len > max {
+        return Err(Error::InvalidParameter { name, got: len as u64, min: min as u64, max: max as u64 });
+    }
+    Ok(())
+}
+
+/// The BLAKE2 functions return -1 for every failure, which isn't an Argon2 error code. The
+/// parameters are checked beforehand, so this only reports what the C library rejects beyond them.
+fn check_blake2(code: c_int, function: &'static str) -> Result<(), Error> {
+    if code != 0 {
+        return Err(Error::BadParam(function));
+    }
+    Ok(())
+}
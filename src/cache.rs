@@ -0,0 +1,139 @@
+use super::types::{secure_wipe, Error, Variant};
+use blake2::digest::consts::U32;
+use blake2::digest::{Key, KeyInit, Mac};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+type Blake2bMac256 = blake2::Blake2bMac<U32>;
+type Tag = [u8; 32];
+
+/// Cache of successful verifications, for services that verify the same password against the same
+/// hash many times in a short period.
+///
+/// `VerifyCache::verify` behaves like `verify`, but remembers successful verifications for `ttl`
+/// and answers repeated identical verifications without hashing. At most `capacity` entries are
+/// kept; when the cache is full, expired entries are dropped first and then the oldest entry.
+///
+/// # Threat model
+///
+/// Entries are keyed by a BLAKE2b MAC of the variant, the encoded hash and the password under a
+/// random key that is generated per cache and never leaves the process. The password is never
+/// stored, and a leaked entry can't be used to test password guesses without the key, so the cache
+/// doesn't give an attacker who reads process memory anything they couldn't get from the password
+/// buffers of a verification in flight. An attacker who can read the key as well can test guesses
+/// at the speed of BLAKE2b for as long as the entries live, which is what `ttl` bounds.
+///
+/// Only successful verifications are cached. A wrong password always costs a full hash, so the
+/// cache doesn't speed up guessing, and a stored hash that is replaced (e.g. on a password change)
+/// stops matching immediately because the encoded hash is part of the key. Hits are faster than
+/// misses, which reveals that the same password was verified successfully within `ttl`; this is
+/// only observable by someone who already knows the password.
+pub struct VerifyCache {
+    key: Key<Blake2bMac256>,
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<HashMap<Tag, Instant>>,
+}
+
+impl VerifyCache {
+    /// Creates a cache with a random key that holds at most `capacity` successful verifications
+    /// for `ttl` each.
+    ///
+    /// Returns `Error::Random` if the operating system's random number generator fails.
+    pub fn new(capacity: usize, ttl: Duration) -> Result<VerifyCache, Error> {
+        let mut key = Key::<Blake2bMac256>::default();
+        getrandom::getrandom(&mut key).map_err(|err| Error::Random(err.into()))?;
+        Ok(VerifyCache { key, capacity, ttl, entries: Mutex::new(HashMap::new()) })
+    }
+
+    /// Verifies a password against an encoded string like `verify`, answering from the cache if
+    /// the same verification succeeded within `ttl`.
+    pub fn verify(&self, encoded: &CStr, pwd: Option<&[u8]>, variant: Variant) -> Result<(), Error> {
+        let tag = self.tag(encoded, pwd, variant);
+        if self.lookup(&tag, Instant::now()) {
+            return Ok(());
+        }
+        super::verify(encoded, pwd, variant)?;
+        self.insert(tag, Instant::now());
+        Ok(())
+    }
+
+    /// Number of entries in the cache, including expired ones that haven't been dropped yet.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns true if the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Drops all entries.
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Tag, Instant>> {
+        // The map is consistent after every operation, so a panic elsewhere doesn't invalidate it.
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn tag(&self, encoded: &CStr, pwd: Option<&[u8]>, variant: Variant) -> Tag {
+        let mut mac = <Blake2bMac256 as KeyInit>::new(&self.key);
+        let encoded = encoded.to_bytes();
+        Mac::update(&mut mac, &variant.to_c().to_le_bytes());
+        Mac::update(&mut mac, &(encoded.len() as u64).to_le_bytes());
+        Mac::update(&mut mac, encoded);
+        Mac::update(&mut mac, pwd.unwrap_or(&[]));
+        mac.finalize().into_bytes().into()
+    }
+
+    pub(crate) fn lookup(&self, tag: &Tag, now: Instant) -> bool {
+        let mut entries = self.lock();
+        match entries.get(tag) {
+            Some(&inserted) if now.saturating_duration_since(inserted) < self.ttl => true,
+            Some(_) => {
+                entries.remove(tag);
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn insert(&self, tag: Tag, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.lock();
+        if entries.len() >= self.capacity && !entries.contains_key(&tag) {
+            let ttl = self.ttl;
+            entries.retain(|_, inserted| now.saturating_duration_since(*inserted) < ttl);
+        }
+        while entries.len() >= self.capacity && !entries.contains_key(&tag) {
+            let oldest = entries.iter().min_by_key(|(_, inserted)| **inserted).map(|(tag, _)| *tag);
+            match oldest {
+                Some(oldest) => entries.remove(&oldest),
+                None => break,
+            };
+        }
+        entries.insert(tag, now);
+    }
+}
+
+impl std::fmt::Debug for VerifyCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("VerifyCache")
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Drop for VerifyCache {
+    fn drop(&mut self) {
+        secure_wipe(&mut self.key);
+    }
+}
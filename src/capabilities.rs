@@ -0,0 +1,82 @@
+use super::dispatch::{core_kind, linked_implementation, CoreKind, LinkKind};
+use super::types::{Context, Version, THREADS_AVAILABLE};
+use std::fmt;
+
+/// How the crate was built, for logs and bug reports. See `capabilities`.
+///
+/// New fields are added as the crate grows, so this struct is marked `#[non_exhaustive]`.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Version of this crate.
+    pub crate_version: &'static str,
+    /// Whether hashing can use more than one thread; `false` on targets without threads, see
+    /// `Error::ThreadsUnavailable`.
+    pub threads_enabled: bool,
+    /// Whether Argon2d is available, i.e. the `no-argon2d` feature is disabled.
+    pub argon2d: bool,
+    /// The implementations of the inner loop that were compiled in.
+    pub core: CoreKind,
+    /// The C library that is linked, or `LinkKind::PureRust` for the Rust implementation.
+    pub linked: LinkKind,
+    /// The version used when none is given.
+    pub version_default: Version,
+    /// `Context::MIN_SALT_LENGTH`.
+    pub min_salt_len: u32,
+    /// `Context::MIN_OUTLEN`.
+    pub min_hash_len: u32,
+    /// `Context::MAX_LANES`.
+    pub max_lanes: u32,
+    /// `Context::MAX_THREADS`.
+    pub max_threads: u32,
+}
+
+/// Returns how the crate was built. Everything is fixed at compile time; the `Display` output is
+/// a single line meant to be logged at startup or pasted into a bug report:
+///
+/// ```
+/// println!("{}", argon2::capabilities());
+/// ```
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        crate_version: env!("CARGO_PKG_VERSION"),
+        threads_enabled: THREADS_AVAILABLE,
+        argon2d: cfg!(not(feature = "no-argon2d")),
+        core: core_kind(),
+        linked: linked_implementation(),
+        version_default: Version::DEFAULT,
+        min_salt_len: Context::MIN_SALT_LENGTH,
+        min_hash_len: Context::MIN_OUTLEN,
+        max_lanes: Context::MAX_LANES,
+        max_threads: Context::MAX_THREADS,
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let backend = match self.linked {
+            LinkKind::Bundled => "bundled libargon2",
+            LinkKind::System => "system libargon2",
+            LinkKind::Prebuilt => "prebuilt libargon2",
+            LinkKind::PureRust => "pure Rust backend",
+        };
+        let core = match self.core {
+            CoreKind::Reference => "reference",
+            CoreKind::Optimized => "optimized",
+            CoreKind::System => "system",
+        };
+        write!(f,
+               "just-argon2 {}: {}, {} core, threads {}, argon2d {}, default version 0x{:02x}, \
+                salt >= {}, hash >= {}, lanes <= {}, threads <= {}",
+               self.crate_version,
+               backend,
+               core,
+               if self.threads_enabled { "enabled" } else { "disabled" },
+               if self.argon2d { "enabled" } else { "disabled" },
+               self.version_default.to_int(),
+               self.min_salt_len,
+               self.min_hash_len,
+               self.max_lanes,
+               self.max_threads)
+    }
+}
@@ -0,0 +1,196 @@
+use super::params::PhcParams;
+use super::types::{Context, Error, Variant, Version};
+use std::convert::TryFrom;
+
+/// Number of passes, checked to be within the bounds of the argon2 C library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TimeCost(u32);
+
+/// Memory cost in kibibytes, checked to be within the bounds of the argon2 C library.
+///
+/// The library also requires at least 8 KiB per lane, which depends on the `Parallelism` and is
+/// only checked when hashing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemoryCost(u32);
+
+/// Number of lanes and threads, checked to be within the bounds of the argon2 C library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Parallelism(u32);
+
+impl TimeCost {
+    /// Smallest number of passes.
+    pub const MIN: TimeCost = TimeCost(Context::MIN_TIME);
+    /// Largest number of passes.
+    pub const MAX: TimeCost = TimeCost(Context::MAX_TIME);
+
+    /// Returns `Error::InvalidParameter` for `"t_cost"` if `passes` is 0.
+    pub fn new(passes: u32) -> Result<TimeCost, Error> {
+        check_range("t_cost", passes, TimeCost::MIN.0, TimeCost::MAX.0).map(TimeCost)
+    }
+
+    /// The number of passes.
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl MemoryCost {
+    /// Smallest memory cost, 8 KiB (`ARGON2_MIN_MEMORY`).
+    pub const MIN: MemoryCost = MemoryCost(2 * Context::SYNC_POINTS);
+    /// Largest memory cost (`ARGON2_MAX_MEMORY`), 4 TiB - 1 KiB on 64 bit targets.
+    #[cfg(target_pointer_width = "64")]
+    pub const MAX: MemoryCost = MemoryCost(0xFFFFFFFF);
+    /// Largest memory cost (`ARGON2_MAX_MEMORY`), 2 GiB on 32 bit targets.
+    #[cfg(not(target_pointer_width = "64"))]
+    pub const MAX: MemoryCost = MemoryCost(1 << 21);
+
+    /// Returns `Error::InvalidParameter` for `"m_cost"` if `kib` is outside of `MIN..=MAX`.
+    pub fn kib(kib: u32) -> Result<MemoryCost, Error> {
+        check_range("m_cost", kib, MemoryCost::MIN.0, MemoryCost::MAX.0).map(MemoryCost)
+    }
+
+    /// Like `kib`, in mebibytes.
+    pub fn mib(mib: u32) -> Result<MemoryCost, Error> {
+        let kib = mib.checked_mul(1024).ok_or(Error::InvalidParameter {
+            name: "m_cost",
+            got: u64::from(mib) * 1024,
+            min: MemoryCost::MIN.0.into(),
+            max: MemoryCost::MAX.0.into(),
+        })?;
+        MemoryCost::kib(kib)
+    }
+
+    /// The memory cost in kibibytes.
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl Parallelism {
+    /// Smallest number of lanes.
+    pub const MIN: Parallelism = Parallelism(Context::MIN_LANES);
+    /// Largest number of lanes.
+    pub const MAX: Parallelism = Parallelism(Context::MAX_LANES);
+
+    /// Returns `Error::InvalidParameter` for `"p_cost"` if `lanes` is outside of `MIN..=MAX`.
+    pub fn new(lanes: u32) -> Result<Parallelism, Error> {
+        check_range("p_cost", lanes, Parallelism::MIN.0, Parallelism::MAX.0).map(Parallelism)
+    }
+
+    /// The number of lanes.
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+fn check_range(name: &'static str, got: u32, min: u32, max: u32) -> Result<u32, Error> {
+    if (min..=max).contains(&got) {
+        Ok(got)
+    } else {
+        Err(Error::InvalidParameter { name, got: got.into(), min: min.into(), max: max.into() })
+    }
+}
+
+impl TryFrom<u32> for TimeCost {
+    type Error = Error;
+
+    fn try_from(passes: u32) -> Result<TimeCost, Error> {
+        TimeCost::new(passes)
+    }
+}
+
+impl TryFrom<u32> for MemoryCost {
+    type Error = Error;
+
+    /// Like `MemoryCost::kib`.
+    fn try_from(kib: u32) -> Result<MemoryCost, Error> {
+        MemoryCost::kib(kib)
+    }
+}
+
+impl TryFrom<u32> for Parallelism {
+    type Error = Error;
+
+    fn try_from(lanes: u32) -> Result<Parallelism, Error> {
+        Parallelism::new(lanes)
+    }
+}
+
+impl From<TimeCost> for u32 {
+    fn from(cost: TimeCost) -> u32 {
+        cost.0
+    }
+}
+
+impl From<MemoryCost> for u32 {
+    fn from(cost: MemoryCost) -> u32 {
+        cost.0
+    }
+}
+
+impl From<Parallelism> for u32 {
+    fn from(cost: Parallelism) -> u32 {
+        cost.0
+    }
+}
+
+impl PhcParams {
+    /// Returns the parameters with the given costs and the default output length.
+    pub fn new(m_cost: MemoryCost, t_cost: TimeCost, p_cost: Parallelism) -> PhcParams {
+        PhcParams {
+            m_cost: m_cost.get(),
+            t_cost: t_cost.get(),
+            p_cost: p_cost.get(),
+            output_len: PhcParams::DEFAULT_OUTPUT_LEN,
+        }
+    }
+
+    /// Returns `Error::InvalidParameter` for the first of `t_cost`, `m_cost`, `p_cost` and
+    /// `output_len` that the C library would reject, including an `m_cost` below 8 KiB per lane.
+    pub fn validate(&self) -> Result<(), Error> {
+        TimeCost::new(self.t_cost)?;
+        MemoryCost::kib(self.m_cost)?;
+        let p_cost = Parallelism::new(self.p_cost)?;
+        let min_m_cost = u64::from(p_cost.get()) * u64::from(2 * Context::SYNC_POINTS);
+        if u64::from(self.m_cost) < min_m_cost {
+            return Err(Error::InvalidParameter {
+                name: "m_cost",
+                got: self.m_cost.into(),
+                min: min_m_cost,
+                max: MemoryCost::MAX.0.into(),
+            });
+        }
+        let output_len = u32::try_from(self.output_len).unwrap_or(u32::MAX);
+        check_range("output_len", output_len, Context::MIN_OUTLEN, Context::MAX_OUTLEN).map(|_| ())
+    }
+}
+
+/// Like `hash`, with costs that are already known to be in range.
+#[allow(clippy::too_many_arguments)]
+pub fn hash_with_costs(
+    t_cost: TimeCost,
+    m_cost: MemoryCost,
+    parallelism: Parallelism,
+    pwd: Option<&[u8]>,
+    salt: Option<&[u8]>,
+    hash: Option<&mut [u8]>,
+    encoded: Option<&mut [u8]>,
+    variant: Variant,
+    version: Version) -> Result<(), Error> {
+    super::hash(t_cost.get(), m_cost.get(), parallelism.get(), pwd, salt, hash, encoded, variant, version)
+}
+
+/// Hashes a password like `hash_with_costs` and returns the encoded hash without the terminating
+/// NUL byte.
+#[allow(clippy::too_many_arguments)]
+pub fn hash_encoded_with_costs(
+    t_cost: TimeCost,
+    m_cost: MemoryCost,
+    parallelism: Parallelism,
+    pwd: &[u8],
+    salt: &[u8],
+    hashlen: usize,
+    variant: Variant,
+    version: Version) -> Result<String, Error> {
+    super::hash_encoded_string(t_cost.get(), m_cost.get(), parallelism.get(), pwd, salt, hashlen, variant, version)
+}
@@ -0,0 +1,133 @@
+use super::sys;
+#[cfg(not(feature = "system"))]
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+/// The argon2 C library that the crate is linked against, or the Rust implementation that
+/// replaces it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    /// The sources bundled with the crate, compiled by the build script.
+    Bundled,
+    /// A libargon2 installed on the system, linked with the `system` feature.
+    System,
+    /// A prebuilt libargon2 from `ARGON2_LIB_DIR`, linked instead of the bundled sources or the
+    /// system library.
+    Prebuilt,
+    /// The Rust implementation of the `pure-rust` feature; no C library is linked.
+    PureRust,
+}
+
+/// Returns the argon2 C library that is in use, e.g. for bug reports.
+pub fn linked_implementation() -> LinkKind {
+    if sys::PREBUILT {
+        LinkKind::Prebuilt
+    } else if cfg!(feature = "system") {
+        LinkKind::System
+    } else if cfg!(feature = "pure-rust") {
+        LinkKind::PureRust
+    } else {
+        LinkKind::Bundled
+    }
+}
+
+/// Implementations of the C library's inner loop that were compiled into the crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreKind {
+    /// Only the reference implementation (`ref.c`), with the `ref-core` feature, without the
+    /// `opt-core` feature or on targets other than x86. The `pure-rust` feature has only its
+    /// port of it.
+    Reference,
+    /// The optimized implementations (`opt.c`) next to the reference one, selected at runtime.
+    Optimized,
+    /// The implementation of the system library (`LinkKind::System`) or of a prebuilt one
+    /// (`LinkKind::Prebuilt`), which isn't known.
+    System,
+}
+
+/// Returns the implementations that were compiled into the crate, e.g. for diagnostics.
+pub fn core_kind() -> CoreKind {
+    if cfg!(feature = "system") || sys::PREBUILT {
+        CoreKind::System
+    } else if OPT_COMPILED {
+        CoreKind::Optimized
+    } else {
+        CoreKind::Reference
+    }
+}
+
+// Whether `opt.c` is compiled in; the same condition as in the `build.rs` of `argon2-sys`.
+const OPT_COMPILED: bool = cfg!(all(
+    any(target_arch = "x86", target_arch = "x86_64"),
+    feature = "opt-core",
+    not(feature = "ref-core"),
+    not(feature = "system"),
+    not(feature = "pure-rust"),
+)) && !sys::PREBUILT;
+
+/// Implementation of the C library's inner loop that fills the memory blocks.
+///
+/// All implementations produce the same hashes; they only differ in speed. The fastest one that is
+/// compiled in (see `core_kind`) and that the CPU supports is selected before the first hash.
+///
+/// A system or prebuilt library (`LinkKind::System`, `LinkKind::Prebuilt`) uses the
+/// implementation it was built with, so selecting one has no effect on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Core {
+    /// The portable reference implementation (`ref.c`).
+    Reference = 0,
+    /// The SSE implementation (`opt.c`), x86 only.
+    Optimized = 1,
+    /// The SSE implementation compiled for AVX2, x86 only.
+    OptimizedAvx2 = 2,
+}
+
+static INIT: Once = Once::new();
+static FORCE_REFERENCE: AtomicBool = AtomicBool::new(false);
+
+/// Returns the implementation that is used for hashing.
+pub fn selected_core() -> Core {
+    init();
+    choose()
+}
+
+/// Forces the use of the reference implementation if `force` is true, or goes back to the fastest
+/// implementation otherwise. Meant for testing and for ruling out the optimized code when
+/// debugging; it affects all threads, including hashes that are already running.
+pub fn force_reference(force: bool) {
+    init();
+    FORCE_REFERENCE.store(force, Ordering::SeqCst);
+    select(choose());
+}
+
+/// Selects the fastest implementation, once. Called before every hash and verification.
+pub(crate) fn init() {
+    INIT.call_once(|| select(choose()));
+}
+
+fn choose() -> Core {
+    if FORCE_REFERENCE.load(Ordering::SeqCst) {
+        return Core::Reference;
+    }
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    if OPT_COMPILED {
+        if std::is_x86_feature_detected!("avx2") {
+            return Core::OptimizedAvx2;
+        }
+        if std::is_x86_feature_detected!("sse2") {
+            return Core::Optimized;
+        }
+    }
+    Core::Reference
+}
+
+#[cfg(feature = "system")]
+fn select(_core: Core) {}
+
+#[cfg(not(feature = "system"))]
+fn select(core: Core) {
+    // Every implementation that `choose` returns for the target is compiled in.
+    let selected = unsafe { sys::argon2_select_fill_segment(core as c_int) };
+    debug_assert_eq!(1, selected, "{:?} is not compiled in", core);
+}
@@ -0,0 +1,60 @@
+use super::memory::max_m_cost;
+use super::params::PhcParams;
+use super::types::{Context, Error, ErrorCode, Variant, Version};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Memory cost of the probe hash in kibibytes (8 MiB).
+const PROBE_M_COST: u32 = 8 * 1024;
+
+/// The memory cost and duration of the last probe hash, until `invalidate_estimate` is called.
+static PROBE: Mutex<Option<(u32, Duration)>> = Mutex::new(None);
+
+/// Estimates how long hashing with `params` takes on this machine, without spending the memory.
+///
+/// The first call hashes once with 8 MiB and a single pass (or less memory if the cap of
+/// `set_max_m_cost` is lower), which takes a few milliseconds. The estimate scales the duration of
+/// that probe linearly with `m_cost * t_cost`. The probe is kept for the rest of the process, see
+/// `invalidate_estimate`.
+///
+/// This is a rough estimate: it ignores `p_cost`, so it is too high for several lanes on several
+/// cores, and it doesn't account for larger amounts of memory being slower, e.g. because they no
+/// longer fit into the caches or have to be paged in. Expect it to be off by a factor of two.
+///
+/// Returns `ErrorCode::TimeTooSmall`, `ErrorCode::LanesTooFew` or `ErrorCode::MemoryTooLittle`
+/// for parameters the C library would reject, and the errors of `hash` if the probe fails.
+pub fn estimate_duration(params: &PhcParams) -> Result<Duration, Error> {
+    if params.t_cost < Context::MIN_TIME {
+        return Err(Error::Code(ErrorCode::TimeTooSmall));
+    }
+    if params.p_cost < Context::MIN_LANES {
+        return Err(Error::Code(ErrorCode::LanesTooFew));
+    }
+    if u64::from(params.m_cost) < 2 * u64::from(Context::SYNC_POINTS) * u64::from(params.p_cost) {
+        return Err(Error::Code(ErrorCode::MemoryTooLittle));
+    }
+    let (probe_m_cost, probe) = probe()?;
+    let scale = f64::from(params.m_cost) * f64::from(params.t_cost) / f64::from(probe_m_cost);
+    Ok(Duration::try_from_secs_f64(probe.as_secs_f64() * scale).unwrap_or(Duration::MAX))
+}
+
+/// Forgets the probe of `estimate_duration`, so that the next estimate measures again, e.g. after
+/// the load of the machine changed.
+pub fn invalidate_estimate() {
+    *PROBE.lock().unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+fn probe() -> Result<(u32, Duration), Error> {
+    // Holding the lock while probing keeps concurrent first estimates from all probing at once.
+    let mut cached = PROBE.lock().unwrap_or_else(|err| err.into_inner());
+    if let Some(probe) = *cached {
+        return Ok(probe);
+    }
+    let m_cost = max_m_cost().map_or(PROBE_M_COST, |cap| cap.min(PROBE_M_COST));
+    let mut out = [0u8; 32];
+    let start = Instant::now();
+    super::hash(1, m_cost, 1, Some(b"password"), Some(b"somesalt"), Some(&mut out), None, Variant::ID, Version::DEFAULT)?;
+    let probe = (m_cost, start.elapsed());
+    *cached = Some(probe);
+    Ok(probe)
+}
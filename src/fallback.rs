@@ -0,0 +1,135 @@
+use super::allocator::{MemoryAllocator, SystemAllocator};
+use super::encoded::EncodedHash;
+use super::params::PhcParams;
+use super::types::{checked_len_u32, secure_wipe, Context, Error, Flags, OwnedContext, Variant, Version};
+
+/// Ladder of parameters for hashing a new password when the memory of the preferred parameters
+/// can't be allocated, e.g. during a burst of concurrent hashes on a small machine.
+///
+/// The first rung holds the preferred parameters. Whenever a hash fails with
+/// `Error::AllocationFailed`, it is retried with the next rung; any other error is returned right
+/// away. `FallbackHash::rung` tells which rung was used, so that degraded hashes can be logged and
+/// rehashed with the preferred parameters at the next login.
+///
+/// There is deliberately no way to verify with a policy: verification has to use the parameters
+/// stored in the hash, and a lower memory cost would just compute a different hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryFallbackPolicy {
+    rungs: Vec<PhcParams>,
+}
+
+/// A hash computed by `MemoryFallbackPolicy::hash_new_password`.
+#[derive(Debug, Clone)]
+pub struct FallbackHash {
+    /// The encoded hash.
+    pub hash: EncodedHash,
+    /// Index of the rung whose parameters were used; 0 for the preferred parameters.
+    pub rung: usize,
+    /// The parameters that were used.
+    pub params: PhcParams,
+}
+
+impl FallbackHash {
+    /// Returns true if the hash was computed with a fallback rung instead of the preferred
+    /// parameters, and should be rehashed when possible.
+    pub fn is_degraded(&self) -> bool {
+        self.rung != 0
+    }
+}
+
+impl MemoryFallbackPolicy {
+    /// Creates a policy with only the preferred parameters, i.e. without fallback. Add rungs with
+    /// `with_rung`.
+    pub fn new(params: PhcParams) -> MemoryFallbackPolicy {
+        MemoryFallbackPolicy { rungs: vec![params] }
+    }
+
+    /// Creates a policy that halves the memory cost and doubles the time cost on each of up to
+    /// `steps` rungs below `params`, which keeps the total work roughly the same. The ladder ends
+    /// early where the memory cost would drop below the minimum of 8 KiB per lane.
+    pub fn halving(params: PhcParams, steps: u32) -> MemoryFallbackPolicy {
+        let mut policy = MemoryFallbackPolicy::new(params);
+        let mut rung = params;
+        for _ in 0..steps {
+            rung.m_cost /= 2;
+            rung.t_cost = rung.t_cost.saturating_mul(2);
+            if u64::from(rung.m_cost) < 2 * u64::from(Context::SYNC_POINTS) * u64::from(rung.p_cost) {
+                break;
+            }
+            policy.rungs.push(rung);
+        }
+        policy
+    }
+
+    /// Appends a rung that is tried after the existing ones.
+    pub fn with_rung(mut self, params: PhcParams) -> MemoryFallbackPolicy {
+        self.rungs.push(params);
+        self
+    }
+
+    /// The rungs, starting with the preferred parameters.
+    pub fn rungs(&self) -> &[PhcParams] {
+        &self.rungs
+    }
+
+    /// Hashes a new password with the first rung whose memory can be allocated, with memory from
+    /// `SystemAllocator`. Returns the `Error::AllocationFailed` of the last rung if none can.
+    pub fn hash_new_password(
+        &self,
+        pwd: &[u8],
+        salt: &[u8],
+        variant: Variant,
+        version: Version) -> Result<FallbackHash, Error> {
+        self.hash_new_password_with_allocator(pwd, salt, variant, version, &SystemAllocator)
+    }
+
+    /// Like `hash_new_password`, but the memory of every attempt is allocated from `allocator`.
+    ///
+    /// Returns `Error::InvalidParameter` for `"output_len"` before hashing if the output length of
+    /// any rung is larger than `Context::MAX_OUTLEN`.
+    pub fn hash_new_password_with_allocator(
+        &self,
+        pwd: &[u8],
+        salt: &[u8],
+        variant: Variant,
+        version: Version,
+        allocator: &dyn MemoryAllocator) -> Result<FallbackHash, Error> {
+        for params in &self.rungs {
+            checked_len_u32("output_len", params.output_len, Context::MAX_OUTLEN)?;
+        }
+        let mut last_err = None;
+        for (rung, params) in self.rungs.iter().enumerate() {
+            let mut context = OwnedContext {
+                out: vec![0u8; params.output_len],
+                pwd: Some(pwd.to_vec()),
+                salt: Some(salt.to_vec()),
+                secret: None,
+                ad: None,
+                t_cost: params.t_cost,
+                m_cost: params.m_cost,
+                lanes: params.p_cost,
+                threads: params.p_cost,
+                version,
+                flags: Flags::DEFAULT,
+            };
+            let result = super::ctx_with_allocator(&mut context, variant, allocator);
+            if let Some(pwd) = &mut context.pwd {
+                secure_wipe(pwd);
+            }
+            match result {
+                Ok(()) => {
+                    let hash = EncodedHash::new(variant, version, params.m_cost, params.t_cost, params.p_cost,
+                                                salt.to_vec(), std::mem::take(&mut context.out));
+                    return Ok(FallbackHash { hash, rung, params: *params });
+                }
+                Err(err @ Error::AllocationFailed { .. }) => last_err = Some(err),
+                Err(err) => return Err(err),
+            }
+        }
+        // Every constructor adds the preferred rung, so the loop ran at least once and either
+        // returned or set `last_err`.
+        #[allow(clippy::expect_used)]
+        let err = last_err.expect("MemoryFallbackPolicy without rungs");
+        Err(err)
+    }
+}
@@ -0,0 +1,48 @@
+//! The functions of the crate root under the names of `argon2.h`, as a porting aid.
+//!
+//! The crate root drops the `argon2` prefix (`argon2i_hash_encoded` is `i_hash_encoded`,
+//! `argon2_ctx` is `ctx`). When C code is translated line by line, these re-exports keep the
+//! original names:
+//!
+//! ```
+//! use argon2::ffi_names::{argon2_verify, argon2i_hash_encoded};
+//! use argon2::Variant;
+//!
+//! let mut encoded = [0u8; 128];
+//! argon2i_hash_encoded(2, 256, 1, Some(b"password"), Some(b"somesalt"), 32, &mut encoded)?;
+//! argon2_verify(argon2::c_str(&encoded)?, Some(b"password"), Variant::I)?;
+//! # Ok::<(), argon2::Error>(())
+//! ```
+//!
+//! They are the same functions, with the same Rust signatures: slices instead of pointer and
+//! length, `Result` instead of an error code, and `Variant` and `Version` instead of integers.
+//! Without a counterpart in `argon2.h`, the other functions of the crate root have no alias.
+
+pub use super::{
+    ctx as argon2_ctx,
+    encodedlen as argon2_encodedlen,
+    error_message as argon2_error_message,
+    hash as argon2_hash,
+    i_ctx as argon2i_ctx,
+    i_hash_encoded as argon2i_hash_encoded,
+    i_hash_raw as argon2i_hash_raw,
+    i_verify as argon2i_verify,
+    i_verify_ctx as argon2i_verify_ctx,
+    id_ctx as argon2id_ctx,
+    id_hash_encoded as argon2id_hash_encoded,
+    id_hash_raw as argon2id_hash_raw,
+    id_verify as argon2id_verify,
+    id_verify_ctx as argon2id_verify_ctx,
+    type2string as argon2_type2string,
+    verify as argon2_verify,
+    verify_ctx as argon2_verify_ctx,
+};
+
+#[cfg(not(feature = "no-argon2d"))]
+pub use super::{
+    d_ctx as argon2d_ctx,
+    d_hash_encoded as argon2d_hash_encoded,
+    d_hash_raw as argon2d_hash_raw,
+    d_verify as argon2d_verify,
+    d_verify_ctx as argon2d_verify_ctx,
+};
@@ -0,0 +1,97 @@
+//! Salt sources and parameters that must not be used for real passwords.
+//!
+//! With the `random-salt` feature, `FixedSaltSource` gives the same salt every time, so that tests
+//! can compare the encoded hashes of a `PhcHasher` against fixed strings. Password hashes with a
+//! fixed salt can be attacked all at once, which is why it is only reachable through this module:
+//!
+#![cfg_attr(feature = "random-salt", doc = "```compile_fail")]
+#![cfg_attr(not(feature = "random-salt"), doc = "```ignore")]
+//! use argon2::FixedSaltSource;
+//! ```
+//!
+//! and can only be created with `FixedSaltSource::new`:
+//!
+#![cfg_attr(feature = "random-salt", doc = "```")]
+#![cfg_attr(not(feature = "random-salt"), doc = "```ignore")]
+//! use argon2::insecure::FixedSaltSource;
+//!
+//! let source = FixedSaltSource::new(b"somesaltsomesalt".to_vec());
+//! ```
+//!
+#![cfg_attr(feature = "random-salt", doc = "```compile_fail")]
+#![cfg_attr(not(feature = "random-salt"), doc = "```ignore")]
+//! let source = argon2::insecure::FixedSaltSource(b"somesaltsomesalt".to_vec());
+//! ```
+//!
+//! With the `test-util` feature, `insecure_test_params` gives parameters that make hashing nearly
+//! free, for the fixtures of application test suites that create many users. They only run in
+//! builds with debug assertions, unless `ALLOW_TEST_PARAMS_VAR` is set to `1`.
+
+#[cfg(feature = "test-util")]
+use super::params::PhcParams;
+#[cfg(feature = "random-salt")]
+use super::salt::SaltSource;
+#[cfg(feature = "random-salt")]
+use super::types::Error;
+#[cfg(feature = "test-util")]
+use std::ffi::{OsStr, OsString};
+
+/// Environment variable that allows `insecure_test_params` in builds without debug assertions,
+/// if it is set to `1`.
+#[cfg(feature = "test-util")]
+pub const ALLOW_TEST_PARAMS_VAR: &str = "ARGON2_ALLOW_INSECURE_TEST_PARAMS";
+
+/// Returns parameters for tests only: 8 KiB of memory, a single pass and a single lane, with a 32
+/// byte hash. Hashing with them takes microseconds instead of the tens of milliseconds of the
+/// defaults, and the hashes verify like any other.
+///
+/// **These parameters offer no protection at all.** A leaked hash computed with them can be
+/// brute-forced about as fast as an unsalted fast hash. Never use them outside of tests, and never
+/// store hashes computed with them where real passwords are stored.
+///
+/// # Panics
+///
+/// Panics in builds without debug assertions (e.g. `--release`), unless the environment variable
+/// `ALLOW_TEST_PARAMS_VAR` is set to `1`, so that a test helper that leaks into production code
+/// fails loudly instead of weakening every password.
+#[cfg(feature = "test-util")]
+pub fn insecure_test_params() -> PhcParams {
+    check_test_mode(cfg!(debug_assertions), std::env::var_os(ALLOW_TEST_PARAMS_VAR));
+    PhcParams { m_cost: 8, t_cost: 1, p_cost: 1, output_len: PhcParams::DEFAULT_OUTPUT_LEN }
+}
+
+/// The guard of `insecure_test_params`, with the build configuration and the environment
+/// variable passed in.
+#[cfg(feature = "test-util")]
+pub(crate) fn check_test_mode(debug_assertions: bool, allow: Option<OsString>) {
+    if !debug_assertions && allow.as_deref() != Some(OsStr::new("1")) {
+        panic!("argon2::insecure::insecure_test_params is for tests only and refuses to run without \
+                debug assertions; set {}=1 to allow it", ALLOW_TEST_PARAMS_VAR);
+    }
+}
+
+/// Fills every salt with the same bytes.
+#[cfg(feature = "random-salt")]
+#[derive(Debug, Clone)]
+pub struct FixedSaltSource(Vec<u8>);
+
+#[cfg(feature = "random-salt")]
+impl FixedSaltSource {
+    /// Creates a source that always gives `salt`.
+    pub fn new(salt: impl Into<Vec<u8>>) -> FixedSaltSource {
+        FixedSaltSource(salt.into())
+    }
+}
+
+#[cfg(feature = "random-salt")]
+impl SaltSource for FixedSaltSource {
+    /// Returns `Error::InvalidParameter` for `"salt.len"` if `buf` isn't as long as the salt.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        let len = self.0.len() as u64;
+        if buf.len() != self.0.len() {
+            return Err(Error::InvalidParameter { name: "salt.len", got: buf.len() as u64, min: len, max: len });
+        }
+        buf.copy_from_slice(&self.0);
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use super::params::PhcParams;
+use super::types::{secure_wipe, Context, Error, Flags, Variant, Version};
+use std::convert::TryFrom;
+
+/// Argon2 KDF parameters of a KeePass KDBX4 database, as stored in its KDF parameter dictionary.
+///
+/// KDBX4 identifies the variant by the UUID of the KDF (`$UUID`) and stores the memory in bytes
+/// (`M`), the iterations (`I`), the parallelism (`P`), the version (`V`) and a 32 byte salt (`S`).
+/// The optional secret key (`K`) and associated data (`A`) are passed on to Argon2 as
+/// `Context::secret` and `Context::ad`. Only Argon2d and Argon2id are defined; the memory has to be
+/// a whole number of kibibytes. With the `no-argon2d` feature, only Argon2id is supported.
+#[derive(Debug, Clone)]
+pub struct KdbxKdfParams {
+    /// `Variant::D` or `Variant::ID`.
+    pub variant: Variant,
+    /// Version of the algorithm (`V`).
+    pub version: Version,
+    /// Memory in bytes (`M`).
+    pub memory: u64,
+    /// Number of passes (`I`).
+    pub iterations: u64,
+    /// Number of lanes (`P`).
+    pub parallelism: u32,
+    /// Salt (`S`).
+    pub salt: [u8; 32],
+    /// Secret key (`K`), if the dictionary has one.
+    pub secret: Option<Vec<u8>>,
+    /// Associated data (`A`), if the dictionary has one.
+    pub associated_data: Option<Vec<u8>>,
+}
+
+impl KdbxKdfParams {
+    /// UUID of the Argon2d KDF, `ef636ddf-8c29-444b-91f7-a9a403e30a0c`.
+    pub const ARGON2D_UUID: [u8; 16] = [
+        0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4, 0x03, 0xe3, 0x0a, 0x0c,
+    ];
+
+    /// UUID of the Argon2id KDF, `9e298b19-56db-4773-b23d-fc3ec6f0a1e6`.
+    pub const ARGON2ID_UUID: [u8; 16] = [
+        0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e, 0xc6, 0xf0, 0xa1, 0xe6,
+    ];
+
+    /// Creates KDF parameters from the costs used by the hash functions of this crate; `m_cost`
+    /// is in kibibytes. There is no secret key or associated data. Returns
+    /// `Error::BadParam("variant")` for `Variant::I`.
+    pub fn new(
+        variant: Variant,
+        version: Version,
+        m_cost: u32,
+        t_cost: u32,
+        parallelism: u32,
+        salt: [u8; 32]) -> Result<KdbxKdfParams, Error> {
+        uuid(variant)?;
+        Ok(KdbxKdfParams {
+            variant,
+            version,
+            memory: u64::from(m_cost) * 1024,
+            iterations: t_cost.into(),
+            parallelism,
+            salt,
+            secret: None,
+            associated_data: None,
+        })
+    }
+
+    /// Creates KDF parameters from the raw fields of the dictionary. `secret` and
+    /// `associated_data` are the `K` and `A` fields, `None` if the dictionary doesn't have them.
+    ///
+    /// Returns `Error::BadParam` for `"uuid"` if the UUID isn't one of the Argon2 KDFs, for
+    /// `"version"` if the version is unknown and for `"salt"` if the salt isn't 32 bytes long.
+    /// Returns `Error::UnknownAlgorithm` for the Argon2d KDF with the `no-argon2d` feature.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_fields(
+        uuid: &[u8; 16],
+        memory: u64,
+        iterations: u64,
+        parallelism: u32,
+        version: u32,
+        salt: &[u8],
+        secret: Option<&[u8]>,
+        associated_data: Option<&[u8]>) -> Result<KdbxKdfParams, Error> {
+        let variant = match *uuid {
+            #[cfg(not(feature = "no-argon2d"))]
+            KdbxKdfParams::ARGON2D_UUID => Variant::D,
+            #[cfg(feature = "no-argon2d")]
+            KdbxKdfParams::ARGON2D_UUID => return Err(Error::UnknownAlgorithm("argon2d")),
+            KdbxKdfParams::ARGON2ID_UUID => Variant::ID,
+            _ => return Err(Error::BadParam("uuid")),
+        };
+        Ok(KdbxKdfParams {
+            variant,
+            version: Version::from_int(version).ok_or(Error::BadParam("version"))?,
+            memory,
+            iterations,
+            parallelism,
+            salt: <[u8; 32]>::try_from(salt).map_err(|_| Error::BadParam("salt"))?,
+            secret: secret.map(<[u8]>::to_vec),
+            associated_data: associated_data.map(<[u8]>::to_vec),
+        })
+    }
+
+    /// UUID of the KDF. Returns `Error::BadParam("variant")` for `Variant::I`.
+    pub fn uuid(&self) -> Result<[u8; 16], Error> {
+        uuid(self.variant)
+    }
+
+    /// Memory in kibibytes, as `m_cost` of the hash functions.
+    ///
+    /// Returns `Error::BadParam("memory")` if the memory isn't a whole number of kibibytes and
+    /// `Error::InvalidParameter` if it doesn't fit a `u32` in kibibytes.
+    pub fn m_cost(&self) -> Result<u32, Error> {
+        if !self.memory.is_multiple_of(1024) {
+            return Err(Error::BadParam("memory"));
+        }
+        u32::try_from(self.memory / 1024).map_err(|_| Error::InvalidParameter {
+            name: "memory",
+            got: self.memory,
+            min: 0,
+            max: u64::from(u32::MAX) * 1024,
+        })
+    }
+
+    /// Iterations as `t_cost` of the hash functions. Returns `Error::InvalidParameter` if they
+    /// don't fit a `u32`.
+    pub fn t_cost(&self) -> Result<u32, Error> {
+        u32::try_from(self.iterations).map_err(|_| Error::InvalidParameter {
+            name: "iterations",
+            got: self.iterations,
+            min: 0,
+            max: u32::MAX.into(),
+        })
+    }
+}
+
+impl TryFrom<&KdbxKdfParams> for PhcParams {
+    type Error = Error;
+
+    /// Converts the costs. The output length is 32 bytes, the length of the key KDBX derives.
+    fn try_from(params: &KdbxKdfParams) -> Result<PhcParams, Error> {
+        Ok(PhcParams {
+            m_cost: params.m_cost()?,
+            t_cost: params.t_cost()?,
+            p_cost: params.parallelism,
+            output_len: 32,
+        })
+    }
+}
+
+fn uuid(variant: Variant) -> Result<[u8; 16], Error> {
+    match variant {
+        #[cfg(not(feature = "no-argon2d"))]
+        Variant::D => Ok(KdbxKdfParams::ARGON2D_UUID),
+        Variant::ID => Ok(KdbxKdfParams::ARGON2ID_UUID),
+        Variant::I => Err(Error::BadParam("variant")),
+    }
+}
+
+/// Derives the 32 byte transformed key of a KDBX4 database from its composite key, the SHA-256
+/// hash of the key components, like KeePass does with an Argon2 KDF. The secret key and the
+/// associated data of `params` are used if present.
+///
+/// The parameters are validated as by `KdbxKdfParams::m_cost`, `KdbxKdfParams::t_cost` and
+/// `KdbxKdfParams::uuid`; the C library validates the rest. The copies of the composite key and the
+/// secret key are wiped before returning.
+pub fn derive_composite_key(params: &KdbxKdfParams, key_material: &[u8]) -> Result<[u8; 32], Error> {
+    uuid(params.variant)?;
+    let (t_cost, m_cost) = (params.t_cost()?, params.m_cost()?);
+    // `Context` takes mutable buffers, which the C library only writes to with `Flags::CLEAR_*`.
+    let mut pwd = key_material.to_vec();
+    let mut salt = params.salt;
+    let mut secret = params.secret.clone();
+    let mut ad = params.associated_data.clone();
+    let mut key = [0u8; 32];
+    let result = super::ctx(&mut Context {
+        out: &mut key,
+        pwd: Some(&mut pwd),
+        salt: Some(&mut salt),
+        secret: secret.as_deref_mut(),
+        ad: ad.as_deref_mut(),
+        t_cost,
+        m_cost,
+        lanes: params.parallelism,
+        threads: params.parallelism,
+        version: params.version,
+        flags: Flags::DEFAULT,
+    }, params.variant);
+    secure_wipe(&mut pwd);
+    if let Some(secret) = secret.as_mut() {
+        secure_wipe(secret);
+    }
+    result.map(|()| key)
+}
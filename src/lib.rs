@@ -2,62 +2,330 @@
 //! All public argon2 functions are mapped to functions with the `argon2` prefix
 //! and any leftover underscores after the prefix removed.
 //! e.g. `argon2_ctx` -> `ctx` and `argon2i_ctx` -> `i_ctx`
+//!
+//! None of the functions panic on data returned by the C library, failures are reported as an
+//! `Error` instead.
+//! When a hash function fails, the output buffers passed to it are zeroed before the error is
+//! returned, so that no partial hash is left behind.
+//!
+//! # Quick start
+//!
+//! With the `random-salt` feature, `hash_password` and `verify_password` are the recommended
+//! entry points: they hash with Argon2id, the current version, the default `PhcParams` and a
+//! random salt, and verify any encoded Argon2 hash, without any decisions to make.
+//!
+#![cfg_attr(feature = "random-salt", doc = "```")]
+#![cfg_attr(not(feature = "random-salt"), doc = "```ignore")]
+//! let encoded = argon2::hash_password("hunter2")?;
+//! assert!(argon2::verify_password(&encoded, "hunter2")?);
+//! assert!(!argon2::verify_password(&encoded, "hunter3")?);
+//! # Ok::<(), argon2::Error>(())
+//! ```
+//!
+//! The `ffi_names` module re-exports the functions under the names of `argon2.h`, for porting C
+//! code line by line.
+//!
+//! # Supported targets
+//!
+//! Targets with 32 and 64 bit pointers are supported. The C library takes the lengths of the
+//! inputs to the `hash` functions as `size_t` and stores them as `uint32_t` in the context; lengths
+//! that don't fit are rejected instead of being truncated. On 32 bit targets every slice length
+//! fits into a `uint32_t`, so only the limits of the C library apply.
+//!
+//! On wasm targets, the C library is built without threads (`ARGON2_NO_THREADS`). Hashing and
+//! verifying with more than one thread fails with `Error::ThreadsUnavailable` there. On Windows,
+//! including the MSVC targets, the lanes run on Win32 threads like on other platforms.
+//! `capabilities().threads_enabled` tells which applies to a build. With the `wasm-bindgen`
+//! feature, the `wasm` module exports `hash_encoded` and `verify` to JavaScript.
+//!
+//! Android and iOS are built like other Unix targets, with threads. For Android the build script
+//! uses the compiler of the NDK in `ANDROID_NDK_HOME`, unless `CC_<target>` is set; for iOS the
+//! `cc` crate finds the Xcode toolchain. Apps there are killed when they use too much memory,
+//! `MOBILE_MAX_M_COST` is a memory cost that is safe to use.
+//!
+//! # Thread safety
+//!
+//! Every function can be called from any number of threads at once. The C library keeps no global
+//! mutable state: each call works on its own context and memory, and the worker threads it spawns
+//! for `parallelism > 1` only touch the memory of that call. The only process-wide state of this
+//! crate is the cap of `set_max_m_cost`, which can be set once.
+//!
+//! `OwnedContext`, `Password`, `Error` and the long-lived helpers (`MemoryArena`,
+//! `InstrumentedAllocator`, `SecureAllocator`, `VerifyCache` and `MemoryBudget`) are
+//! `Send + Sync`, so a single instance can be shared between threads, e.g. in an `Arc`.
+//!
+//! # Self test
+//!
+//! `self_test` hashes a few of the reference test vectors and reports the first one that doesn't
+//! give the expected hash. It takes a few milliseconds and little memory, so deployments that need
+//! a power-on self test of their cryptography can call it once at process startup, before hashing
+//! anything:
+//!
+//! ```
+//! if let Err(err) = argon2::self_test() {
+//!     eprintln!("{}", err);
+//!     std::process::exit(1);
+//! }
+//! ```
+//!
+//! # Tracing
+//!
+//! With the `tracing` feature, the hash and verify functions run in `argon2.hash` and
+//! `argon2.verify` spans that carry the variant, version, t_cost, m_cost and parallelism, and the
+//! outcome once they return. Failures are additionally recorded as an event with the C error code.
+//! Passwords, salts, secrets and hashes are never recorded.
+//!
+//! # Auditing
+//!
+//! `audit::verify_audited` verifies like `verify_bytes` and passes an `AuditEvent` with the
+//! outcome, the parameters of the stored hash and a correlation ID of the caller to the hook
+//! installed with `audit::set_audit_hook`, for an audit trail of verification attempts.
+//!
+//! # Metrics
+//!
+//! With the `metrics` feature, `metrics::set_metrics_sink` installs a `MetricsSink` that is told
+//! the outcome, duration and costs of every hash and verification, e.g. to export them to
+//! Prometheus without wrapping every call site.
+//!
+//! # password-hash
+//!
+//! With the `password-hash` feature, `PhcHasher` implements the `PasswordHasher` and
+//! `PasswordVerifier` traits of the `password-hash` crate, so this crate can stand in for the pure
+//! Rust `argon2` crate. The PHC strings of both crates are interchangeable.
+//!
+//! The `interop-rustcrypto` feature adds conversions between `PhcParams`, `Variant` and `Version`
+//! and `argon2::Params`, `argon2::Algorithm` and `argon2::Version` of the pure Rust crate.
+//!
+//! The `cross-check` feature (which needs both) recomputes hashes with the pure Rust crate and
+//! fails with `Error::CrossCheckMismatch` if the two implementations disagree: for every hash of a
+//! `PhcHasher` created `with_cross_check(true)`, and for any hash passed to `cross_check`. Every
+//! hash and verification then costs twice the time, and the memory is allocated twice in a row.
+//!
+//! With the `random-salt` feature, `PhcHasher::hash_new_password` hashes with a new salt from a
+//! `SaltSource`, by default the operating system's random number generator. Tests can swap in
+//! `insecure::FixedSaltSource` with `with_salt_source` to get the same encoded hash on every run.
+//! Applications that have to take all randomness from their own `rand_core::CryptoRngCore`
+//! enable the `rand-core` feature and pass it to `PhcHasher::with_rng`, or generate salts for the
+//! other functions with `generate_salt_with_rng`.
+//!
+//! The `blake2b` feature adds the `blake2` module, which exposes the BLAKE2b implementation that
+//! the C library bundles (or its port, with `pure-rust`): keyed BLAKE2b and Argon2's
+//! variable-length hash function H'. It also adds `hash_raw_from_reader` and
+//! `verify_from_reader`, which hash an input from a `Read` (e.g. a large key file) by pre-hashing
+//! it with BLAKE2b in chunks, with a construction that is fixed across releases.
+//!
+//! The `server_relief` module moves the cost of hashing to clients: they send an expensive
+//! pre-hash of the password, and the server stores and checks a cheap hash of it.
+//!
+//! # Fast hashing in tests
+//!
+//! The `test-util` feature is meant for the `[dev-dependencies]` of applications whose tests
+//! create many users. `insecure::insecure_test_params` returns parameters that make hashing
+//! nearly free (8 KiB, one pass, one lane), and with `password-hash`,
+//! `PhcHasher::insecure_for_tests` hashes with them. The hashes verify normally. Both panic in
+//! builds without debug assertions unless `ARGON2_ALLOW_INSECURE_TEST_PARAMS=1` is set, so that
+//! they can't weaken real passwords by accident.
+//!
+//! # System library
+//!
+//! With the `system` feature, the crate links against the libargon2 installed on the system instead
+//! of compiling the bundled sources, so that it receives the updates of the system's packages. The
+//! build script of `argon2-sys` finds it with pkg-config, or in `ARGON2_LIB_DIR` with the header in
+//! `ARGON2_INCLUDE_DIR` if those are set, and fails if the header doesn't declare Argon2id.
+//! `linked_implementation` tells which library is in use.
+//!
+//! Setting `ARGON2_LIB_DIR` also works without the feature, e.g. to link a libargon2 that a build
+//! farm compiled with its own hardening flags instead of the bundled sources. The library is
+//! linked statically if `ARGON2_STATIC=1` and dynamically otherwise; `ARGON2_INCLUDE_DIR` defaults
+//! to the bundled header. Both with and without `system`, `linked_implementation` then reports
+//! `LinkKind::Prebuilt`. The `blake2b` feature needs a static library, since the shared one hides
+//! the BLAKE2b functions. The `pure-rust` feature ignores these variables.
+//!
+//! # Pure Rust backend
+//!
+//! The `pure-rust` feature replaces the C library with a Rust implementation of its functions in
+//! `argon2-sys`, for targets without a C compiler. The API stays the same, with the same hashes
+//! and the same errors; `linked_implementation` and `capabilities` report `LinkKind::PureRust`.
+//! Lanes are computed one after the other on the calling thread, so more threads don't make it
+//! faster, and there is only the reference core. It can't be combined with `system`.
+//!
+//! # Core implementations
+//!
+//! On x86, the `opt-core` feature (enabled by default) builds the SSE and AVX2 implementations of
+//! the C library's core, and the fastest one the CPU supports is selected at runtime. The
+//! `ref-core` feature builds only the portable reference implementation instead. All of them give
+//! the same hashes; `core_kind` tells which were built.
+//!
+//! # Compiling out Argon2d
+//!
+//! Argon2d is vulnerable to side-channel attacks. The `no-argon2d` feature removes it from the API:
+//! `Variant::D`, `d_ctx`, `d_hash_encoded`, `d_hash_raw`, `d_verify` and `d_verify_ctx` don't
+//! exist, and `EncodedHash::parse` and `KdbxKdfParams::from_fields` reject Argon2d with
+//! `Error::UnknownAlgorithm`. The raw bindings in `sys` are unaffected.
+//!
+#![cfg_attr(feature = "no-argon2d", doc = "```compile_fail")]
+#![cfg_attr(not(feature = "no-argon2d"), doc = "```")]
+//! let variant = argon2::Variant::D;
+//! ```
+#![deny(clippy::unwrap_used, clippy::expect_used)]
 
-#[allow(bad_style, dead_code)]
-mod sys;
 mod types;
+mod allocator;
+pub mod audit;
+mod memory;
+#[cfg(feature = "cache")]
+mod cache;
+mod nonblocking;
+mod fallback;
+mod trace;
+mod dispatch;
+mod estimate;
+mod capabilities;
+mod costs;
+mod encoded;
+mod kdbx;
+mod params;
+mod pinned;
+pub mod raw_record;
+pub mod server_relief;
+pub mod ffi_names;
+#[cfg(feature = "password-hash")]
+mod phc;
+#[cfg(any(feature = "random-salt", feature = "rand-core"))]
+mod salt;
+#[cfg(feature = "random-salt")]
+mod simple;
+#[cfg(any(feature = "random-salt", feature = "test-util"))]
+pub mod insecure;
+#[cfg(feature = "interop-rustcrypto")]
+mod rustcrypto;
+#[cfg(any(feature = "sqlx", feature = "diesel"))]
+mod sql;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "blake2b")]
+pub mod blake2;
+#[cfg(feature = "blake2b")]
+mod reader;
+#[cfg(feature = "wasm-bindgen")]
+pub mod wasm;
+#[cfg(feature = "test-vectors")]
+pub mod test_vectors;
+// `self_test` uses some of the vectors.
+#[cfg(not(feature = "test-vectors"))]
+#[allow(dead_code)]
+mod test_vectors;
+mod selftest;
+
+#[cfg(all(feature = "blake2b", feature = "system"))]
+compile_error!("The `blake2b` feature needs the bundled C library, the system's libargon2 doesn't export BLAKE2b.");
 
-use std::convert::TryInto;
 use std::ffi::CStr;
-use types::{opt_slice_ptr_mut, opt_slice_len, opt_slice_ptr};
+use std::time::{Duration, Instant};
+use memory::{check_encoded_m_cost_cap, check_m_cost_cap};
+use types::{check, check_alloc, check_encoded_threads, check_hash_len, check_threads, opt_slice_ptr_mut, opt_slice_len, opt_slice_ptr};
 
+/// The raw bindings to the C library, from the `argon2-sys` crate (package `just-argon2-sys`).
+pub use argon2_sys as sys;
 pub use self::types::*;
+pub use self::allocator::{AllocStats, InstrumentedAllocator, MemoryAllocator, MemoryArena, SystemAllocator};
+pub use self::capabilities::{capabilities, Capabilities};
+pub use self::costs::{hash_encoded_with_costs, hash_with_costs, MemoryCost, Parallelism, TimeCost};
+pub use self::dispatch::{core_kind, force_reference, linked_implementation, selected_core, Core, CoreKind, LinkKind};
+pub use self::encoded::{EncodedHash, ParseOptions};
+pub use self::fallback::{FallbackHash, MemoryFallbackPolicy};
+pub use self::estimate::{estimate_duration, invalidate_estimate};
+pub use self::kdbx::{derive_composite_key, KdbxKdfParams};
+pub use self::params::PhcParams;
+pub use self::pinned::{verify_pinned, PinnedConfig};
+pub use self::raw_record::verify_raw_record;
+pub use self::selftest::{self_test, SelfTestError, SelfTestFailure};
+#[cfg(feature = "cache")]
+pub use self::cache::VerifyCache;
+pub use self::nonblocking::{hash_encoded_on, hash_encoded_with_timeout, hash_raw_on, verify_on, BlockingExecutor, BoxFuture, StdThreadExecutor};
+#[cfg(feature = "password-hash")]
+pub use self::phc::{decode_salt, PhcHasher};
+#[cfg(feature = "cross-check")]
+pub use self::rustcrypto::cross_check;
+#[cfg(feature = "random-salt")]
+pub use self::salt::{OsSaltSource, SaltSource};
+#[cfg(feature = "random-salt")]
+pub use self::simple::{hash_password, verify_password};
+#[cfg(feature = "rand-core")]
+pub use self::salt::generate_salt_with_rng;
+#[cfg(all(feature = "random-salt", feature = "rand-core"))]
+pub use self::salt::RngSaltSource;
+#[cfg(feature = "blake2b")]
+pub use self::reader::{hash_raw_from_reader, verify_from_reader, READER_PREHASH_V1};
+#[cfg(feature = "tokio")]
+pub use self::nonblocking::{hash_encoded_async, hash_raw_async, verify_async, TokioExecutor};
+pub use self::memory::{
+    available_memory, available_parallelism, check_memory_against_system, max_m_cost, set_max_m_cost, MemoryBudget, MemoryPermit, MOBILE_MAX_M_COST,
+    PLATFORM_MAX_M_COST,
+};
+#[cfg(all(feature = "mlock", any(unix, windows)))]
+pub use self::allocator::{
+    AdviceFailure, HugePagePolicy, LockFailure, MemoryOptions, MemoryProtection, SecureAllocator,
+};
+
+// Lengths are widened from `u32` to `usize` and from `usize` to `u64` without checks.
+const _: () = assert!(usize::BITS >= 32 && usize::BITS <= 64, "Unsupported pointer width");
 
 /// Function that gives the string representation of an argon2 Variant.
 /// If the `uppercase` parameter is true, the name of the variant is returned with the first letter
 /// uppercased.
-pub fn type2string(variant: Variant, uppercase: bool) -> &'static str {
+///
+/// Returns `Error::NullPointer` or `Error::InvalidUtf8` if the C library doesn't return a valid
+/// name.
+pub fn type2string(variant: Variant, uppercase: bool) -> Result<&'static str, Error> {
     unsafe {
         let uppercase_i = if uppercase { 1 } else { 0 };
         let str_ptr = sys::argon2_type2string(variant.to_c(), uppercase_i);
-        assert!(!str_ptr.is_null(), "null variant name.");
+        if str_ptr.is_null() {
+            return Err(Error::NullPointer("argon2_type2string"));
+        }
         let str_cstr = CStr::from_ptr(str_ptr);
-        str_cstr.to_str().expect("Variant name is not valid UTF-8")
+        str_cstr.to_str().map_err(Error::InvalidUtf8)
     }
 }
 
 /// Function that performs memory-hard hashing with certain degree of parallelism.
-pub fn ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(context: C, variant: Variant) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(sys::argon2_ctx(&mut context.try_into()?, variant.to_c()) as _)
-    }
+pub fn ctx<C: AsContext>(context: &mut C, variant: Variant) -> Result<(), Error> {
+    let mut context = context.try_to_c()?;
+    run_hash(variant, context.version, context.t_cost, context.m_cost, context.lanes, || unsafe {
+        check_alloc(sys::argon2_ctx(&mut context, variant.to_c()), context.m_cost, context.lanes)
+    })
 }
 
 /// Argon2d: Version of Argon2 that picks memory blocks depending on the password and salt. Only
 /// for side-channel-free environment!!
-pub fn d_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(context: C) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(sys::argon2d_ctx(&mut context.try_into()?))
-    }
+#[cfg(not(feature = "no-argon2d"))]
+pub fn d_ctx<C: AsContext>(context: &mut C) -> Result<(), Error> {
+    let mut context = context.try_to_c()?;
+    run_hash(Variant::D, context.version, context.t_cost, context.m_cost, context.lanes, || unsafe {
+        check_alloc(sys::argon2d_ctx(&mut context), context.m_cost, context.lanes)
+    })
 }
 
 /// Argon2i: Version of Argon2 that picks memory blocks
 /// independent on the password and salt. Good for side-channels,
 /// but worse with respect to tradeoff attacks if only one pass is used.
-pub fn i_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(context: C) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(sys::argon2i_ctx(&mut context.try_into()?))
-    }
+pub fn i_ctx<C: AsContext>(context: &mut C) -> Result<(), Error> {
+    let mut context = context.try_to_c()?;
+    run_hash(Variant::I, context.version, context.t_cost, context.m_cost, context.lanes, || unsafe {
+        check_alloc(sys::argon2i_ctx(&mut context), context.m_cost, context.lanes)
+    })
 }
 
 /// Argon2id: Version of Argon2 where the first half-pass over memory is
 /// password-independent, the rest are password-dependent (on the password and
 /// salt). OK against side channels (they reduce to 1/2-pass Argon2i), and
 /// better with respect to tradeoff attacks (similar to Argon2d).
-pub fn id_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(context: C) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(sys::argon2id_ctx(&mut context.try_into()?))
-    }
+pub fn id_ctx<C: AsContext>(context: &mut C) -> Result<(), Error> {
+    let mut context = context.try_to_c()?;
+    run_hash(Variant::ID, context.version, context.t_cost, context.m_cost, context.lanes, || unsafe {
+        check_alloc(sys::argon2id_ctx(&mut context), context.m_cost, context.lanes)
+    })
 }
 
 /// Hashes a password with Argon2i, producing an encoded (string) hash.
@@ -74,6 +342,7 @@ pub fn id_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(context: C)
 /// # Notes
 ///
 /// - The different parallelism levels will give different results.
+/// - The hash is computed with `Version::DEFAULT`; use `hash` to select another version.
 pub fn i_hash_encoded(
     t_cost: u32,
     m_cost: u32,
@@ -82,20 +351,27 @@ pub fn i_hash_encoded(
     salt: Option<&[u8]>,
     hashlen: usize,
     encoded: &mut [u8]) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
-            sys::argon2i_hash_encoded(
-                t_cost, m_cost, parallelism,
-                opt_slice_ptr(&pwd) as _,
-                opt_slice_len(&pwd),
-                opt_slice_ptr(&salt) as _,
-                opt_slice_len(&salt),
-                hashlen,
-                encoded.as_mut_ptr() as _,
-                encoded.len(),
+    run_hash(Variant::I, Version::DEFAULT.to_int(), t_cost, m_cost, parallelism, || {
+        let result = check_m_cost_cap(m_cost)
+            .and_then(|()| check_threads(parallelism))
+            .and_then(|()| check_encoded_len(t_cost, m_cost, parallelism, opt_slice_len(&salt), hashlen, Variant::I, encoded.len()));
+        let result = result.and_then(|()| unsafe {
+            check_alloc(
+                sys::argon2i_hash_encoded(
+                    t_cost, m_cost, parallelism,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                    opt_slice_ptr(&salt) as _,
+                    opt_slice_len(&salt),
+                    hashlen,
+                    encoded.as_mut_ptr() as _,
+                    encoded.len(),
+                ),
+                m_cost, parallelism,
             )
-        )
-    }
+        });
+        wipe_on_err(result, encoded)
+    })
 }
 
 /// Hashes a password with Argon2i, producing a raw hash.
@@ -111,6 +387,7 @@ pub fn i_hash_encoded(
 /// # Notes
 ///
 /// - The different parallelism levels will give different results.
+/// - The hash is computed with `Version::DEFAULT`; use `hash` to select another version.
 pub fn i_hash_raw(
     t_cost: u32,
     m_cost: u32,
@@ -118,19 +395,23 @@ pub fn i_hash_raw(
     pwd: Option<&[u8]>,
     salt: Option<&[u8]>,
     hash: &mut [u8]) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
-            sys::argon2i_hash_raw(
-                t_cost, m_cost, parallelism,
-                opt_slice_ptr(&pwd) as _,
-                opt_slice_len(&pwd),
-                opt_slice_ptr(&salt) as _,
-                opt_slice_len(&salt),
-                hash.as_mut_ptr() as _,
-                hash.len(),
+    run_hash(Variant::I, Version::DEFAULT.to_int(), t_cost, m_cost, parallelism, || {
+        let result = check_m_cost_cap(m_cost).and_then(|()| check_threads(parallelism)).and_then(|()| unsafe {
+            check_alloc(
+                sys::argon2i_hash_raw(
+                    t_cost, m_cost, parallelism,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                    opt_slice_ptr(&salt) as _,
+                    opt_slice_len(&salt),
+                    hash.as_mut_ptr() as _,
+                    hash.len(),
+                ),
+                m_cost, parallelism,
             )
-        )
-    }
+        });
+        wipe_on_err(result, hash)
+    })
 }
 
 /// Hashes a password with Argon2d, producing an encoded (string) hash.
@@ -147,6 +428,8 @@ pub fn i_hash_raw(
 /// # Notes
 ///
 /// - The different parallelism levels will give different results.
+/// - The hash is computed with `Version::DEFAULT`; use `hash` to select another version.
+#[cfg(not(feature = "no-argon2d"))]
 pub fn d_hash_encoded(
     t_cost: u32,
     m_cost: u32,
@@ -155,20 +438,27 @@ pub fn d_hash_encoded(
     salt: Option<&[u8]>,
     hashlen: usize,
     encoded: &mut [u8]) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
-            sys::argon2d_hash_encoded(
-                t_cost, m_cost, parallelism,
-                opt_slice_ptr(&pwd) as _,
-                opt_slice_len(&pwd),
-                opt_slice_ptr(&salt) as _,
-                opt_slice_len(&salt),
-                hashlen,
-                encoded.as_mut_ptr() as _,
-                encoded.len(),
+    run_hash(Variant::D, Version::DEFAULT.to_int(), t_cost, m_cost, parallelism, || {
+        let result = check_m_cost_cap(m_cost)
+            .and_then(|()| check_threads(parallelism))
+            .and_then(|()| check_encoded_len(t_cost, m_cost, parallelism, opt_slice_len(&salt), hashlen, Variant::D, encoded.len()));
+        let result = result.and_then(|()| unsafe {
+            check_alloc(
+                sys::argon2d_hash_encoded(
+                    t_cost, m_cost, parallelism,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                    opt_slice_ptr(&salt) as _,
+                    opt_slice_len(&salt),
+                    hashlen,
+                    encoded.as_mut_ptr() as _,
+                    encoded.len(),
+                ),
+                m_cost, parallelism,
             )
-        )
-    }
+        });
+        wipe_on_err(result, encoded)
+    })
 }
 
 /// Hashes a password with Argon2d, producing a raw hash.
@@ -184,6 +474,8 @@ pub fn d_hash_encoded(
 /// # Notes
 ///
 /// - The different parallelism levels will give different results.
+/// - The hash is computed with `Version::DEFAULT`; use `hash` to select another version.
+#[cfg(not(feature = "no-argon2d"))]
 pub fn d_hash_raw(
     t_cost: u32,
     m_cost: u32,
@@ -191,19 +483,23 @@ pub fn d_hash_raw(
     pwd: Option<&[u8]>,
     salt: Option<&[u8]>,
     hash: &mut [u8]) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
-            sys::argon2d_hash_raw(
-                t_cost, m_cost, parallelism,
-                opt_slice_ptr(&pwd) as _,
-                opt_slice_len(&pwd),
-                opt_slice_ptr(&salt) as _,
-                opt_slice_len(&salt),
-                hash.as_mut_ptr() as _,
-                hash.len(),
+    run_hash(Variant::D, Version::DEFAULT.to_int(), t_cost, m_cost, parallelism, || {
+        let result = check_m_cost_cap(m_cost).and_then(|()| check_threads(parallelism)).and_then(|()| unsafe {
+            check_alloc(
+                sys::argon2d_hash_raw(
+                    t_cost, m_cost, parallelism,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                    opt_slice_ptr(&salt) as _,
+                    opt_slice_len(&salt),
+                    hash.as_mut_ptr() as _,
+                    hash.len(),
+                ),
+                m_cost, parallelism,
             )
-        )
-    }
+        });
+        wipe_on_err(result, hash)
+    })
 }
 
 /// Hashes a password with Argon2id, producing an encoded (string) hash.
@@ -220,6 +516,7 @@ pub fn d_hash_raw(
 /// # Notes
 ///
 /// - The different parallelism levels will give different results.
+/// - The hash is computed with `Version::DEFAULT`; use `hash` to select another version.
 pub fn id_hash_encoded(
     t_cost: u32,
     m_cost: u32,
@@ -228,20 +525,27 @@ pub fn id_hash_encoded(
     salt: Option<&[u8]>,
     hashlen: usize,
     encoded: &mut [u8]) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
-            sys::argon2id_hash_encoded(
-                t_cost, m_cost, parallelism,
-                opt_slice_ptr(&pwd) as _,
-                opt_slice_len(&pwd),
-                opt_slice_ptr(&salt) as _,
-                opt_slice_len(&salt),
-                hashlen,
-                encoded.as_mut_ptr() as _,
-                encoded.len(),
+    run_hash(Variant::ID, Version::DEFAULT.to_int(), t_cost, m_cost, parallelism, || {
+        let result = check_m_cost_cap(m_cost)
+            .and_then(|()| check_threads(parallelism))
+            .and_then(|()| check_encoded_len(t_cost, m_cost, parallelism, opt_slice_len(&salt), hashlen, Variant::ID, encoded.len()));
+        let result = result.and_then(|()| unsafe {
+            check_alloc(
+                sys::argon2id_hash_encoded(
+                    t_cost, m_cost, parallelism,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                    opt_slice_ptr(&salt) as _,
+                    opt_slice_len(&salt),
+                    hashlen,
+                    encoded.as_mut_ptr() as _,
+                    encoded.len(),
+                ),
+                m_cost, parallelism,
             )
-        )
-    }
+        });
+        wipe_on_err(result, encoded)
+    })
 }
 
 /// Hashes a password with Argon2id, producing a raw hash.
@@ -257,6 +561,7 @@ pub fn id_hash_encoded(
 /// # Notes
 ///
 /// - The different parallelism levels will give different results.
+/// - The hash is computed with `Version::DEFAULT`; use `hash` to select another version.
 pub fn id_hash_raw(
     t_cost: u32,
     m_cost: u32,
@@ -264,19 +569,23 @@ pub fn id_hash_raw(
     pwd: Option<&[u8]>,
     salt: Option<&[u8]>,
     hash: &mut [u8]) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
-            sys::argon2id_hash_raw(
-                t_cost, m_cost, parallelism,
-                opt_slice_ptr(&pwd) as _,
-                opt_slice_len(&pwd),
-                opt_slice_ptr(&salt) as _,
-                opt_slice_len(&salt),
-                hash.as_mut_ptr() as _,
-                hash.len(),
+    run_hash(Variant::ID, Version::DEFAULT.to_int(), t_cost, m_cost, parallelism, || {
+        let result = check_m_cost_cap(m_cost).and_then(|()| check_threads(parallelism)).and_then(|()| unsafe {
+            check_alloc(
+                sys::argon2id_hash_raw(
+                    t_cost, m_cost, parallelism,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                    opt_slice_ptr(&salt) as _,
+                    opt_slice_len(&salt),
+                    hash.as_mut_ptr() as _,
+                    hash.len(),
+                ),
+                m_cost, parallelism,
             )
-        )
-    }
+        });
+        wipe_on_err(result, hash)
+    })
 }
 
 /// Generic Argon2 hash function.
@@ -295,6 +604,10 @@ pub fn id_hash_raw(
 /// # Notes
 ///
 /// - The different parallelism levels will give different results.
+/// - If both `hash` and `encoded` are `None`, `Error::BadParam("hash/encoded")` is returned
+///   without calling into the C library.
+/// - The length of `hash` determines the length of the hash that is encoded, so passing only
+///   `encoded` fails with `ErrorCode::OutputTooShort`.
 pub fn hash(
     t_cost: u32,
     m_cost: u32,
@@ -305,22 +618,176 @@ pub fn hash(
     mut encoded: Option<&mut [u8]>,
     variant: Variant,
     version: Version) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
-            sys::argon2_hash(
-                t_cost, m_cost, parallelism,
-                opt_slice_ptr(&pwd) as _,
-                opt_slice_len(&pwd),
-                opt_slice_ptr(&salt) as _,
-                opt_slice_len(&salt),
-                opt_slice_ptr_mut(&mut hash) as _,
-                opt_slice_len(&hash),
-                opt_slice_ptr_mut(&mut encoded) as _,
-                opt_slice_len(&encoded),
-                variant.to_c() as _,
-                version.to_c() as _,
+    run_hash(variant, version.to_int(), t_cost, m_cost, parallelism, || {
+        if hash.is_none() && encoded.is_none() {
+            return Err(Error::BadParam("hash/encoded"));
+        }
+        let result = check_m_cost_cap(m_cost).and_then(|()| check_threads(parallelism)).and_then(|()| match (&hash, &encoded) {
+            (Some(hash), Some(encoded)) => {
+                check_encoded_len(t_cost, m_cost, parallelism, opt_slice_len(&salt), hash.len(), variant, encoded.len())
+            }
+            _ => Ok(()),
+        });
+        let result = result.and_then(|()| unsafe {
+            check_alloc(
+                sys::argon2_hash(
+                    t_cost, m_cost, parallelism,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                    opt_slice_ptr(&salt) as _,
+                    opt_slice_len(&salt),
+                    opt_slice_ptr_mut(&mut hash) as _,
+                    opt_slice_len(&hash),
+                    opt_slice_ptr_mut(&mut encoded) as _,
+                    opt_slice_len(&encoded),
+                    variant.to_c(),
+                    version.to_int(),
+                ),
+                m_cost, parallelism,
             )
-        )
+        });
+        if result.is_err() {
+            for buf in hash.into_iter().chain(encoded) {
+                secure_wipe(buf);
+            }
+        }
+        result
+    })
+}
+
+/// Hashes a password like `hash` and returns the encoded hash without the terminating NUL byte.
+#[allow(clippy::too_many_arguments)]
+fn hash_encoded_string(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    pwd: &[u8],
+    salt: &[u8],
+    hashlen: usize,
+    variant: Variant,
+    version: Version) -> Result<String, Error> {
+    hash_encoded_timed(t_cost, m_cost, parallelism, pwd, salt, hashlen, variant, version).map(|(encoded, _)| encoded)
+}
+
+/// Hashes a password like `hash` and returns the encoded hash without the terminating NUL byte,
+/// together with the time spent in the C library.
+///
+/// The duration only covers the call to the C library, not the allocation of the buffers or the
+/// conversion to a `String`, which makes it suitable for monitoring how long hashing takes.
+#[allow(clippy::too_many_arguments)]
+pub fn hash_encoded_timed(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    pwd: &[u8],
+    salt: &[u8],
+    hashlen: usize,
+    variant: Variant,
+    version: Version) -> Result<(String, Duration), Error> {
+    let saltlen = types::checked_len_u32("salt.len", salt.len(), Context::MAX_SALT_LENGTH)?;
+    let outlen = types::checked_len_u32("hashlen", hashlen, Context::MAX_OUTLEN)?;
+    let encodedlen = encoded_len_checked(t_cost, m_cost, parallelism, saltlen, outlen, variant)
+        .ok_or(Error::BadParam("hashlen"))?;
+    let mut hash = vec![0u8; hashlen];
+    let mut encoded = vec![0u8; encodedlen.get()];
+    let start = Instant::now();
+    let result = self::hash(t_cost, m_cost, parallelism, Some(pwd), Some(salt), Some(&mut hash), Some(&mut encoded),
+                            variant, version);
+    let elapsed = start.elapsed();
+    secure_wipe(&mut hash);
+    result?;
+
+    let len = c_str(&encoded)?.to_bytes().len();
+    encoded.truncate(len);
+    let encoded = String::from_utf8(encoded).map_err(|err| Error::InvalidUtf8(err.utf8_error()))?;
+    Ok((encoded, elapsed))
+}
+
+/// Hashes a password like `hash_encoded_timed`, but returns the encoded hash in a `String` that is
+/// wiped when it is dropped.
+///
+/// The `String` is the buffer the C library wrote into, so no unwiped copy is left behind.
+#[cfg(feature = "zeroize")]
+#[allow(clippy::too_many_arguments)]
+pub fn hash_encoded_zeroizing(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    pwd: &[u8],
+    salt: &[u8],
+    hashlen: usize,
+    variant: Variant,
+    version: Version) -> Result<zeroize::Zeroizing<String>, Error> {
+    hash_encoded_timed(t_cost, m_cost, parallelism, pwd, salt, hashlen, variant, version)
+        .map(|(encoded, _)| zeroize::Zeroizing::new(encoded))
+}
+
+/// Hashes every `(pwd, salt)` pair of `items` like `hash` and returns the encoded hashes in the
+/// order of `items`.
+///
+/// The items are hashed on a dedicated rayon thread pool with `max_concurrent` threads, so at most
+/// `max_concurrent * m_cost` KiB are used for hashing at any time. `max_concurrent` of 0 uses one
+/// thread per CPU. Returns `Error::TaskFailed` if the thread pool can't be created.
+#[cfg(feature = "rayon")]
+#[allow(clippy::too_many_arguments)]
+pub fn batch_hash_encoded<I, P, S>(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    hashlen: usize,
+    variant: Variant,
+    version: Version,
+    max_concurrent: usize,
+    items: I) -> Result<Vec<Result<String, Error>>, Error>
+where
+    I: rayon::iter::IntoParallelIterator<Item = (P, S)> + Send,
+    P: AsRef<[u8]>,
+    S: AsRef<[u8]>,
+{
+    use rayon::iter::ParallelIterator;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(max_concurrent)
+        .build()
+        .map_err(|err| Error::TaskFailed(std::io::Error::other(err)))?;
+    Ok(pool.install(|| {
+        items.into_par_iter()
+            .map(|(pwd, salt)| {
+                hash_encoded_string(t_cost, m_cost, parallelism, pwd.as_ref(), salt.as_ref(), hashlen, variant, version)
+            })
+            .collect()
+    }))
+}
+
+/// Wipes `buf` if `result` is an error, so that no partial output is left behind.
+fn wipe_on_err(result: Result<(), Error>, buf: &mut [u8]) -> Result<(), Error> {
+    if result.is_err() {
+        secure_wipe(buf);
+    }
+    result
+}
+
+/// Returns `Error::BufferTooSmall` if a buffer of `provided` bytes can't hold the encoded hash for
+/// the given parameters. Lengths that don't fit the C API are left for the C library to reject.
+fn check_encoded_len(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    saltlen: usize,
+    hashlen: usize,
+    variant: Variant,
+    provided: usize) -> Result<(), Error> {
+    use std::convert::TryFrom;
+
+    let (saltlen, hashlen) = match (u32::try_from(saltlen), u32::try_from(hashlen)) {
+        (Ok(saltlen), Ok(hashlen)) => (saltlen, hashlen),
+        _ => return Ok(()),
+    };
+    let required = encodedlen(t_cost, m_cost, parallelism, saltlen, hashlen, variant);
+    if provided < required {
+        Err(Error::BufferTooSmall { required, provided })
+    } else {
+        Ok(())
     }
 }
 
@@ -330,15 +797,20 @@ pub fn hash(
 /// - `encoded`: String encoding parameters, salt, hash.
 /// - `pwd`: Slice containing password.
 pub fn i_verify(encoded: &CStr, pwd: Option<&[u8]>) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
-            sys::argon2i_verify(
-                encoded.as_ptr() as _,
-                opt_slice_ptr(&pwd) as _,
-                opt_slice_len(&pwd),
+    run_verify_encoded(Variant::I, encoded, || {
+        check_encoded_m_cost_cap(encoded)?;
+        check_encoded_threads(encoded)?;
+
+        unsafe {
+            check(
+                sys::argon2i_verify(
+                    encoded.as_ptr() as _,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                )
             )
-        )
-    }
+        }
+    })
 }
 
 /// Verifies a password against an encoded string using Argon2d.
@@ -346,16 +818,22 @@ pub fn i_verify(encoded: &CStr, pwd: Option<&[u8]>) -> Result<(), Error> {
 /// # Parameters
 /// - `encoded`: String encoding parameters, salt, hash.
 /// - `pwd`: Slice containing password.
+#[cfg(not(feature = "no-argon2d"))]
 pub fn d_verify(encoded: &CStr, pwd: Option<&[u8]>) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
-            sys::argon2d_verify(
-                encoded.as_ptr() as _,
-                opt_slice_ptr(&pwd) as _,
-                opt_slice_len(&pwd),
+    run_verify_encoded(Variant::D, encoded, || {
+        check_encoded_m_cost_cap(encoded)?;
+        check_encoded_threads(encoded)?;
+
+        unsafe {
+            check(
+                sys::argon2d_verify(
+                    encoded.as_ptr() as _,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                )
             )
-        )
-    }
+        }
+    })
 }
 
 /// Verifies a password against an encoded string using Argon2id.
@@ -364,15 +842,20 @@ pub fn d_verify(encoded: &CStr, pwd: Option<&[u8]>) -> Result<(), Error> {
 /// - `encoded`: String encoding parameters, salt, hash.
 /// - `pwd`: Slice containing password.
 pub fn id_verify(encoded: &CStr, pwd: Option<&[u8]>) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
-            sys::argon2id_verify(
-                encoded.as_ptr() as _,
-                opt_slice_ptr(&pwd) as _,
-                opt_slice_len(&pwd),
+    run_verify_encoded(Variant::ID, encoded, || {
+        check_encoded_m_cost_cap(encoded)?;
+        check_encoded_threads(encoded)?;
+
+        unsafe {
+            check(
+                sys::argon2id_verify(
+                    encoded.as_ptr() as _,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                )
             )
-        )
-    }
+        }
+    })
 }
 
 /// Verifies a password against an encoded string.
@@ -381,16 +864,129 @@ pub fn id_verify(encoded: &CStr, pwd: Option<&[u8]>) -> Result<(), Error> {
 /// - `encoded`: String encoding parameters, salt, hash.
 /// - `pwd`: Slice containing password.
 pub fn verify(encoded: &CStr, pwd: Option<&[u8]>, variant: Variant) -> Result<(), Error> {
-    unsafe {
-        Error::check_code(
+    run_verify_encoded(variant, encoded, || {
+        check_encoded_m_cost_cap(encoded)?;
+        check_encoded_threads(encoded)?;
+
+        unsafe {
+            check(
+                sys::argon2_verify(
+                    encoded.as_ptr() as _,
+                    opt_slice_ptr(&pwd) as _,
+                    opt_slice_len(&pwd),
+                    variant.to_c(),
+                )
+            )
+        }
+    })
+}
+
+/// Encoded hashes without a null byte that are shorter than this are copied to the stack by
+/// `verify_bytes`.
+const ENCODED_STACK_LEN: usize = 512;
+
+/// Verifies a password against an encoded string given as bytes. Behaves like
+/// `verify(&c_str_cow(encoded), pwd, variant)`, but avoids copying the encoded string to the heap.
+///
+/// Like `c_str_cow`, everything after the first null byte is ignored. If there is no null byte,
+/// the encoded string is copied to a buffer on the stack to add one. Only encoded strings of
+/// 512 bytes or more, which need salts or hashes of hundreds of bytes, are copied to the heap.
+/// With the `zeroize` feature, both copies are wiped afterwards.
+pub fn verify_bytes(encoded: &[u8], pwd: Option<&[u8]>, variant: Variant) -> Result<(), Error> {
+    match c_str(encoded) {
+        Ok(encoded) => verify(encoded, pwd, variant),
+        Err(Error::MissingNul) if encoded.len() < ENCODED_STACK_LEN => {
+            let mut buf = [0u8; ENCODED_STACK_LEN];
+            buf[..encoded.len()].copy_from_slice(encoded);
+            let result = verify(c_str(&buf)?, pwd, variant);
+            #[cfg(feature = "zeroize")]
+            zeroize::Zeroize::zeroize(&mut buf);
+            result
+        }
+        #[cfg(feature = "zeroize")]
+        Err(Error::MissingNul) => verify(&c_string_zeroizing(encoded)?, pwd, variant),
+        #[cfg(not(feature = "zeroize"))]
+        Err(Error::MissingNul) => verify(&try_c_str_cow(encoded)?, pwd, variant),
+        Err(err) => Err(err),
+    }
+}
+
+/// Verifies a password against several encoded hashes, e.g. the old and the new hash of a user
+/// during a migration to a new pepper or new parameters. The variant of each hash is taken from
+/// its encoding, so the hashes may use different variants.
+///
+/// The hashes are tried in order. Returns the index of the first one that matches, or `None` if
+/// none does. Malformed hashes are skipped, unless all of them are malformed, which returns
+/// `Error::AllMalformed` with the error of every hash. Any other error, like
+/// `Error::ExceedsMemoryCap`, is returned as soon as it occurs.
+pub fn verify_any_of(encoded: &[&str], pwd: Option<&[u8]>) -> Result<Option<usize>, Error> {
+    let mut malformed = Vec::new();
+    for (i, entry) in encoded.iter().enumerate() {
+        let result = EncodedHash::parse(entry).and_then(|hash| verify_bytes(entry.as_bytes(), pwd, hash.variant()));
+        match result {
+            Ok(()) => return Ok(Some(i)),
+            Err(err) if err.is_verify_mismatch() => {}
+            Err(err) if err.is_decoding_error() || matches!(err, Error::UnknownAlgorithm(_)) => malformed.push(err),
+            Err(err) => return Err(err),
+        }
+    }
+    if !malformed.is_empty() && malformed.len() == encoded.len() {
+        return Err(Error::AllMalformed(malformed));
+    }
+    Ok(None)
+}
+
+/// Verifies a password against an encoded string like `verify` and returns the time spent in the
+/// C library along with the result.
+///
+/// The duration is reported for a mismatch as well as for a successful verification. A mismatch
+/// is an error (`ErrorCode::VerifyMismatch`), so the duration is returned next to the result
+/// instead of as `Result<Duration, Error>`, which could only time successful verifications. Failed
+/// logins are the ones that monitoring usually needs. The duration is zero if the encoded string
+/// was rejected before calling into the C library.
+pub fn verify_timed(encoded: &CStr, pwd: Option<&[u8]>, variant: Variant) -> (Result<(), Error>, Duration) {
+    let mut elapsed = Duration::ZERO;
+    let result = run_verify_encoded(variant, encoded, || {
+        check_encoded_m_cost_cap(encoded)?;
+        check_encoded_threads(encoded)?;
+
+        let start = Instant::now();
+        let code = unsafe {
             sys::argon2_verify(
                 encoded.as_ptr() as _,
                 opt_slice_ptr(&pwd) as _,
                 opt_slice_len(&pwd),
-                variant.to_c() as _,
+                variant.to_c(),
             )
-        )
+        };
+        elapsed = start.elapsed();
+        check(code)
+    });
+    (result, elapsed)
+}
+
+/// Verifies a password against an encoded hash and pads the time taken to at least `floor`, for
+/// every outcome: a match, a mismatch and any error, e.g. a malformed hash or one that exceeds the
+/// cap of `set_max_m_cost`. The variant is taken from the hash.
+///
+/// Errors that are detected before hashing return much faster than a verification, which tells
+/// an attacker that a stored hash is malformed or of an unexpected kind. Set `floor` to at least
+/// the usual duration of a verification, e.g. from `estimate_duration`, to hide that.
+///
+/// This is jitter mitigation, not a hard guarantee: the sleep only ends at or after `floor`, it
+/// may overshoot depending on the scheduler, and verifications that take longer than `floor` are
+/// not padded at all.
+pub fn verify_constant_duration(encoded: &str, pwd: Option<&[u8]>, floor: Duration) -> Result<bool, Error> {
+    let start = Instant::now();
+    let result = match EncodedHash::parse(encoded).and_then(|hash| verify_bytes(encoded.as_bytes(), pwd, hash.variant())) {
+        Ok(()) => Ok(true),
+        Err(err) if err.is_verify_mismatch() => Ok(false),
+        Err(err) => Err(err),
+    };
+    if let Some(remaining) = floor.checked_sub(start.elapsed()) {
+        std::thread::sleep(remaining);
     }
+    result
 }
 
 /// Verify if a given password is correct for Argon2d hashing.
@@ -400,21 +996,21 @@ pub fn verify(encoded: &CStr, pwd: Option<&[u8]>, variant: Variant) -> Result<()
 /// - `context`: The current Argon2 context.
 /// - `hash`: The password hash to verify. The length of the hash must match the length of the out
 /// parameter in context.
-pub fn d_verify_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(context: C, hash: &[u8]) -> Result<(), Error> {
+#[cfg(not(feature = "no-argon2d"))]
+pub fn d_verify_ctx<C: AsContext>(context: &mut C, hash: &[u8]) -> Result<(), Error> {
 
-    let mut argon_context = context.try_into()?;
-    if hash.len() as u32 != argon_context.outlen {
-        return Err(Error::BadParam("hash.len"))
-    }
+    let mut argon_context = context.try_to_c()?;
+    check_hash_len(hash.len(), argon_context.outlen)?;
 
-    unsafe {
-        Error::check_code(
+    run_verify(Variant::D, argon_context.version, argon_context.t_cost, argon_context.m_cost, argon_context.lanes, || unsafe {
+        check_alloc(
             sys::argon2d_verify_ctx(
                 &mut argon_context,
                 hash.as_ptr() as _,
-            )
+            ),
+            argon_context.m_cost, argon_context.lanes,
         )
-    }
+    })
 }
 
 /// Verify if a given password is correct for Argon2i hashing.
@@ -424,21 +1020,20 @@ pub fn d_verify_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(contex
 /// - `context`: The current Argon2 context.
 /// - `hash`: The password hash to verify. The length of the hash must match the length of the out
 /// parameter in context.
-pub fn i_verify_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(context: C, hash: &[u8]) -> Result<(), Error> {
+pub fn i_verify_ctx<C: AsContext>(context: &mut C, hash: &[u8]) -> Result<(), Error> {
 
-    let mut argon_context = context.try_into()?;
-    if hash.len() as u32 != argon_context.outlen {
-        return Err(Error::BadParam("hash.len"))
-    }
+    let mut argon_context = context.try_to_c()?;
+    check_hash_len(hash.len(), argon_context.outlen)?;
 
-    unsafe {
-        Error::check_code(
+    run_verify(Variant::I, argon_context.version, argon_context.t_cost, argon_context.m_cost, argon_context.lanes, || unsafe {
+        check_alloc(
             sys::argon2i_verify_ctx(
                 &mut argon_context,
                 hash.as_ptr() as _,
-            )
+            ),
+            argon_context.m_cost, argon_context.lanes,
         )
-    }
+    })
 }
 
 /// Verify if a given password is correct for Argon2id hashing.
@@ -448,21 +1043,20 @@ pub fn i_verify_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(contex
 /// - `context`: The current Argon2 context.
 /// - `hash`: The password hash to verify. The length of the hash must match the length of the out
 /// parameter in context.
-pub fn id_verify_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(context: C, hash: &[u8]) -> Result<(), Error> {
+pub fn id_verify_ctx<C: AsContext>(context: &mut C, hash: &[u8]) -> Result<(), Error> {
 
-    let mut argon_context = context.try_into()?;
-    if hash.len() as u32 != argon_context.outlen {
-        return Err(Error::BadParam("hash.len"))
-    }
+    let mut argon_context = context.try_to_c()?;
+    check_hash_len(hash.len(), argon_context.outlen)?;
 
-    unsafe {
-        Error::check_code(
+    run_verify(Variant::ID, argon_context.version, argon_context.t_cost, argon_context.m_cost, argon_context.lanes, || unsafe {
+        check_alloc(
             sys::argon2id_verify_ctx(
                 &mut argon_context,
                 hash.as_ptr() as _,
-            )
+            ),
+            argon_context.m_cost, argon_context.lanes,
         )
-    }
+    })
 }
 
 /// Verify if a given password is correct for a given variant of Argon2 hashing.
@@ -472,37 +1066,238 @@ pub fn id_verify_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(conte
 /// - `context`: The current Argon2 context.
 /// - `hash`: The password hash to verify. The length of the hash must match the length of the out
 /// parameter in context.
-pub fn verify_ctx<C: TryInto<sys::Argon2_Context, Error = self::Error>>(context: C, hash: &[u8], variant: Variant) -> Result<(), Error> {
+pub fn verify_ctx<C: AsContext>(context: &mut C, hash: &[u8], variant: Variant) -> Result<(), Error> {
 
-    let mut argon_context = context.try_into()?;
-    if hash.len() as u32 != argon_context.outlen {
-        return Err(Error::BadParam("hash.len"))
-    }
+    let mut argon_context = context.try_to_c()?;
+    check_hash_len(hash.len(), argon_context.outlen)?;
 
-    unsafe {
-        Error::check_code(
+    run_verify(variant, argon_context.version, argon_context.t_cost, argon_context.m_cost, argon_context.lanes, || unsafe {
+        check_alloc(
             sys::argon2_verify_ctx(
                 &mut argon_context,
                 hash.as_ptr() as _,
-                variant.to_c() as _,
-            )
+                variant.to_c(),
+            ),
+            argon_context.m_cost, argon_context.lanes,
         )
+    })
+}
+
+/// Like `ctx`, but the memory used for hashing is allocated through `allocator`.
+pub fn ctx_with_allocator<C: AsContext>(
+    context: &mut C,
+    variant: Variant,
+    allocator: &dyn MemoryAllocator,
+) -> Result<(), Error> {
+    ctx_with_options(context, variant, Some(allocator), ThreadFallbackPolicy::Fail).map(|_| ())
+}
+
+/// Like `verify_ctx`, but the memory used for hashing is allocated through `allocator`.
+pub fn verify_ctx_with_allocator<C: AsContext>(
+    context: &mut C,
+    hash: &[u8],
+    variant: Variant,
+    allocator: &dyn MemoryAllocator,
+) -> Result<(), Error> {
+    let mut argon_context = context.try_to_c()?;
+    check_hash_len(hash.len(), argon_context.outlen)?;
+    allocator::install(&mut argon_context);
+
+    run_verify(variant, argon_context.version, argon_context.t_cost, argon_context.m_cost, argon_context.lanes, || {
+        allocator::with_allocator(allocator, || unsafe {
+            check_alloc(
+                sys::argon2_verify_ctx(
+                    &mut argon_context,
+                    hash.as_ptr() as _,
+                    variant.to_c(),
+                ),
+                argon_context.m_cost, argon_context.lanes,
+            )
+        })
+    })
+}
+
+/// Like `ctx`, but if spawning threads fails and `policy` is `ThreadFallbackPolicy::SingleThreaded`,
+/// hashes again with a single thread instead of returning `Error::ThreadFailed`.
+///
+/// Returns `true` if the hash was computed by the fallback. Only the number of threads is changed,
+/// never the number of lanes, so the resulting hash doesn't depend on whether the fallback was
+/// taken.
+///
+/// The C library wipes the password and the secret for `Flags::CLEAR_PASSWORD` and
+/// `Flags::CLEAR_SECRET` before it spawns its threads, so with the fallback policy they are copied
+/// first and put back for the second attempt, which wipes them again. The copies are wiped when
+/// hashing is done.
+pub fn ctx_with_thread_fallback<C: AsContext>(
+    context: &mut C,
+    variant: Variant,
+    policy: ThreadFallbackPolicy,
+) -> Result<bool, Error> {
+    ctx_with_options(context, variant, None, policy)
+}
+
+/// Runs `argon2_ctx` with the memory allocated through `allocator`, if any, and with
+/// `ctx_with_thread_fallback` if spawning threads fails. Returns `true` if the fallback was taken.
+pub(crate) fn ctx_with_options<C: AsContext>(
+    context: &mut C,
+    variant: Variant,
+    allocator: Option<&dyn MemoryAllocator>,
+    policy: ThreadFallbackPolicy,
+) -> Result<bool, Error> {
+    let mut context = context.try_to_c()?;
+    if allocator.is_some() {
+        allocator::install(&mut context);
+    }
+    let cleared = match policy {
+        ThreadFallbackPolicy::SingleThreaded if context.threads > 1 => Some(unsafe { ClearedInputs::save(&context) }),
+        _ => None,
+    };
+    let attempt = |context: &mut sys::Argon2_Context| {
+        let mut call = || unsafe { check_alloc(argon2_ctx(context, variant), context.m_cost, context.lanes) };
+        match allocator {
+            Some(allocator) => allocator::with_allocator(allocator, call),
+            None => call(),
+        }
+    };
+    run_hash(variant, context.version, context.t_cost, context.m_cost, context.lanes, || {
+        match (attempt(&mut context), &cleared) {
+            (Err(Error::ThreadFailed(_)), Some(cleared)) => {
+                unsafe { cleared.restore(&mut context) };
+                context.threads = 1;
+                attempt(&mut context).map(|()| true)
+            }
+            (result, _) => result.map(|()| false),
+        }
+    })
+}
+
+/// Copies of the inputs that the C library wipes for `Flags::CLEAR_PASSWORD` and
+/// `Flags::CLEAR_SECRET`, to put them back for another attempt. Wiped when dropped.
+struct ClearedInputs {
+    pwd: Option<Password>,
+    secret: Option<Password>,
+}
+
+impl ClearedInputs {
+    /// The pointers of `context` must be valid for their lengths.
+    unsafe fn save(context: &sys::Argon2_Context) -> ClearedInputs {
+        let copy = |ptr: *const u8, len: u32, flag: Flags| {
+            if context.flags & flag.bits() != 0 && !ptr.is_null() {
+                Some(Password::new(std::slice::from_raw_parts(ptr, len as usize)))
+            } else {
+                None
+            }
+        };
+        ClearedInputs {
+            pwd: copy(context.pwd, context.pwdlen, Flags::CLEAR_PASSWORD),
+            secret: copy(context.secret, context.secretlen, Flags::CLEAR_SECRET),
+        }
+    }
+
+    /// `context` must be the one the copies were made from.
+    unsafe fn restore(&self, context: &mut sys::Argon2_Context) {
+        if let Some(pwd) = &self.pwd {
+            std::ptr::copy_nonoverlapping(pwd.as_ptr(), context.pwd, pwd.len());
+            context.pwdlen = pwd.len() as u32;
+        }
+        if let Some(secret) = &self.secret {
+            std::ptr::copy_nonoverlapping(secret.as_ptr(), context.secret, secret.len());
+            context.secretlen = secret.len() as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Makes `argon2_ctx` fail with `ARGON2_THREAD_FAIL` for multithreaded contexts.
+    static FAIL_THREADS: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+unsafe fn argon2_ctx(context: &mut sys::Argon2_Context, variant: Variant) -> std::os::raw::c_int {
+    #[cfg(test)]
+    {
+        if context.threads > 1 && FAIL_THREADS.with(|fail| fail.get()) {
+            // Let the C library get as far as it would before spawning threads, so that it wipes
+            // the inputs for `Flags::CLEAR_*` like in a real failure, but leave no hash behind.
+            let threads = std::mem::replace(&mut context.threads, 1);
+            sys::argon2_ctx(context, variant.to_c());
+            context.threads = threads;
+            std::ptr::write_bytes(context.out, 0, context.outlen as usize);
+            return sys::Argon2_ErrorCodes_ARGON2_THREAD_FAIL;
+        }
+    }
+    sys::argon2_ctx(context, variant.to_c())
+}
+
+/// Runs a hash function in the span of `trace::hash`, with the `fill_segment` implementation
+/// selected. Every hash goes through this or one of the `run_verify*` functions before calling
+/// into the C library.
+#[inline]
+fn run_hash<T, F>(variant: Variant, version: u32, t_cost: u32, m_cost: u32, parallelism: u32, f: F)
+    -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    dispatch::init();
+    trace::hash(variant, version, t_cost, m_cost, parallelism, f)
+}
+
+/// Runs a verify function with known parameters like `run_hash`, in the span of `trace::verify`.
+#[inline]
+fn run_verify<T, F>(variant: Variant, version: u32, t_cost: u32, m_cost: u32, parallelism: u32, f: F)
+    -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    dispatch::init();
+    trace::verify(variant, version, t_cost, m_cost, parallelism, f)
+}
+
+/// Runs a verify function of an encoded hash like `run_hash`, in the span of
+/// `trace::verify_encoded`.
+#[inline]
+fn run_verify_encoded<T, F>(variant: Variant, encoded: &CStr, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    dispatch::init();
+    trace::verify_encoded(variant, encoded, f)
+}
+
+/// Suggested limit for `check_password_len`, and the default limit of `PhcHasher`.
+pub const DEFAULT_MAX_PASSWORD_LEN: usize = 4096;
+
+/// Returns `Error::PasswordTooLong` if `pwd` is longer than `max_len` bytes.
+///
+/// The C library accepts passwords of up to 4 GiB, so hashing untrusted input of unbounded length
+/// can be used to exhaust CPU time and memory. Call this before both hashing and verifying;
+/// `PhcHasher` does so itself (see `PhcHasher::with_max_password_len`). `max_len` of `None`
+/// disables the check.
+pub fn check_password_len(pwd: Option<&[u8]>, max_len: Option<usize>) -> Result<(), Error> {
+    match (opt_slice_len(&pwd), max_len) {
+        (len, Some(max)) if len > max => Err(Error::PasswordTooLong { len, max }),
+        _ => Ok(()),
     }
 }
 
 /// Get the associated error message for a given error code.
-pub fn error_message(code: ErrorCode) -> &'static str {
+/// Returns `None` if the C library has no (valid UTF-8) message for the code.
+pub fn error_message(code: ErrorCode) -> Option<&'static str> {
     unsafe {
         let str_ptr = sys::argon2_error_message(code.to_c());
         if str_ptr.is_null() {
-            "UNKNOWN_ERROR_CODE"
+            None
         } else {
-            let str_cstr = CStr::from_ptr(str_ptr);
-            str_cstr.to_str().expect("Variant name is not valid UTF-8")
+            CStr::from_ptr(str_ptr).to_str().ok()
         }
     }
 }
 
+/// Like `error_message`, but returns `"UNKNOWN_ERROR_CODE"` if there is no message for the code.
+pub fn error_message_or_unknown(code: ErrorCode) -> &'static str {
+    error_message(code).unwrap_or("UNKNOWN_ERROR_CODE")
+}
+
 /// Returns the encoded hash length for the given input parameters.
 ///
 /// # Parameters
@@ -515,7 +1310,7 @@ pub fn error_message(code: ErrorCode) -> &'static str {
 ///
 /// # Returns
 ///
-/// The encoded hash length in bytes.
+/// The encoded hash length in bytes, including the terminating null byte.
 pub fn encodedlen(
     t_cost: u32,
     m_cost: u32,
@@ -524,54 +1319,666 @@ pub fn encodedlen(
     hashlen: u32,
     variant: Variant) -> usize {
     unsafe {
-        sys::argon2_encodedlen(t_cost, m_cost, parallelism, saltlen, hashlen, variant.to_c() as _)
+        sys::argon2_encodedlen(t_cost, m_cost, parallelism, saltlen, hashlen, variant.to_c())
     }
 }
 
-/// Converts a slice of bytes to a CStr.
-/// Unlike CStr::from_bytes_with_nul this will stop at the first
-/// null byte instead of returning an error for interior null bytes.
-/// This will return an error if there are no null bytes at all.
-pub fn c_str(bytes: &[u8]) -> Result<&CStr, Error> {
-    for (idx, b) in bytes.iter().enumerate() {
-        if *b == 0 {
-            return Ok(CStr::from_bytes_with_nul(&bytes[0..(idx + 1)]).expect("Failed CStr conversion."));
-        }
+/// Returns the size of the buffer needed to hold an encoded hash for the given input parameters,
+/// *including* the terminating null byte written by the C encoder, or `None` if the size doesn't
+/// fit into a `usize`.
+///
+/// This mirrors `encodedlen`, but is computed in Rust, so it can be used in constant expressions.
+/// Like `encodedlen` it assumes `Version::DEFAULT`; the version field has the same length for all
+/// supported versions.
+///
+/// # Parameters
+/// `t_cost`: Number of iterations.
+/// `m_cost`: Memory usage in kibibytes.
+/// `parallelism`: Number of threads; used to compute lanes.
+/// `saltlen`: Salt size in bytes.
+/// `hashlen`: Hash size in bytes.
+/// `variant`: The Argon2 Variant that we want the encoded length for.
+pub const fn encoded_len_checked(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    saltlen: u32,
+    hashlen: u32,
+    variant: Variant) -> Option<std::num::NonZeroUsize> {
+    let salt = match base64_len(saltlen) {
+        Some(len) => len,
+        None => return None,
+    };
+    let hash = match base64_len(hashlen) {
+        Some(len) => len,
+        None => return None,
+    };
+    let fixed = "$$v=$m=,t=,p=$$".len()
+        + variant.name().len()
+        + decimal_len(Version::DEFAULT.to_int())
+        + decimal_len(m_cost)
+        + decimal_len(t_cost)
+        + decimal_len(parallelism)
+        + 1;
+    match salt.checked_add(hash) {
+        Some(len) => match len.checked_add(fixed) {
+            Some(len) => std::num::NonZeroUsize::new(len),
+            None => None,
+        },
+        None => None,
     }
-    Err(Error::BadParam("bytes"))
 }
 
-/// Converts a slice of bytes to a CStr much like `c_str` except this will allocate a C string for
-/// you instead with a terminating null byte if one cannot be found inside of the given byte
-/// string.
-pub fn c_str_cow<'a>(bytes: &'a [u8]) -> std::borrow::Cow<'a, CStr> {
-    for (idx, b) in bytes.iter().enumerate() {
-        if *b == 0 {
-            return std::borrow::Cow::Borrowed(
-                CStr::from_bytes_with_nul(&bytes[0..(idx + 1)])
-                .expect("Failed CStr conversion.")
-            );
-        }
+/// Returns the same as `encodedlen`, the encoded hash length *including* the terminating null
+/// byte, as a `const fn` for sizing buffers and storage columns at compile time:
+///
+/// ```
+/// use argon2::{encoded_len_const, Variant};
+///
+/// const LEN: usize = encoded_len_const(3, 65536, 4, 16, 32, Variant::ID);
+/// let buffer = [0u8; LEN];
+/// # assert_eq!(argon2::encodedlen(3, 65536, 4, 16, 32, Variant::ID), buffer.len());
+/// ```
+///
+/// A string column needs one byte less, see `encoded_str_len`.
+///
+/// # Panics
+///
+/// Panics if the length doesn't fit into a `usize`, which fails the build in a constant
+/// expression. Use `encoded_len_checked` to handle that case.
+pub const fn encoded_len_const(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    saltlen: u32,
+    hashlen: u32,
+    variant: Variant) -> usize {
+    match encoded_len_checked(t_cost, m_cost, parallelism, saltlen, hashlen, variant) {
+        Some(len) => len.get(),
+        None => panic!("encoded hash length overflows usize"),
     }
-
-    std::borrow::Cow::Owned(
-        std::ffi::CString::new(bytes).expect("Failed to create CString.")
-    )
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
+/// Returns the length of an encoded hash for the given input parameters, *excluding* the
+/// terminating null byte, or `None` if the length doesn't fit into a `usize`.
+///
+/// See `encoded_len_checked` for the parameters.
+pub const fn encoded_str_len(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    saltlen: u32,
+    hashlen: u32,
+    variant: Variant) -> Option<usize> {
+    match encoded_len_checked(t_cost, m_cost, parallelism, saltlen, hashlen, variant) {
+        Some(len) => Some(len.get() - 1),
+        None => None,
+    }
+}
 
-    /// Make sure that all variants have names.
-    #[test]
-    fn test_variant_names() {
-        assert_eq!("argon2i", type2string(Variant::I, false));
-        assert_eq!("Argon2i", type2string(Variant::I, true));
-        assert_eq!("argon2d", type2string(Variant::D, false));
-        assert_eq!("Argon2d", type2string(Variant::D, true));
-        assert_eq!("argon2id", type2string(Variant::ID, false));
-        assert_eq!("Argon2id", type2string(Variant::ID, true));
+/// Number of decimal digits needed to print `n`.
+const fn decimal_len(mut n: u32) -> usize {
+    let mut len = 1;
+    while n >= 10 {
+        len += 1;
+        n /= 10;
+    }
+    len
+}
+
+/// Length of `len` bytes encoded as unpadded base64.
+const fn base64_len(len: u32) -> Option<usize> {
+    // Lossless, usize has at least 32 bits on all supported targets.
+    let len = len as usize;
+    let tail = match len % 3 {
+        2 => 3,
+        1 => 2,
+        _ => 0,
+    };
+    match (len / 3).checked_mul(4) {
+        Some(olen) => olen.checked_add(tail),
+        None => None,
+    }
+}
+
+/// Converts a slice of bytes to a CStr.
+/// Unlike CStr::from_bytes_with_nul this will stop at the first
+/// null byte instead of returning an error for interior null bytes.
+/// This will return `Error::MissingNul` if there are no null bytes at all.
+pub fn c_str(bytes: &[u8]) -> Result<&CStr, Error> {
+    let nul = bytes.iter().position(|b| *b == 0).ok_or(Error::MissingNul)?;
+    CStr::from_bytes_with_nul(&bytes[..=nul]).map_err(|_| Error::BadParam("bytes"))
+}
+
+/// Converts a slice of bytes to a CStr much like `c_str` except this will allocate a C string for
+/// you instead with a terminating null byte if one cannot be found inside of the given byte
+/// string.
+///
+/// Like `c_str`, everything after the first null byte is silently ignored. This matters when
+/// reading encoded hashes from fixed-size, zero-padded buffers (e.g. database columns): the
+/// padding is dropped, but so is anything else that follows a stray null byte.
+///
+/// The copy isn't wiped when it is dropped, since it is handed to the caller as a plain `CString`.
+/// Use `c_string_zeroizing` with the `zeroize` feature for sensitive bytes.
+pub fn try_c_str_cow<'a>(bytes: &'a [u8]) -> Result<std::borrow::Cow<'a, CStr>, Error> {
+    match c_str(bytes) {
+        Ok(c_str) => Ok(std::borrow::Cow::Borrowed(c_str)),
+        Err(Error::MissingNul) => std::ffi::CString::new(bytes)
+            .map(std::borrow::Cow::Owned)
+            .map_err(|_| Error::BadParam("bytes")),
+        Err(err) => Err(err),
+    }
+}
+
+/// Infallible version of `try_c_str_cow`.
+///
+/// `try_c_str_cow` can only fail if `c_str` finds no null byte but `CString::new` then does,
+/// which cannot happen. Should it happen regardless, an empty C string is returned.
+pub fn c_str_cow<'a>(bytes: &'a [u8]) -> std::borrow::Cow<'a, CStr> {
+    try_c_str_cow(bytes).unwrap_or_else(|_| std::borrow::Cow::Borrowed(Default::default()))
+}
+
+/// Copies a slice of bytes up to the first null byte into a `CString` that is wiped when it is
+/// dropped. Use this instead of `c_str_cow` when the bytes are sensitive and may not contain a
+/// null byte, so that the allocated copy doesn't linger in memory.
+#[cfg(feature = "zeroize")]
+pub fn c_string_zeroizing(bytes: &[u8]) -> Result<zeroize::Zeroizing<std::ffi::CString>, Error> {
+    use zeroize::Zeroize;
+
+    let bytes = bytes.iter().position(|b| *b == 0).map_or(bytes, |nul| &bytes[..nul]);
+    // Reserve room for the terminator up front so that CString::new doesn't reallocate and leave
+    // an unwiped copy behind.
+    let mut vec = Vec::with_capacity(bytes.len() + 1);
+    vec.extend_from_slice(bytes);
+    std::ffi::CString::new(vec)
+        .map(zeroize::Zeroizing::new)
+        .map_err(|err| {
+            err.into_vec().zeroize();
+            Error::BadParam("bytes")
+        })
+}
+
+/// Secret password types from the `secrecy` crate. The password can be handed to the hash and
+/// verify functions as `Some(password.expose_password())` without copying it.
+#[cfg(feature = "secrecy")]
+pub trait ExposePassword {
+    /// Exposes the password bytes.
+    fn expose_password(&self) -> &[u8];
+}
+
+#[cfg(feature = "secrecy")]
+impl ExposePassword for secrecy::SecretVec<u8> {
+    fn expose_password(&self) -> &[u8] {
+        secrecy::ExposeSecret::expose_secret(self)
+    }
+}
+
+#[cfg(feature = "secrecy")]
+impl ExposePassword for secrecy::SecretString {
+    fn expose_password(&self) -> &[u8] {
+        secrecy::ExposeSecret::expose_secret(self).as_bytes()
+    }
+}
+
+/// Hashes a password, producing a raw hash of `hashlen` bytes that is returned as a secret.
+///
+/// The hash is written directly into the buffer that ends up in the secret, which is wiped if
+/// hashing fails. See `hash` for the parameters.
+#[cfg(feature = "secrecy")]
+#[allow(clippy::too_many_arguments)]
+pub fn hash_raw_secret(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    pwd: Option<&[u8]>,
+    salt: Option<&[u8]>,
+    hashlen: usize,
+    variant: Variant,
+    version: Version) -> Result<secrecy::SecretVec<u8>, Error> {
+    let mut out = vec![0u8; hashlen];
+    hash(t_cost, m_cost, parallelism, pwd, salt, Some(&mut out), None, variant, version)?;
+    Ok(secrecy::Secret::new(out))
+}
+
+/// Hashes a password, producing a raw hash of `hashlen` bytes that is wiped when it is dropped.
+///
+/// Like `hash_raw_secret`, the hash is written directly into the returned buffer, which is wiped if
+/// hashing fails. See `hash` for the parameters.
+#[cfg(feature = "zeroize")]
+#[allow(clippy::too_many_arguments)]
+pub fn hash_raw_zeroizing(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    pwd: Option<&[u8]>,
+    salt: Option<&[u8]>,
+    hashlen: usize,
+    variant: Variant,
+    version: Version) -> Result<zeroize::Zeroizing<Vec<u8>>, Error> {
+    types::checked_len_u32("hashlen", hashlen, Context::MAX_OUTLEN)?;
+    let mut out = zeroize::Zeroizing::new(vec![0u8; hashlen]);
+    hash(t_cost, m_cost, parallelism, pwd, salt, Some(&mut out), None, variant, version)?;
+    Ok(out)
+}
+
+/// Derives a key of `out_len` bytes from a password with Argon2id, e.g. to use it as an
+/// encryption key.
+///
+/// `context` says what the key is for, e.g. `b"disk-encryption"` or `b"api-token"`, and goes into
+/// the associated data of Argon2 (`Context::ad`). Changing the context changes the key, so keys
+/// derived for different purposes differ even with the same password, salt and parameters. An
+/// empty context gives the same key as `id_hash_raw`.
+///
+/// The costs are taken from `params`, whose `output_len` is ignored in favor of `out_len`; the
+/// version is `Version::DEFAULT`. Only the raw key is produced, never an encoded string, so the
+/// parameters and the salt aren't stored anywhere by this function. The copies of the password
+/// and the key are wiped when they are dropped. Returns the errors of `id_ctx`.
+#[cfg(feature = "zeroize")]
+pub fn derive_key(
+    params: &PhcParams,
+    pwd: &[u8],
+    salt: &[u8],
+    context: &[u8],
+    out_len: usize) -> Result<zeroize::Zeroizing<Vec<u8>>, Error> {
+    // `Context` takes mutable buffers, which the C library only writes to with `Flags::CLEAR_*`.
+    let mut pwd = zeroize::Zeroizing::new(pwd.to_vec());
+    let mut salt = salt.to_vec();
+    let mut ad = context.to_vec();
+    let mut out = zeroize::Zeroizing::new(vec![0u8; out_len]);
+    id_ctx(&mut Context {
+        out: &mut out,
+        pwd: Some(&mut pwd),
+        salt: Some(&mut salt),
+        secret: None,
+        ad: Some(&mut ad),
+        t_cost: params.t_cost,
+        m_cost: params.m_cost,
+        lanes: params.p_cost,
+        threads: params.p_cost,
+        version: Version::DEFAULT,
+        flags: Flags::DEFAULT,
+    })?;
+    Ok(out)
+}
+
+/// Derives one subkey of `subkey_len` bytes per label from a password, running Argon2 only once.
+///
+/// The subkeys are derived as follows, which won't change between releases:
+///
+/// 1. The master key is `derive_key(params, pwd, salt, b"derive_subkeys", 64)`, i.e. 64 bytes of
+///    Argon2id with `b"derive_subkeys"` as the associated data.
+/// 2. The subkey for `labels[i]` is keyed BLAKE2b (`blake2::blake2b`) with an output of
+///    `subkey_len` bytes, the master key as the key, and the label followed by `i` as 32 bit
+///    little endian as the message.
+///
+/// Different labels, or the same label at different positions, give unrelated subkeys. The master
+/// key and the subkeys are wiped when they are dropped.
+///
+/// Returns `Error::InvalidParameter` for `"subkey_len"` unless it is between 1 and
+/// `blake2::MAX_OUTPUT_LEN`, and the errors of `derive_key`.
+#[cfg(all(feature = "zeroize", feature = "blake2b"))]
+pub fn derive_subkeys(
+    params: &PhcParams,
+    pwd: &[u8],
+    salt: &[u8],
+    labels: &[&[u8]],
+    subkey_len: usize) -> Result<Vec<zeroize::Zeroizing<Vec<u8>>>, Error> {
+    use std::convert::TryFrom;
+
+    if !(1..=blake2::MAX_OUTPUT_LEN).contains(&subkey_len) {
+        return Err(Error::InvalidParameter {
+            name: "subkey_len",
+            got: subkey_len as u64,
+            min: 1,
+            max: blake2::MAX_OUTPUT_LEN as u64,
+        });
+    }
+    let master = derive_key(params, pwd, salt, b"derive_subkeys", blake2::MAX_KEY_LEN)?;
+    labels.iter().enumerate().map(|(index, label)| {
+        let index = u32::try_from(index).map_err(|_| Error::BadParam("labels.len"))?;
+        let mut message = label.to_vec();
+        message.extend_from_slice(&index.to_le_bytes());
+        blake2::blake2b(subkey_len, &master, &message).map(zeroize::Zeroizing::new)
+    }).collect()
+}
+
+// The types that are meant to be shared between threads must stay `Send + Sync`.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<OwnedContext>();
+    assert_send_sync::<Context<'static, 'static, 'static, 'static, 'static>>();
+    assert_send_sync::<Password>();
+    assert_send_sync::<Error>();
+    assert_send_sync::<SystemAllocator>();
+    assert_send_sync::<MemoryArena>();
+    assert_send_sync::<InstrumentedAllocator>();
+    assert_send_sync::<MemoryBudget>();
+    assert_send_sync::<MemoryPermit<'static>>();
+    assert_send_sync::<StdThreadExecutor>();
+    #[cfg(all(feature = "mlock", any(unix, windows)))]
+    assert_send_sync::<SecureAllocator>();
+    #[cfg(feature = "cache")]
+    assert_send_sync::<VerifyCache>();
+    #[cfg(feature = "password-hash")]
+    assert_send_sync::<PhcHasher>();
+};
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::expect_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_c_str() {
+        assert_eq!(Err(Error::MissingNul), c_str(b""));
+        assert_eq!(Err(Error::MissingNul), c_str(b"no nul"));
+        assert_eq!(Ok(&b""[..]), c_str(b"\0trailing").map(CStr::to_bytes));
+        assert_eq!(Ok(&b"abc"[..]), c_str(b"abc\0def\0").map(CStr::to_bytes));
+    }
+
+    #[test]
+    fn test_c_str_cow() {
+        use std::borrow::Cow;
+
+        match try_c_str_cow(b"abc\0def") {
+            Ok(Cow::Borrowed(s)) => assert_eq!(b"abc", s.to_bytes()),
+            other => panic!("expected a borrowed CStr, got {:?}", other),
+        }
+        match try_c_str_cow(b"abc") {
+            Ok(Cow::Owned(s)) => assert_eq!(b"abc", s.as_bytes()),
+            other => panic!("expected an owned CString, got {:?}", other),
+        }
+        match try_c_str_cow(b"") {
+            Ok(Cow::Owned(s)) => assert!(s.as_bytes().is_empty()),
+            other => panic!("expected an owned CString, got {:?}", other),
+        }
+        assert_eq!(b"abc", c_str_cow(b"abc\0def").to_bytes());
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        let params: &[(u32, u32, u32, usize, usize)] = &[
+            (1, 8, 1, 8, 4),
+            (2, 16, 1, 16, 32),
+            (3, 65, 2, 17, 33),
+            (10, 100, 4, 1000, 1000),
+            (1, 8, 1, 1024, 1025),
+        ];
+        for &(t_cost, m_cost, parallelism, saltlen, hashlen) in params {
+            let salt = vec![0x5A; saltlen];
+            let mut encoded = vec![0xFF; 4096];
+            id_hash_encoded(t_cost, m_cost, parallelism, Some(b"password"), Some(&salt), hashlen, &mut encoded)
+                .expect("Test hash failed.");
+            let written = encoded.iter().position(|b| *b == 0).expect("no NUL written") + 1;
+
+            let expected = encoded_len_checked(t_cost, m_cost, parallelism, saltlen as u32, hashlen as u32, Variant::ID)
+                .expect("encoded length overflow");
+            assert_eq!(written, expected.get());
+            assert_eq!(Some(written - 1),
+                       encoded_str_len(t_cost, m_cost, parallelism, saltlen as u32, hashlen as u32, Variant::ID));
+            assert_eq!(written,
+                       encodedlen(t_cost, m_cost, parallelism, saltlen as u32, hashlen as u32, Variant::ID));
+        }
+
+        const LEN: Option<std::num::NonZeroUsize> = encoded_len_checked(2, 16, 1, 8, 32, Variant::I);
+        assert_eq!(Some(encodedlen(2, 16, 1, 8, 32, Variant::I)), LEN.map(|len| len.get()));
+        // Two maximum sized base64 strings only fit into 64 bit.
+        #[cfg(target_pointer_width = "64")]
+        assert!(encoded_len_checked(u32::MAX, u32::MAX, u32::MAX, u32::MAX, u32::MAX, Variant::ID).is_some());
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(None, encoded_len_checked(u32::MAX, u32::MAX, u32::MAX, u32::MAX, u32::MAX, Variant::ID));
+    }
+
+    #[test]
+    fn test_encoded_len_const() {
+        // Every decimal width up to the full u32 range, and salt and hash lengths around each
+        // base64 block boundary.
+        let numbers = [0, 1, 9, 10, 99, 100, 65536, 999_999, 1_000_000, u32::MAX];
+        let lens = [0, 1, 2, 3, 4, 8, 16, 31, 32, 33, 64, 1000, 1 << 16];
+        for variant in Variant::iter() {
+            for &n in &numbers {
+                for &len in &lens {
+                    for &(t_cost, m_cost, parallelism, saltlen, hashlen) in
+                        &[(n, 8, 1, len, 32), (1, n, 1, 16, len), (1, 8, n, len, len)] {
+                        assert_eq!(encodedlen(t_cost, m_cost, parallelism, saltlen, hashlen, variant),
+                                   encoded_len_const(t_cost, m_cost, parallelism, saltlen, hashlen, variant),
+                                   "t={} m={} p={} salt={} hash={} {}",
+                                   t_cost, m_cost, parallelism, saltlen, hashlen, variant);
+                    }
+                }
+            }
+        }
+
+        const LEN: usize = encoded_len_const(3, 65536, 4, 16, 32, Variant::ID);
+        assert_eq!(encodedlen(3, 65536, 4, 16, 32, Variant::ID), LEN);
+    }
+
+    /// A global allocator for the tests that checks the blocks freed on a thread while it is spying
+    /// for a marker, to find copies of secrets that are freed without being wiped.
+    #[cfg(feature = "zeroize")]
+    mod wipe_spy {
+        use std::alloc::{GlobalAlloc, Layout, System};
+        use std::cell::Cell;
+
+        thread_local! {
+            static MARKER: Cell<Option<&'static [u8]>> = const { Cell::new(None) };
+            static UNWIPED: Cell<usize> = const { Cell::new(0) };
+        }
+
+        struct SpyAllocator;
+
+        unsafe impl GlobalAlloc for SpyAllocator {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                System.alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                let _ = MARKER.try_with(|marker| {
+                    if let Some(marker) = marker.get() {
+                        let block = std::slice::from_raw_parts(ptr, layout.size());
+                        if block.windows(marker.len()).any(|window| window == marker) {
+                            UNWIPED.with(|unwiped| unwiped.set(unwiped.get() + 1));
+                        }
+                    }
+                });
+                System.dealloc(ptr, layout)
+            }
+        }
+
+        #[global_allocator]
+        static ALLOCATOR: SpyAllocator = SpyAllocator;
+
+        /// Runs `f` and returns the number of blocks it freed on this thread that still contained
+        /// `marker`.
+        pub fn unwiped(marker: &'static [u8], f: impl FnOnce()) -> usize {
+            UNWIPED.with(|unwiped| unwiped.set(0));
+            MARKER.with(|cell| cell.set(Some(marker)));
+            f();
+            MARKER.with(|cell| cell.set(None));
+            UNWIPED.with(|unwiped| unwiped.get())
+        }
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_zeroize() {
+        use zeroize::Zeroize;
+
+        const MARKER: &[u8] = b"spy-secret-7f3a9c";
+
+        // The spy notices a copy that isn't wiped.
+        assert_eq!(1, wipe_spy::unwiped(MARKER, || drop(MARKER.to_vec())));
+        assert_eq!(0, wipe_spy::unwiped(MARKER, || drop(zeroize::Zeroizing::new(MARKER.to_vec()))));
+
+        let mut encoded = c_string_zeroizing(b"$argon2id$secret\0trailing").unwrap();
+        assert_eq!(b"$argon2id$secret", encoded.as_bytes());
+        encoded.zeroize();
+        assert!(encoded.as_bytes().is_empty());
+        assert_eq!(0, wipe_spy::unwiped(MARKER, || drop(c_string_zeroizing(MARKER).unwrap())));
+
+        // The hashes returned by the `_zeroizing` functions are wiped when they are dropped, unlike
+        // the ones of their plain counterparts.
+        let raw = hash_raw_zeroizing(2, 16, 1, Some(b"password"), Some(b"somesalt"), 32, Variant::ID, Version::DEFAULT).unwrap();
+        let mut expected = [0u8; 32];
+        id_hash_raw(2, 16, 1, Some(b"password"), Some(b"somesalt"), &mut expected).unwrap();
+        assert_eq!(&expected[..], &raw[..]);
+        let raw_marker: &'static [u8] = Box::leak(raw.to_vec().into_boxed_slice());
+        assert_eq!(0, wipe_spy::unwiped(raw_marker, || drop(raw)));
+        assert_eq!(1, wipe_spy::unwiped(raw_marker, || drop(expected.to_vec())));
+        assert_eq!(Err(Error::Code(ErrorCode::OutputTooShort)),
+                   hash_raw_zeroizing(2, 16, 1, Some(b"password"), Some(b"somesalt"), 3, Variant::ID, Version::DEFAULT).map(|_| ()));
+
+        let encoded = hash_encoded_zeroizing(2, 16, 1, b"password", b"somesalt", 32, Variant::ID, Version::DEFAULT).unwrap();
+        let plain = hash_encoded_string(2, 16, 1, b"password", b"somesalt", 32, Variant::ID, Version::DEFAULT).unwrap();
+        assert_eq!(plain, *encoded);
+        let encoded_marker: &'static [u8] = Box::leak(encoded.as_bytes().to_vec().into_boxed_slice());
+        assert_eq!(0, wipe_spy::unwiped(encoded_marker, || drop(encoded)));
+        assert_eq!(1, wipe_spy::unwiped(encoded_marker, || drop(plain)));
+
+        // verify_bytes copies encoded hashes of 512 bytes or more without a NUL to the heap.
+        let salt = [b'S'; 384];
+        let encoded = hash_encoded_string(1, 8, 1, b"password", &salt, 32, Variant::ID, Version::DEFAULT).unwrap();
+        assert!(encoded.len() >= ENCODED_STACK_LEN);
+        let salt_b64: &'static [u8] = Box::leak(encoded.split('$').nth(4).unwrap().as_bytes()[..64].to_vec().into_boxed_slice());
+        assert_eq!(0, wipe_spy::unwiped(salt_b64, || {
+            assert_eq!(Ok(()), verify_bytes(encoded.as_bytes(), Some(b"password"), Variant::ID));
+        }));
+
+        let new_context = || OwnedContext {
+            out: vec![0xAA; 32],
+            pwd: Some(MARKER.to_vec()),
+            salt: Some(b"somesalt".to_vec()),
+            secret: Some(b"secret".to_vec()),
+            ad: Some(b"ad".to_vec()),
+            t_cost: 2,
+            m_cost: 16,
+            lanes: 1,
+            threads: 1,
+            version: Version::DEFAULT,
+            flags: Flags::DEFAULT,
+        };
+        let mut context = new_context();
+        id_ctx(&mut context).unwrap();
+        let context = zeroize::Zeroizing::new(context);
+        assert_eq!(0, wipe_spy::unwiped(MARKER, || drop(context)));
+        assert_eq!(1, wipe_spy::unwiped(MARKER, || drop(new_context())));
+
+        let mut context = new_context();
+        context.zeroize();
+        assert!(context.out.is_empty());
+        assert!(context.pwd.is_none() && context.salt.is_none());
+        assert!(context.secret.is_none() && context.ad.is_none());
+    }
+
+    #[test]
+    fn test_password() {
+        let password: Password = "hunter22".parse().unwrap();
+        assert_eq!(b"hunter22", &*password);
+        assert_eq!("Password { len: 8 }", format!("{:?}", password));
+        assert!(!format!("{:#?}", password).contains("hunter22"));
+
+        let mut out = [0u8; 32];
+        let mut expected = [0u8; 32];
+        id_hash_raw(2, 1 << 4, 1, Some(&*password), Some(b"somesalt"), &mut out).unwrap();
+        id_hash_raw(2, 1 << 4, 1, Some(b"hunter22"), Some(b"somesalt"), &mut expected).unwrap();
+        assert_eq!(expected, out);
+
+        // Wipe while the buffer is still allocated and look at it through a raw pointer.
+        let mut bytes = Vec::with_capacity(16);
+        bytes.extend_from_slice(b"hunter22");
+        let mut password = Password::new(bytes);
+        let ptr = password.as_ptr();
+        password.wipe();
+        assert!(unsafe { std::slice::from_raw_parts(ptr, 16) }.iter().all(|b| *b == 0));
+    }
+
+    #[cfg(feature = "secrecy")]
+    #[test]
+    fn test_secrecy() {
+        use secrecy::{ExposeSecret, SecretString, SecretVec};
+
+        let password = SecretString::new("password".to_string());
+        let raw = hash_raw_secret(2, 1 << 4, 1, Some(password.expose_password()), Some(b"somesalt"), 32,
+                                  Variant::ID, Version::DEFAULT).unwrap();
+        let mut expected = [0u8; 32];
+        id_hash_raw(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), &mut expected).unwrap();
+        assert_eq!(&expected[..], &raw.expose_secret()[..]);
+
+        let mut encoded = [0u8; 128];
+        id_hash_encoded(2, 1 << 4, 1, Some(password.expose_password()), Some(b"somesalt"), 32, &mut encoded)
+            .unwrap();
+        let encoded = c_str(&encoded).unwrap();
+        id_verify(encoded, Some(password.expose_password())).unwrap();
+        let bytes = SecretVec::new(b"password".to_vec());
+        id_verify(encoded, Some(bytes.expose_password())).unwrap();
+        let wrong = SecretString::new("wrong".to_string());
+        assert!(id_verify(encoded, Some(wrong.expose_password())).unwrap_err().is_verify_mismatch());
+
+        assert!(hash_raw_secret(2, 1 << 4, 1, Some(password.expose_password()), Some(b"s"), 32,
+                                Variant::ID, Version::DEFAULT).is_err());
+    }
+
+    /// Make sure that all variants have names.
+    #[test]
+    fn test_variant_names() {
+        let mut names = vec![("argon2d", "Argon2d"), ("argon2i", "Argon2i"), ("argon2id", "Argon2id")];
+        if cfg!(feature = "no-argon2d") {
+            names.remove(0);
+        }
+        assert_eq!(Variant::ALL.len(), names.len());
+        for (variant, (lower, upper)) in Variant::iter().zip(names.iter()) {
+            assert_eq!(Ok(*lower), type2string(variant, false));
+            assert_eq!(Ok(*upper), type2string(variant, true));
+            assert_eq!(*lower, variant.to_string());
+        }
+    }
+
+    #[test]
+    fn test_from_c() {
+        for variant in Variant::iter() {
+            assert_eq!(Some(variant.to_c()), Variant::from_c(variant.to_c()).map(Variant::to_c));
+        }
+        assert!(Variant::from_c(-1).is_none());
+        assert!(Variant::from_c(3).is_none());
+
+        for version in Version::iter() {
+            assert_eq!(Some(version.to_int()), Version::from_c(version.to_c()).map(Version::to_int));
+        }
+        assert!(Version::from_c(0x11).is_none());
+    }
+
+    /// The default version must be the one the C library falls back to.
+    #[test]
+    fn test_default_version() {
+        assert_eq!(sys::Argon2_version_ARGON2_VERSION_NUMBER as u32, Version::default().to_int());
+        assert_eq!(Version::DEFAULT.to_int(), Version::default().to_int());
+    }
+
+    /// Make sure that the version list is ordered and round-trips through integers.
+    #[test]
+    fn test_version_list() {
+        let numbers: Vec<u32> = Version::iter().map(Version::to_int).collect();
+        assert_eq!(vec![0x10, 0x13], numbers);
+        for version in Version::iter() {
+            assert_eq!(Some(version.to_int()), Version::from_int(version.to_int()).map(Version::to_int));
+        }
+    }
+
+    #[cfg(feature = "clap")]
+    #[test]
+    fn test_clap_value_enum() {
+        let command = clap::Command::new("argon2")
+            .arg(clap::Arg::new("variant").long("variant").value_parser(clap::value_parser!(Variant)))
+            .arg(clap::Arg::new("version").long("version").value_parser(clap::value_parser!(Version)));
+
+        let matches = command.clone()
+            .try_get_matches_from(["argon2", "--variant", "argon2id", "--version", "10"])
+            .expect("Failed to parse arguments.");
+        assert!(matches!(matches.get_one::<Variant>("variant"), Some(Variant::ID)));
+        assert!(matches!(matches.get_one::<Version>("version"), Some(Version::Version10)));
+
+        assert!(command.try_get_matches_from(["argon2", "--variant", "argon2x"]).is_err());
     }
 
     fn hex_conv(bytes: &[u8], hex_dest: &mut [u8]) {
@@ -605,7 +2012,7 @@ mod test {
                  unsafe { std::str::from_utf8_unchecked(pwd) },
                  unsafe { std::str::from_utf8_unchecked(salt) },);
 
-        hash(t, 1<<m, p, Some(pwd), Some(salt), Some(&mut out), Some(&mut encoded), variant, version).expect("Test hash failed.");
+        hash(t, m, p, Some(pwd), Some(salt), Some(&mut out), Some(&mut encoded), variant, version).expect("Test hash failed.");
         hex_conv(&out, &mut hex_out);
 
         assert_eq!(str_conv(hexref), str_conv(&hex_out[0..(OUTLEN * 2)]));
@@ -619,14 +2026,24 @@ mod test {
         ).expect("Failed verify-1");
     }
 
-    fn hashtest(version: Version, t: u32, m: u32, p: u32, pwd: &str, salt: &str, hexref: &str, mcfref: &str, variant: Variant) {
+    fn hashtest(vector: &test_vectors::TestVector) {
+        let encoded = vector.encoded.expect("Test vector without encoded hash.");
         hashtest_bytes(
-            version, t, m, p,
-            &mut tovec(pwd.as_bytes()),
-            &mut tovec(salt.as_bytes()),
-            &mut tovec(hexref.as_bytes()),
-            &mut tovec(mcfref.as_bytes()),
-            variant);
+            vector.version, vector.t_cost, vector.m_cost, vector.parallelism,
+            &mut tovec(vector.password),
+            &mut tovec(vector.salt),
+            &mut tovec(vector.hash.as_bytes()),
+            &mut tovec(encoded.as_bytes()),
+            vector.variant);
+    }
+
+    /// Vectors with at least this much memory are only hashed by the ignored `_large_ram` tests.
+    const LARGE_RAM: u32 = 1 << 20;
+
+    fn hashtest_all(vectors: &[test_vectors::TestVector], large_ram: bool) {
+        for vector in vectors.iter().filter(|vector| (vector.m_cost >= LARGE_RAM) == large_ram) {
+            hashtest(vector);
+        }
     }
 
     macro_rules! check_error_code {
@@ -638,194 +2055,3084 @@ mod test {
     #[test]
     fn test_argon2i_0x10() {
         println!("Test Argon2i version number: 0x{:02X}", (Version::Version10).to_int());
-        hashtest(Version::Version10, 2, 16, 1, "password", "somesalt",
-             "f6c4db4a54e2a370627aff3db6176b94a2a209a62c8e36152711802f7b30c694",
-             "$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ",
-             Variant::I);
-        hashtest(Version::Version10, 2, 18, 1, "password", "somesalt",
-                 "3e689aaa3d28a77cf2bc72a51ac53166761751182f1ee292e3f677a7da4c2467",
-                 "$argon2i$m=262144,t=2,p=1$c29tZXNhbHQ$Pmiaqj0op3zyvHKlGsUxZnYXURgvHuKS4/Z3p9pMJGc",
-                 Variant::I);
-        hashtest(Version::Version10, 2, 8, 1, "password", "somesalt",
-                 "fd4dd83d762c49bdeaf57c47bdcd0c2f1babf863fdeb490df63ede9975fccf06",
-                 "$argon2i$m=256,t=2,p=1$c29tZXNhbHQ$/U3YPXYsSb3q9XxHvc0MLxur+GP960kN9j7emXX8zwY",
-                 Variant::I);
-        hashtest(Version::Version10, 2, 8, 2, "password", "somesalt",
-                 "b6c11560a6a9d61eac706b79a2f97d68b4463aa3ad87e00c07e2b01e90c564fb",
-                 "$argon2i$m=256,t=2,p=2$c29tZXNhbHQ$tsEVYKap1h6scGt5ovl9aLRGOqOth+AMB+KwHpDFZPs",
-                 Variant::I);
-        hashtest(Version::Version10, 1, 16, 1, "password", "somesalt",
-                 "81630552b8f3b1f48cdb1992c4c678643d490b2b5eb4ff6c4b3438b5621724b2",
-                 "$argon2i$m=65536,t=1,p=1$c29tZXNhbHQ$gWMFUrjzsfSM2xmSxMZ4ZD1JCytetP9sSzQ4tWIXJLI",
-                 Variant::I);
-        hashtest(Version::Version10, 4, 16, 1, "password", "somesalt",
-                 "f212f01615e6eb5d74734dc3ef40ade2d51d052468d8c69440a3a1f2c1c2847b",
-                 "$argon2i$m=65536,t=4,p=1$c29tZXNhbHQ$8hLwFhXm6110c03D70Ct4tUdBSRo2MaUQKOh8sHChHs",
-                 Variant::I);
-        hashtest(Version::Version10, 2, 16, 1, "differentpassword", "somesalt",
-                 "e9c902074b6754531a3a0be519e5baf404b30ce69b3f01ac3bf21229960109a3",
-                 "$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ$6ckCB0tnVFMaOgvlGeW69ASzDOabPwGsO/ISKZYBCaM",
-                 Variant::I);
-        hashtest(Version::Version10, 2, 16, 1, "password", "diffsalt",
-                 "79a103b90fe8aef8570cb31fc8b22259778916f8336b7bdac3892569d4f1c497",
-                 "$argon2i$m=65536,t=2,p=1$ZGlmZnNhbHQ$eaEDuQ/orvhXDLMfyLIiWXeJFvgza3vaw4kladTxxJc",
-                 Variant::I);
+        hashtest_all(test_vectors::ARGON2I_V10, false);
     }
 
     #[test]
     #[ignore]
     fn test_argon2i_0x10_large_ram() {
-        hashtest(Version::Version10, 2, 20, 1, "password", "somesalt",
-                "9690ec55d28d3ed32562f2e73ea62b02b018757643a2ae6e79528459de8106e9",
-                "$argon2i$m=1048576,t=2,p=1$c29tZXNhbHQ$lpDsVdKNPtMlYvLnPqYrArAYdXZDoq5ueVKEWd6BBuk",
-                Variant::I);
+        hashtest_all(test_vectors::ARGON2I_V10, true);
     }
 
     #[test]
-    fn test_argon2i_0x10_errors() {
-        // Handle an invalid encoding correctly (it is missing a $)
-        check_error_code!(DecodingFail, verify(&c_str_cow(b"$argon2i$m=65536,t=2,p=1c29tZXNhbHQ$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ"),
-               Some(b"password"), Variant::I));
+    fn test_linked_implementation() {
+        let expected = if sys::PREBUILT {
+            LinkKind::Prebuilt
+        } else if cfg!(feature = "system") {
+            LinkKind::System
+        } else if cfg!(feature = "pure-rust") {
+            LinkKind::PureRust
+        } else {
+            LinkKind::Bundled
+        };
+        assert_eq!(expected, linked_implementation());
+    }
 
-        // Handle an invalid encoding correctly (it is missing a $)
-        check_error_code!(DecodingFail, verify(&c_str_cow(b"$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ"),
-               Some(b"password"), Variant::I));
+    #[test]
+    fn test_capabilities() {
+        let caps = capabilities();
+        assert_eq!(env!("CARGO_PKG_VERSION"), caps.crate_version);
+        assert_eq!(!cfg!(target_family = "wasm"), caps.threads_enabled);
+        assert_eq!(cfg!(not(feature = "no-argon2d")), caps.argon2d);
+        assert_eq!(core_kind(), caps.core);
+        assert_eq!(linked_implementation(), caps.linked);
+        if cfg!(feature = "system") || sys::PREBUILT {
+            assert_eq!(CoreKind::System, caps.core);
+        } else if cfg!(any(feature = "ref-core", feature = "pure-rust")) {
+            assert_eq!(CoreKind::Reference, caps.core);
+        }
+        assert_eq!(Version::DEFAULT.to_int(), caps.version_default.to_int());
+        assert_eq!((8, 4, 0xFFFFFF, 0xFFFFFF), (caps.min_salt_len, caps.min_hash_len, caps.max_lanes, caps.max_threads));
 
-        // Handle an invalid encoding correctly (salt is too short)
-        check_error_code!(SaltTooShort, verify(&c_str_cow(b"$argon2i$m=65536,t=2,p=1$$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ"),
-               Some(b"password"), Variant::I));
+        let line = caps.to_string();
+        assert!(!line.contains('\n'));
+        assert!(line.starts_with(&format!("just-argon2 {}: ", env!("CARGO_PKG_VERSION"))));
+        assert!(line.contains(match caps.linked {
+            LinkKind::Bundled => "bundled libargon2",
+            LinkKind::System => "system libargon2",
+            LinkKind::Prebuilt => "prebuilt libargon2",
+            LinkKind::PureRust => "pure Rust backend",
+        }));
+        assert!(line.contains("default version 0x13"));
+    }
 
-        // Handle an invalid encoding correctly (the encoded password is "passwore")
-        check_error_code!(VerifyMismatch, verify(&c_str_cow(b"$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ$b2G3seW+uPzerwQQC+/E1K50CLLO7YXy0JRcaTuswRo"),
-               Some(b"password"), Variant::I));
+    #[test]
+    fn test_core_dispatch() {
+        let vectors = || {
+            hashtest(&test_vectors::ARGON2I_V10[4]);
+            hashtest(&test_vectors::ARGON2I_V13[0]);
+            hashtest(&test_vectors::ARGON2I_V13[4]);
+        };
+
+        let detected = selected_core();
+        vectors();
+
+        force_reference(true);
+        assert_eq!(Core::Reference, selected_core());
+        vectors();
+
+        force_reference(false);
+        assert_eq!(detected, selected_core());
+        if cfg!(target_arch = "x86_64") && core_kind() == CoreKind::Optimized {
+            assert_ne!(Core::Reference, detected);
+        }
+        if core_kind() == CoreKind::Reference {
+            assert_eq!(Core::Reference, detected);
+        }
     }
 
     #[test]
-    fn test_argon2i_0x13() {
-        println!("Test Argon2i version number: 0x{:02X}", (Version::Version13).to_int());
+    fn test_core_kind() {
+        let expected = if cfg!(feature = "system") || sys::PREBUILT {
+            CoreKind::System
+        } else if cfg!(all(any(target_arch = "x86", target_arch = "x86_64"), feature = "opt-core", not(feature = "ref-core"), not(feature = "pure-rust"))) {
+            CoreKind::Optimized
+        } else {
+            CoreKind::Reference
+        };
+        assert_eq!(expected, core_kind());
+    }
+
+    #[cfg(feature = "password-hash")]
+    #[test]
+    fn test_password_hash_interop() {
+        use password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, Salt};
+
+        // Produced by `argon2::Argon2::hash_password` of the pure Rust `argon2` crate (0.5.3) for
+        // the password "password" and the salt "somesaltsomesalt".
+        let fixtures = [
+            (Variant::ID, Version::Version13, 256, 2, 1,
+                "$argon2id$v=19$m=256,t=2,p=1$c29tZXNhbHRzb21lc2FsdA$yrdGtGIZk/3JHsUHh5gLQUqQppLwvGjf4Z+cJbPLqew"),
+            (Variant::I, Version::Version13, 256, 3, 2,
+                "$argon2i$v=19$m=256,t=3,p=2$c29tZXNhbHRzb21lc2FsdA$yXzDgWCHW1iApRj4LGNyp4jGWgFU4hCaBAtwpDsbuBg"),
+            (Variant::ID, Version::Version13, 512, 2, 4,
+                "$argon2id$v=19$m=512,t=2,p=4$c29tZXNhbHRzb21lc2FsdA$mskTs1Hwo3MDziPqDyxw3REpP3VytJOsFPrikRqe1MVYU8PGsxKyIJL6JnjpPLl8oOyO0eoeX6zjCtOokVJlUg"),
+            (Variant::I, Version::Version10, 256, 2, 1,
+                "$argon2i$v=16$m=256,t=2,p=1$c29tZXNhbHRzb21lc2FsdA$xw01fBXWdoeA9mwz9ZQrZZw1K05/qsgbvHAvbVXvstI"),
+        ];
+        #[cfg(not(feature = "no-argon2d"))]
+        let fixtures = [&fixtures[..], &[(Variant::D, Version::Version13, 128, 1, 1,
+            "$argon2d$v=19$m=128,t=1,p=1$c29tZXNhbHRzb21lc2FsdA$17CMmInvVCFdXmvFk/3dQA")]].concat();
 
-        hashtest(Version::Version13, 2, 16, 1, "password", "somesalt",
-                 "c1628832147d9720c5bd1cfd61367078729f6dfb6f8fea9ff98158e0d7816ed0",
-                 "$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweHKfbftvj+qf+YFY4NeBbtA",
-                 Variant::I);
-        hashtest(Version::Version13, 2, 18, 1, "password", "somesalt",
-                 "296dbae80b807cdceaad44ae741b506f14db0959267b183b118f9b24229bc7cb",
-                 "$argon2i$v=19$m=262144,t=2,p=1$c29tZXNhbHQ$KW266AuAfNzqrUSudBtQbxTbCVkmexg7EY+bJCKbx8s",
-                 Variant::I);
-        hashtest(Version::Version13, 2, 8, 1, "password", "somesalt",
-                 "89e9029f4637b295beb027056a7336c414fadd43f6b208645281cb214a56452f",
-                 "$argon2i$v=19$m=256,t=2,p=1$c29tZXNhbHQ$iekCn0Y3spW+sCcFanM2xBT63UP2sghkUoHLIUpWRS8",
-                 Variant::I);
-        hashtest(Version::Version13, 2, 8, 2, "password", "somesalt",
-                 "4ff5ce2769a1d7f4c8a491df09d41a9fbe90e5eb02155a13e4c01e20cd4eab61",
-                 "$argon2i$v=19$m=256,t=2,p=2$c29tZXNhbHQ$T/XOJ2mh1/TIpJHfCdQan76Q5esCFVoT5MAeIM1Oq2E",
-                 Variant::I);
-        hashtest(Version::Version13, 1, 16, 1, "password", "somesalt",
-                 "d168075c4d985e13ebeae560cf8b94c3b5d8a16c51916b6f4ac2da3ac11bbecf",
-                 "$argon2i$v=19$m=65536,t=1,p=1$c29tZXNhbHQ$0WgHXE2YXhPr6uVgz4uUw7XYoWxRkWtvSsLaOsEbvs8",
-                 Variant::I);
-        hashtest(Version::Version13, 4, 16, 1, "password", "somesalt",
-                 "aaa953d58af3706ce3df1aefd4a64a84e31d7f54175231f1285259f88174ce5b",
-                 "$argon2i$v=19$m=65536,t=4,p=1$c29tZXNhbHQ$qqlT1YrzcGzj3xrv1KZKhOMdf1QXUjHxKFJZ+IF0zls",
-                 Variant::I);
-        hashtest(Version::Version13, 2, 16, 1, "differentpassword", "somesalt",
-                 "14ae8da01afea8700c2358dcef7c5358d9021282bd88663a4562f59fb74d22ee",
-                 "$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$FK6NoBr+qHAMI1jc73xTWNkCEoK9iGY6RWL1n7dNIu4",
-                 Variant::I);
-        hashtest(Version::Version13, 2, 16, 1, "password", "diffsalt",
-                 "b0357cccfbef91f3860b0dba447b2348cbefecadaf990abfe9cc40726c521271",
-                 "$argon2i$v=19$m=65536,t=2,p=1$ZGlmZnNhbHQ$sDV8zPvvkfOGCw26RHsjSMvv7K2vmQq/6cxAcmxSEnE",
-                 Variant::I);
+        let salt = Salt::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+        for &(variant, version, m_cost, t_cost, p_cost, fixture) in fixtures.iter() {
+            let parsed = PasswordHash::new(fixture).unwrap();
+            let output_len = parsed.hash.unwrap().len();
+            let params = PhcParams { m_cost, t_cost, p_cost, output_len };
+            let hasher = PhcHasher::new(variant, version, params);
+            assert_eq!(fixture, hasher.hash_password(b"password", salt).unwrap().to_string());
+
+            // Verification uses the variant and version of the hash, not those of the hasher.
+            let verifier = PhcHasher::default();
+            assert_eq!(Ok(()), verifier.verify_password(b"password", &parsed));
+            assert_eq!(Err(password_hash::Error::Password), verifier.verify_password(b"passwore", &parsed));
+            let encoded = EncodedHash::parse(fixture).unwrap();
+            assert_eq!(Ok(()), verifier.verify(b"password", &encoded));
+            assert!(verifier.verify(b"passwore", &encoded).unwrap_err().is_verify_mismatch());
+
+            // Strings produced by the C library's own encoder are accepted as well.
+            let encoded = hash_encoded_string(t_cost, m_cost, p_cost, b"password", b"somesaltsomesalt", output_len, variant, version).unwrap();
+            if version.to_int() == 0x13 {
+                assert_eq!(fixture, encoded);
+            }
+            assert_eq!(Ok(()), verifier.verify_password(b"password", &PasswordHash::new(&encoded).unwrap()));
+        }
+
+        let hasher = PhcHasher::default();
+        let salt = Salt::from_b64("c2hvcnQ").unwrap();
+        assert_eq!(Err(password_hash::Error::SaltInvalid(password_hash::errors::InvalidValue::TooShort)),
+                   hasher.hash_password(b"password", salt).map(|_| ()));
+        let params = PhcParams { output_len: 4, ..PhcParams::default() };
+        assert!(matches!(hasher.hash_password_customized(b"password", None, None, params, salt),
+                         Err(password_hash::Error::OutputSize { .. })));
+        let unknown = PasswordHash::new("$argon2id$v=19$m=256,t=2,p=1,x=1$c29tZXNhbHRzb21lc2FsdA$yrdGtGIZk/3JHsUHh5gLQUqQppLwvGjf4Z+cJbPLqew").unwrap();
+        assert_eq!(Err(password_hash::Error::ParamNameInvalid), hasher.verify_password(b"password", &unknown));
+        let scrypt = password_hash::Ident::new("scrypt").unwrap();
+        assert_eq!(Err(password_hash::Error::Algorithm),
+                   hasher.hash_password_customized(b"password", Some(scrypt), None, PhcParams::default(), salt).map(|_| ()));
     }
 
+    #[cfg(feature = "cross-check")]
     #[test]
-    #[ignore]
-    pub fn test_argon2i_0x13_large_ram() {
-        hashtest(Version::Version13, 2, 20, 1, "password", "somesalt",
-                 "d1587aca0922c3b5d6a83edab31bee3c4ebaef342ed6127a55d19b2351ad1f41",
-                 "$argon2i$v=19$m=1048576,t=2,p=1$c29tZXNhbHQ$0Vh6ygkiw7XWqD7asxvuPE667zQu1hJ6VdGbI1GtH0E",
-                 Variant::I);
+    fn test_cross_check() {
+        use password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, Salt};
+
+        // The vectors without secret that are small enough for debug builds of the pure Rust crate.
+        for vector in test_vectors::all().filter(|vector| vector.m_cost <= 256 && vector.secret.is_empty()) {
+            let params = PhcParams {
+                m_cost: vector.m_cost,
+                t_cost: vector.t_cost,
+                p_cost: vector.parallelism,
+                output_len: vector.hash_len(),
+            };
+            let mut out = vec![0u8; vector.hash_len()];
+            hash(params.t_cost, params.m_cost, params.p_cost, Some(vector.password), Some(vector.salt), Some(&mut out), None,
+                 vector.variant, vector.version).unwrap();
+            assert_eq!(Ok(()), cross_check(&params, vector.password, vector.salt, &out, vector.variant, vector.version));
+
+            out[0] ^= 1;
+            assert_eq!(Err(Error::CrossCheckMismatch),
+                       cross_check(&params, vector.password, vector.salt, &out, vector.variant, vector.version));
+            out[0] ^= 1;
+
+            // A corrupted result of the second implementation is caught as well.
+            assert_eq!(Err(Error::CrossCheckMismatch), rustcrypto::cross_check_with(&out, |other| {
+                other.copy_from_slice(&out);
+                other[other.len() - 1] ^= 0x80;
+                Ok(())
+            }));
+        }
+
+        let params = PhcParams { m_cost: 256, t_cost: 2, p_cost: 2, output_len: 32 };
+        let hasher = PhcHasher::new(Variant::ID, Version::Version13, params);
+        let checked = hasher.clone().with_cross_check(true);
+        let salt = Salt::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+        let hash = checked.hash_password(b"password", salt).unwrap();
+        assert_eq!(hasher.hash_password(b"password", salt).unwrap(), hash);
+        assert_eq!(Ok(()), checked.verify_password(b"password", &hash));
+        assert_eq!(Err(password_hash::Error::Password), checked.verify_password(b"passwore", &hash));
+        let parsed = PasswordHash::new("$argon2i$v=16$m=256,t=2,p=1$c29tZXNhbHRzb21lc2FsdA$xw01fBXWdoeA9mwz9ZQrZZw1K05/qsgbvHAvbVXvstI").unwrap();
+        assert_eq!(Ok(()), checked.verify_password(b"password", &parsed));
+
+        assert_eq!("Hash differs from the one of the cross-check implementation", Error::CrossCheckMismatch.to_string());
     }
 
+    #[cfg(feature = "random-salt")]
     #[test]
-    fn test_argon2i_0x13_errors() {
-        // Handle an invalid encoding correctly (it is missing a $)
-        check_error_code!(DecodingFail, verify(
-                &c_str_cow(b"$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQwWKIMhR9lyDFvRz9YTZweHKfbftvj+qf+YFY4NeBbtA"),
-                Some(b"password"), Variant::I));
+    fn test_salt_source() {
+        use insecure::FixedSaltSource;
 
-        // Handle an invalid encoding correctly (it is missing a $)
-        check_error_code!(DecodingFail, verify(
-                &c_str_cow(b"$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQwWKIMhR9lyDFvRz9YTZweHKfbftvj+qf+YFY4NeBbtA"),
-                Some(b"password"), Variant::I));
+        let params = PhcParams { m_cost: 256, t_cost: 2, p_cost: 1, output_len: 32 };
+        let hasher = PhcHasher::new(Variant::ID, Version::Version13, params)
+            .with_salt_source(FixedSaltSource::new(&b"somesaltsomesalt"[..]));
+        let expected = "$argon2id$v=19$m=256,t=2,p=1$c29tZXNhbHRzb21lc2FsdA$yrdGtGIZk/3JHsUHh5gLQUqQppLwvGjf4Z+cJbPLqew";
+        assert_eq!(expected, hasher.hash_new_password(b"password").unwrap().as_str());
+        assert_eq!(expected, hasher.clone().hash_new_password(b"password").unwrap().as_str());
 
-        // Handle an invalid encoding correctly (salt is too short)
-        check_error_code!(SaltTooShort, verify(
-                &c_str_cow(b"$argon2i$v=19$m=65536,t=2,p=1$$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ"),
-                Some(b"password"), Variant::I));
+        let short = PhcHasher::default().with_salt_source(FixedSaltSource::new(&b"somesalt"[..]));
+        assert_eq!(Err(Error::InvalidParameter { name: "salt.len", got: 16, min: 8, max: 8 }),
+                   short.hash_new_password(b"password").map(|_| ()));
 
-        // Handle an invalid encoding correctly (the encoded password is "passwore")
-        check_error_code!(VerifyMismatch, verify(
-                &c_str_cow(b"$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$8iIuixkI73Js3G1uMbezQXD0b8LG4SXGsOwoQkdAQIM"),
-                Some(b"password"), Variant::I));
+        // The default source gives a different salt every time.
+        let hasher = PhcHasher::new(Variant::ID, Version::Version13, params);
+        let first = hasher.hash_new_password(b"password").unwrap();
+        let second = hasher.hash_new_password(b"password").unwrap();
+        assert_eq!(PhcHasher::NEW_SALT_LEN, first.salt().len());
+        assert_ne!(first.salt(), second.salt());
+        assert_eq!(Ok(()), verify_bytes(first.as_str().as_bytes(), Some(b"password"), Variant::ID));
+
+        let mut source = OsSaltSource;
+        let mut salt = [0u8; 32];
+        source.fill(&mut salt).unwrap();
+        assert_ne!([0u8; 32], salt);
     }
 
+    #[cfg(feature = "random-salt")]
     #[test]
-    fn test_argon2id_0x13() {
-        println!("Test Argon2id version number: 0x{:02X}", (Version::Version13).to_int());
+    fn test_hash_password() {
+        let encoded = hash_password("hunter2").unwrap();
+        assert_eq!(Ok(true), verify_password(&encoded, "hunter2"));
+        assert_eq!(Ok(false), verify_password(&encoded, "hunter3"));
+        assert_eq!(Ok(false), verify_password(&encoded, ""));
 
-        hashtest(Version::Version13, 2, 16, 1, "password", "somesalt",
-                 "09316115d5cf24ed5a15a31a3ba326e5cf32edc24702987c02b6566f61913cf7",
-                 "$argon2id$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$CTFhFdXPJO1aFaMaO6Mm5c8y7cJHAph8ArZWb2GRPPc", Variant::ID);
-        hashtest(Version::Version13, 2, 18, 1, "password", "somesalt",
-                 "78fe1ec91fb3aa5657d72e710854e4c3d9b9198c742f9616c2f085bed95b2e8c",
-                 "$argon2id$v=19$m=262144,t=2,p=1$c29tZXNhbHQ$eP4eyR+zqlZX1y5xCFTkw9m5GYx0L5YWwvCFvtlbLow", Variant::ID);
-        hashtest(Version::Version13, 2, 8, 1, "password", "somesalt",
-                 "9dfeb910e80bad0311fee20f9c0e2b12c17987b4cac90c2ef54d5b3021c68bfe",
-                 "$argon2id$v=19$m=256,t=2,p=1$c29tZXNhbHQ$nf65EOgLrQMR/uIPnA4rEsF5h7TKyQwu9U1bMCHGi/4", Variant::ID);
-        hashtest(Version::Version13, 2, 8, 2, "password", "somesalt",
-                 "6d093c501fd5999645e0ea3bf620d7b8be7fd2db59c20d9fff9539da2bf57037",
-                 "$argon2id$v=19$m=256,t=2,p=2$c29tZXNhbHQ$bQk8UB/VmZZF4Oo79iDXuL5/0ttZwg2f/5U52iv1cDc", Variant::ID);
-        hashtest(Version::Version13, 1, 16, 1, "password", "somesalt",
-                 "f6a5adc1ba723dddef9b5ac1d464e180fcd9dffc9d1cbf76cca2fed795d9ca98",
-                 "$argon2id$v=19$m=65536,t=1,p=1$c29tZXNhbHQ$9qWtwbpyPd3vm1rB1GThgPzZ3/ydHL92zKL+15XZypg", Variant::ID);
-        hashtest(Version::Version13, 4, 16, 1, "password", "somesalt",
-                 "9025d48e68ef7395cca9079da4c4ec3affb3c8911fe4f86d1a2520856f63172c",
-                 "$argon2id$v=19$m=65536,t=4,p=1$c29tZXNhbHQ$kCXUjmjvc5XMqQedpMTsOv+zyJEf5PhtGiUghW9jFyw", Variant::ID);
-        hashtest(Version::Version13, 2, 16, 1, "differentpassword", "somesalt",
-                 "0b84d652cf6b0c4beaef0dfe278ba6a80df6696281d7e0d2891b817d8c458fde",
-                 "$argon2id$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$C4TWUs9rDEvq7w3+J4umqA32aWKB1+DSiRuBfYxFj94", Variant::ID);
-        hashtest(Version::Version13, 2, 16, 1, "password", "diffsalt",
-                 "bdf32b05ccc42eb15d58fd19b1f856b113da1e9a5874fdcc544308565aa8141c",
-                 "$argon2id$v=19$m=65536,t=2,p=1$ZGlmZnNhbHQ$vfMrBczELrFdWP0ZsfhWsRPaHppYdP3MVEMIVlqoFBw", Variant::ID);
+        let hash = EncodedHash::parse(&encoded).unwrap();
+        let params = PhcParams::default();
+        assert_eq!(Variant::ID.name(), hash.variant().name());
+        assert_eq!(Version::DEFAULT.to_int(), hash.version().to_int());
+        assert!(hash.has_version());
+        assert_eq!((params.m_cost, params.t_cost, params.p_cost), (hash.m_cost(), hash.t_cost(), hash.parallelism()));
+        assert_eq!((16, params.output_len), (hash.salt().len(), hash.hash().len()));
+        assert_ne!(hash.salt(), EncodedHash::parse(&hash_password("hunter2").unwrap()).unwrap().salt());
+
+        // The variant is detected from the hash.
+        let argon2i = "$argon2i$v=19$m=256,t=2,p=1$c29tZXNhbHQ$iekCn0Y3spW+sCcFanM2xBT63UP2sghkUoHLIUpWRS8";
+        assert_eq!(Ok(true), verify_password(argon2i, "password"));
+        assert_eq!(Ok(false), verify_password(argon2i, "passwore"));
+        check_error_code!(DecodingFail, verify_password("$argon2x$v=19$m=256,t=2,p=1$c29tZXNhbHQ$AAAA", "password"));
     }
 
+    #[cfg(all(feature = "random-salt", feature = "rand-core"))]
     #[test]
-    fn test_common_error_states() {
-        const OUTLEN: usize = 32;
-        let mut out = [0u8; OUTLEN];
+    fn test_rng_salt() {
+        use rand_chacha::rand_core::SeedableRng;
+        use rand_chacha::ChaCha20Rng;
 
-        check_error_code!(MemoryTooLittle, hash(2, 1, 1,
-                                                Some(b"password"), Some(b"diffsalt"),
-                                                Some(&mut out), None,
-                                                Variant::ID, Version::Version13));
-        check_error_code!(SaltTooShort, hash(2, 1 << 12, 1,
-                                                Some(b"password"), Some(b"s"),
-                                                Some(&mut out), None,
-                                                Variant::ID, Version::Version13));
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let salt = generate_salt_with_rng(&mut rng, 16).unwrap();
+        assert_eq!(salt, generate_salt_with_rng(&mut ChaCha20Rng::seed_from_u64(1), 16).unwrap());
+        assert_ne!(salt, generate_salt_with_rng(&mut rng, 16).unwrap());
+        assert_eq!(Vec::<u8>::new(), generate_salt_with_rng(&mut rng, 0).unwrap());
 
-        // @NOTE This test is missing because it's not possible to pass a mismatched length/pointer
-        // pair to this function :)
-        //
-        //     ret = argon2_hash(2, 1 << 12, 1, NULL, strlen("password"),
-        //                "diffsalt", strlen("diffsalt"),
-        //                out, OUT_LEN, NULL, 0, Argon2_id, version);
-        //
-        // It would look something like this:
-        //
-        //     check_error_code!(PwdPtrMismatch, hash(2, 1 << 12, 1,
-        //                                            Some(b"password"), Some(b"diffsalt"),
-        //                                            Some(&mut out), None,
-        //                                            Variant::ID, Version::Version13));
+        // The same seed gives the same salts, and hence the same encoded hashes.
+        let params = PhcParams { m_cost: 256, t_cost: 2, p_cost: 1, output_len: 32 };
+        let hash = |seed| {
+            let hasher = PhcHasher::new(Variant::ID, Version::Version13, params).with_rng(ChaCha20Rng::seed_from_u64(seed));
+            (hasher.hash_new_password(b"password").unwrap(), hasher.hash_new_password(b"password").unwrap())
+        };
+        let (first, second) = hash(1);
+        assert_eq!(salt, first.salt());
+        assert_ne!(first.salt(), second.salt());
+        assert_eq!(first.as_str(), hash(1).0.as_str());
+        assert_ne!(first.as_str(), hash(2).0.as_str());
+        assert_eq!(Ok(()), verify_bytes(first.as_str().as_bytes(), Some(b"password"), Variant::ID));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_insecure_test_params() {
+        use insecure::{check_test_mode, insecure_test_params, ALLOW_TEST_PARAMS_VAR};
+        use std::ffi::OsString;
+        use std::panic::catch_unwind;
+
+        // The guard refuses builds without debug assertions unless the variable is `1`.
+        assert!(catch_unwind(|| check_test_mode(false, None)).is_err());
+        assert!(catch_unwind(|| check_test_mode(false, Some(OsString::from("0")))).is_err());
+        assert!(catch_unwind(|| check_test_mode(false, Some(OsString::from("")))).is_err());
+        check_test_mode(false, Some(OsString::from("1")));
+        check_test_mode(true, None);
+
+        if cfg!(not(debug_assertions)) {
+            std::env::set_var(ALLOW_TEST_PARAMS_VAR, "1");
+        }
+        let params = insecure_test_params();
+        assert_eq!(PhcParams { m_cost: 8, t_cost: 1, p_cost: 1, output_len: 32 }, params);
+
+        // Hashing with them is much faster than with the defaults, and the hashes verify normally.
+        let time = |params: &PhcParams| {
+            let start = Instant::now();
+            let hash = hash_encoded_string(params.t_cost, params.m_cost, params.p_cost, b"password", b"somesaltsomesalt", params.output_len, Variant::ID, Version::Version13).unwrap();
+            (start.elapsed(), hash)
+        };
+        let (fast, hash) = time(&params);
+        let (slow, _) = time(&PhcParams::default());
+        assert!(fast * 10 < slow, "{:?} vs. {:?}", fast, slow);
+        assert_eq!(Ok(()), verify_bytes(hash.as_bytes(), Some(b"password"), Variant::ID));
+        assert_eq!(Err(Error::Code(ErrorCode::VerifyMismatch)), verify_bytes(hash.as_bytes(), Some(b"passwore"), Variant::ID));
+
+        #[cfg(feature = "password-hash")]
+        {
+            use password_hash::{PasswordHasher, PasswordVerifier, Salt};
+
+            let hasher = PhcHasher::insecure_for_tests();
+            assert_eq!(&params, hasher.params());
+            let salt = Salt::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+            let hash = hasher.hash_password(b"password", salt).unwrap();
+            assert_eq!(Ok(()), PhcHasher::default().verify_password(b"password", &hash));
+            assert_eq!(Err(password_hash::Error::Password), PhcHasher::default().verify_password(b"passwore", &hash));
+        }
+    }
+
+    #[cfg(feature = "interop-rustcrypto")]
+    #[test]
+    fn test_rustcrypto_conversions() {
+        use rustcrypto_argon2 as rc;
+        use std::convert::TryFrom;
+
+        #[cfg(not(feature = "no-argon2d"))]
+        let variant_from = Variant::from;
+        #[cfg(feature = "no-argon2d")]
+        let variant_from = |algorithm: rc::Algorithm| Variant::try_from(algorithm).unwrap();
+        for variant in Variant::iter() {
+            assert_eq!(variant.name(), variant_from(rc::Algorithm::from(variant)).name());
+        }
+        for version in Version::iter() {
+            assert_eq!(version.to_int(), Version::from(rc::Version::from(version)).to_int());
+        }
+        assert_eq!(Variant::ID.name(), variant_from(rc::Algorithm::default()).name());
+        #[cfg(feature = "no-argon2d")]
+        assert_eq!(Err(Error::UnknownAlgorithm("argon2d")), Variant::try_from(rc::Algorithm::Argon2d).map(|_| ()));
+        assert_eq!(Version::DEFAULT.to_int(), Version::from(rc::Version::default()).to_int());
+
+        // Defaults, the RFC 9106 recommendations and the OWASP recommendations.
+        let presets = [
+            rc::Params::DEFAULT,
+            rc::Params::new(2 << 20, 1, 4, None).unwrap(),
+            rc::Params::new(64 << 10, 3, 4, None).unwrap(),
+            rc::Params::new(46 << 10, 1, 1, None).unwrap(),
+            rc::Params::new(12 << 10, 3, 1, Some(64)).unwrap(),
+            rc::Params::new(7 << 10, 5, 1, Some(16)).unwrap(),
+        ];
+        for preset in presets.iter() {
+            let params = PhcParams::try_from(preset).unwrap();
+            assert_eq!((preset.m_cost(), preset.t_cost(), preset.p_cost()), (params.m_cost, params.t_cost, params.p_cost));
+            assert_eq!(preset.output_len().unwrap_or(32), params.output_len);
+            let back = rc::Params::try_from(params).unwrap();
+            assert_eq!((preset.m_cost(), preset.t_cost(), preset.p_cost()), (back.m_cost(), back.t_cost(), back.p_cost()));
+            assert_eq!(Some(params.output_len), back.output_len());
+            assert_eq!(params, PhcParams::try_from(back).unwrap());
+        }
+        assert_eq!(PhcParams::default(), PhcParams::try_from(rc::Params::DEFAULT).unwrap());
+
+        // Both crates hash the same with converted parameters.
+        let params = PhcParams { m_cost: 64, t_cost: 2, p_cost: 2, output_len: 24 };
+        for variant in Variant::iter() {
+            for version in Version::iter() {
+                let mut expected = [0u8; 24];
+                let rc_params = rc::Params::try_from(params).unwrap();
+                let mut blocks = vec![rc::Block::default(); rc_params.block_count()];
+                rc::Argon2::new(variant.into(), version.into(), rc_params)
+                    .hash_password_into_with_memory(b"password", b"somesaltsomesalt", &mut expected, &mut blocks)
+                    .unwrap();
+                let mut out = [0u8; 24];
+                hash(params.t_cost, params.m_cost, params.p_cost, Some(b"password"), Some(b"somesaltsomesalt"),
+                     Some(&mut out), None, variant, version).unwrap();
+                assert_eq!(expected, out);
+            }
+        }
+
+        let params = PhcParams { m_cost: 16, p_cost: 4, ..PhcParams::default() };
+        assert_eq!(Err(Error::InvalidParameter { name: "m_cost", got: 16, min: 32, max: u32::MAX.into() }),
+                   rc::Params::try_from(params));
+        let params = PhcParams { p_cost: 0, ..PhcParams::default() };
+        assert_eq!(Err(Error::InvalidParameter { name: "p_cost", got: 0, min: 1, max: 0xFFFFFF }),
+                   rc::Params::try_from(params));
+        let params = PhcParams { output_len: 3, ..PhcParams::default() };
+        assert_eq!(Err(Error::InvalidParameter { name: "output_len", got: 3, min: 4, max: 0xFFFFFFFF }),
+                   rc::Params::try_from(params));
+
+        let mut builder = rc::ParamsBuilder::new();
+        builder.keyid(rc::KeyId::new(b"key").unwrap());
+        assert_eq!(Err(Error::BadParam("keyid")), PhcParams::try_from(builder.build().unwrap()));
+        let mut builder = rc::ParamsBuilder::new();
+        builder.data(rc::AssociatedData::new(b"data").unwrap());
+        assert_eq!(Err(Error::BadParam("data")), PhcParams::try_from(builder.build().unwrap()));
+    }
+
+    const ENCODED_VECTORS: [&str; 6] = [
+        "$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ",
+        "$argon2i$m=256,t=2,p=2$c29tZXNhbHQ$tsEVYKap1h6scGt5ovl9aLRGOqOth+AMB+KwHpDFZPs",
+        "$argon2i$m=65536,t=2,p=1$ZGlmZnNhbHQ$eaEDuQ/orvhXDLMfyLIiWXeJFvgza3vaw4kladTxxJc",
+        "$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweHKfbftvj+qf+YFY4NeBbtA",
+        "$argon2i$v=19$m=1048576,t=2,p=1$c29tZXNhbHQ$0Vh6ygkiw7XWqD7asxvuPE667zQu1hJ6VdGbI1GtH0E",
+        "$argon2id$v=19$m=512,t=2,p=4$c29tZXNhbHRzb21lc2FsdA$mskTs1Hwo3MDziPqDyxw3REpP3VytJOsFPrikRqe1MVYU8PGsxKyIJL6JnjpPLl8oOyO0eoeX6zjCtOokVJlUg",
+    ];
+
+    #[test]
+    fn test_encoded_hash() {
+        for vector in ENCODED_VECTORS.iter() {
+            assert_eq!(*vector, vector.parse::<EncodedHash>().unwrap().to_string());
+        }
+
+        let parsed = EncodedHash::parse(ENCODED_VECTORS[0]).unwrap();
+        assert_eq!("argon2i", parsed.variant().name());
+        assert_eq!(0x10, parsed.version().to_int());
+        assert!(!parsed.has_version());
+        assert_eq!((65536, 2, 1), (parsed.m_cost(), parsed.t_cost(), parsed.parallelism()));
+        assert_eq!(b"somesalt", parsed.salt());
+        assert_eq!(32, parsed.hash().len());
+
+        let mut out = [0u8; 32];
+        hash(2, 256, 2, Some(b"password"), Some(b"somesalt"), Some(&mut out), None, Variant::ID, Version::Version13).unwrap();
+        let encoded = EncodedHash::new(Variant::ID, Version::Version13, 256, 2, 2, b"somesalt".to_vec(), out.to_vec());
+        assert!(encoded.has_version());
+        let encoded = encoded.to_string();
+        assert_eq!(encoded, hash_encoded_string(2, 256, 2, b"password", b"somesalt", 32, Variant::ID, Version::Version13).unwrap());
+        verify(&c_str_cow(encoded.as_bytes()), Some(b"password"), Variant::ID).unwrap();
+
+        for s in ["", "$argon2x$v=19$m=256,t=2,p=1$c29tZXNhbHQ$aGFzaGhhc2g", "$argon2i$v=18$m=256,t=2,p=1$c29tZXNhbHQ$aGFzaGhhc2g",
+                  "$argon2i$v=19$m=256,p=1,t=2$c29tZXNhbHQ$aGFzaGhhc2g", "$argon2i$v=19$m=-1,t=2,p=1$c29tZXNhbHQ$aGFzaGhhc2g",
+                  "$argon2i$v=19$m=256,t=2,p=1$c29tZXNhbHQ=$aGFzaGhhc2g", "$argon2i$v=19$m=256,t=2,p=1$c29tZXNhbHR$aGFzaGhhc2g",
+                  "$argon2i$v=19$m=256,t=2,p=1$c29tZXNhbHQ", "$argon2i$v=19$m=4294967296,t=2,p=1$c29tZXNhbHQ$aGFzaGhhc2g"].iter() {
+            assert_eq!(Err(Error::Code(ErrorCode::DecodingFail)), EncodedHash::parse(s).map(|_| ()), "{}", s);
+        }
+
+        let argon2d = "$argon2d$v=19$m=128,t=1,p=1$c29tZXNhbHRzb21lc2FsdA$17CMmInvVCFdXmvFk/3dQA";
+        if cfg!(feature = "no-argon2d") {
+            assert_eq!(Err(Error::UnknownAlgorithm("argon2d")), EncodedHash::parse(argon2d).map(|_| ()));
+        } else {
+            assert_eq!("argon2d", EncodedHash::parse(argon2d).unwrap().variant().name());
+        }
+    }
+
+    #[cfg(feature = "blake2b")]
+    #[test]
+    fn test_blake2b() {
+        use crate::blake2::{blake2b, blake2b_long, MAX_KEY_LEN, MAX_OUTPUT_LEN};
+
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+
+        // RFC 7693, appendix A.
+        assert_eq!("ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923",
+                   hex(&blake2b(64, &[], b"abc").unwrap()));
+        assert_eq!("0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8", hex(&blake2b(32, &[], b"").unwrap()));
+
+        // The keyed vectors of blake2b-kat.txt of the BLAKE2 reference implementation: the key is
+        // 0x00..=0x3f and the input 0x00..n.
+        let key: Vec<u8> = (0u8..64).collect();
+        let input: Vec<u8> = (0u8..=255).collect();
+        let kat = [
+            (0, "10ebb67700b1868efb4417987acf4690ae9d972fb7a590c2f02871799aaa4786b5e996e8f0f4eb981fc214b005f42d2ff4233499391653df7aefcbc13fc51568"),
+            (1, "961f6dd1e4dd30f63901690c512e78e4b45e4742ed197c3c5e45c549fd25f2e4187b0bc9fe30492b16b0d0bc4ef9b0f34c7003fac09a5ef1532e69430234cebd"),
+            (2, "da2cfbe2d8409a0f38026113884f84b50156371ae304c4430173d08a99d9fb1b983164a3770706d537f49e0c916d9f32b95cc37a95b99d857436f0232c88a965"),
+            (3, "33d0825dddf7ada99b0e7e307104ad07ca9cfd9692214f1561356315e784f3e5a17e364ae9dbb14cb2036df932b77f4b292761365fb328de7afdc6d8998f5fc1"),
+            (64, "65676d800617972fbd87e4b9514e1c67402b7a331096d3bfac22f1abb95374abc942f16e9ab0ead33b87c91968a6e509e119ff07787b3ef483e1dcdccf6e3022"),
+            (128, "72065ee4dd91c2d8509fa1fc28a37c7fc9fa7d5b3f8ad3d0d7a25626b57b1b44788d4caf806290425f9890a3a2a35a905ab4b37acfd0da6e4517b2525c9651e4"),
+            (255, "142709d62e28fcccd0af97fad0f8465b971e82201dc51070faa0372aa43e92484be1c1e73ba10906d5d1853db6a4106e0a7bf9800d373d6dee2d46d62ef2a461"),
+        ];
+        for &(len, expected) in kat.iter() {
+            assert_eq!(expected, hex(&blake2b(64, &key, &input[..len]).unwrap()), "input length {}", len);
+        }
+
+        // H' is BLAKE2b of the length and the input up to 64 bytes, and chains BLAKE2b beyond.
+        for &len in [1, 4, 32, 64].iter() {
+            let mut prefixed = (len as u32).to_le_bytes().to_vec();
+            prefixed.extend_from_slice(b"password");
+            assert_eq!(blake2b(len, &[], &prefixed).unwrap(), blake2b_long(len, b"password").unwrap());
+        }
+        assert_eq!("339ae0af9609bb08b50c63edda48f5feb1010af624465efa5a92a320d0e8c1f15c50030612022b67cd0d7142d10e66e595f6d983efe918ebf38d0fa1408c5a0051",
+                   hex(&blake2b_long(65, b"password").unwrap()));
+        assert_eq!("b10ea62452ed40d08c239f0ad49750e214733d30e44ee962d7a854ca0573cc68f0f91ac341ea2108a53b1fbd74d631d35e946c1520ece52aed5d85d278e6cca024220ee0053a224d9cc1f4d0a8727978a29cd181eada035de77928b1b23b0a94bfedc1bc",
+                   hex(&blake2b_long(100, b"password").unwrap()));
+
+        assert_eq!(Err(Error::InvalidParameter { name: "out_len", got: 0, min: 1, max: 64 }), blake2b(0, &[], b"").map(|_| ()));
+        assert_eq!(Err(Error::InvalidParameter { name: "out_len", got: 65, min: 1, max: 64 }),
+                   blake2b(MAX_OUTPUT_LEN + 1, &[], b"").map(|_| ()));
+        assert_eq!(Err(Error::InvalidParameter { name: "key.len", got: 65, min: 0, max: 64 }),
+                   blake2b(32, &[0; MAX_KEY_LEN + 1], b"").map(|_| ()));
+        assert_eq!(Some("out_len"), blake2b_long(0, b"").unwrap_err().param_name());
+    }
+
+    #[cfg(feature = "blake2b")]
+    #[test]
+    fn test_hash_from_reader() {
+        use std::io::{self, Read};
+
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+
+        /// Returns the input a few bytes at a time, and fails with `Interrupted` in between.
+        struct Trickle<'a> {
+            input: &'a [u8],
+            reads: usize,
+        }
+
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.reads += 1;
+                if self.reads % 5 == 4 {
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+                let len = (1 + self.reads % 7).min(buf.len()).min(self.input.len());
+                buf[..len].copy_from_slice(&self.input[..len]);
+                self.input = &self.input[len..];
+                Ok(len)
+            }
+        }
+
+        // Computed independently: Argon2 of BLAKE2b-512(READER_PREHASH_V1 || input).
+        let input: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+        let params = PhcParams { m_cost: 64, t_cost: 2, p_cost: 1, output_len: 0 };
+        let expected = "218cf5d16ebda59d0d9b27626c55c2d9e127256073c21fd8b211fe088bea55f8";
+        let hash = hash_raw_from_reader(&params, Variant::ID, Version::Version13, &input[..], b"somesaltsomesalt", 32).unwrap();
+        assert_eq!(expected, hex(&hash));
+        let empty = PhcParams { m_cost: 8, t_cost: 1, p_cost: 1, output_len: 0 };
+        assert_eq!("fe1c78ac24c7c43beb90134a914d0f53",
+                   hex(&hash_raw_from_reader(&empty, Variant::I, Version::Version13, io::empty(), b"saltsalt", 16).unwrap()));
+
+        // The hash doesn't depend on how the input is split into reads.
+        let trickle = Trickle { input: &input[..1000], reads: 0 };
+        assert_eq!(hash_raw_from_reader(&params, Variant::ID, Version::Version13, &input[..1000], b"somesaltsomesalt", 32).unwrap(),
+                   hash_raw_from_reader(&params, Variant::ID, Version::Version13, trickle, b"somesaltsomesalt", 32).unwrap());
+
+        assert_eq!(Ok(()), verify_from_reader(&params, Variant::ID, Version::Version13, &input[..], b"somesaltsomesalt", &hash));
+        assert_eq!(Err(Error::Code(ErrorCode::VerifyMismatch)),
+                   verify_from_reader(&params, Variant::ID, Version::Version13, &input[1..], b"somesaltsomesalt", &hash));
+
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::InvalidData.into())
+            }
+        }
+        assert_eq!(Err(Error::Read(io::ErrorKind::InvalidData.into())),
+                   hash_raw_from_reader(&params, Variant::ID, Version::Version13, (&input[..100]).chain(Failing), b"somesaltsomesalt", 32));
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn test_derive_key() {
+        let params = PhcParams { m_cost: 256, t_cost: 2, p_cost: 2, output_len: 16 };
+        let disk = derive_key(&params, b"password", b"somesalt", b"disk-encryption", 32).unwrap();
+        let token = derive_key(&params, b"password", b"somesalt", b"api-token", 32).unwrap();
+        assert_eq!(32, disk.len());
+        assert_ne!(disk, token);
+        assert_eq!(disk, derive_key(&params, b"password", b"somesalt", b"disk-encryption", 32).unwrap());
+        assert_ne!(disk, derive_key(&params, b"password", b"somesalt", b"disk-encryption\0", 32).unwrap());
+
+        // The context is the associated data of Argon2id.
+        let mut expected = [0u8; 32];
+        id_ctx(&mut Context {
+            out: &mut expected,
+            pwd: Some(&mut b"password".to_vec()),
+            salt: Some(&mut b"somesalt".to_vec()),
+            secret: None,
+            ad: Some(&mut b"disk-encryption".to_vec()),
+            t_cost: 2,
+            m_cost: 256,
+            lanes: 2,
+            threads: 2,
+            version: Version::DEFAULT,
+            flags: Flags::DEFAULT,
+        }).unwrap();
+        assert_eq!(&expected[..], &disk[..]);
+
+        let mut raw = [0u8; 32];
+        id_hash_raw(2, 256, 2, Some(b"password"), Some(b"somesalt"), &mut raw).unwrap();
+        assert_eq!(&raw[..], &derive_key(&params, b"password", b"somesalt", b"", 32).unwrap()[..]);
+
+        check_error_code!(OutputTooShort, derive_key(&params, b"password", b"somesalt", b"api-token", 3));
+        check_error_code!(SaltTooShort, derive_key(&params, b"password", b"salt", b"api-token", 32));
+    }
+
+    #[cfg(all(feature = "zeroize", feature = "blake2b"))]
+    #[test]
+    fn test_derive_subkeys() {
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+
+        // The construction is a stable format, these must never change.
+        let params = PhcParams { m_cost: 256, t_cost: 2, p_cost: 2, output_len: 32 };
+        let subkeys = derive_subkeys(&params, b"password", b"somesalt", &[b"cipher", b"mac", b"nonce"], 32).unwrap();
+        assert_eq!(vec![
+            "488363629a74e6c3b2fb09b6f220c099e16e516c2ccb61e7545fbd700fc1ae3b",
+            "23dd90014ce4ba5e7b91640ce56df39c8548a79a294cbc8a8d2478b56874aadf",
+            "e87c1942b87400fcf5be8bbf1cda94e5deb43a17844f228a442f236251675dc2",
+        ], subkeys.iter().map(|key| hex(key)).collect::<Vec<_>>());
+        // The index separates equal labels.
+        let subkeys = derive_subkeys(&params, b"password", b"somesalt", &[b"enc", b"enc"], 16).unwrap();
+        assert_eq!(vec!["24736302ef1b599178e234d1cd9b2b28", "31982479d3298595218123405e959c6a"],
+                   subkeys.iter().map(|key| hex(key)).collect::<Vec<_>>());
+        let subkeys = derive_subkeys(&params, b"password", b"somesalt", &[b"mac"], 64).unwrap();
+        assert_eq!("4bb84e318c61a7dd9e0caeaccc4d560e6e87db77849771fcb83d2489fa354f8729e89ea1aba0312db418f9b72de604b5b81177bda2ba9cb40840ce1ef2c8c598",
+                   hex(&subkeys[0]));
+
+        // The master key is a `derive_key` key, and the subkeys are keyed BLAKE2b of it.
+        let master = derive_key(&params, b"password", b"somesalt", b"derive_subkeys", 64).unwrap();
+        assert_eq!(&blake2::blake2b(64, &master, b"mac\0\0\0\0").unwrap()[..], &subkeys[0][..]);
+
+        assert!(derive_subkeys(&params, b"password", b"somesalt", &[], 32).unwrap().is_empty());
+        assert_eq!(Err(Error::InvalidParameter { name: "subkey_len", got: 0, min: 1, max: 64 }),
+                   derive_subkeys(&params, b"password", b"somesalt", &[b"cipher"], 0));
+        assert_eq!(Err(Error::InvalidParameter { name: "subkey_len", got: 65, min: 1, max: 64 }),
+                   derive_subkeys(&params, b"password", b"somesalt", &[b"cipher"], 65));
+        check_error_code!(SaltTooShort, derive_subkeys(&params, b"password", b"salt", &[b"cipher"], 32));
+    }
+
+    #[test]
+    fn test_kdbx() {
+        // The default Argon2 parameters of KeePass 2.x (1 MiB, 2 iterations, 2 lanes). These aren't
+        // taken from a KeePass database: the salt and the composite key are made up, and the
+        // expected keys were derived with the pure Rust `argon2` crate and reference libargon2.
+        let salt: Vec<u8> = (0u8..32).collect();
+        let key_material: Vec<u8> = (0u8..32).map(|b| b.wrapping_mul(7).wrapping_add(3)).collect();
+        let expected = [
+            (KdbxKdfParams::ARGON2D_UUID, "72d6537bfeb87df86d65dd68ce0f29374cbcd166964c6b804f4c30915495a29b"),
+            (KdbxKdfParams::ARGON2ID_UUID, "1f5753f25d3cb9f38df90614801b59ea39c13b1c1dcdcdb877a0181541053d7d"),
+        ];
+        let supported = expected.iter()
+            .filter(|(uuid, _)| !cfg!(feature = "no-argon2d") || *uuid != KdbxKdfParams::ARGON2D_UUID);
+        for (uuid, expected) in supported {
+            let params = KdbxKdfParams::from_fields(uuid, 1024 * 1024, 2, 2, 0x13, &salt, None, None).unwrap();
+            assert_eq!(*uuid, params.uuid().unwrap());
+            assert_eq!((1024, 2), (params.m_cost().unwrap(), params.t_cost().unwrap()));
+            let key = derive_composite_key(&params, &key_material).unwrap();
+            let mut hex = [0u8; 64];
+            hex_conv(&key, &mut hex);
+            assert_eq!(*expected, str_conv(&hex));
+
+            let same = KdbxKdfParams::new(params.variant, Version::Version13, 1024, 2, 2, params.salt).unwrap();
+            assert_eq!((params.memory, params.iterations), (same.memory, same.iterations));
+            assert_eq!(key, derive_composite_key(&same, &key_material).unwrap());
+        }
+
+        assert_eq!(Err(Error::BadParam("variant")),
+                   KdbxKdfParams::new(Variant::I, Version::Version13, 1024, 2, 2, [0; 32]).map(|_| ()));
+        assert_eq!(Err(Error::BadParam("uuid")),
+                   KdbxKdfParams::from_fields(&[0; 16], 1024 * 1024, 2, 2, 0x13, &salt, None, None).map(|_| ()));
+        assert_eq!(Err(Error::BadParam("version")),
+                   KdbxKdfParams::from_fields(&KdbxKdfParams::ARGON2ID_UUID, 1024 * 1024, 2, 2, 0x12, &salt, None, None).map(|_| ()));
+        assert_eq!(Err(Error::BadParam("salt")),
+                   KdbxKdfParams::from_fields(&KdbxKdfParams::ARGON2ID_UUID, 1024 * 1024, 2, 2, 0x13, &salt[..16], None, None).map(|_| ()));
+
+        #[cfg(feature = "no-argon2d")]
+        assert_eq!(Err(Error::UnknownAlgorithm("argon2d")),
+                   KdbxKdfParams::from_fields(&KdbxKdfParams::ARGON2D_UUID, 1024 * 1024, 2, 2, 0x13, &salt, None, None).map(|_| ()));
+
+        let mut params = KdbxKdfParams::from_fields(&KdbxKdfParams::ARGON2ID_UUID, 1000, 2, 2, 0x13, &salt, None, None).unwrap();
+        assert_eq!(Err(Error::BadParam("memory")), params.m_cost());
+        assert_eq!(Err(Error::BadParam("memory")), derive_composite_key(&params, &key_material));
+        params.memory = 1 << 45;
+        assert_eq!(Err(Error::InvalidParameter { name: "memory", got: 1 << 45, min: 0, max: u64::from(u32::MAX) * 1024 }),
+                   params.m_cost());
+        params.iterations = 1 << 32;
+        assert_eq!(Err(Error::InvalidParameter { name: "iterations", got: 1 << 32, min: 0, max: u32::MAX.into() }),
+                   params.t_cost());
+        params.variant = Variant::I;
+        assert_eq!(Err(Error::BadParam("variant")), params.uuid());
+
+        // `K` and `A` go into the secret and the associated data of Argon2.
+        let mut params = KdbxKdfParams::from_fields(
+            &KdbxKdfParams::ARGON2ID_UUID, 1024 * 1024, 2, 2, 0x13, &salt, Some(&b"key file"[..]), Some(&b"data"[..])).unwrap();
+        let mut expected = [0u8; 32];
+        id_ctx(&mut Context {
+            out: &mut expected,
+            pwd: Some(&mut key_material.clone()),
+            salt: Some(&mut salt.clone()),
+            secret: Some(&mut b"key file".to_vec()),
+            ad: Some(&mut b"data".to_vec()),
+            t_cost: 2,
+            m_cost: 1024,
+            lanes: 2,
+            threads: 2,
+            version: Version::Version13,
+            flags: Flags::DEFAULT,
+        }).unwrap();
+        assert_eq!(expected, derive_composite_key(&params, &key_material).unwrap());
+        params.associated_data = None;
+        let secret_only = derive_composite_key(&params, &key_material).unwrap();
+        assert_ne!(expected, secret_only);
+        params.secret = None;
+        assert_ne!(secret_only, derive_composite_key(&params, &key_material).unwrap());
+
+        let params = KdbxKdfParams::new(Variant::ID, Version::Version13, 1024, 2, 2, [0; 32]).unwrap();
+        assert_eq!((None, None), (params.secret.as_deref(), params.associated_data.as_deref()));
+        assert_eq!(PhcParams { m_cost: 1024, t_cost: 2, p_cost: 2, output_len: 32 },
+                   std::convert::TryFrom::try_from(&params).unwrap());
+    }
+
+    #[test]
+    fn test_sodium_limits() {
+        // Generated by libsodium's `crypto_pwhash_str` for the password "correct horse".
+        let fixtures = [
+            (PhcParams::SODIUM_OPSLIMIT_INTERACTIVE, PhcParams::SODIUM_MEMLIMIT_INTERACTIVE,
+             "$argon2id$v=19$m=65536,t=2,p=1$yKc7aD7kJY+OJWvXkwgClA$BqeGcThhc523SfpZDHGP/1Fmf/C1qVdC9nPIE4QZJes"),
+            (1, 1 << 20, "$argon2id$v=19$m=1024,t=1,p=1$hKeyyxz5CfyU/LBTYN3UXA$tSep/w3czRuC0Uzca08QAzWkqysOWPW8k0Sk8+EXMxw"),
+            (3, (1 << 20) + 1023, "$argon2id$v=19$m=1024,t=3,p=1$ty/mz9UAEgXwIYrqyggcuA$b3JaFCYVL1l918VOFtNIarRowX+LhR+QQ4XtfgocGLo"),
+        ];
+        for &(opslimit, memlimit, fixture) in fixtures.iter() {
+            let params = PhcParams::from_sodium_limits(opslimit, memlimit).unwrap();
+            let parsed = EncodedHash::parse(fixture).unwrap();
+            assert_eq!((parsed.m_cost(), parsed.t_cost(), parsed.parallelism()), (params.m_cost, params.t_cost, params.p_cost));
+            assert_eq!(parsed.hash().len(), params.output_len);
+            assert_eq!((opslimit, memlimit / 1024 * 1024), params.to_sodium_limits().unwrap());
+
+            verify(&c_str_cow(fixture.as_bytes()), Some(b"correct horse"), Variant::ID).unwrap();
+            let encoded = hash_encoded_string(params.t_cost, params.m_cost, params.p_cost, b"correct horse", parsed.salt(),
+                                              params.output_len, Variant::ID, Version::Version13).unwrap();
+            assert_eq!(fixture, encoded);
+        }
+
+        let levels = [
+            (PhcParams::SODIUM_OPSLIMIT_INTERACTIVE, PhcParams::SODIUM_MEMLIMIT_INTERACTIVE, 2, 64 << 10),
+            (PhcParams::SODIUM_OPSLIMIT_MODERATE, PhcParams::SODIUM_MEMLIMIT_MODERATE, 3, 256 << 10),
+            (PhcParams::SODIUM_OPSLIMIT_SENSITIVE, PhcParams::SODIUM_MEMLIMIT_SENSITIVE, 4, 1 << 20),
+        ];
+        for &(opslimit, memlimit, t_cost, m_cost) in levels.iter() {
+            assert_eq!(PhcParams { m_cost, t_cost, p_cost: 1, output_len: 32 }, PhcParams::from_sodium_limits(opslimit, memlimit).unwrap());
+        }
+
+        assert_eq!(Err(Error::InvalidParameter { name: "opslimit", got: 0, min: 1, max: u32::MAX.into() }),
+                   PhcParams::from_sodium_limits(0, PhcParams::SODIUM_MEMLIMIT_MIN));
+        assert_eq!(Err(Error::InvalidParameter { name: "opslimit", got: 1 << 32, min: 1, max: u32::MAX.into() }),
+                   PhcParams::from_sodium_limits(1 << 32, PhcParams::SODIUM_MEMLIMIT_MIN));
+        assert_eq!(Err(Error::InvalidParameter { name: "memlimit", got: 8191, min: 8192, max: PhcParams::SODIUM_MEMLIMIT_MAX as u64 }),
+                   PhcParams::from_sodium_limits(1, 8191));
+        let params = PhcParams { p_cost: 2, ..PhcParams::default() };
+        assert_eq!(Err(Error::InvalidParameter { name: "p_cost", got: 2, min: 1, max: 1 }), params.to_sodium_limits());
+        let params = PhcParams { m_cost: 7, ..PhcParams::default() };
+        assert_eq!(Err(Error::InvalidParameter { name: "m_cost", got: 7, min: 8, max: (PhcParams::SODIUM_MEMLIMIT_MAX / 1024) as u64 }),
+                   params.to_sodium_limits());
+    }
+
+    #[test]
+    fn test_encoded_hash_bytes() {
+        for vector in test_vectors::all() {
+            let encoded = match vector.encoded {
+                Some(encoded) => encoded,
+                None => continue,
+            };
+            let hash = EncodedHash::parse(encoded).unwrap();
+            let bytes = hash.to_bytes();
+            assert_eq!(22 + vector.salt.len() + vector.hash_len(), bytes.len());
+            assert!(bytes.len() * 10 < encoded.len() * 8, "{}", encoded);
+            let decoded = EncodedHash::from_bytes(&bytes).unwrap();
+            assert_eq!(encoded, decoded.as_str());
+            assert_eq!(hash.has_version(), decoded.has_version());
+            assert_eq!(bytes, decoded.to_bytes());
+        }
+
+        let hash = EncodedHash::parse("$argon2id$v=19$m=65536,t=2,p=4$c29tZXNhbHQ$aGFzaA").unwrap();
+        let mut expected = vec![0x12, 0x13, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00];
+        expected.extend_from_slice(&[8, 0, 0, 0]);
+        expected.extend_from_slice(b"somesalt");
+        expected.extend_from_slice(&[4, 0, 0, 0]);
+        expected.extend_from_slice(b"hash");
+        assert_eq!(expected, hash.to_bytes());
+        let no_version = EncodedHash::parse("$argon2i$m=256,t=2,p=1$c29tZXNhbHQ$aGFzaA").unwrap();
+        assert_eq!(&[0x11, 0x00], &no_version.to_bytes()[..2]);
+
+        // Truncated, with bytes left over, or with a corrupted tag or version.
+        for len in 0..expected.len() {
+            check_error_code!(DecodingFail, EncodedHash::from_bytes(&expected[..len]).map(|_| ()));
+        }
+        let mut longer = expected.clone();
+        longer.push(0);
+        check_error_code!(DecodingFail, EncodedHash::from_bytes(&longer).map(|_| ()));
+        for tag in [0x00, 0x02, 0x13, 0x20, 0x22, 0xff] {
+            let mut corrupted = expected.clone();
+            corrupted[0] = tag;
+            check_error_code!(DecodingFail, EncodedHash::from_bytes(&corrupted).map(|_| ()));
+        }
+        let mut corrupted = expected.clone();
+        corrupted[1] = 0x12;
+        check_error_code!(DecodingFail, EncodedHash::from_bytes(&corrupted).map(|_| ()));
+        let mut corrupted = expected.clone();
+        corrupted[14] = 0xff;
+        check_error_code!(DecodingFail, EncodedHash::from_bytes(&corrupted).map(|_| ()));
+    }
+
+    #[test]
+    fn test_encoded_hash_hand_padded() {
+        // A libsodium hash of "correct horse". The padded form isn't the output of any encoder:
+        // the `=` padding of standard base64 was added to the salt and the hash by hand.
+        let canonical = "$argon2id$v=19$m=1024,t=1,p=1$hKeyyxz5CfyU/LBTYN3UXA$tSep/w3czRuC0Uzca08QAzWkqysOWPW8k0Sk8+EXMxw";
+        let padded = "$argon2id$v=19$m=1024,t=1,p=1$hKeyyxz5CfyU/LBTYN3UXA==$tSep/w3czRuC0Uzca08QAzWkqysOWPW8k0Sk8+EXMxw=";
+        let padding = ParseOptions { allow_padding: true };
+
+        assert_eq!(Err(Error::Code(ErrorCode::DecodingFail)), EncodedHash::parse(padded).map(|_| ()));
+        assert!(verify_bytes(padded.as_bytes(), Some(b"correct horse"), Variant::ID).unwrap_err().is_decoding_error());
+
+        let parsed = EncodedHash::parse_with(padded, padding).unwrap();
+        assert_eq!(padded, parsed.as_str());
+        assert!(!parsed.is_canonical());
+        assert_eq!(canonical, parsed.canonicalize().as_str());
+        assert!(parsed.canonicalize().is_canonical());
+        assert_eq!(Ok(()), parsed.verify(Some(b"correct horse")));
+        assert!(parsed.verify(Some(b"wrong horse")).unwrap_err().is_verify_mismatch());
+
+        let unpadded = EncodedHash::parse_with(canonical, padding).unwrap();
+        assert!(unpadded.is_canonical());
+        assert_eq!(Ok(()), unpadded.verify(Some(b"correct horse")));
+        assert!(!EncodedHash::parse("$argon2id$m=1024,t=1,p=1$hKeyyxz5CfyU/LBTYN3UXA$tSep/w3czRuC0Uzca08QAzWkqysOWPW8k0Sk8+EXMxw")
+            .unwrap()
+            .is_canonical());
+
+        // Only padding of the right length is stripped.
+        for wrong in ["$hKeyyxz5CfyU/LBTYN3UXA=$", "$hKeyyxz5CfyU/LBTYN3UXA===$", "$hKeyyxz5CfyU/LBTYN3UX==$", "$=$"] {
+            let encoded = padded.replacen("$hKeyyxz5CfyU/LBTYN3UXA==$", wrong, 1);
+            assert_eq!(Err(Error::Code(ErrorCode::DecodingFail)), EncodedHash::parse_with(&encoded, padding).map(|_| ()));
+        }
+    }
+
+    #[cfg(feature = "password-hash")]
+    #[test]
+    fn test_encoded_hash_password_hash() {
+        use password_hash::{PasswordHash, PasswordHasher, SaltString};
+        use std::convert::TryFrom;
+
+        for vector in ENCODED_VECTORS.iter() {
+            let encoded = EncodedHash::parse(vector).unwrap();
+            let phc = PasswordHash::try_from(&encoded).unwrap();
+            assert_eq!(*vector, phc.to_string());
+            assert_eq!(*vector, EncodedHash::try_from(&PasswordHash::new(vector).unwrap()).unwrap().to_string());
+        }
+
+        let missing = PasswordHash::new("$argon2i$v=19$m=256,t=2$c29tZXNhbHQ$aGFzaGhhc2hoYXNo").unwrap();
+        assert_eq!(Err(password_hash::Error::PhcStringField), EncodedHash::try_from(&missing).map(|_| ()));
+        let scrypt = PasswordHash::new("$scrypt$ln=4,r=8,p=1$c29tZXNhbHQ$aGFzaGhhc2hoYXNo").unwrap();
+        assert_eq!(Err(password_hash::Error::Algorithm), EncodedHash::try_from(&scrypt).map(|_| ()));
+        let short = EncodedHash::new(Variant::I, Version::Version13, 256, 2, 1, b"somesalt".to_vec(), vec![0; 4]);
+        assert!(PasswordHash::try_from(&short).is_err());
+
+        let salt = SaltString::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+        assert_eq!(b"somesaltsomesalt".to_vec(), decode_salt(&salt).unwrap());
+        let hasher = PhcHasher::new(Variant::ID, Version::Version13, PhcParams { m_cost: 256, t_cost: 2, p_cost: 1, output_len: 32 });
+        let expected = hasher.hash_password(b"password", &salt).unwrap().to_string();
+        let encoded = hash_encoded_string(2, 256, 1, b"password", &decode_salt(&salt).unwrap(), 32, Variant::ID, Version::Version13).unwrap();
+        assert_eq!(expected, encoded);
+        assert_eq!(Err(Error::BadParam("salt")), decode_salt(password_hash::Salt::from_b64("c29tZXNhbHR").unwrap()));
+    }
+
+    #[test]
+    fn test_argon2i_0x10_errors() {
+        // Handle an invalid encoding correctly (it is missing a $)
+        check_error_code!(DecodingFail, verify(&c_str_cow(b"$argon2i$m=65536,t=2,p=1c29tZXNhbHQ$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ"),
+               Some(b"password"), Variant::I));
+
+        // Handle an invalid encoding correctly (it is missing a $)
+        check_error_code!(DecodingFail, verify(&c_str_cow(b"$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ"),
+               Some(b"password"), Variant::I));
+
+        // Handle an invalid encoding correctly (salt is too short)
+        check_error_code!(SaltTooShort, verify(&c_str_cow(b"$argon2i$m=65536,t=2,p=1$$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ"),
+               Some(b"password"), Variant::I));
+
+        // Handle an invalid encoding correctly (the encoded password is "passwore")
+        check_error_code!(VerifyMismatch, verify(&c_str_cow(b"$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ$b2G3seW+uPzerwQQC+/E1K50CLLO7YXy0JRcaTuswRo"),
+               Some(b"password"), Variant::I));
+    }
+
+    #[test]
+    fn test_verify_bytes() {
+        let malformed: [&[u8]; 4] = [
+            b"$argon2i$m=65536,t=2,p=1c29tZXNhbHQ$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ",
+            b"$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ",
+            b"$argon2i$m=65536,t=2,p=1$$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ",
+            b"$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ$b2G3seW+uPzerwQQC+/E1K50CLLO7YXy0JRcaTuswRo",
+        ];
+        for encoded in &malformed {
+            let expected = verify(&c_str_cow(encoded), Some(b"password"), Variant::I);
+            assert!(expected.is_err());
+            assert_eq!(expected, verify_bytes(encoded, Some(b"password"), Variant::I));
+            let mut terminated = encoded.to_vec();
+            terminated.extend_from_slice(b"\0garbage");
+            assert_eq!(expected, verify_bytes(&terminated, Some(b"password"), Variant::I));
+        }
+
+        // Encoded strings right at the size of the stack buffer, and just past it.
+        for &len in &[ENCODED_STACK_LEN - 1, ENCODED_STACK_LEN] {
+            let (saltlen, hashlen) = (8..ENCODED_STACK_LEN as u32)
+                .flat_map(|saltlen| (4..64).map(move |hashlen| (saltlen, hashlen)))
+                .find(|&(saltlen, hashlen)| encodedlen(2, 1 << 5, 1, saltlen, hashlen, Variant::ID) > len)
+                .unwrap();
+            let salt = vec![b's'; saltlen as usize];
+            let mut encoded = vec![0u8; len + 1];
+            id_hash_encoded(2, 1 << 5, 1, Some(b"password"), Some(&salt), hashlen as usize, &mut encoded).unwrap();
+            let encoded = c_str(&encoded).unwrap().to_bytes();
+            assert_eq!(len, encoded.len());
+            assert_eq!(Ok(()), verify_bytes(encoded, Some(b"password"), Variant::ID));
+            assert!(verify_bytes(encoded, Some(b"wrong"), Variant::ID).unwrap_err().is_verify_mismatch());
+        }
+    }
+
+    #[test]
+    fn test_argon2i_0x13() {
+        println!("Test Argon2i version number: 0x{:02X}", (Version::Version13).to_int());
+        hashtest_all(test_vectors::ARGON2I_V13, false);
+    }
+
+    #[test]
+    #[ignore]
+    pub fn test_argon2i_0x13_large_ram() {
+        hashtest_all(test_vectors::ARGON2I_V13, true);
+    }
+
+    #[test]
+    fn test_argon2i_0x13_errors() {
+        // Handle an invalid encoding correctly (it is missing a $)
+        check_error_code!(DecodingFail, verify(
+                &c_str_cow(b"$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQwWKIMhR9lyDFvRz9YTZweHKfbftvj+qf+YFY4NeBbtA"),
+                Some(b"password"), Variant::I));
+
+        // Handle an invalid encoding correctly (it is missing a $)
+        check_error_code!(DecodingFail, verify(
+                &c_str_cow(b"$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQwWKIMhR9lyDFvRz9YTZweHKfbftvj+qf+YFY4NeBbtA"),
+                Some(b"password"), Variant::I));
+
+        // Handle an invalid encoding correctly (salt is too short)
+        check_error_code!(SaltTooShort, verify(
+                &c_str_cow(b"$argon2i$v=19$m=65536,t=2,p=1$$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ"),
+                Some(b"password"), Variant::I));
+
+        // Handle an invalid encoding correctly (the encoded password is "passwore")
+        check_error_code!(VerifyMismatch, verify(
+                &c_str_cow(b"$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$8iIuixkI73Js3G1uMbezQXD0b8LG4SXGsOwoQkdAQIM"),
+                Some(b"password"), Variant::I));
+    }
+
+    #[test]
+    fn test_argon2id_0x13() {
+        println!("Test Argon2id version number: 0x{:02X}", (Version::Version13).to_int());
+        hashtest_all(test_vectors::ARGON2ID_V13, false);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_threads() {
+        assert!(capabilities().threads_enabled);
+        let vector = test_vectors::KATS.iter()
+            .find(|vector| vector.variant.name() == "argon2id" && vector.version.to_int() == 0x13)
+            .unwrap();
+        assert_eq!(4, vector.parallelism);
+        let mut out = vec![0u8; vector.hash_len()];
+        id_ctx(&mut Context {
+            out: &mut out,
+            pwd: Some(&mut tovec(vector.password)),
+            salt: Some(&mut tovec(vector.salt)),
+            secret: Some(&mut tovec(vector.secret)),
+            ad: Some(&mut tovec(vector.ad)),
+            t_cost: vector.t_cost,
+            m_cost: vector.m_cost,
+            lanes: 4,
+            threads: 4,
+            version: vector.version,
+            flags: Flags::DEFAULT,
+        }).expect("Hashing with 4 threads failed.");
+        let mut hex_out = vec![0u8; out.len() * 2];
+        hex_conv(&out, &mut hex_out);
+        assert_eq!(vector.hash, str_conv(&hex_out));
+    }
+
+    #[test]
+    fn test_kats() {
+        for vector in test_vectors::KATS {
+            let mut out = vec![0u8; vector.hash_len()];
+            ctx(&mut Context {
+                out: &mut out,
+                pwd: Some(&mut tovec(vector.password)),
+                salt: Some(&mut tovec(vector.salt)),
+                secret: Some(&mut tovec(vector.secret)),
+                ad: Some(&mut tovec(vector.ad)),
+                t_cost: vector.t_cost,
+                m_cost: vector.m_cost,
+                lanes: vector.parallelism,
+                threads: vector.parallelism,
+                version: vector.version,
+                flags: Flags::DEFAULT,
+            }, vector.variant).expect("Test hash failed.");
+            let mut hex_out = vec![0u8; out.len() * 2];
+            hex_conv(&out, &mut hex_out);
+            assert_eq!(vector.hash, str_conv(&hex_out), "{:?} {:?}", vector.variant, vector.version);
+        }
+        assert_eq!(test_vectors::ARGON2I_V10.len() + test_vectors::ARGON2I_V13.len()
+                   + test_vectors::ARGON2ID_V13.len() + test_vectors::KATS.len(), test_vectors::all().count());
+    }
+
+    #[test]
+    fn test_self_test() {
+        self_test().unwrap();
+
+        let wrong_hash = test_vectors::TestVector { hash: test_vectors::KATS[0].hash, ..test_vectors::ARGON2ID_V13[2] };
+        let err = selftest::check(&wrong_hash).unwrap_err();
+        assert!(matches!((err.variant, err.version, &err.failure), (Variant::ID, Version::Version13, SelfTestFailure::HashMismatch)), "{:?}", err);
+        assert_eq!(test_vectors::KATS[0].hash, err.expected);
+        assert_eq!(format!("Self test of argon2id version 0x13 failed for {}: hash mismatch", err.expected), err.to_string());
+
+        let wrong_encoded = test_vectors::TestVector { encoded: test_vectors::ARGON2I_V13[3].encoded, ..test_vectors::ARGON2ID_V13[2] };
+        let err = selftest::check(&wrong_encoded).unwrap_err();
+        assert!(matches!(err.failure, SelfTestFailure::Error(Error::Code(ErrorCode::DecodingFail))), "{:?}", err);
+
+        let wrong_secret = test_vectors::TestVector { secret: b"secret", ..test_vectors::KATS[1] };
+        assert!(matches!(selftest::check(&wrong_secret).unwrap_err().failure, SelfTestFailure::HashMismatch));
+        let no_salt = test_vectors::TestVector { salt: b"", ..test_vectors::KATS[1] };
+        let err = selftest::check(&no_salt).unwrap_err();
+        assert!(matches!(err.failure, SelfTestFailure::Error(Error::Code(ErrorCode::SaltTooShort))), "{:?}", err);
+        assert!(std::error::Error::source(&err).is_some());
+    }
+
+    /// Hashes with arbitrary variants and versions at tiny costs and makes sure every result
+    /// verifies.
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_round_trip() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let seed: Vec<u8> = (0u8..128).map(|b| b.wrapping_mul(151).wrapping_add(7)).collect();
+        let mut u = Unstructured::new(&seed);
+
+        for _ in 0..8 {
+            let variant = Variant::arbitrary(&mut u).expect("Failed to generate variant.");
+            let version = Version::arbitrary(&mut u).expect("Failed to generate version.");
+            let t_cost = u.int_in_range(1..=2).expect("Failed to generate t_cost.");
+            let m_cost = u.int_in_range(8..=32).expect("Failed to generate m_cost.");
+
+            let mut out = [0u8; 32];
+            let mut encoded = [0u8; 128];
+            hash(t_cost, m_cost, 1, Some(b"password"), Some(b"somesalt"),
+                 Some(&mut out), Some(&mut encoded), variant, version).expect("Test hash failed.");
+            verify(c_str(&encoded).expect("bad C string."), Some(b"password"), variant)
+                .expect("Failed verify.");
+        }
+    }
+
+    /// Hashes with arbitrary valid parameters and makes sure every result verifies, and that the
+    /// invalid ones are rejected.
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_params() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let seed: Vec<u8> = (0u8..=255).map(|b| b.wrapping_mul(89).wrapping_add(3)).collect();
+        let mut u = Unstructured::new(&seed);
+
+        for _ in 0..8 {
+            let params = PhcParams::arbitrary(&mut u).expect("Failed to generate params.");
+            let variant = Variant::arbitrary(&mut u).expect("Failed to generate variant.");
+            assert_eq!(Ok(()), params.validate());
+            let encoded = hash_encoded_string(params.t_cost, params.m_cost, params.p_cost, b"password", b"somesalt",
+                                              params.output_len, variant, Version::DEFAULT)
+                .expect("Test hash failed.");
+            assert_eq!(Ok(()), verify_bytes(encoded.as_bytes(), Some(b"password"), variant));
+
+            let invalid = PhcParams::arbitrary_invalid(&mut u).expect("Failed to generate invalid params.");
+            assert!(invalid.validate().is_err(), "{:?}", invalid);
+            let mut out = vec![0u8; invalid.output_len];
+            assert!(hash(invalid.t_cost, invalid.m_cost, invalid.p_cost, Some(b"password"), Some(b"somesalt"),
+                         Some(&mut out), None, variant, Version::DEFAULT).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_common_error_states() {
+        const OUTLEN: usize = 32;
+        let mut out = [0u8; OUTLEN];
+
+        check_error_code!(MemoryTooLittle, hash(2, 1, 1,
+                                                Some(b"password"), Some(b"diffsalt"),
+                                                Some(&mut out), None,
+                                                Variant::ID, Version::Version13));
+        check_error_code!(SaltTooShort, hash(2, 1 << 12, 1,
+                                                Some(b"password"), Some(b"s"),
+                                                Some(&mut out), None,
+                                                Variant::ID, Version::Version13));
+
+        // @NOTE This test is missing because it's not possible to pass a mismatched length/pointer
+        // pair to this function :)
+        //
+        //     ret = argon2_hash(2, 1 << 12, 1, NULL, strlen("password"),
+        //                "diffsalt", strlen("diffsalt"),
+        //                out, OUT_LEN, NULL, 0, Argon2_id, version);
+        //
+        // It would look something like this:
+        //
+        //     check_error_code!(PwdPtrMismatch, hash(2, 1 << 12, 1,
+        //                                            Some(b"password"), Some(b"diffsalt"),
+        //                                            Some(&mut out), None,
+        //                                            Variant::ID, Version::Version13));
+    }
+
+    /// Every code defined by the C library must map to an `ErrorCode` and back, anything else
+    /// must come out as `Error::UnknownCode`.
+    #[test]
+    fn test_error_code_mapping() {
+        use std::convert::TryFrom;
+
+        let mut known = 0;
+        for raw in -64..=16 {
+            match ErrorCode::try_from(raw) {
+                Ok(code) => {
+                    known += 1;
+                    assert_eq!(raw, code.to_c());
+                    assert!(!error_message(code).expect("no message for known code").is_empty());
+                }
+                Err(err) => {
+                    assert!(!(sys::Argon2_ErrorCodes_ARGON2_VERIFY_MISMATCH..0).contains(&raw));
+                    assert_eq!(Error::UnknownCode(raw), err);
+                }
+            }
+        }
+        assert_eq!(35, known);
+
+        assert_eq!(Ok(()), check(sys::Argon2_ErrorCodes_ARGON2_OK));
+        assert_eq!(Err(Error::Code(ErrorCode::VerifyMismatch)),
+                   check(sys::Argon2_ErrorCodes_ARGON2_VERIFY_MISMATCH));
+        assert_eq!(Err(Error::UnknownCode(-100)), check(-100));
+        assert!(matches!(check(sys::Argon2_ErrorCodes_ARGON2_THREAD_FAIL), Err(Error::ThreadFailed(_))));
+
+        #[allow(deprecated)]
+        let unknown = Error::Unknown;
+        assert_eq!("Unknown error", unknown.to_string());
+        assert_ne!(unknown, Error::UnknownCode(-100));
+    }
+
+    #[test]
+    fn test_allocation_failed() {
+        let err = check_alloc(sys::Argon2_ErrorCodes_ARGON2_MEMORY_ALLOCATION_ERROR, 1 << 20, 4).unwrap_err();
+        assert_eq!(Error::AllocationFailed { requested_bytes: 1 << 30 }, err);
+        assert!(err.is_memory_error());
+        assert_eq!("Memory allocation error (1073741824 bytes requested)", err.to_string());
+
+        // The C library allocates whole segments of 4 lanes * 4 sync points, and at least 2 per lane.
+        assert_eq!(Err(Error::AllocationFailed { requested_bytes: 1008 * 1024 }),
+                   check_alloc(sys::Argon2_ErrorCodes_ARGON2_MEMORY_ALLOCATION_ERROR, 1023, 4));
+        assert_eq!(Err(Error::AllocationFailed { requested_bytes: 24 * 1024 }),
+                   check_alloc(sys::Argon2_ErrorCodes_ARGON2_MEMORY_ALLOCATION_ERROR, 10, 3));
+        assert_eq!(Err(Error::AllocationFailed { requested_bytes: 16 * 1024 }),
+                   check_alloc(sys::Argon2_ErrorCodes_ARGON2_MEMORY_ALLOCATION_ERROR, 19, 1));
+
+        assert_eq!(Ok(()), check_alloc(sys::Argon2_ErrorCodes_ARGON2_OK, 1 << 20, 1));
+        assert_eq!(Err(Error::Code(ErrorCode::MemoryTooLittle)),
+                   check_alloc(sys::Argon2_ErrorCodes_ARGON2_MEMORY_TOO_LITTLE, 1, 1));
+    }
+
+    #[test]
+    fn test_thread_failed() {
+        use std::error::Error as _;
+
+        let os_error = std::io::Error::from_raw_os_error(11);
+        let message = os_error.to_string();
+        let err = Error::ThreadFailed(os_error);
+        assert_eq!(format!("Threading failure (code -33): {}", message), err.to_string());
+        let source = err.source().expect("ThreadFailed has a source");
+        assert_eq!(Some(11), source.downcast_ref::<std::io::Error>().and_then(|err| err.raw_os_error()));
+
+        assert_eq!(Error::ThreadFailed(std::io::Error::from_raw_os_error(11)), err);
+        assert_ne!(Error::ThreadFailed(std::io::Error::from_raw_os_error(1)), err);
+    }
+
+    #[derive(Default)]
+    struct CountingAllocator {
+        allocations: std::cell::RefCell<Vec<usize>>,
+        deallocations: std::cell::RefCell<Vec<usize>>,
+    }
+
+    unsafe impl MemoryAllocator for CountingAllocator {
+        fn allocate(&self, bytes: usize) -> Result<std::ptr::NonNull<u8>, Error> {
+            self.allocations.borrow_mut().push(bytes);
+            SystemAllocator.allocate(bytes)
+        }
+
+        fn deallocate(&self, ptr: std::ptr::NonNull<u8>, bytes: usize) {
+            self.deallocations.borrow_mut().push(bytes);
+            SystemAllocator.deallocate(ptr, bytes)
+        }
+    }
+
+    #[test]
+    fn test_memory_arena() {
+        let hash_with = |arena: &MemoryArena<CountingAllocator>, m_cost: u32, pwd: &[u8]| {
+            let mut context = OwnedContext {
+                out: vec![0u8; 32],
+                pwd: Some(pwd.to_vec()),
+                salt: Some(b"somesalt".to_vec()),
+                secret: None,
+                ad: None,
+                t_cost: 2,
+                m_cost,
+                lanes: 1,
+                threads: 1,
+                version: Version::DEFAULT,
+                flags: Flags::DEFAULT,
+            };
+            ctx_with_allocator(&mut context, Variant::ID, arena).unwrap();
+            let mut expected = [0u8; 32];
+            id_hash_raw(2, m_cost, 1, Some(pwd), Some(b"somesalt"), &mut expected).unwrap();
+            assert_eq!(&expected[..], &context.out[..]);
+        };
+
+        let arena = MemoryArena::with_allocator(1 << 5, CountingAllocator::default()).unwrap();
+        for pwd in [&b"password"[..], b"hunter22", b"password"] {
+            hash_with(&arena, 1 << 5, pwd);
+        }
+        assert_eq!(vec![1 << 15], *arena.allocator().allocations.borrow());
+        assert!(arena.allocator().deallocations.borrow().is_empty());
+
+        // A different m_cost gets its own allocation every time.
+        hash_with(&arena, 1 << 6, b"password");
+        hash_with(&arena, 1 << 6, b"password");
+        assert_eq!(vec![1 << 15, 1 << 16, 1 << 16], *arena.allocator().allocations.borrow());
+        assert_eq!(vec![1 << 16, 1 << 16], *arena.allocator().deallocations.borrow());
+
+        // The arena's region is still there after the fallback.
+        hash_with(&arena, 1 << 5, b"password");
+        assert_eq!(3, arena.allocator().allocations.borrow().len());
+
+        // A `PhcHasher` with an arena hashes and verifies through it.
+        #[cfg(feature = "password-hash")]
+        {
+            use password_hash::{PasswordHasher, PasswordVerifier, Salt};
+            use std::convert::TryFrom;
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            use std::sync::Arc;
+
+            #[derive(Default)]
+            struct AtomicCountingAllocator(AtomicUsize);
+
+            unsafe impl MemoryAllocator for AtomicCountingAllocator {
+                fn allocate(&self, bytes: usize) -> Result<std::ptr::NonNull<u8>, Error> {
+                    self.0.fetch_add(1, Ordering::SeqCst);
+                    SystemAllocator.allocate(bytes)
+                }
+
+                fn deallocate(&self, ptr: std::ptr::NonNull<u8>, bytes: usize) {
+                    SystemAllocator.deallocate(ptr, bytes)
+                }
+            }
+
+            let params = PhcParams { m_cost: 1 << 5, t_cost: 2, p_cost: 1, output_len: 32 };
+            let salt = Salt::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+            let arena = Arc::new(MemoryArena::with_allocator(1 << 5, AtomicCountingAllocator::default()).unwrap());
+            let plain = PhcHasher::new(Variant::ID, Version::Version13, params);
+            let hasher = plain.clone().with_arena(Arc::clone(&arena));
+            let hash = hasher.hash_password(b"password", salt).unwrap();
+            assert_eq!(plain.hash_password(b"password", salt).unwrap(), hash);
+            assert_eq!(Ok(()), hasher.verify_password(b"password", &hash));
+            assert_eq!(Err(password_hash::Error::Password), hasher.verify_password(b"passwore", &hash));
+            assert_eq!(Ok(()), hasher.verify(b"password", &EncodedHash::try_from(&hash).unwrap()));
+            assert_eq!(1, arena.allocator().0.load(Ordering::SeqCst));
+
+            let params = PhcParams { m_cost: 1 << 6, ..params };
+            assert_eq!(plain.hash_password_customized(b"password", None, None, params, salt).unwrap(),
+                       hasher.hash_password_customized(b"password", None, None, params, salt).unwrap());
+            assert_eq!(2, arena.allocator().0.load(Ordering::SeqCst));
+        }
+
+        // The region is only allocated on first use, so any m_cost that fits is accepted.
+        if cfg!(target_pointer_width = "64") {
+            assert!(MemoryArena::new(u32::MAX).is_ok());
+        } else {
+            check_error_code!(MemoryTooMuch, MemoryArena::new(u32::MAX).map(|_| ()));
+        }
+    }
+
+    #[test]
+    fn test_instrumented_allocator() {
+        use std::sync::{Arc, Mutex};
+
+        let hash_with = |allocator: &dyn MemoryAllocator, m_cost: u32, lanes: u32| {
+            let mut context = OwnedContext {
+                out: vec![0u8; 32],
+                pwd: Some(b"password".to_vec()),
+                salt: Some(b"somesalt".to_vec()),
+                secret: None,
+                ad: None,
+                t_cost: 2,
+                m_cost,
+                lanes,
+                threads: 1,
+                version: Version::DEFAULT,
+                flags: Flags::DEFAULT,
+            };
+            ctx_with_allocator(&mut context, Variant::ID, allocator).unwrap();
+            let mut expected = [0u8; 32];
+            id_hash_raw(2, m_cost, lanes, Some(b"password"), Some(b"somesalt"), &mut expected).unwrap();
+            assert_eq!(&expected[..], &context.out[..]);
+        };
+
+        let ended = Arc::new(Mutex::new(Vec::new()));
+        let callback_ended = Arc::clone(&ended);
+        let allocator = InstrumentedAllocator::with_allocator(CountingAllocator::default())
+            .with_callback(move |stats| callback_ended.lock().unwrap().push(*stats));
+        assert_eq!(None, allocator.last_alloc_stats());
+
+        // A single lane rounds m_cost down to a multiple of 4 KiB.
+        hash_with(&allocator, 37, 1);
+        let single = AllocStats { total_bytes: 36 * 1024, peak_bytes: 36 * 1024, allocations: 1 };
+        assert_eq!(Some(single), allocator.last_alloc_stats());
+        assert_eq!(vec![36 * 1024], *allocator.allocator().allocations.borrow());
+
+        // Two lanes round it down to a multiple of 8 KiB, and every hash is recorded on its own.
+        hash_with(&allocator, 37, 2);
+        let double = AllocStats { total_bytes: 32 * 1024, peak_bytes: 32 * 1024, allocations: 1 };
+        assert_eq!(Some(double), allocator.last_alloc_stats());
+        assert_eq!(vec![single, double], *ended.lock().unwrap());
+
+        // Overlapping allocations are one hash, with the peak of both.
+        let allocator = InstrumentedAllocator::new();
+        let first = allocator.allocate(1024).unwrap();
+        let second = allocator.allocate(2048).unwrap();
+        allocator.deallocate(first, 1024);
+        assert_eq!(None, allocator.last_alloc_stats());
+        let third = allocator.allocate(4096).unwrap();
+        allocator.deallocate(second, 2048);
+        allocator.deallocate(third, 4096);
+        assert_eq!(Some(AllocStats { total_bytes: 7168, peak_bytes: 6144, allocations: 3 }), allocator.last_alloc_stats());
+    }
+
+    #[test]
+    fn test_memory_fallback_policy() {
+        use std::cell::Cell;
+        use std::ptr::NonNull;
+
+        /// Fails the first `failures` allocations.
+        struct FlakyAllocator {
+            failures: u32,
+            attempts: Cell<u32>,
+        }
+
+        unsafe impl MemoryAllocator for FlakyAllocator {
+            fn allocate(&self, bytes: usize) -> Result<NonNull<u8>, Error> {
+                self.attempts.set(self.attempts.get() + 1);
+                if self.attempts.get() <= self.failures {
+                    return Err(Error::AllocationFailed { requested_bytes: bytes as u64 });
+                }
+                SystemAllocator.allocate(bytes)
+            }
+
+            fn deallocate(&self, ptr: NonNull<u8>, bytes: usize) {
+                SystemAllocator.deallocate(ptr, bytes)
+            }
+        }
+
+        let params = PhcParams { m_cost: 1 << 6, t_cost: 2, p_cost: 1, output_len: 32 };
+        let policy = MemoryFallbackPolicy::halving(params, 2);
+        assert_eq!(&[params, PhcParams { m_cost: 1 << 5, t_cost: 4, ..params }, PhcParams { m_cost: 1 << 4, t_cost: 8, ..params }],
+                   policy.rungs());
+        // The ladder stops at the minimum memory.
+        assert_eq!(4, MemoryFallbackPolicy::halving(params, 10).rungs().len());
+
+        for failures in 0..3 {
+            let allocator = FlakyAllocator { failures, attempts: Cell::new(0) };
+            let hashed = policy.hash_new_password_with_allocator(b"password", b"somesalt", Variant::ID, Version::Version13, &allocator)
+                .unwrap();
+            assert_eq!(failures as usize, hashed.rung);
+            assert_eq!(failures != 0, hashed.is_degraded());
+            assert_eq!(policy.rungs()[hashed.rung], hashed.params);
+            assert_eq!(failures + 1, allocator.attempts.get());
+
+            let mut expected = [0u8; 32];
+            id_hash_raw(hashed.params.t_cost, hashed.params.m_cost, 1, Some(b"password"), Some(b"somesalt"), &mut expected).unwrap();
+            assert_eq!(&expected[..], hashed.hash.hash());
+            assert_eq!((hashed.params.m_cost, hashed.params.t_cost), (hashed.hash.m_cost(), hashed.hash.t_cost()));
+            assert_eq!(Ok(()), verify_bytes(hashed.hash.as_str().as_bytes(), Some(b"password"), Variant::ID));
+        }
+
+        // Past the last rung, the allocation failure of the last rung is returned.
+        let allocator = FlakyAllocator { failures: 3, attempts: Cell::new(0) };
+        assert_eq!(Err(Error::AllocationFailed { requested_bytes: 1 << 14 }),
+                   policy.hash_new_password_with_allocator(b"password", b"somesalt", Variant::ID, Version::Version13, &allocator)
+                       .map(|hashed| hashed.rung));
+
+        // Other errors aren't retried.
+        let allocator = FlakyAllocator { failures: 0, attempts: Cell::new(0) };
+        check_error_code!(SaltTooShort, policy.hash_new_password_with_allocator(b"password", b"salt", Variant::ID, Version::Version13, &allocator)
+                              .map(|hashed| hashed.rung));
+        assert_eq!(0, allocator.attempts.get());
+
+        let hashed = MemoryFallbackPolicy::new(params).hash_new_password(b"password", b"somesalt", Variant::ID, Version::Version13).unwrap();
+        assert!(!hashed.is_degraded());
+
+        // An output length that is out of range is reported instead of being allocated, even on a
+        // rung that wouldn't be reached.
+        #[cfg(target_pointer_width = "64")]
+        {
+            let huge = PhcParams { output_len: 1 << 40, ..params };
+            let allocator = FlakyAllocator { failures: 0, attempts: Cell::new(0) };
+            assert_eq!(Err(Error::InvalidParameter { name: "output_len", got: 1 << 40, min: 0, max: Context::MAX_OUTLEN.into() }),
+                       policy.clone().with_rung(huge)
+                           .hash_new_password_with_allocator(b"password", b"somesalt", Variant::ID, Version::Version13, &allocator)
+                           .map(|hashed| hashed.rung));
+            assert_eq!(0, allocator.attempts.get());
+        }
+    }
+
+    #[test]
+    fn test_memory_allocator() {
+        use std::ptr::NonNull;
+
+        struct FailingAllocator;
+
+        unsafe impl MemoryAllocator for FailingAllocator {
+            fn allocate(&self, bytes: usize) -> Result<NonNull<u8>, Error> {
+                Err(Error::AllocationFailed { requested_bytes: bytes as u64 })
+            }
+
+            fn deallocate(&self, _ptr: NonNull<u8>, _bytes: usize) {
+                unreachable!("nothing was allocated");
+            }
+        }
+
+        struct PanickingAllocator;
+
+        unsafe impl MemoryAllocator for PanickingAllocator {
+            fn allocate(&self, _bytes: usize) -> Result<NonNull<u8>, Error> {
+                panic!("allocation panicked");
+            }
+
+            fn deallocate(&self, _ptr: NonNull<u8>, _bytes: usize) {
+                unreachable!("nothing was allocated");
+            }
+        }
+
+        let m_cost = 1 << 5;
+        let pwd = b"password".to_vec();
+        let salt = b"somesalt".to_vec();
+        let mut context = OwnedContext {
+            out: vec![0u8; 32],
+            pwd: Some(pwd.clone()),
+            salt: Some(salt.clone()),
+            secret: None,
+            ad: None,
+            t_cost: 2,
+            m_cost,
+            lanes: 1,
+            threads: 1,
+            version: Version::DEFAULT,
+            flags: Flags::DEFAULT,
+        };
+
+        let counting = CountingAllocator::default();
+        ctx_with_allocator(&mut context, Variant::ID, &counting).unwrap();
+        assert_eq!(vec![m_cost as usize * 1024], *counting.allocations.borrow());
+        assert_eq!(vec![m_cost as usize * 1024], *counting.deallocations.borrow());
+
+        let mut expected = [0u8; 32];
+        id_hash_raw(2, m_cost, 1, Some(&pwd), Some(&salt), &mut expected).unwrap();
+        assert_eq!(&expected[..], &context.out[..]);
+
+        let hash = context.out.clone();
+        verify_ctx_with_allocator(&mut context, &hash, Variant::ID, &counting).unwrap();
+        assert_eq!(2, counting.allocations.borrow().len());
+        assert_eq!(2, counting.deallocations.borrow().len());
+
+        assert_eq!(Err(Error::AllocationFailed { requested_bytes: m_cost as u64 * 1024 }),
+                   ctx_with_allocator(&mut context, Variant::ID, &FailingAllocator));
+        assert_eq!(Err(Error::AllocationFailed { requested_bytes: m_cost as u64 * 1024 }),
+                   ctx_with_allocator(&mut context, Variant::ID, &PanickingAllocator));
+
+        // The allocator is only used for the call it was passed to.
+        ctx(&mut context, Variant::ID).unwrap();
+        assert_eq!(2, counting.allocations.borrow().len());
+    }
+
+    #[test]
+    #[cfg(all(feature = "mlock", any(unix, windows)))]
+    fn test_secure_allocator() {
+        let pwd = b"password".to_vec();
+        let salt = b"somesalt".to_vec();
+        let mut context = OwnedContext {
+            out: vec![0u8; 32],
+            pwd: Some(pwd.clone()),
+            salt: Some(salt.clone()),
+            secret: None,
+            ad: None,
+            t_cost: 2,
+            m_cost: 1 << 6,
+            lanes: 2,
+            threads: 2,
+            version: Version::DEFAULT,
+            flags: Flags::DEFAULT,
+        };
+
+        let allocator = SecureAllocator::new(LockFailure::Continue);
+        for variant in Variant::ALL {
+            ctx_with_allocator(&mut context, variant, &allocator).unwrap();
+            let mut expected = [0u8; 32];
+            hash(2, 1 << 6, 2, Some(&pwd), Some(&salt), Some(&mut expected), None, variant, Version::DEFAULT).unwrap();
+            assert_eq!(&expected[..], &context.out[..]);
+        }
+
+        // Locking 64 KiB may fail with a low RLIMIT_MEMLOCK, but must not produce a different hash.
+        let strict = SecureAllocator::default();
+        match ctx_with_allocator(&mut context, Variant::ID, &strict) {
+            Ok(()) => {
+                let mut expected = [0u8; 32];
+                id_hash_raw(2, 1 << 6, 2, Some(&pwd), Some(&salt), &mut expected).unwrap();
+                assert_eq!(&expected[..], &context.out[..]);
+            }
+            Err(err) => assert_eq!(Error::AllocationFailed { requested_bytes: 1 << 16 }, err),
+        }
+        assert_eq!(0, strict.unlocked_allocations());
+    }
+
+    #[test]
+    #[cfg(all(feature = "mlock", target_os = "windows"))]
+    fn test_secure_allocator_working_set() {
+        // 16 MiB is above the default maximum working set size, so the quota has to be raised.
+        let m_cost = 1 << 14;
+        let pwd = b"password".to_vec();
+        let salt = b"somesalt".to_vec();
+        let mut context = OwnedContext {
+            out: vec![0u8; 32],
+            pwd: Some(pwd.clone()),
+            salt: Some(salt.clone()),
+            secret: None,
+            ad: None,
+            t_cost: 1,
+            m_cost,
+            lanes: 1,
+            threads: 1,
+            version: Version::DEFAULT,
+            flags: Flags::DEFAULT,
+        };
+
+        let allocator = SecureAllocator::default();
+        ctx_with_allocator(&mut context, Variant::ID, &allocator).unwrap();
+        let mut expected = [0u8; 32];
+        id_hash_raw(1, m_cost, 1, Some(&pwd), Some(&salt), &mut expected).unwrap();
+        assert_eq!(&expected[..], &context.out[..]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "mlock", target_os = "linux"))]
+    fn test_memory_protection() {
+        let pwd = b"password".to_vec();
+        let salt = b"somesalt".to_vec();
+        let mut context = OwnedContext {
+            out: vec![0u8; 32],
+            pwd: Some(pwd.clone()),
+            salt: Some(salt.clone()),
+            secret: None,
+            ad: None,
+            t_cost: 2,
+            m_cost: 1 << 6,
+            lanes: 1,
+            threads: 1,
+            version: Version::DEFAULT,
+            flags: Flags::DEFAULT,
+        };
+        let mut expected = [0u8; 32];
+        id_hash_raw(2, 1 << 6, 1, Some(&pwd), Some(&salt), &mut expected).unwrap();
+
+        let protection = MemoryProtection { dont_dump: true, wipe_on_fork: true, on_failure: AdviceFailure::Warn };
+        let allocator = SecureAllocator::new(LockFailure::Continue).with_protection(protection);
+        ctx_with_allocator(&mut context, Variant::ID, &allocator).unwrap();
+        assert_eq!(&expected[..], &context.out[..]);
+
+        // MADV_WIPEONFORK is missing on kernels before 4.14.
+        let protection = MemoryProtection { on_failure: AdviceFailure::Error, ..protection };
+        let allocator = SecureAllocator::new(LockFailure::Continue).with_protection(protection);
+        match ctx_with_allocator(&mut context, Variant::ID, &allocator) {
+            Ok(()) => assert_eq!(&expected[..], &context.out[..]),
+            Err(err) => assert_eq!(Error::AllocationFailed { requested_bytes: 1 << 16 }, err),
+        }
+    }
+
+    #[cfg(all(feature = "mlock", target_os = "linux"))]
+    #[test]
+    fn test_huge_pages() {
+        let pwd = b"password".to_vec();
+        let salt = b"somesalt".to_vec();
+        let mut expected = [0u8; 32];
+        id_hash_raw(2, 1 << 12, 1, Some(&pwd), Some(&salt), &mut expected).unwrap();
+
+        for &huge_pages in &[HugePagePolicy::Off, HugePagePolicy::Transparent, HugePagePolicy::Reserved] {
+            let mut context = OwnedContext {
+                out: vec![0u8; 32],
+                pwd: Some(pwd.clone()),
+                salt: Some(salt.clone()),
+                secret: None,
+                ad: None,
+                t_cost: 2,
+                m_cost: 1 << 12,
+                lanes: 1,
+                threads: 1,
+                version: Version::DEFAULT,
+                flags: Flags::DEFAULT,
+            };
+            let allocator = SecureAllocator::new(LockFailure::Continue).with_options(MemoryOptions { huge_pages });
+            for _ in 0..2 {
+                ctx_with_allocator(&mut context, Variant::ID, &allocator).unwrap();
+                assert_eq!(&expected[..], &context.out[..]);
+            }
+
+            // The reserved pool is usually empty, in which case the allocator falls back to normal
+            // pages; either way the mappings have to be released properly for the second hash.
+            match huge_pages {
+                HugePagePolicy::Reserved => assert!(allocator.huge_page_allocations() <= 2),
+                HugePagePolicy::Off | HugePagePolicy::Transparent => assert_eq!(0, allocator.huge_page_allocations()),
+            }
+        }
+
+        // Sizes that aren't a multiple of the huge page size are rounded up for the mapping only.
+        let allocator = SecureAllocator::new(LockFailure::Continue)
+            .with_options(MemoryOptions { huge_pages: HugePagePolicy::Reserved });
+        let memory = allocator.allocate(3 << 10).unwrap();
+        unsafe { std::ptr::write_bytes(memory.as_ptr(), 0xff, 3 << 10) };
+        allocator.deallocate(memory, 3 << 10);
+    }
+
+    #[test]
+    fn test_thread_fallback() {
+        let hash_with = |policy: ThreadFallbackPolicy| {
+            let mut context = OwnedContext {
+                out: vec![0u8; 32],
+                pwd: Some(b"password".to_vec()),
+                salt: Some(b"somesalt".to_vec()),
+                secret: None,
+                ad: None,
+                t_cost: 2,
+                m_cost: 1 << 6,
+                lanes: 4,
+                threads: 4,
+                version: Version::DEFAULT,
+                flags: Flags::DEFAULT,
+            };
+            ctx_with_thread_fallback(&mut context, Variant::ID, policy).map(|degraded| (degraded, context.out))
+        };
+
+        let mut expected = [0u8; 32];
+        hash(2, 1 << 6, 4, Some(b"password"), Some(b"somesalt"), Some(&mut expected), None, Variant::ID, Version::DEFAULT)
+            .unwrap();
+        assert_eq!((false, expected.to_vec()), hash_with(ThreadFallbackPolicy::SingleThreaded).unwrap());
+
+        FAIL_THREADS.with(|fail| fail.set(true));
+        let failed = hash_with(ThreadFallbackPolicy::Fail);
+        let degraded = hash_with(ThreadFallbackPolicy::SingleThreaded);
+        FAIL_THREADS.with(|fail| fail.set(false));
+
+        match failed {
+            Err(Error::ThreadFailed(_)) => {}
+            other => panic!("expected ThreadFailed, got {:?}", other),
+        }
+        assert_eq!((true, expected.to_vec()), degraded.unwrap());
+
+        // The fallback hashes the password and the secret that the first attempt wiped, and wipes
+        // them again.
+        let mut context = OwnedContext {
+            out: vec![0u8; 32],
+            pwd: Some(b"password".to_vec()),
+            salt: Some(b"somesalt".to_vec()),
+            secret: Some(b"secret".to_vec()),
+            ad: None,
+            t_cost: 2,
+            m_cost: 1 << 6,
+            lanes: 4,
+            threads: 1,
+            version: Version::DEFAULT,
+            flags: Flags::DEFAULT,
+        };
+        ctx(&mut context, Variant::ID).unwrap();
+        let expected = std::mem::take(&mut context.out);
+        context.out = vec![0u8; 32];
+        context.threads = 4;
+        context.flags = Flags::CLEAR_PASSWORD | Flags::CLEAR_SECRET;
+        FAIL_THREADS.with(|fail| fail.set(true));
+        let degraded = ctx_with_thread_fallback(&mut context, Variant::ID, ThreadFallbackPolicy::SingleThreaded);
+        FAIL_THREADS.with(|fail| fail.set(false));
+        assert_eq!(Ok(true), degraded);
+        assert_eq!(expected, context.out);
+        assert_eq!(Some(&[0u8; 8][..]), context.pwd.as_deref());
+        assert_eq!(Some(&[0u8; 6][..]), context.secret.as_deref());
+
+        // A hasher applies its policy to hashes and verifications, also through an arena.
+        #[cfg(feature = "password-hash")]
+        {
+            let params = PhcParams { m_cost: 1 << 6, t_cost: 2, p_cost: 4, output_len: 32 };
+            let encoded = hash_encoded_string(2, 1 << 6, 4, b"password", b"somesalt", 32, Variant::ID, Version::Version13).unwrap();
+            let hash = EncodedHash::parse(&encoded).unwrap();
+            let arena = std::sync::Arc::new(MemoryArena::new(1 << 6).unwrap());
+            let hasher = PhcHasher::new(Variant::ID, Version::Version13, params).with_arena(arena);
+            let fallback = hasher.clone().with_thread_fallback(ThreadFallbackPolicy::SingleThreaded);
+
+            FAIL_THREADS.with(|fail| fail.set(true));
+            let failed = hasher.verify(b"password", &hash);
+            let degraded = fallback.verify(b"password", &hash);
+            let mismatch = fallback.verify(b"passwore", &hash);
+            let without_arena = PhcHasher::new(Variant::ID, Version::Version13, params)
+                .with_thread_fallback(ThreadFallbackPolicy::SingleThreaded)
+                .verify(b"password", &hash);
+            FAIL_THREADS.with(|fail| fail.set(false));
+
+            assert!(matches!(failed, Err(Error::ThreadFailed(_))), "{:?}", failed);
+            assert_eq!(Ok(()), degraded);
+            check_error_code!(VerifyMismatch, mismatch);
+            assert_eq!(Ok(()), without_arena);
+        }
+    }
+
+    #[test]
+    fn test_timed() {
+        let (encoded, elapsed) =
+            hash_encoded_timed(2, 1 << 6, 1, b"password", b"somesalt", 32, Variant::ID, Version::DEFAULT).unwrap();
+        assert!(elapsed > Duration::ZERO);
+        let mut expected = [0u8; 128];
+        id_hash_encoded(2, 1 << 6, 1, Some(b"password"), Some(b"somesalt"), 32, &mut expected).unwrap();
+        assert_eq!(c_str(&expected).unwrap().to_str().unwrap(), encoded);
+
+        let encoded = std::ffi::CString::new(encoded).unwrap();
+        let (result, elapsed) = verify_timed(&encoded, Some(b"password"), Variant::ID);
+        assert_eq!(Ok(()), result);
+        assert!(elapsed > Duration::ZERO);
+
+        let (result, elapsed) = verify_timed(&encoded, Some(b"wrong"), Variant::ID);
+        assert_eq!(verify(&encoded, Some(b"wrong"), Variant::ID), result);
+        assert_eq!(Err(Error::Code(ErrorCode::VerifyMismatch)), result);
+        assert!(elapsed > Duration::ZERO);
+
+        #[cfg(feature = "password-hash")]
+        {
+            let hasher = PhcHasher::new(Variant::ID, Version::DEFAULT, PhcParams { m_cost: 1 << 6, t_cost: 2, p_cost: 1, output_len: 32 });
+            let hash = EncodedHash::parse(encoded.to_str().unwrap()).unwrap();
+            let (result, elapsed) = hasher.verify_timed(b"password", &hash);
+            assert_eq!(Ok(()), result);
+            assert!(elapsed > Duration::ZERO);
+
+            let (result, elapsed) = hasher.verify_timed(b"wrong", &hash);
+            assert_eq!(Err(Error::Code(ErrorCode::VerifyMismatch)), result);
+            assert!(elapsed > Duration::ZERO);
+
+            // Passwords that are rejected before hashing take no time.
+            let (result, elapsed) = hasher.clone().with_max_password_len(Some(4)).verify_timed(b"password", &hash);
+            assert_eq!(Err(Error::PasswordTooLong { len: 8, max: 4 }), result);
+            assert_eq!(Duration::ZERO, elapsed);
+
+            #[cfg(feature = "random-salt")]
+            {
+                let (hash, elapsed) = hasher.hash_new_password_timed(b"password").unwrap();
+                assert!(elapsed > Duration::ZERO);
+                assert_eq!(Ok(()), hasher.verify(b"password", &hash));
+            }
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_tracing() {
+        use std::sync::{Arc, Mutex};
+        use tracing_subscriber::fmt::format::FmtSpan;
+
+        #[derive(Clone, Default)]
+        struct Output(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Output {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let output = Output::default();
+        let writer = output.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_span_events(FmtSpan::CLOSE)
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .finish();
+
+        let pwd = b"correct horse battery staple";
+        let salt = b"pepper-salt-1234";
+        let mut raw = [0u8; 32];
+        let encoded = tracing::subscriber::with_default(subscriber, || {
+            id_hash_raw(2, 1 << 6, 1, Some(pwd), Some(salt), &mut raw).unwrap();
+            let (encoded, _) = hash_encoded_timed(2, 1 << 6, 1, pwd, salt, 32, Variant::ID, Version::DEFAULT).unwrap();
+            let encoded = std::ffi::CString::new(encoded).unwrap();
+            id_verify(&encoded, Some(pwd)).unwrap();
+            id_verify(&encoded, Some(b"wrong")).unwrap_err();
+            id_hash_raw(2, 1, 1, Some(pwd), Some(salt), &mut [0u8; 32]).unwrap_err();
+            encoded
+        });
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+
+        let closed = |span: &str| output.lines().filter(|line| line.contains(span) && line.contains(" close ")).count();
+        assert_eq!(3, closed("argon2.hash{"), "{}", output);
+        assert_eq!(2, closed("argon2.verify{"), "{}", output);
+        for field in &["variant=argon2id", "version=19", "t_cost=2", "m_cost=64", "parallelism=1"] {
+            assert!(output.contains(field), "missing {} in {}", field, output);
+        }
+        for outcome in &["outcome=\"ok\"", "outcome=\"mismatch\"", "outcome=\"error\""] {
+            assert!(output.contains(outcome), "missing {} in {}", outcome, output);
+        }
+        assert!(output.contains(&format!("error_code={}", ErrorCode::MemoryTooLittle.to_c())), "{}", output);
+
+        // Nothing derived from the secrets may show up.
+        let encoded = encoded.to_str().unwrap();
+        let mut secrets = vec![String::from_utf8_lossy(pwd).into_owned(), String::from_utf8_lossy(salt).into_owned()];
+        secrets.extend(encoded.rsplit('$').take(2).map(str::to_owned));
+        secrets.push(raw.iter().map(|b| format!("{:02x}", b)).collect());
+        secrets.push(format!("{:?}", raw));
+        for secret in &secrets {
+            assert!(!output.contains(secret.as_str()), "{} leaked in {}", secret, output);
+        }
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        use std::sync::Arc;
+
+        let arena = Arc::new(MemoryArena::new(1 << 5).unwrap());
+        let encoded: Vec<_> = (0..8u8)
+            .map(|i| {
+                let mut encoded = [0u8; 128];
+                id_hash_encoded(2, 1 << 5, 1, Some(&[b'a' + i]), Some(b"somesalt"), 32, &mut encoded).unwrap();
+                c_str(&encoded).unwrap().to_owned()
+            })
+            .collect();
+        let encoded = Arc::new(encoded);
+
+        let threads: Vec<_> = (0..8u8)
+            .map(|i| {
+                let arena = Arc::clone(&arena);
+                let encoded = Arc::clone(&encoded);
+                std::thread::spawn(move || {
+                    for round in 0..4u8 {
+                        let j = (i + round) % 8;
+                        let pwd = [b'a' + j];
+                        assert_eq!(Ok(()), verify(&encoded[j as usize], Some(&pwd), Variant::ID));
+                        assert!(verify(&encoded[j as usize], Some(b"wrong"), Variant::ID).unwrap_err().is_verify_mismatch());
+
+                        let mut context = OwnedContext {
+                            out: vec![0u8; 32],
+                            pwd: Some(pwd.to_vec()),
+                            salt: Some(b"somesalt".to_vec()),
+                            secret: None,
+                            ad: None,
+                            t_cost: 2,
+                            m_cost: 1 << 5,
+                            lanes: 1,
+                            threads: 1,
+                            version: Version::DEFAULT,
+                            flags: Flags::DEFAULT,
+                        };
+                        ctx_with_allocator(&mut context, Variant::ID, &*arena).unwrap();
+                        let mut expected = [0u8; 32];
+                        id_hash_raw(2, 1 << 5, 1, Some(&pwd), Some(b"somesalt"), &mut expected).unwrap();
+                        assert_eq!(&expected[..], &context.out[..]);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[cfg(feature = "password-hash")]
+    #[test]
+    fn test_hasher_shared_between_threads() {
+        use password_hash::{PasswordHasher, PasswordVerifier, Salt};
+        use std::sync::Arc;
+
+        // One hasher, with the arena and the budget it shares between its hashes, used by 8
+        // threads at once.
+        let params = PhcParams { m_cost: 1 << 5, t_cost: 2, p_cost: 1, output_len: 32 };
+        let hasher = Arc::new(PhcHasher::new(Variant::ID, Version::Version13, params)
+            .with_arena(Arc::new(MemoryArena::new(1 << 5).unwrap()))
+            .with_memory_budget(Arc::new(MemoryBudget::new(3 << 15))));
+
+        let threads: Vec<_> = (0..8u8)
+            .map(|i| {
+                let hasher = Arc::clone(&hasher);
+                std::thread::spawn(move || {
+                    for round in 0..4u8 {
+                        let pwd = [b'a' + i, b'0' + round];
+                        let phc = hasher.hash_password(&pwd, Salt::from_b64("c29tZXNhbHQ").unwrap()).unwrap();
+                        let hash = EncodedHash::parse(&phc.to_string()).unwrap();
+                        let mut expected = [0u8; 32];
+                        id_hash_raw(2, 1 << 5, 1, Some(&pwd), Some(b"somesalt"), &mut expected).unwrap();
+                        assert_eq!(&expected[..], hash.hash());
+
+                        assert_eq!(Ok(()), hasher.verify(&pwd, &hash));
+                        check_error_code!(VerifyMismatch, hasher.verify(b"wrong", &hash));
+                        assert_eq!(Ok(()), hasher.verify_password(&pwd, &phc));
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_error_message() {
+        use std::convert::TryFrom;
+
+        assert_eq!(Some("Salt is too short"), error_message(ErrorCode::SaltTooShort));
+        assert_eq!("Salt is too short", error_message_or_unknown(ErrorCode::SaltTooShort));
+
+        let err = ErrorCode::try_from(-100).expect_err("-100 is not an argon2 error code");
+        assert_eq!(Error::UnknownCode(-100), err);
+        assert_eq!("Unknown error code -100", err.to_string());
+    }
+
+    #[test]
+    fn test_error_code_display() {
+        assert_eq!("Salt is too short", ErrorCode::SaltTooShort.to_string());
+        assert_eq!("The password does not match the supplied hash", ErrorCode::VerifyMismatch.to_string());
+        assert_eq!("Salt is too short (code -6)", Error::Code(ErrorCode::SaltTooShort).to_string());
+        assert_eq!("The password does not match the supplied hash (code -35)",
+                   Error::Code(ErrorCode::VerifyMismatch).to_string());
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!("Bad parameter: hash.len", Error::BadParam("hash.len").to_string());
+        assert_eq!("Salt is too short (code -6)", Error::Code(ErrorCode::SaltTooShort).to_string());
+        assert_eq!("Invalid parameter hash.len: got 16, expected 32",
+                   Error::InvalidParameter { name: "hash.len", got: 16, min: 32, max: 32 }.to_string());
+        assert_eq!("Invalid parameter context.out.len: got 4294967296, expected 0..=4294967295",
+                   Error::InvalidParameter { name: "context.out.len", got: 1 << 32, min: 0, max: 0xFFFFFFFF }.to_string());
+        assert_eq!("argon2_type2string returned a null pointer",
+                   Error::NullPointer("argon2_type2string").to_string());
+        assert_eq!("Buffer too small: 10 bytes provided, 20 required",
+                   Error::BufferTooSmall { required: 20, provided: 10 }.to_string());
+        assert_eq!("Unknown error code -100", Error::UnknownCode(-100).to_string());
+        assert_eq!("Unsupported algorithm argon2d", Error::UnknownAlgorithm("argon2d").to_string());
+        assert_eq!("4 threads requested, but threads are unavailable on this target",
+                   Error::ThreadsUnavailable { threads: 4 }.to_string());
+    }
+
+    #[test]
+    fn test_verify_any_of() {
+        let encode = |pwd: &[u8], variant| {
+            let mut out = [0u8; 32];
+            let mut encoded = [0u8; 128];
+            hash(2, 1 << 4, 1, Some(pwd), Some(b"somesalt"), Some(&mut out), Some(&mut encoded), variant, Version::DEFAULT)
+                .expect("Test hash failed.");
+            c_str(&encoded).expect("bad C string.").to_str().unwrap().to_owned()
+        };
+        let old = encode(b"old password", Variant::I);
+        let new = encode(b"password", Variant::ID);
+        let malformed = "$argon2i$m=16,t=2,p=1c29tZXNhbHQ$AAAA";
+
+        assert_eq!(Ok(Some(1)), verify_any_of(&[&old, &new], Some(b"password")));
+        assert_eq!(Ok(Some(0)), verify_any_of(&[&old, &new], Some(b"old password")));
+        assert_eq!(Ok(Some(0)), verify_any_of(&[&new, &old], Some(b"password")));
+        assert_eq!(Ok(None), verify_any_of(&[&old, &new], Some(b"wrong password")));
+        assert_eq!(Ok(None), verify_any_of(&[], Some(b"password")));
+        assert_eq!(Ok(Some(2)), verify_any_of(&[malformed, &old, &new], Some(b"password")));
+        assert_eq!(Ok(None), verify_any_of(&[malformed, &old], Some(b"password")));
+
+        let err = verify_any_of(&[malformed, "", "$argon2x$v=19$m=16,t=2,p=1$c29tZXNhbHQ$AAAA"], Some(b"password"))
+            .expect_err("all malformed");
+        assert_eq!(Error::AllMalformed((0..3).map(|_| Error::Code(ErrorCode::DecodingFail)).collect()), err);
+        assert!(err.is_decoding_error());
+        assert_eq!("All 3 encoded hashes are malformed: 0: Decoding failed (code -32); 1: Decoding failed (code -32); \
+                    2: Decoding failed (code -32)",
+                   err.to_string());
+    }
+
+    #[test]
+    fn test_server_relief() {
+        use crate::server_relief::{client_prehash, server_hash, server_verify};
+
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+
+        // Computed independently with the reference implementation, from the salt described in the
+        // module documentation. These must never change.
+        let params = PhcParams { m_cost: 256, t_cost: 2, p_cost: 1, output_len: 16 };
+        let prehash = client_prehash(&params, b"alice", b"example.com", b"correct horse battery staple").unwrap();
+        assert_eq!("a3e49a22de275fd9459f5812da405f2fe49ac31f5ca1c12974af4ba7dec83989", hex(&prehash));
+        let empty = PhcParams { m_cost: 64, t_cost: 1, p_cost: 1, output_len: 32 };
+        assert_eq!("a84aac0b9a6aca2ccbae0191da6333a7f4cc9faf4c90cb20963d4cc5052dd7a0",
+                   hex(&client_prehash(&empty, b"", b"", b"").unwrap()));
+        let encoded = server_hash(&prehash, b"serversaltserver").unwrap();
+        assert_eq!("$argon2id$v=19$m=1024,t=1,p=1$c2VydmVyc2FsdHNlcnZlcg$6f0Q3jnphN5jJ2JXmXmP9evjcc2iUMZx9FXEqmuoUOw", encoded);
+
+        assert_eq!(Ok(()), server_verify(&encoded, &prehash));
+        let other_user = client_prehash(&params, b"bob", b"example.com", b"correct horse battery staple").unwrap();
+        let other_domain = client_prehash(&params, b"alice", b"example.org", b"correct horse battery staple").unwrap();
+        // The length prefix keeps the domain tag and the username apart.
+        let shifted = client_prehash(&params, b"e.comalice", b"exampl", b"correct horse battery staple").unwrap();
+        for wrong in [other_user, other_domain, shifted].iter() {
+            assert_eq!(Err(Error::Code(ErrorCode::VerifyMismatch)), server_verify(&encoded, wrong));
+        }
+        let argon2i = encoded.replacen("argon2id", "argon2i", 1);
+        assert_eq!(Err(Error::Code(ErrorCode::DecodingFail)), server_verify(&argon2i, &prehash));
+    }
+
+    #[test]
+    fn test_raw_record() {
+        let params = PhcParams { m_cost: 1 << 4, t_cost: 2, p_cost: 1, output_len: 32 };
+        let salt = b"somesaltsomesalt";
+        let mut hash = [0u8; 32];
+        id_hash_raw(2, 1 << 4, 1, Some(b"password"), Some(salt), &mut hash).expect("Test hash failed.");
+        let record = raw_record::encode(salt, &hash);
+        assert_eq!(48, record.len());
+        assert_eq!(Ok((&salt[..], &hash[..])), raw_record::decode(&record, 16));
+
+        assert_eq!(Ok(()), verify_raw_record(&params, Variant::ID, Version::DEFAULT, Some(b"password"), &record, 16));
+        assert_eq!(Err(Error::Code(ErrorCode::VerifyMismatch)),
+                   verify_raw_record(&params, Variant::ID, Version::DEFAULT, Some(b"wrong password"), &record, 16));
+        assert_eq!(Err(Error::Code(ErrorCode::VerifyMismatch)),
+                   verify_raw_record(&params, Variant::I, Version::DEFAULT, Some(b"password"), &record, 16));
+        let mut tampered = record.clone();
+        tampered[0] ^= 1;
+        assert_eq!(Err(Error::Code(ErrorCode::VerifyMismatch)),
+                   verify_raw_record(&params, Variant::ID, Version::DEFAULT, Some(b"password"), &tampered, 16));
+
+        // Each length error names the part that is wrong.
+        assert_eq!(Err(Error::InvalidParameter { name: "salt_len", got: 4, min: 8, max: 0xFFFFFFFF }),
+                   raw_record::decode(&record, 4));
+        assert_eq!(Err(Error::InvalidParameter { name: "salt.len", got: 12, min: 16, max: 16 }),
+                   raw_record::decode(&record[..12], 16));
+        assert_eq!(Err(Error::InvalidParameter { name: "hash.len", got: 2, min: 4, max: 0xFFFFFFFF }),
+                   raw_record::decode(&record[..18], 16));
+        assert_eq!(Err(Error::InvalidParameter { name: "hash.len", got: 24, min: 32, max: 32 }),
+                   verify_raw_record(&params, Variant::ID, Version::DEFAULT, Some(b"password"), &record, 24));
+    }
+
+    #[test]
+    fn test_estimate_duration() {
+        let estimate = |m_cost, t_cost| {
+            estimate_duration(&PhcParams { m_cost, t_cost, p_cost: 1, output_len: 32 }).expect("estimate failed")
+        };
+        let small = estimate(1 << 13, 1);
+        assert!(small > Duration::ZERO);
+        assert!(estimate(1 << 16, 1) > small);
+        assert!(estimate(1 << 16, 4) > estimate(1 << 16, 1));
+        assert!(estimate(1 << 20, 4) > estimate(1 << 16, 4));
+        assert!(estimate(u32::MAX, u32::MAX) >= estimate(1 << 20, 4));
+
+        invalidate_estimate();
+        assert!(estimate(1 << 20, 4) > estimate(1 << 13, 1));
+
+        let params = PhcParams { m_cost: 1 << 16, t_cost: 1, p_cost: 1, output_len: 32 };
+        assert_eq!(Err(Error::Code(ErrorCode::TimeTooSmall)), estimate_duration(&PhcParams { t_cost: 0, ..params }));
+        assert_eq!(Err(Error::Code(ErrorCode::LanesTooFew)), estimate_duration(&PhcParams { p_cost: 0, ..params }));
+        assert_eq!(Err(Error::Code(ErrorCode::MemoryTooLittle)),
+                   estimate_duration(&PhcParams { m_cost: 15, p_cost: 2, ..params }));
+    }
+
+    #[test]
+    fn test_threads_unavailable() {
+        let mut out = [0u8; 32];
+        let result = i_hash_raw(2, 1 << 4, 2, Some(b"password"), Some(b"somesalt"), &mut out);
+        let encoded = c_str_cow(b"$argon2i$v=19$m=256,t=2,p=2$c29tZXNhbHQ$T/XOJ2mh1/TIpJHfCdQan76Q5esCFVoT5MAeIM1Oq2E");
+        if cfg!(target_family = "wasm") {
+            assert_eq!(Err(Error::ThreadsUnavailable { threads: 2 }), result);
+            assert_eq!([0u8; 32], out);
+            assert_eq!(Err(Error::ThreadsUnavailable { threads: 2 }), i_verify(&encoded, Some(b"password")));
+        } else {
+            result.expect("Test hash failed.");
+            i_verify(&encoded, Some(b"password")).expect("Test verify failed.");
+        }
+    }
+
+    #[test]
+    fn test_error_predicates() {
+        let mut out = [0u8; 32];
+        let mut encoded = [0u8; 128];
+        hash(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"),
+             Some(&mut out), Some(&mut encoded), Variant::I, Version::DEFAULT).expect("Test hash failed.");
+        let encoded = c_str(&encoded).expect("bad C string.");
+
+        let mismatch = i_verify(encoded, Some(b"wrong password")).expect_err("wrong password");
+        assert!(mismatch.is_verify_mismatch());
+        assert!(!mismatch.is_decoding_error());
+        assert!(!mismatch.is_memory_error());
+        assert!(!mismatch.is_invalid_parameter());
+
+        let malformed = i_verify(&c_str_cow(b"$argon2i$m=16,t=2,p=1c29tZXNhbHQ$AAAA"), Some(b"password"))
+            .expect_err("malformed hash");
+        assert!(malformed.is_decoding_error());
+        assert!(!malformed.is_verify_mismatch());
+
+        let memory = hash(2, 1, 1, Some(b"password"), Some(b"somesalt"), Some(&mut out), None,
+                          Variant::I, Version::DEFAULT).expect_err("m_cost too low");
+        assert!(memory.is_memory_error());
+        assert!(memory.is_invalid_parameter());
+        assert!(!memory.is_verify_mismatch());
+
+        let salt = i_hash_raw(2, 1 << 4, 1, Some(b"password"), Some(b"s"), &mut out).expect_err("salt too short");
+        assert!(salt.is_invalid_parameter());
+        assert!(!salt.is_memory_error());
+
+        assert!(c_str(b"no nul").expect_err("missing NUL").is_invalid_parameter());
+        assert!(!Error::UnknownCode(-100).is_invalid_parameter());
+    }
+
+    #[test]
+    fn test_hash_without_output() {
+        assert_eq!(Err(Error::BadParam("hash/encoded")),
+                   hash(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), None, None,
+                        Variant::ID, Version::DEFAULT));
+
+        let mut encoded = [0u8; 128];
+        check_error_code!(OutputTooShort, hash(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"),
+                                               None, Some(&mut encoded), Variant::ID, Version::DEFAULT));
+    }
+
+    #[test]
+    fn test_output_wiped_on_error() {
+        let mut out = [0xAAu8; 32];
+        check_error_code!(MemoryTooLittle, id_hash_raw(2, 1, 1, Some(b"password"), Some(b"somesalt"), &mut out));
+        assert_eq!([0u8; 32], out);
+
+        let mut encoded = [0xAAu8; 128];
+        check_error_code!(MemoryTooLittle,
+                          i_hash_encoded(2, 1, 1, Some(b"password"), Some(b"somesalt"), 32, &mut encoded));
+        assert!(encoded.iter().all(|&b| b == 0));
+
+        #[cfg(not(feature = "no-argon2d"))]
+        {
+            let mut encoded = [0xAAu8; 16];
+            assert!(matches!(d_hash_encoded(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), 32, &mut encoded),
+                             Err(Error::BufferTooSmall { .. })));
+            assert_eq!([0u8; 16], encoded);
+        }
+
+        let mut out = [0xAAu8; 32];
+        let mut encoded = [0xAAu8; 128];
+        check_error_code!(MemoryTooLittle,
+                          hash(2, 1, 1, Some(b"password"), Some(b"somesalt"), Some(&mut out), Some(&mut encoded),
+                               Variant::ID, Version::DEFAULT));
+        assert_eq!([0u8; 32], out);
+        assert!(encoded.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_check_memory() {
+        use memory::{check_memory, parse_meminfo};
+
+        let meminfo = "MemTotal:       16318412 kB\nMemFree:          861344 kB\nMemAvailable:    8388608 kB\n";
+        assert_eq!(Some(8 << 30), parse_meminfo(meminfo));
+        assert_eq!(None, parse_meminfo("MemTotal:       16318412 kB\n"));
+        assert_eq!(None, parse_meminfo("MemAvailable:    lots kB\n"));
+        assert_eq!(Some(2 << 20), memory::meminfo_field("Hugepagesize:       2048 kB\n", "Hugepagesize"));
+
+        let available = Some(8 << 30);
+        assert_eq!(Ok(()), check_memory(4 << 20, available, 0.5));
+        assert_eq!(Err(Error::InvalidParameter { name: "m_cost", got: (4 << 20) + 1, min: 0, max: 4 << 20 }),
+                   check_memory((4 << 20) + 1, available, 0.5));
+        // A value meant as GiB instead of KiB.
+        assert!(check_memory(1 << 30, available, 1.0).is_err());
+        assert!(check_memory(8, available, 0.0).is_err());
+        assert_eq!(Ok(()), check_memory(u32::MAX, None, 0.5));
+
+        if cfg!(target_os = "linux") {
+            assert!(available_memory().is_some());
+        }
+        assert_eq!(Ok(()), check_memory_against_system(8, 1.0));
+    }
+
+    #[cfg(feature = "password-hash")]
+    #[test]
+    fn test_phc_hasher_memory_check() {
+        use password_hash::errors::InvalidValue;
+        use password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, Salt};
+
+        let params = PhcParams { m_cost: 256, t_cost: 2, p_cost: 1, output_len: 32 };
+        let salt = Salt::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+        let hasher = PhcHasher::new(Variant::ID, Version::Version13, params);
+        let hash = hasher.hash_password(b"password", salt).unwrap();
+        let checked = hasher.clone().with_memory_check(Some(0.5));
+        assert_eq!(hash, checked.hash_password(b"password", salt).unwrap());
+        assert_eq!(Ok(()), checked.verify_password(b"password", &hash));
+
+        // Nothing fits into none of the memory.
+        let none = hasher.with_memory_check(Some(0.0));
+        if available_memory().is_some() {
+            let rejected = Err(password_hash::Error::ParamValueInvalid(InvalidValue::TooLong));
+            assert_eq!(rejected, none.hash_password(b"password", salt).map(|_| ()));
+            assert_eq!(rejected, none.verify_password(b"password", &hash));
+            let huge = PasswordHash::new("$argon2id$v=19$m=4294967295,t=2,p=1$c29tZXNhbHRzb21lc2FsdA$yrdGtGIZk/3JHsUHh5gLQUqQppLwvGjf4Z+cJbPLqew").unwrap();
+            assert_eq!(rejected, PhcHasher::default().with_memory_check(Some(1.0)).verify_password(b"password", &huge));
+            #[cfg(feature = "random-salt")]
+            assert!(matches!(none.hash_new_password(b"password"), Err(Error::InvalidParameter { name: "m_cost", .. })));
+        } else {
+            assert_eq!(Ok(()), none.verify_password(b"password", &hash));
+        }
+    }
+
+    #[test]
+    fn test_cgroup_limits() {
+        use memory::{cgroup_cpus, cgroup_memory, parse_cgroup_limit};
+
+        fn files<'a>(files: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+            move |name| files.iter().find(|&&(n, _)| n == name).map(|&(_, contents)| contents.to_owned())
+        }
+
+        assert_eq!(Some(None), parse_cgroup_limit("max\n"));
+        assert_eq!(Some(None), parse_cgroup_limit("-1\n"));
+        assert_eq!(Some(Some(512 << 20)), parse_cgroup_limit("536870912\n"));
+        assert_eq!(None, parse_cgroup_limit(""));
+        assert_eq!(None, parse_cgroup_limit("512M\n"));
+
+        // cgroup v2.
+        assert_eq!(Some(384 << 20),
+                   cgroup_memory(files(&[("memory.max", "536870912\n"), ("memory.current", "134217728\n")])));
+        assert_eq!(Some(0), cgroup_memory(files(&[("memory.max", "1024\n"), ("memory.current", "4096\n")])));
+        assert_eq!(None, cgroup_memory(files(&[("memory.max", "max\n"), ("memory.current", "134217728\n")])));
+        assert_eq!(None, cgroup_memory(files(&[("memory.max", "lots\n")])));
+        assert_eq!(Some(2), cgroup_cpus(files(&[("cpu.max", "150000 100000\n")])));
+        assert_eq!(Some(1), cgroup_cpus(files(&[("cpu.max", "10000 100000\n")])));
+        assert_eq!(None, cgroup_cpus(files(&[("cpu.max", "max 100000\n")])));
+        assert_eq!(None, cgroup_cpus(files(&[("cpu.max", "150000 0\n")])));
+        assert_eq!(None, cgroup_cpus(files(&[("cpu.max", "half 100000\n")])));
+        assert_eq!(None, cgroup_cpus(files(&[("cpu.max", "\n")])));
+
+        // cgroup v1, where no limit is reported as a huge number for memory and -1 for the quota.
+        let v1 = [
+            ("memory/memory.limit_in_bytes", "536870912\n"), ("memory/memory.usage_in_bytes", "0\n"),
+            ("cpu/cpu.cfs_quota_us", "400000\n"), ("cpu/cpu.cfs_period_us", "100000\n"),
+        ];
+        assert_eq!(Some(512 << 20), cgroup_memory(files(&v1)));
+        assert_eq!(Some(4), cgroup_cpus(files(&v1)));
+        assert_eq!(Some(9223372036854771712),
+                   cgroup_memory(files(&[("memory/memory.limit_in_bytes", "9223372036854771712\n")])));
+        assert_eq!(None, cgroup_cpus(files(&[("cpu/cpu.cfs_quota_us", "-1\n"), ("cpu/cpu.cfs_period_us", "100000\n")])));
+        assert_eq!(None, cgroup_cpus(files(&[("cpu/cpu.cfs_quota_us", "400000\n")])));
+
+        // Outside of a cgroup, or not on Linux.
+        assert_eq!(None, cgroup_memory(files(&[])));
+        assert_eq!(None, cgroup_cpus(files(&[])));
+
+        let parallelism = available_parallelism();
+        assert!(parallelism >= 1);
+        assert!(parallelism as usize <= std::thread::available_parallelism().map_or(1, |n| n.get()));
+    }
+
+    #[test]
+    fn test_secure_wipe() {
+        let mut buf = *b"hunter2";
+        secure_wipe(&mut buf);
+        assert_eq!([0u8; 7], buf);
+
+        // The buffer is not read after the wipe, so only the volatile writes keep it from being
+        // removed as a dead store.
+        let mut buf = std::hint::black_box(*b"hunter2");
+        let ptr = buf.as_ptr();
+        secure_wipe(&mut buf);
+        assert_eq!([0u8; 7], unsafe { std::ptr::read_volatile(ptr as *const [u8; 7]) });
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"hunter22", b"hunter22"));
+        assert!(!constant_time_eq(b"hunter22", b"hunter23"));
+        assert!(!constant_time_eq(b"hunter22", b"Hunter22"));
+        assert!(!constant_time_eq(b"hunter2", b"hunter22"));
+        assert!(!constant_time_eq(b"", b"\0"));
+        assert!(!constant_time_eq(&[0x80], &[0x00]));
+
+        // Compare against `==` for pseudo-random slices, half of which are equal.
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..1000 {
+            let a: Vec<u8> = (0..next() % 40).map(|_| next() as u8).collect();
+            let mut b = a.clone();
+            match next() % 4 {
+                0 => {}
+                1 if !b.is_empty() => {
+                    let idx = next() as usize % b.len();
+                    b[idx] ^= 1 << (next() % 8);
+                }
+                2 => b.push(next() as u8),
+                _ => b = (0..next() % 40).map(|_| next() as u8).collect(),
+            }
+            assert_eq!(a == b, constant_time_eq(&a, &b), "{:?} {:?}", a, b);
+            assert_eq!(a == b, constant_time_eq(&b, &a), "{:?} {:?}", b, a);
+        }
+    }
+
+    #[test]
+    fn test_check_password_len() {
+        let pwd = vec![b'a'; DEFAULT_MAX_PASSWORD_LEN];
+        assert_eq!(Ok(()), check_password_len(Some(&pwd), Some(DEFAULT_MAX_PASSWORD_LEN)));
+
+        let pwd = vec![b'a'; DEFAULT_MAX_PASSWORD_LEN + 1];
+        let err = check_password_len(Some(&pwd), Some(DEFAULT_MAX_PASSWORD_LEN)).unwrap_err();
+        assert_eq!(Error::PasswordTooLong { len: 4097, max: 4096 }, err);
+        assert_eq!("Password too long: 4097 bytes, at most 4096 allowed", err.to_string());
+        assert_eq!(Some("pwd"), err.param_name());
+        assert!(err.is_invalid_parameter());
+
+        assert_eq!(Ok(()), check_password_len(Some(&pwd), None));
+        assert_eq!(Ok(()), check_password_len(None, Some(0)));
+    }
+
+    #[cfg(feature = "password-hash")]
+    #[test]
+    fn test_phc_hasher_max_password_len() {
+        use password_hash::{PasswordHasher, PasswordVerifier, Salt};
+
+        let params = PhcParams { m_cost: 8, t_cost: 1, p_cost: 1, output_len: 32 };
+        let salt = Salt::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+        let hasher = PhcHasher::new(Variant::ID, Version::Version13, params);
+        let at_limit = vec![b'a'; DEFAULT_MAX_PASSWORD_LEN];
+        let over_limit = vec![b'a'; DEFAULT_MAX_PASSWORD_LEN + 1];
+
+        let hash = hasher.hash_password(&at_limit, salt).unwrap();
+        assert_eq!(Ok(()), hasher.verify_password(&at_limit, &hash));
+        assert_eq!(Err(password_hash::Error::Password), hasher.hash_password(&over_limit, salt).map(|_| ()));
+
+        // A hash of a longer password is rejected before it is computed, even if it matches.
+        let unlimited = hasher.clone().with_max_password_len(None);
+        let hash = unlimited.hash_password(&over_limit, salt).unwrap();
+        assert_eq!(Ok(()), unlimited.verify_password(&over_limit, &hash));
+        assert_eq!(Err(password_hash::Error::Password), hasher.verify_password(&over_limit, &hash));
+
+        let short = hasher.with_max_password_len(Some(8));
+        assert!(short.hash_password(b"password", salt).is_ok());
+        assert_eq!(Err(password_hash::Error::Password), short.hash_password(b"password1", salt).map(|_| ()));
+        #[cfg(feature = "random-salt")]
+        assert_eq!(Err(Error::PasswordTooLong { len: 9, max: 8 }), short.hash_new_password(b"password1").map(|_| ()));
+    }
+
+    #[test]
+    fn test_encoded_m_cost() {
+        use memory::encoded_m_cost;
+
+        assert_eq!(Some(65536), encoded_m_cost(b"$argon2id$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$aGFzaA"));
+        assert_eq!(Some(4096), encoded_m_cost(b"$argon2i$m=4096,t=3,p=1$c29tZXNhbHQ$aGFzaA"));
+        assert_eq!(None, encoded_m_cost(b"$argon2id$v=19$t=2,p=1$c29tZXNhbHQ$aGFzaA"));
+        assert_eq!(None, encoded_m_cost(b"$argon2id$v=19$m=99999999999,t=2,p=1$c29tZXNhbHQ$aGFzaA"));
+        assert_eq!(None, encoded_m_cost(b""));
+
+        let encoded = b"$argon2id$v=19$m=65536,t=2,p=4$c29tZXNhbHQ$aGFzaA";
+        assert_eq!(Some(19), memory::encoded_param(encoded, b"v"));
+        assert_eq!(Some(2), memory::encoded_param(encoded, b"t"));
+        assert_eq!(Some(4), memory::encoded_param(encoded, b"p"));
+        assert_eq!(None, memory::encoded_param(b"$argon2i$m=4096,t=3,p=1$c29tZXNhbHQ$aGFzaA", b"v"));
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn test_verify_cache() {
+        use std::time::{Duration, Instant};
+
+        let mut encoded = [0u8; 128];
+        id_hash_encoded(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), 32, &mut encoded).unwrap();
+        let encoded = c_str(&encoded).unwrap();
+
+        let cache = VerifyCache::new(2, Duration::from_secs(60)).unwrap();
+        assert!(cache.is_empty());
+
+        // Failures are never cached.
+        assert!(cache.verify(encoded, Some(b"hunter22"), Variant::ID).unwrap_err().is_verify_mismatch());
+        assert!(cache.verify(encoded, Some(b"password"), Variant::I).is_err());
+        assert!(cache.is_empty());
+
+        cache.verify(encoded, Some(b"password"), Variant::ID).unwrap();
+        assert_eq!(1, cache.len());
+        let tag = cache.tag(encoded, Some(b"password"), Variant::ID);
+        assert!(cache.lookup(&tag, Instant::now()));
+        cache.verify(encoded, Some(b"password"), Variant::ID).unwrap();
+        assert_eq!(1, cache.len());
+
+        // The password, encoded hash and variant are all part of the key.
+        assert_ne!(tag, cache.tag(encoded, Some(b"passwore"), Variant::ID));
+        assert_ne!(tag, cache.tag(encoded, Some(b"password"), Variant::I));
+        assert_ne!(tag, cache.tag(c_str(b"$argon2id$\0").unwrap(), Some(b"password"), Variant::ID));
+        // A second cache has a different key.
+        let other = VerifyCache::new(2, Duration::from_secs(60)).unwrap();
+        assert_ne!(tag, other.tag(encoded, Some(b"password"), Variant::ID));
+
+        // Expired entries miss and are dropped.
+        let start = Instant::now();
+        let cache = VerifyCache::new(2, Duration::from_secs(60)).unwrap();
+        cache.insert([1; 32], start);
+        assert!(cache.lookup(&[1; 32], start + Duration::from_secs(59)));
+        assert!(!cache.lookup(&[1; 32], start + Duration::from_secs(60)));
+        assert!(cache.is_empty());
+
+        // When full, expired entries go first, then the oldest.
+        cache.insert([1; 32], start);
+        cache.insert([2; 32], start + Duration::from_secs(30));
+        cache.insert([3; 32], start + Duration::from_secs(40));
+        assert_eq!(2, cache.len());
+        assert!(!cache.lookup(&[1; 32], start + Duration::from_secs(40)));
+        assert!(cache.lookup(&[2; 32], start + Duration::from_secs(40)));
+
+        cache.insert([4; 32], start + Duration::from_secs(100));
+        assert_eq!(1, cache.len());
+        assert!(cache.lookup(&[4; 32], start + Duration::from_secs(100)));
+
+        cache.insert([5; 32], start + Duration::from_secs(101));
+        cache.insert([6; 32], start + Duration::from_secs(102));
+        assert_eq!(2, cache.len());
+        assert!(!cache.lookup(&[4; 32], start + Duration::from_secs(102)));
+
+        cache.clear();
+        assert!(cache.is_empty());
+
+        let disabled = VerifyCache::new(0, Duration::from_secs(60)).unwrap();
+        disabled.verify(encoded, Some(b"password"), Variant::ID).unwrap();
+        assert!(disabled.is_empty());
+    }
+
+    /// Polls `future` to completion on the current thread, without an async runtime.
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = std::task::Waker::from(std::sync::Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                std::task::Poll::Ready(output) => return output,
+                std::task::Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_batch_hash_encoded() {
+        let items: Vec<(Vec<u8>, Vec<u8>)> = (0..40)
+            .map(|i| (format!("password{}", i).into_bytes(), format!("somesalt{}", i % 7).into_bytes()))
+            .collect();
+        let mut invalid = items.clone();
+        invalid[3].1 = b"short".to_vec();
+
+        let results = batch_hash_encoded(1, 1 << 4, 1, 32, Variant::ID, Version::DEFAULT, 4, invalid).unwrap();
+        assert_eq!(items.len(), results.len());
+        for (i, ((pwd, salt), result)) in items.iter().zip(results).enumerate() {
+            if i == 3 {
+                check_error_code!(SaltTooShort, result);
+                continue;
+            }
+            let mut expected = [0u8; 128];
+            id_hash_encoded(1, 1 << 4, 1, Some(pwd), Some(salt), 32, &mut expected).unwrap();
+            assert_eq!(c_str(&expected).unwrap().to_str().unwrap(), result.unwrap());
+        }
+
+        assert_eq!(Vec::<Result<String, Error>>::new(),
+                   batch_hash_encoded(1, 1 << 4, 1, 32, Variant::ID, Version::DEFAULT, 0, Vec::<(&[u8], &[u8])>::new())
+                       .unwrap());
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        use std::future::Future;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Barrier;
+
+        let budget = MemoryBudget::new(1 << 14);
+        assert_eq!(Err(Error::InvalidParameter { name: "m_cost", got: 17, min: 0, max: 16 }),
+                   budget.acquire(17).map(|_| ()));
+
+        // Two hashes that each need the whole budget run one after the other.
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+        let barrier = Barrier::new(2);
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    barrier.wait();
+                    let _permit = budget.acquire(1 << 4).unwrap();
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(now, Ordering::SeqCst);
+                    let mut out = [0u8; 32];
+                    id_hash_raw(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), &mut out).unwrap();
+                    std::thread::sleep(std::time::Duration::from_millis(20));
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert_eq!(1, max_in_flight.load(Ordering::SeqCst));
+        assert_eq!(1 << 14, budget.available_bytes());
+
+        // The permit is returned when hashing fails.
+        let result = (|| {
+            let _permit = budget.acquire(1 << 3)?;
+            assert_eq!(1 << 13, budget.available_bytes());
+            assert!(budget.try_acquire(1 << 4).unwrap().is_none());
+            let mut out = [0u8; 32];
+            id_hash_raw(2, 1 << 3, 1, Some(b"password"), Some(b"salt"), &mut out)
+        })();
+        check_error_code!(SaltTooShort, result);
+        assert_eq!(1 << 14, budget.available_bytes());
+
+        // Asynchronous waiters are woken when memory is released.
+        let permit = budget.acquire(1 << 4).unwrap();
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| block_on(budget.acquire_async(1 << 4)).map(|permit| permit.bytes()));
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            drop(permit);
+            assert_eq!(Ok(1 << 14), waiter.join().unwrap());
+        });
+        assert_eq!(1 << 14, budget.available_bytes());
+
+        // A waiter that is polled again keeps only its latest waker, and a dropped one none.
+        struct CountingWaker(AtomicUsize);
+
+        impl std::task::Wake for CountingWaker {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let counting = || std::sync::Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let (first, second, dropped) = (counting(), counting(), counting());
+        let permit = budget.acquire(1 << 4).unwrap();
+        let mut waiting = Box::pin(budget.acquire_async(1 << 4));
+        let mut abandoned = Box::pin(budget.acquire_async(1 << 4));
+        for waker in [&first, &first, &first, &second, &second] {
+            let waker = std::task::Waker::from(std::sync::Arc::clone(waker));
+            assert!(waiting.as_mut().poll(&mut std::task::Context::from_waker(&waker)).is_pending());
+        }
+        let waker = std::task::Waker::from(std::sync::Arc::clone(&dropped));
+        assert!(abandoned.as_mut().poll(&mut std::task::Context::from_waker(&waker)).is_pending());
+        drop(abandoned);
+        drop(permit);
+        assert_eq!(0, first.0.load(Ordering::SeqCst));
+        assert_eq!(1, second.0.load(Ordering::SeqCst));
+        assert_eq!(0, dropped.0.load(Ordering::SeqCst));
+        let waker = std::task::Waker::from(second);
+        match waiting.as_mut().poll(&mut std::task::Context::from_waker(&waker)) {
+            std::task::Poll::Ready(permit) => assert_eq!(1 << 14, permit.unwrap().bytes()),
+            std::task::Poll::Pending => panic!("the released memory is available"),
+        }
+        drop(waiting);
+        assert_eq!(1 << 14, budget.available_bytes());
+
+        // A hasher with a budget waits for it, returns its permits on mismatches and rejects hashes
+        // that exceed it.
+        #[cfg(feature = "password-hash")]
+        {
+            let budget = std::sync::Arc::new(budget);
+            let params = PhcParams { m_cost: 1 << 4, t_cost: 2, p_cost: 1, output_len: 32 };
+            let hasher = PhcHasher::new(Variant::ID, Version::Version13, params).with_memory_budget(std::sync::Arc::clone(&budget));
+            let encoded = hash_encoded_string(2, 1 << 4, 1, b"password", b"somesalt", 32, Variant::ID, Version::Version13).unwrap();
+            let hash = EncodedHash::parse(&encoded).unwrap();
+
+            let permit = budget.acquire(1 << 4).unwrap();
+            let (done, finished) = std::sync::mpsc::channel();
+            std::thread::scope(|scope| {
+                scope.spawn(|| done.send(hasher.verify(b"password", &hash)).unwrap());
+                assert!(finished.recv_timeout(std::time::Duration::from_millis(50)).is_err(), "the hasher waits for the budget");
+                drop(permit);
+                assert_eq!(Ok(()), finished.recv().unwrap());
+            });
+            assert_eq!(1 << 14, budget.available_bytes());
+
+            check_error_code!(VerifyMismatch, hasher.verify(b"passwore", &hash));
+            assert_eq!(1 << 14, budget.available_bytes());
+
+            let small = hasher.clone().with_memory_budget(std::sync::Arc::new(MemoryBudget::new(1 << 13)));
+            assert_eq!(Err(Error::InvalidParameter { name: "m_cost", got: 16, min: 0, max: 8 }), small.verify(b"password", &hash));
+            {
+                use password_hash::{PasswordHasher, Salt};
+
+                assert_eq!(Err(password_hash::Error::ParamValueInvalid(password_hash::errors::InvalidValue::TooLong)),
+                           small.hash_password(b"password", Salt::from_b64("c29tZXNhbHQ").unwrap()).map(|_| ()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_std_thread_executor() {
+        let executor = StdThreadExecutor;
+        let encoded = block_on(hash_encoded_on(&executor, 2, 1 << 4, 1, b"password".to_vec(), b"somesalt".to_vec(),
+                                               32, Variant::ID, Version::DEFAULT)).unwrap();
+        let mut expected = [0u8; 128];
+        id_hash_encoded(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), 32, &mut expected).unwrap();
+        assert_eq!(c_str(&expected).unwrap().to_str().unwrap(), encoded);
+
+        #[cfg(not(feature = "no-argon2d"))]
+        {
+            let raw = block_on(hash_raw_on(&executor, 2, 1 << 4, 1, b"password".to_vec(), b"somesalt".to_vec(), 32,
+                                           Variant::D, Version::DEFAULT)).unwrap();
+            let mut expected = [0u8; 32];
+            d_hash_raw(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), &mut expected).unwrap();
+            assert_eq!(&expected[..], &raw[..]);
+        }
+
+        block_on(verify_on(&executor, encoded.clone(), b"password".to_vec(), Variant::ID)).unwrap();
+        assert!(block_on(verify_on(&executor, encoded, b"hunter22".to_vec(), Variant::ID))
+            .unwrap_err().is_verify_mismatch());
+
+        let err = block_on(executor.run::<(), _>(|| panic!("task panicked"))).unwrap_err();
+        assert!(matches!(err, Error::TaskFailed(_)));
+
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(Err(Error::InvalidParameter { name: "hashlen", got: usize::MAX as u64, min: 0, max: 0xFFFFFFFF }),
+                   block_on(hash_raw_on(&executor, 2, 1 << 4, 1, b"password".to_vec(), b"somesalt".to_vec(), usize::MAX,
+                                        Variant::ID, Version::DEFAULT)));
+    }
+
+    #[test]
+    fn test_hash_encoded_with_timeout() {
+        use std::time::Duration;
+
+        let fast = PhcParams { m_cost: 1 << 4, t_cost: 2, p_cost: 1, output_len: 32 };
+        let encoded = hash_encoded_with_timeout(&fast, b"password".to_vec(), b"somesalt".to_vec(), Variant::ID,
+                                                Duration::from_secs(60)).unwrap();
+        let mut expected = [0u8; 128];
+        id_hash_encoded(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), 32, &mut expected).unwrap();
+        assert_eq!(c_str(&expected).unwrap().to_str().unwrap(), encoded);
+        assert_eq!(Ok(()), verify_bytes(encoded.as_bytes(), Some(b"password"), Variant::ID));
+
+        // The slow hash keeps running on its detached thread after the timeout.
+        let slow = PhcParams { m_cost: 1 << 15, t_cost: 20, p_cost: 1, output_len: 32 };
+        assert_eq!(Err(Error::TimedOut), hash_encoded_with_timeout(&slow, b"password".to_vec(), b"somesalt".to_vec(),
+                                                                   Variant::ID, Duration::from_millis(1)));
+        assert_eq!("Hashing didn't finish in time", Error::TimedOut.to_string());
+
+        // Errors of the hash are passed through.
+        check_error_code!(MemoryTooLittle, hash_encoded_with_timeout(&PhcParams { m_cost: 1, ..fast }, b"password".to_vec(),
+                                                                     b"somesalt".to_vec(), Variant::ID, Duration::from_secs(60)));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_async() {
+        // The runtime is built by hand so that tokio is only needed with the `tokio` feature, which
+        // doesn't enable its macros.
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let encoded = hash_encoded_async(2, 1 << 4, 1, b"password".to_vec(), b"somesalt".to_vec(), 32, Variant::ID,
+                                             Version::DEFAULT).await.unwrap();
+            let mut expected = [0u8; 128];
+            id_hash_encoded(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), 32, &mut expected).unwrap();
+            assert_eq!(c_str(&expected).unwrap().to_str().unwrap(), encoded);
+
+            let raw = hash_raw_async(2, 1 << 4, 1, b"password".to_vec(), b"somesalt".to_vec(), 32, Variant::I,
+                                     Version::Version10).await.unwrap();
+            let mut expected = [0u8; 32];
+            hash(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), Some(&mut expected), None, Variant::I,
+                 Version::Version10).unwrap();
+            assert_eq!(&expected[..], &raw[..]);
+
+            verify_async(encoded.clone(), b"password".to_vec(), Variant::ID).await.unwrap();
+            assert!(verify_async(encoded.clone(), b"hunter22".to_vec(), Variant::ID).await.unwrap_err().is_verify_mismatch());
+            assert_eq!(Err(Error::BadParam("encoded")),
+                       verify_async("$argon2id\0$".to_owned(), b"password".to_vec(), Variant::ID).await);
+            check_error_code!(MemoryTooLittle,
+                              hash_raw_async(2, 1, 1, b"password".to_vec(), b"somesalt".to_vec(), 32, Variant::ID,
+                                             Version::DEFAULT).await);
+
+            #[cfg(feature = "password-hash")]
+            {
+                let hasher = PhcHasher::new(Variant::ID, Version::Version13, PhcParams { m_cost: 1 << 4, t_cost: 2, p_cost: 1, output_len: 32 });
+                let hash = EncodedHash::parse(&encoded).unwrap();
+                assert_eq!(Ok(()), hasher.verify(b"password", &hash));
+                assert_eq!(Ok(()), hasher.verify_async(b"password".to_vec(), hash.clone()).await);
+                assert!(hasher.verify_async(b"hunter22".to_vec(), hash.clone()).await.unwrap_err().is_verify_mismatch());
+                let limited = hasher.clone().with_max_password_len(Some(4));
+                assert_eq!(Err(Error::PasswordTooLong { len: 8, max: 4 }), limited.verify_async(b"password".to_vec(), hash).await);
+
+                #[cfg(feature = "random-salt")]
+                {
+                    let hash = hasher.hash_new_password_async(b"password".to_vec()).await.unwrap();
+                    assert_eq!(Ok(()), verify_bytes(hash.as_str().as_bytes(), Some(b"password"), Variant::ID));
+                    assert_eq!(Ok(()), hasher.verify(b"password", &hash));
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_ffi_names() {
+        use ffi_names::{argon2_error_message, argon2_hash, argon2_type2string, argon2_verify, argon2id_hash_encoded, argon2id_hash_raw};
+
+        let mut encoded = [0u8; 128];
+        argon2id_hash_encoded(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), 32, &mut encoded).unwrap();
+        let mut expected = [0u8; 128];
+        id_hash_encoded(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), 32, &mut expected).unwrap();
+        assert_eq!(&expected[..], &encoded[..]);
+        assert_eq!(Ok(()), argon2_verify(c_str(&encoded).unwrap(), Some(b"password"), Variant::ID));
+        check_error_code!(VerifyMismatch, argon2_verify(c_str(&encoded).unwrap(), Some(b"passwore"), Variant::ID));
+
+        let mut raw = [0u8; 32];
+        argon2id_hash_raw(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), &mut raw).unwrap();
+        let mut expected = [0u8; 32];
+        argon2_hash(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), Some(&mut expected), None, Variant::ID, Version::DEFAULT).unwrap();
+        assert_eq!(expected, raw);
+
+        assert_eq!(error_message(ErrorCode::VerifyMismatch), argon2_error_message(ErrorCode::VerifyMismatch));
+        assert_eq!(Ok("Argon2id"), argon2_type2string(Variant::ID, true));
+    }
+
+    #[test]
+    fn test_cost_newtypes() {
+        use std::convert::TryFrom;
+
+        assert_eq!(Err(Error::InvalidParameter { name: "t_cost", got: 0, min: 1, max: u32::MAX.into() }),
+                   TimeCost::new(0));
+        assert_eq!(1, TimeCost::new(1).unwrap().get());
+        assert_eq!(TimeCost::MAX, TimeCost::try_from(u32::MAX).unwrap());
+
+        let max_m_cost = u64::from(MemoryCost::MAX.get());
+        assert_eq!(Err(Error::InvalidParameter { name: "m_cost", got: 4, min: 8, max: max_m_cost }),
+                   MemoryCost::kib(4));
+        assert_eq!(Err(Error::InvalidParameter { name: "m_cost", got: 7, min: 8, max: max_m_cost }),
+                   MemoryCost::try_from(7));
+        assert_eq!(MemoryCost::MIN, MemoryCost::kib(8).unwrap());
+        assert_eq!(MemoryCost::MAX, MemoryCost::kib(MemoryCost::MAX.get()).unwrap());
+        assert_eq!(19 * 1024, MemoryCost::mib(19).unwrap().get());
+        assert_eq!(Err(Error::InvalidParameter { name: "m_cost", got: u64::from(u32::MAX) * 1024, min: 8, max: max_m_cost }),
+                   MemoryCost::mib(u32::MAX));
+
+        assert_eq!(Err(Error::InvalidParameter { name: "p_cost", got: 0, min: 1, max: 0xFFFFFF }),
+                   Parallelism::new(0));
+        assert_eq!(Err(Error::InvalidParameter { name: "p_cost", got: 0x1000000, min: 1, max: 0xFFFFFF }),
+                   Parallelism::try_from(0x1000000));
+        assert_eq!(Parallelism::MIN, Parallelism::new(1).unwrap());
+        assert_eq!(0xFFFFFF, u32::from(Parallelism::new(0xFFFFFF).unwrap()));
+
+        let t_cost = TimeCost::new(2).unwrap();
+        let m_cost = MemoryCost::kib(256).unwrap();
+        let p_cost = Parallelism::new(1).unwrap();
+        assert_eq!(PhcParams { m_cost: 256, t_cost: 2, p_cost: 1, output_len: 32 }, PhcParams::new(m_cost, t_cost, p_cost));
+        assert_eq!(Ok(()), PhcParams::new(m_cost, t_cost, p_cost).validate());
+        assert_eq!(Err(Error::InvalidParameter { name: "m_cost", got: 24, min: 32, max: MemoryCost::MAX.get().into() }),
+                   PhcParams { m_cost: 24, p_cost: 4, ..PhcParams::default() }.validate());
+        assert_eq!(Err(Error::InvalidParameter { name: "output_len", got: 3, min: 4, max: u32::MAX.into() }),
+                   PhcParams { output_len: 3, ..PhcParams::default() }.validate());
+        assert!(PhcParams { t_cost: 0, ..PhcParams::default() }.validate().is_err());
+        assert_eq!("$argon2i$v=19$m=256,t=2,p=1$c29tZXNhbHQ$iekCn0Y3spW+sCcFanM2xBT63UP2sghkUoHLIUpWRS8",
+                   hash_encoded_with_costs(t_cost, m_cost, p_cost, b"password", b"somesalt", 32, Variant::I,
+                                           Version::DEFAULT).unwrap());
+        let mut out = [0u8; 32];
+        hash_with_costs(t_cost, m_cost, p_cost, Some(b"password"), Some(b"somesalt"), Some(&mut out), None, Variant::I,
+                        Version::DEFAULT).unwrap();
+        let mut expected = [0u8; 32];
+        i_hash_raw(2, 256, 1, Some(b"password"), Some(b"somesalt"), &mut expected).unwrap();
+        assert_eq!(expected, out);
+    }
+
+    #[test]
+    fn test_verify_pinned() {
+        let params = PhcParams { m_cost: 256, t_cost: 2, p_cost: 1, output_len: 32 };
+        let pinned = PinnedConfig::new(Variant::I, Version::Version13, &params);
+        let encoded = hash_encoded_string(2, 256, 1, b"password", b"somesalt", 32, Variant::I, Version::Version13).unwrap();
+        assert_eq!(Ok(()), verify_pinned(&encoded, Some(b"password"), &pinned));
+        check_error_code!(VerifyMismatch, verify_pinned(&encoded, Some(b"passwore"), &pinned));
+
+        // Weaker and stronger hashes are rejected alike, with the first field that differs.
+        let not_pinned = |field, expected, found| Err(Error::ParamsNotPinned { field, expected, found });
+        let weaker = hash_encoded_string(1, 128, 1, b"password", b"somesalt", 32, Variant::I, Version::Version13).unwrap();
+        assert_eq!(not_pinned("m_cost", 256, 128), verify_pinned(&weaker, Some(b"password"), &pinned));
+        let stronger = hash_encoded_string(3, 256, 1, b"password", b"somesalt", 32, Variant::I, Version::Version13).unwrap();
+        assert_eq!(not_pinned("t_cost", 2, 3), verify_pinned(&stronger, Some(b"password"), &pinned));
+        let stronger = hash_encoded_string(2, 256, 2, b"password", b"somesalt", 32, Variant::I, Version::Version13).unwrap();
+        assert_eq!(not_pinned("p_cost", 1, 2), verify_pinned(&stronger, Some(b"password"), &pinned));
+        let longer = hash_encoded_string(2, 256, 1, b"password", b"somesalt", 64, Variant::I, Version::Version13).unwrap();
+        assert_eq!(not_pinned("hash_len", 32, 64), verify_pinned(&longer, Some(b"password"), &pinned));
+        assert_eq!(Ok(()), verify_pinned(&longer, Some(b"password"), &PinnedConfig { hash_len: None, ..pinned }));
+        let other_variant = hash_encoded_string(2, 256, 1, b"password", b"somesalt", 32, Variant::ID, Version::Version13).unwrap();
+        assert_eq!(not_pinned("variant", 1, 2), verify_pinned(&other_variant, Some(b"password"), &pinned));
+        let old_version = hash_encoded_string(2, 256, 1, b"password", b"somesalt", 32, Variant::I, Version::Version10).unwrap();
+        assert_eq!(not_pinned("version", 0x13, 0x10), verify_pinned(&old_version, Some(b"password"), &pinned));
+
+        // The parameters are checked before hashing, even with a memory cost beyond the cap.
+        let huge = "$argon2i$v=19$m=4194304,t=2,p=1$c29tZXNhbHQ$aGFzaGhhc2hoYXNoaGFzaGhhc2hoYXNoaGFzaGhhc2g";
+        assert_eq!(not_pinned("m_cost", 256, 4194304), verify_pinned(huge, Some(b"password"), &pinned));
+        check_error_code!(DecodingFail, verify_pinned("$argon2i$", Some(b"password"), &pinned));
+        assert_eq!("Hash doesn't use the pinned parameters: t_cost is 3, expected 2",
+                   Error::ParamsNotPinned { field: "t_cost", expected: 2, found: 3 }.to_string());
+    }
+
+    #[test]
+    fn test_verify_constant_duration() {
+        const ENCODED: &str = "$argon2i$v=19$m=256,t=2,p=1$c29tZXNhbHQ$iekCn0Y3spW+sCcFanM2xBT63UP2sghkUoHLIUpWRS8";
+        let floor = Duration::from_millis(50);
+        let timed = |encoded: &str, pwd: &[u8]| {
+            let start = Instant::now();
+            let result = verify_constant_duration(encoded, Some(pwd), floor);
+            (result, start.elapsed())
+        };
+
+        let (result, elapsed) = timed(ENCODED, b"password");
+        assert_eq!(Ok(true), result);
+        assert!(elapsed >= floor, "{:?}", elapsed);
+        let (result, elapsed) = timed(ENCODED, b"passwore");
+        assert_eq!(Ok(false), result);
+        assert!(elapsed >= floor, "{:?}", elapsed);
+
+        // Failures before any hashing are padded as well.
+        let (result, elapsed) = timed("$argon2i$", b"password");
+        check_error_code!(DecodingFail, result);
+        assert!(elapsed >= floor, "{:?}", elapsed);
+        let (result, elapsed) = timed(&ENCODED.replace("argon2i", "argon2x"), b"password");
+        assert!(result.is_err());
+        assert!(elapsed >= floor, "{:?}", elapsed);
+        let (result, elapsed) = timed(&ENCODED.replace("m=256", "m=2"), b"password");
+        check_error_code!(MemoryTooLittle, result);
+        assert!(elapsed >= floor, "{:?}", elapsed);
+
+        assert_eq!(Ok(true), verify_constant_duration(ENCODED, Some(b"password"), Duration::ZERO));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics() {
+        use metrics::{HashParams, MetricsSink, Outcome};
+        use std::sync::{Arc, Mutex};
+        use std::thread::ThreadId;
+
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        enum Event {
+            Hash(Outcome, u32),
+            Verify(Outcome),
+        }
+
+        // Other tests hash at the same time, so events are recorded per thread.
+        #[derive(Default)]
+        struct RecordingSink(Arc<Mutex<Vec<(ThreadId, Event)>>>);
+
+        impl MetricsSink for RecordingSink {
+            fn on_hash(&self, outcome: Outcome, _duration: Duration, params: &HashParams) {
+                self.0.lock().unwrap().push((std::thread::current().id(), Event::Hash(outcome, params.m_cost)));
+            }
+
+            fn on_verify(&self, outcome: Outcome, _duration: Duration) {
+                self.0.lock().unwrap().push((std::thread::current().id(), Event::Verify(outcome)));
+            }
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        metrics::set_metrics_sink(Box::new(RecordingSink(Arc::clone(&events))));
+        let recorded = || {
+            let id = std::thread::current().id();
+            events.lock().unwrap().iter().filter(|(thread, _)| *thread == id).map(|(_, event)| event.clone()).collect::<Vec<_>>()
+        };
+
+        let encoded = hash_encoded_string(2, 264, 1, b"password", b"somesalt", 32, Variant::ID, Version::DEFAULT).unwrap();
+        assert_eq!(vec![Event::Hash(Outcome::Ok, 264)], recorded());
+        let mut out = [0u8; 32];
+        assert!(id_hash_raw(0, 264, 1, Some(b"password"), Some(b"somesalt"), &mut out).is_err());
+        let encoded = std::ffi::CString::new(encoded).unwrap();
+        id_verify(&encoded, Some(b"password")).unwrap();
+        assert!(id_verify(&encoded, Some(b"passwore")).unwrap_err().is_verify_mismatch());
+        assert!(id_verify(c_str(b"$argon2id$\0").unwrap(), Some(b"password")).is_err());
+        assert_eq!(vec![
+            Event::Hash(Outcome::Ok, 264),
+            Event::Hash(Outcome::Error, 264),
+            Event::Verify(Outcome::Ok),
+            Event::Verify(Outcome::Mismatch),
+            Event::Verify(Outcome::Error),
+        ], recorded());
+
+        // Functions built on others report once.
+        let params = PhcParams { m_cost: 264, t_cost: 2, p_cost: 1, output_len: 32 };
+        MemoryFallbackPolicy::new(params).hash_new_password(b"password", b"somesalt", Variant::ID, Version::DEFAULT).unwrap();
+        assert_eq!(Ok(false), verify_constant_duration(encoded.to_str().unwrap(), Some(b"passwore"), Duration::ZERO));
+        assert_eq!(&[Event::Hash(Outcome::Ok, 264), Event::Verify(Outcome::Mismatch)], &recorded()[5..]);
+
+        // Verifications that compare the hash themselves report the comparison, not the hash.
+        hash(2, 264, 1, Some(b"password"), Some(b"somesalt"), Some(&mut out), None, Variant::ID, Version::DEFAULT).unwrap();
+        let record = raw_record::encode(b"somesalt", &out);
+        raw_record::verify_raw_record(&params, Variant::ID, Version::DEFAULT, Some(b"password"), &record, 8).unwrap();
+        check_error_code!(VerifyMismatch, raw_record::verify_raw_record(&params, Variant::ID, Version::DEFAULT, Some(b"passwore"), &record, 8));
+        assert_eq!(&[Event::Hash(Outcome::Ok, 264), Event::Verify(Outcome::Ok), Event::Verify(Outcome::Mismatch)], &recorded()[7..]);
+
+        #[cfg(feature = "blake2b")]
+        {
+            let seen = recorded().len();
+            let hash = hash_raw_from_reader(&params, Variant::ID, Version::DEFAULT, &b"key file"[..], b"somesalt", 32).unwrap();
+            verify_from_reader(&params, Variant::ID, Version::DEFAULT, &b"key file"[..], b"somesalt", &hash).unwrap();
+            check_error_code!(VerifyMismatch, verify_from_reader(&params, Variant::ID, Version::DEFAULT, &b"key filf"[..], b"somesalt", &hash));
+            assert_eq!(&[Event::Hash(Outcome::Ok, 264), Event::Verify(Outcome::Ok), Event::Verify(Outcome::Mismatch)], &recorded()[seen..]);
+        }
+
+        // The limits of the hasher are checked before verifying and aren't reported.
+        #[cfg(feature = "password-hash")]
+        {
+            let seen = recorded().len();
+            let hasher = PhcHasher::new(Variant::ID, Version::DEFAULT, params);
+            let hash = EncodedHash::parse(encoded.to_str().unwrap()).unwrap();
+            hasher.verify(b"password", &hash).unwrap();
+            check_error_code!(VerifyMismatch, hasher.verify(b"passwore", &hash));
+            assert!(hasher.with_max_password_len(Some(4)).verify(b"password", &hash).is_err());
+            assert_eq!(&[Event::Verify(Outcome::Ok), Event::Verify(Outcome::Mismatch)], &recorded()[seen..]);
+        }
+    }
+
+    #[test]
+    fn test_audit_hook() {
+        use audit::{AuditEvent, AuditOutcome};
+        use std::sync::Mutex;
+
+        // Correlation ID, outcome and (version, m_cost, t_cost, hash_len). Other tests may verify at
+        // the same time, so events are told apart by the correlation ID.
+        type Recorded = (String, AuditOutcome, Option<(u32, u32, u32, usize)>);
+        static EVENTS: Mutex<Vec<Recorded>> = Mutex::new(Vec::new());
+        fn hook(event: &AuditEvent) {
+            let params = event.params.map(|params| (params.version.to_int(), params.m_cost, params.t_cost, params.hash_len));
+            EVENTS.lock().unwrap().push((event.correlation.to_owned(), event.outcome, params));
+            assert!(event.timestamp <= std::time::SystemTime::now());
+            if event.correlation.starts_with("panic") {
+                panic!("audit hook panicked");
+            }
+        }
+        let events = |prefix: &str| {
+            EVENTS.lock().unwrap().iter().filter(|(correlation, _, _)| correlation.starts_with(prefix)).cloned().collect::<Vec<_>>()
+        };
+
+        audit::set_audit_hook(hook);
+        const ENCODED: &str = "$argon2i$v=19$m=256,t=2,p=1$c29tZXNhbHQ$iekCn0Y3spW+sCcFanM2xBT63UP2sghkUoHLIUpWRS8";
+        let params = Some((0x13, 256, 2, 32));
+        assert_eq!(Ok(()), audit::verify_audited(ENCODED, Some(b"password"), "audit-1"));
+        check_error_code!(VerifyMismatch, audit::verify_audited(ENCODED, Some(b"passwore"), "audit-2"));
+        check_error_code!(DecodingFail, audit::verify_audited("$argon2i$", Some(b"password"), "audit-3"));
+        let over_cap = EncodedHash::new(Variant::I, Version::Version13, u32::MAX, 2, 1, b"somesalt".to_vec(), vec![0; 32]);
+        assert!(over_cap.verify_audited(Some(b"password"), "audit-4").is_err());
+        assert_eq!(Ok(()), EncodedHash::parse(ENCODED).unwrap().verify_audited(Some(b"password"), "audit-5"));
+        assert_eq!(vec![
+            ("audit-1".to_owned(), AuditOutcome::Match, params),
+            ("audit-2".to_owned(), AuditOutcome::Mismatch, params),
+            ("audit-3".to_owned(), AuditOutcome::Malformed, None),
+            ("audit-4".to_owned(), AuditOutcome::Error, Some((0x13, u32::MAX, 2, 32))),
+            ("audit-5".to_owned(), AuditOutcome::Match, params),
+        ], events("audit-"));
+
+        // A panicking hook doesn't affect the verification.
+        assert_eq!(Ok(()), audit::verify_audited(ENCODED, Some(b"password"), "panic-1"));
+        assert_eq!(vec![("panic-1".to_owned(), AuditOutcome::Match, params)], events("panic-"));
+    }
+
+    #[test]
+    fn test_encoded_buffer_size() {
+        let required = encodedlen(2, 1 << 4, 1, 8, 32, Variant::ID);
+
+        let mut exact = vec![0u8; required];
+        id_hash_encoded(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), 32, &mut exact)
+            .expect("Exactly sized buffer must work.");
+        let mut generous = vec![0u8; required * 2];
+        id_hash_encoded(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), 32, &mut generous)
+            .expect("Larger buffer must work.");
+        assert_eq!(c_str(&exact), c_str(&generous));
+
+        let mut short = vec![0u8; required - 1];
+        assert_eq!(Err(Error::BufferTooSmall { required, provided: required - 1 }),
+                   id_hash_encoded(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), 32, &mut short));
+
+        let mut out = [0u8; 32];
+        assert_eq!(Err(Error::BufferTooSmall { required, provided: required - 1 }),
+                   hash(2, 1 << 4, 1, Some(b"password"), Some(b"somesalt"), Some(&mut out), Some(&mut short),
+                        Variant::ID, Version::DEFAULT));
+    }
+
+    /// `ctx` must hand the exact error code of `argon2_ctx` through.
+    #[test]
+    fn test_ctx_error_code() {
+        let mut out = [0u8; 32];
+        let mut pwd = b"password".to_vec();
+        let mut salt = b"somesalt".to_vec();
+        let mut context = Context {
+            out: &mut out,
+            pwd: Some(&mut pwd),
+            salt: Some(&mut salt),
+            secret: None,
+            ad: None,
+            t_cost: 2,
+            m_cost: 1,
+            lanes: 1,
+            threads: 1,
+            version: Version::DEFAULT,
+            flags: Flags::DEFAULT,
+        };
+        check_error_code!(MemoryTooLittle, ctx(&mut context, Variant::ID));
+    }
+
+    #[test]
+    fn test_checked_len() {
+        use types::{checked_len_u32, opt_slice_len_u32};
+
+        assert_eq!(Ok(8), checked_len_u32("pwd.len", 8, Context::MAX_PWD_LENGTH));
+        assert_eq!(Ok(u32::MAX), checked_len_u32("pwd.len", u32::MAX as usize, Context::MAX_PWD_LENGTH));
+        assert_eq!(Err(Error::InvalidParameter { name: "out.len", got: 10, min: 0, max: 8 }),
+                   checked_len_u32("out.len", 10, 8));
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(Err(Error::InvalidParameter { name: "salt.len", got: 1 << 32, min: 0, max: 0xFFFFFFFF }),
+                   checked_len_u32("salt.len", 1 << 32, Context::MAX_SALT_LENGTH));
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(Ok(u32::MAX), checked_len_u32("salt.len", usize::MAX, Context::MAX_SALT_LENGTH));
+
+        assert_eq!(Ok(0), opt_slice_len_u32::<u8, &[u8]>("ad.len", &None, Context::MAX_AD_LENGTH));
+        assert_eq!(Ok(3), opt_slice_len_u32("ad.len", &Some(b"abc"), Context::MAX_AD_LENGTH));
+        assert_eq!(Err(Error::InvalidParameter { name: "ad.len", got: 3, min: 0, max: 2 }),
+                   opt_slice_len_u32("ad.len", &Some(b"abc"), 2));
+    }
+
+    #[test]
+    fn test_check_hash_len() {
+        assert_eq!(Ok(()), check_hash_len(32, 32));
+        assert_eq!(Err(Error::InvalidParameter { name: "hash.len", got: 31, min: 32, max: 32 }),
+                   check_hash_len(31, 32));
+        // A length of 2^32 used to wrap to 0 and pass the check.
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(Err(Error::InvalidParameter { name: "hash.len", got: 1 << 32, min: 0, max: 0 }),
+                   check_hash_len(1 << 32, 0));
+        #[cfg(target_pointer_width = "32")]
+        assert_eq!(Err(Error::InvalidParameter { name: "hash.len", got: u32::MAX.into(), min: 0, max: 0 }),
+                   check_hash_len(usize::MAX, 0));
+    }
+
+    #[test]
+    fn test_verify_ctx_hash_len() {
+        let mut out = [0u8; 32];
+        let mut pwd = b"password".to_vec();
+        let mut salt = b"somesalt".to_vec();
+        let mut context = Context {
+            out: &mut out,
+            pwd: Some(&mut pwd),
+            salt: Some(&mut salt),
+            secret: None,
+            ad: None,
+            t_cost: 2,
+            m_cost: 1 << 4,
+            lanes: 1,
+            threads: 1,
+            version: Version::DEFAULT,
+            flags: Flags::DEFAULT,
+        };
+
+        let err = verify_ctx(&mut context, &[0u8; 16], Variant::I).expect_err("hash length mismatch");
+        assert_eq!(Error::InvalidParameter { name: "hash.len", got: 16, min: 32, max: 32 }, err);
+        assert_eq!(Some("hash.len"), err.param_name());
+        assert!(err.is_bad_param());
+        assert_eq!(Some("bytes"), Error::BadParam("bytes").param_name());
+        assert!(Error::BadParam("bytes").is_bad_param());
+        assert_eq!(None, Error::Code(ErrorCode::VerifyMismatch).param_name());
+        assert!(!Error::Code(ErrorCode::VerifyMismatch).is_bad_param());
+    }
+
+    #[test]
+    fn test_boxed_error() {
+        use std::error::Error as _;
+
+        fn run() -> Result<(), Box<dyn std::error::Error>> {
+            let mut out = [0u8; 32];
+            i_hash_raw(2, 1 << 4, 1, Some(b"password"), Some(b"s"), &mut out)?;
+            Ok(())
+        }
+
+        let err = run().expect_err("short salt must fail");
+        assert_eq!("Salt is too short (code -6)", err.to_string());
+        assert_eq!(Some(&Error::Code(ErrorCode::SaltTooShort)), err.downcast_ref::<Error>());
+        // The message of the code is already part of the error's, so it isn't repeated as a source.
+        assert!(err.source().is_none());
+
+        assert!(Error::BadParam("bytes").source().is_none());
+        assert!(Error::UnknownCode(-100).source().is_none());
     }
 }
@@ -0,0 +1,356 @@
+use super::types::{Context, Error};
+use std::convert::TryFrom;
+use std::collections::btree_map::{BTreeMap, Entry};
+use std::ffi::CStr;
+use std::path::Path;
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::task::{Poll, Waker};
+
+/// Returns the memory currently available to new allocations in bytes, or `None` if it can't be
+/// determined on this platform.
+///
+/// On Linux this is `MemAvailable` from `/proc/meminfo`, or the room left below the memory limit
+/// of the cgroup if that is less, so that containers don't size hashes for the host's memory. Both
+/// cgroup v2 (`memory.max` and `memory.current`) and v1 (`memory.limit_in_bytes` and
+/// `memory.usage_in_bytes`) are read from `/sys/fs/cgroup`. Other platforms are not supported yet.
+pub fn available_memory() -> Option<u64> {
+    if cfg!(target_os = "linux") {
+        let host = std::fs::read_to_string("/proc/meminfo").ok().as_deref().and_then(parse_meminfo);
+        host.into_iter().chain(cgroup_memory(read_cgroup_file)).min()
+    } else {
+        None
+    }
+}
+
+/// Returns the number of threads that can run in parallel, for use as `parallelism`.
+///
+/// This is `std::thread::available_parallelism`, limited by the CPU quota of the cgroup on Linux
+/// (`cpu.max`, or `cpu.cfs_quota_us` and `cpu.cfs_period_us` for cgroup v1) rounded up to whole
+/// CPUs, and clamped to the range of lanes the C library supports. Returns 1 if the number can't
+/// be determined.
+pub fn available_parallelism() -> u32 {
+    let host = std::thread::available_parallelism().map_or(1, |n| u32::try_from(n.get()).unwrap_or(u32::MAX));
+    let cgroup = if cfg!(target_os = "linux") { cgroup_cpus(read_cgroup_file) } else { None };
+    host.min(cgroup.unwrap_or(u32::MAX)).clamp(Context::MIN_LANES, Context::MAX_LANES)
+}
+
+/// Checks that hashing with `m_cost` kibibytes of memory uses at most `max_fraction` of the memory
+/// that is currently available, returning `Error::InvalidParameter` for `"m_cost"` otherwise.
+///
+/// This guards against a mistyped `m_cost` taking down the process (or the OOM killer taking down
+/// others) before the C library gets to report an allocation failure. If the available memory
+/// can't be determined, the check passes. `PhcHasher::with_memory_check` runs it for every hash.
+pub fn check_memory_against_system(m_cost: u32, max_fraction: f64) -> Result<(), Error> {
+    check_memory(m_cost, available_memory(), max_fraction)
+}
+
+pub(crate) fn check_memory(m_cost: u32, available: Option<u64>, max_fraction: f64) -> Result<(), Error> {
+    let available = match available {
+        Some(available) => available,
+        None => return Ok(()),
+    };
+    let max = (available as f64 * max_fraction.max(0.0) / 1024.0) as u64;
+    if u64::from(m_cost) <= max {
+        Ok(())
+    } else {
+        Err(Error::InvalidParameter { name: "m_cost", got: m_cost.into(), min: 0, max })
+    }
+}
+
+/// Extracts `MemAvailable` in bytes from the contents of `/proc/meminfo`.
+pub(crate) fn parse_meminfo(meminfo: &str) -> Option<u64> {
+    meminfo_field(meminfo, "MemAvailable")
+}
+
+/// Extracts the field `name` in bytes from the contents of `/proc/meminfo`.
+pub(crate) fn meminfo_field(meminfo: &str, name: &str) -> Option<u64> {
+    meminfo.lines().find_map(|line| {
+        let value = line.strip_prefix(name)?.strip_prefix(':')?.trim();
+        let kib = value.strip_suffix("kB").unwrap_or(value).trim();
+        kib.parse::<u64>().ok()?.checked_mul(1024)
+    })
+}
+
+fn read_cgroup_file(name: &str) -> Option<String> {
+    std::fs::read_to_string(Path::new("/sys/fs/cgroup").join(name)).ok()
+}
+
+/// Returns the bytes left below the memory limit of the cgroup, or `None` if there is no limit or
+/// it can't be read. `read` returns the contents of a file relative to `/sys/fs/cgroup`.
+pub(crate) fn cgroup_memory(read: impl Fn(&str) -> Option<String>) -> Option<u64> {
+    let (limit, usage) = match read("memory.max") {
+        Some(limit) => (limit, read("memory.current")),
+        None => (read("memory/memory.limit_in_bytes")?, read("memory/memory.usage_in_bytes")),
+    };
+    // v1 reports no limit as a huge number instead of `max`, which loses against the host's memory.
+    let limit = parse_cgroup_limit(&limit)??;
+    let usage = usage.as_deref().and_then(parse_cgroup_limit).flatten().unwrap_or(0);
+    Some(limit.saturating_sub(usage))
+}
+
+/// Returns the CPU quota of the cgroup in whole CPUs, or `None` if there is no quota or it can't
+/// be read. `read` is as for `cgroup_memory`.
+pub(crate) fn cgroup_cpus(read: impl Fn(&str) -> Option<String>) -> Option<u32> {
+    let (quota, period) = match read("cpu.max") {
+        Some(max) => {
+            let mut fields = max.split_whitespace();
+            let quota = parse_cgroup_limit(fields.next()?)?;
+            let period = fields.next().map_or(Some(100_000), |period| period.parse().ok())?;
+            (quota, period)
+        }
+        None => {
+            let quota = parse_cgroup_limit(&read("cpu/cpu.cfs_quota_us")?)?;
+            (quota, read("cpu/cpu.cfs_period_us")?.trim().parse().ok()?)
+        }
+    };
+    cpus_for_quota(quota?, period)
+}
+
+/// Parses a cgroup limit: `Some(None)` for no limit (`max`, or `-1` in cgroup v1), `Some(Some(n))`
+/// for a number, and `None` if the contents are malformed.
+pub(crate) fn parse_cgroup_limit(contents: &str) -> Option<Option<u64>> {
+    match contents.trim() {
+        "max" | "-1" => Some(None),
+        value => value.parse().ok().map(Some),
+    }
+}
+
+/// Rounds a CPU quota per period up to whole CPUs.
+fn cpus_for_quota(quota: u64, period: u64) -> Option<u32> {
+    if period == 0 {
+        return None;
+    }
+    let cpus = quota.div_ceil(period).max(1);
+    Some(u32::try_from(cpus).unwrap_or(u32::MAX))
+}
+
+/// Largest `m_cost` in kibibytes (64 MiB) that is safe to hash with in an app on Android or iOS.
+///
+/// Both systems kill apps that exceed a memory limit instead of failing the allocation, and the
+/// limit can be a few hundred MiB on older devices, less for iOS app extensions. 64 MiB leaves
+/// room for the rest of the app, and is the `crypto_pwhash` interactive limit of libsodium. The
+/// stack is no concern: the blocks are allocated on the heap, so hashing needs only a few KiB of
+/// stack, well within the 512 KiB of secondary threads on iOS and of the C library's worker
+/// threads.
+pub const MOBILE_MAX_M_COST: u32 = 64 * 1024;
+
+/// `Some(MOBILE_MAX_M_COST)` on Android and iOS, `None` on other targets. Meant for passing to
+/// `set_max_m_cost` in code shared between platforms.
+#[cfg(any(target_os = "android", target_os = "ios"))]
+pub const PLATFORM_MAX_M_COST: Option<u32> = Some(MOBILE_MAX_M_COST);
+/// `Some(MOBILE_MAX_M_COST)` on Android and iOS, `None` on other targets. Meant for passing to
+/// `set_max_m_cost` in code shared between platforms.
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub const PLATFORM_MAX_M_COST: Option<u32> = None;
+
+static MAX_M_COST: OnceLock<u32> = OnceLock::new();
+
+/// Sets a process-wide ceiling on `m_cost` in kibibytes.
+///
+/// Every function that hashes or verifies returns `Error::ExceedsMemoryCap` instead of calling
+/// into the C library when the memory cost exceeds the cap. This includes the memory cost parsed
+/// from encoded hashes passed to the `*verify` functions, which may come from untrusted storage.
+///
+/// The cap can only be set once; if it is already set, the existing cap is returned as the error.
+pub fn set_max_m_cost(kib: u32) -> Result<(), u32> {
+    MAX_M_COST.set(kib).map_err(|kib| max_m_cost().unwrap_or(kib))
+}
+
+/// Returns the cap set with `set_max_m_cost`, if any.
+pub fn max_m_cost() -> Option<u32> {
+    MAX_M_COST.get().copied()
+}
+
+pub(crate) fn check_m_cost_cap(m_cost: u32) -> Result<(), Error> {
+    match max_m_cost() {
+        Some(cap) if m_cost > cap => Err(Error::ExceedsMemoryCap { m_cost, cap }),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the memory cost of an encoded hash against the cap. Hashes that can't be parsed are
+/// left for the C library to reject.
+pub(crate) fn check_encoded_m_cost_cap(encoded: &CStr) -> Result<(), Error> {
+    match encoded_m_cost(encoded.to_bytes()) {
+        Some(m_cost) => check_m_cost_cap(m_cost),
+        None => Ok(()),
+    }
+}
+
+/// Extracts `m` from the `$m=<m_cost>,t=<t_cost>,p=<parallelism>$` part of an encoded hash.
+pub(crate) fn encoded_m_cost(encoded: &[u8]) -> Option<u32> {
+    encoded_param(encoded, b"m")
+}
+
+/// Extracts the parameter `name` (`v`, `m`, `t` or `p`) from an encoded hash.
+pub(crate) fn encoded_param(encoded: &[u8], name: &[u8]) -> Option<u32> {
+    let value = encoded
+        .split(|&b| b == b'$')
+        .flat_map(|part| part.split(|&b| b == b','))
+        .find_map(|param| param.strip_prefix(name)?.strip_prefix(b"="))?;
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+/// Limits the memory used by concurrent hashes.
+///
+/// Acquire a permit for the memory cost of a hash before running it; the permit blocks until
+/// enough of the budget is free and returns its memory when dropped, including when hashing fails.
+/// Waiters are not served in any particular order. `PhcHasher::with_memory_budget` does this for
+/// every hash and verification of a hasher.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    max_bytes: u64,
+    state: Mutex<BudgetState>,
+    released: Condvar,
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    used: u64,
+    // The waker of every pending `acquire_async`, by waiter id.
+    wakers: BTreeMap<u64, Waker>,
+    next_waiter: u64,
+}
+
+/// Removes the waker of an `acquire_async` that is dropped before it gets its permit.
+struct Waiter<'a> {
+    budget: &'a MemoryBudget,
+    id: Option<u64>,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.budget.lock().wakers.remove(&id);
+        }
+    }
+}
+
+/// Memory reserved from a `MemoryBudget`, returned when dropped.
+#[derive(Debug)]
+pub struct MemoryPermit<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl MemoryBudget {
+    /// Creates a budget of `max_bytes` bytes.
+    pub fn new(max_bytes: u64) -> MemoryBudget {
+        MemoryBudget {
+            max_bytes,
+            state: Mutex::new(BudgetState { used: 0, wakers: BTreeMap::new(), next_waiter: 0 }),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Size of the budget in bytes.
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    /// Bytes that are currently not reserved by a permit.
+    pub fn available_bytes(&self) -> u64 {
+        self.max_bytes - self.lock().used
+    }
+
+    /// Reserves `m_cost` KiB if they are available right now.
+    ///
+    /// Returns `Error::InvalidParameter` for `"m_cost"` if `m_cost` KiB exceed the whole budget,
+    /// since such a permit could never be granted.
+    pub fn try_acquire(&self, m_cost: u32) -> Result<Option<MemoryPermit<'_>>, Error> {
+        let bytes = self.bytes(m_cost)?;
+        Ok(self.reserve(&mut self.lock(), bytes))
+    }
+
+    /// Reserves `m_cost` KiB, blocking until they are available.
+    ///
+    /// Returns `Error::InvalidParameter` for `"m_cost"` if `m_cost` KiB exceed the whole budget.
+    pub fn acquire(&self, m_cost: u32) -> Result<MemoryPermit<'_>, Error> {
+        let bytes = self.bytes(m_cost)?;
+        let mut state = self.lock();
+        loop {
+            if let Some(permit) = self.reserve(&mut state, bytes) {
+                return Ok(permit);
+            }
+            state = self.released.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Reserves `m_cost` KiB, waiting asynchronously until they are available. Works with any
+    /// async runtime.
+    ///
+    /// A pending future keeps only the waker of its latest poll, and forgets it when it is
+    /// dropped.
+    ///
+    /// Returns `Error::InvalidParameter` for `"m_cost"` if `m_cost` KiB exceed the whole budget.
+    pub async fn acquire_async(&self, m_cost: u32) -> Result<MemoryPermit<'_>, Error> {
+        let bytes = self.bytes(m_cost)?;
+        let mut waiter = Waiter { budget: self, id: None };
+        std::future::poll_fn(|cx| {
+            let mut state = self.lock();
+            if let Some(permit) = self.reserve(&mut state, bytes) {
+                if let Some(id) = waiter.id.take() {
+                    state.wakers.remove(&id);
+                }
+                return Poll::Ready(Ok(permit));
+            }
+            let id = *waiter.id.get_or_insert_with(|| {
+                state.next_waiter += 1;
+                state.next_waiter
+            });
+            match state.wakers.entry(id) {
+                Entry::Occupied(entry) if entry.get().will_wake(cx.waker()) => {}
+                Entry::Occupied(mut entry) => {
+                    entry.insert(cx.waker().clone());
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(cx.waker().clone());
+                }
+            }
+            Poll::Pending
+        }).await
+    }
+
+    fn bytes(&self, m_cost: u32) -> Result<u64, Error> {
+        let bytes = u64::from(m_cost) * 1024;
+        if bytes <= self.max_bytes {
+            Ok(bytes)
+        } else {
+            Err(Error::InvalidParameter { name: "m_cost", got: m_cost.into(), min: 0, max: self.max_bytes / 1024 })
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BudgetState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn reserve(&self, state: &mut BudgetState, bytes: u64) -> Option<MemoryPermit<'_>> {
+        if self.max_bytes - state.used >= bytes {
+            state.used += bytes;
+            Some(MemoryPermit { budget: self, bytes })
+        } else {
+            None
+        }
+    }
+}
+
+impl MemoryPermit<'_> {
+    /// Number of bytes reserved by this permit.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+}
+
+impl Drop for MemoryPermit<'_> {
+    fn drop(&mut self) {
+        let wakers = {
+            let mut state = self.budget.lock();
+            state.used -= self.bytes;
+            std::mem::take(&mut state.wakers)
+        };
+        self.budget.released.notify_all();
+        for waker in wakers.into_values() {
+            waker.wake();
+        }
+    }
+}
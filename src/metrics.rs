@@ -0,0 +1,131 @@
+//! A hook for exporting metrics of every hash and verification, e.g. to Prometheus, without
+//! wrapping each call site.
+//!
+//! Install a `MetricsSink` with `set_metrics_sink`. Every call that gets to hashing or verifying
+//! reports to it once, on the calling thread: the `*hash*` and `*ctx` functions and everything
+//! built on them, like `PhcHasher` and `hash_password`, call `on_hash`; the `*verify*` functions,
+//! `raw_record::verify_raw_record`, `verify_from_reader` and `PhcHasher::verify` call
+//! `on_verify`, with `Outcome::Mismatch` for a wrong password. The hash that a verification
+//! computes isn't reported on its own.
+//!
+//! The checks right before the C library is called, like an `m_cost` over the cap of
+//! `set_max_m_cost`, are part of the call and their failures are reported as `Outcome::Error`.
+//! Inputs that are rejected before that, like a record that can't be decoded or a password over
+//! the limit of a `PhcHasher`, aren't reported.
+//!
+//! `PasswordVerifier::verify_password` on a `PhcHasher` is the blanket implementation of the
+//! `password-hash` crate, which hashes with `hash_password_customized` and compares the result
+//! itself. It reports `on_hash` with `Outcome::Ok` whether the password matches or not; use
+//! `PhcHasher::verify` to have mismatches counted.
+//!
+//! Only the outcome, the duration and the costs are passed to the sink, never passwords, salts,
+//! secrets or hashes.
+
+use super::types::{Error, Variant};
+use std::cell::Cell;
+use std::sync::{PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+/// Outcome of a hash or verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// The hash was computed, or the password matches.
+    Ok,
+    /// The password doesn't match (`ErrorCode::VerifyMismatch`).
+    Mismatch,
+    /// Any other error.
+    Error,
+}
+
+/// The parameters of a hash, as passed to `MetricsSink::on_hash`.
+#[derive(Debug, Clone, Copy)]
+pub struct HashParams {
+    /// The variant.
+    pub variant: Variant,
+    /// The version number, 0x10 or 0x13.
+    pub version: u32,
+    /// Number of passes.
+    pub t_cost: u32,
+    /// Memory cost in kibibytes.
+    pub m_cost: u32,
+    /// Number of lanes.
+    pub parallelism: u32,
+}
+
+/// Receives an event for every hash and verification, see the module documentation.
+///
+/// The methods are called on the thread that hashes, right after it is done, so they should
+/// return quickly, e.g. by updating counters and histograms. They must not hash or verify
+/// themselves.
+pub trait MetricsSink: Send + Sync {
+    /// Called after hashing, with the time it took.
+    fn on_hash(&self, outcome: Outcome, duration: Duration, params: &HashParams);
+
+    /// Called after verifying, with the time it took.
+    fn on_verify(&self, outcome: Outcome, duration: Duration);
+}
+
+static SINK: RwLock<Option<Box<dyn MetricsSink>>> = RwLock::new(None);
+
+thread_local! {
+    /// Whether a call on this thread is being reported, so that the calls it makes aren't.
+    static REPORTING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Installs the process-wide sink, replacing the previous one, if any.
+pub fn set_metrics_sink(sink: Box<dyn MetricsSink>) {
+    *SINK.write().unwrap_or_else(PoisonError::into_inner) = Some(sink);
+}
+
+/// Runs a hash function and reports it to the sink.
+pub(crate) fn hash<T, F>(params: HashParams, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    report(f, |sink, outcome, duration| sink.on_hash(outcome, duration, &params))
+}
+
+/// Runs a verify function and reports it to the sink.
+pub(crate) fn verify<T, F>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    report(f, |sink, outcome, duration| sink.on_verify(outcome, duration))
+}
+
+/// Times `f` and passes the outcome to `on_report` with the sink, unless this is nested in another
+/// call that is reported.
+fn report<T, F, R>(f: F, on_report: R) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+    R: FnOnce(&dyn MetricsSink, Outcome, Duration),
+{
+    if REPORTING.with(|reporting| reporting.replace(true)) {
+        return f();
+    }
+    let _reset = ResetReporting;
+    let start = Instant::now();
+    let result = f();
+    let duration = start.elapsed();
+    if let Some(sink) = SINK.read().unwrap_or_else(PoisonError::into_inner).as_ref() {
+        on_report(&**sink, outcome(&result), duration);
+    }
+    result
+}
+
+/// Clears `REPORTING` when the reported call returns or panics.
+struct ResetReporting;
+
+impl Drop for ResetReporting {
+    fn drop(&mut self) {
+        REPORTING.with(|reporting| reporting.set(false));
+    }
+}
+
+fn outcome<T>(result: &Result<T, Error>) -> Outcome {
+    match result {
+        Ok(_) => Outcome::Ok,
+        Err(err) if err.is_verify_mismatch() => Outcome::Mismatch,
+        Err(_) => Outcome::Error,
+    }
+}
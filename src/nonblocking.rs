@@ -0,0 +1,230 @@
+use super::params::PhcParams;
+use super::types::{checked_len_u32, Context, Error, Password, Variant, Version};
+use std::ffi::CString;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Poll, Waker};
+use std::time::Duration;
+
+/// Boxed future returned by `BlockingExecutor::run`.
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+
+/// Runs blocking work, such as hashing, outside of an async runtime's worker threads.
+///
+/// The `*_on` functions accept any implementation, so they can be used with any async runtime.
+/// `StdThreadExecutor` works without a runtime; with the `tokio` feature, `TokioExecutor` uses
+/// tokio's blocking thread pool.
+pub trait BlockingExecutor {
+    /// Runs `f` and resolves to its result. Returns `Error::TaskFailed` if the work couldn't be
+    /// started, was cancelled or panicked.
+    fn run<T, F>(&self, f: F) -> BoxFuture<Result<T, Error>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static;
+}
+
+/// `BlockingExecutor` that spawns a new thread for every call.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdThreadExecutor;
+
+struct Shared<T> {
+    result: Option<Result<T, Error>>,
+    waker: Option<Waker>,
+}
+
+impl BlockingExecutor for StdThreadExecutor {
+    fn run<T, F>(&self, f: F) -> BoxFuture<Result<T, Error>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Shared { result: None, waker: None }));
+
+        let thread_shared = Arc::clone(&shared);
+        let spawned = std::thread::Builder::new().spawn(move || {
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).map_err(|_| {
+                Error::TaskFailed(std::io::Error::other("task panicked"))
+            });
+            let mut shared = thread_shared.lock().unwrap_or_else(PoisonError::into_inner);
+            shared.result = Some(result);
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        });
+        if let Err(err) = spawned {
+            shared.lock().unwrap_or_else(PoisonError::into_inner).result = Some(Err(Error::TaskFailed(err)));
+        }
+
+        Box::pin(std::future::poll_fn(move |cx| {
+            let mut shared = shared.lock().unwrap_or_else(PoisonError::into_inner);
+            match shared.result.take() {
+                Some(result) => Poll::Ready(result),
+                None => {
+                    shared.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }))
+    }
+}
+
+/// `BlockingExecutor` that runs the work with `tokio::task::spawn_blocking`. Must be used from
+/// within a tokio runtime.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioExecutor;
+
+#[cfg(feature = "tokio")]
+impl BlockingExecutor for TokioExecutor {
+    fn run<T, F>(&self, f: F) -> BoxFuture<Result<T, Error>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let handle = tokio::task::spawn_blocking(f);
+        Box::pin(async move { handle.await.map_err(|err| Error::TaskFailed(err.into())) })
+    }
+}
+
+/// Like `hash`, but runs on `executor` and returns the encoded hash, without the terminating NUL
+/// byte. The hash is `hashlen` bytes long.
+///
+/// The inputs are owned so that nothing is borrowed across the await; the password is wiped when
+/// hashing is done.
+#[allow(clippy::too_many_arguments)]
+pub async fn hash_encoded_on<E: BlockingExecutor>(
+    executor: &E,
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    pwd: Vec<u8>,
+    salt: Vec<u8>,
+    hashlen: usize,
+    variant: Variant,
+    version: Version) -> Result<String, Error> {
+    let pwd = Password::new(pwd);
+    executor.run(move || {
+        super::hash_encoded_string(t_cost, m_cost, parallelism, &pwd, &salt, hashlen, variant, version)
+    }).await?
+}
+
+/// Like `hash`, but runs on `executor` and returns a raw hash of `hashlen` bytes.
+///
+/// The inputs are owned so that nothing is borrowed across the await; the password is wiped when
+/// hashing is done. Returns `Error::InvalidParameter` for `"hashlen"` without running anything if
+/// it is longer than `Context::MAX_OUTLEN`.
+#[allow(clippy::too_many_arguments)]
+pub async fn hash_raw_on<E: BlockingExecutor>(
+    executor: &E,
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    pwd: Vec<u8>,
+    salt: Vec<u8>,
+    hashlen: usize,
+    variant: Variant,
+    version: Version) -> Result<Vec<u8>, Error> {
+    checked_len_u32("hashlen", hashlen, Context::MAX_OUTLEN)?;
+    let pwd = Password::new(pwd);
+    executor.run(move || {
+        let mut hash = vec![0u8; hashlen];
+        super::hash(t_cost, m_cost, parallelism, Some(&pwd), Some(&salt), Some(&mut hash), None, variant, version)?;
+        Ok(hash)
+    }).await?
+}
+
+/// Like `verify`, but runs on `executor`.
+///
+/// The inputs are owned so that nothing is borrowed across the await; the password is wiped when
+/// verification is done. Returns `Error::BadParam("encoded")` if `encoded` contains a NUL byte.
+pub async fn verify_on<E: BlockingExecutor>(
+    executor: &E,
+    encoded: String,
+    pwd: Vec<u8>,
+    variant: Variant) -> Result<(), Error> {
+    let pwd = Password::new(pwd);
+    executor.run(move || {
+        let encoded = CString::new(encoded).map_err(|_| Error::BadParam("encoded"))?;
+        super::verify(&encoded, Some(&pwd), variant)
+    }).await?
+}
+
+/// Like `hash_encoded_on` with `StdThreadExecutor`, but blocks until the hash is done or `timeout`
+/// has passed, and returns `Error::TimedOut` in the latter case. Hashes with the costs and output
+/// length of `params` and `Version::DEFAULT`.
+///
+/// This bounds how long the caller waits, e.g. a request handler with misconfigured costs, not how
+/// long hashing takes: the C library can't be interrupted, so after a timeout the worker thread is
+/// detached and keeps running, with all of its memory, until the hash is done. Its result is then
+/// discarded. Repeated timeouts can pile up such threads; fix the parameters rather than relying
+/// on the timeout.
+///
+/// The worker owns the password and wipes it when it is done, whether or not the caller still
+/// waits. Returns `Error::TaskFailed` if the thread can't be spawned (e.g. on wasm) or panics.
+pub fn hash_encoded_with_timeout(
+    params: &PhcParams,
+    pwd: Vec<u8>,
+    salt: Vec<u8>,
+    variant: Variant,
+    timeout: Duration) -> Result<String, Error> {
+    let pwd = Password::new(pwd);
+    let params = *params;
+    let (sender, receiver) = mpsc::channel();
+    std::thread::Builder::new().name("argon2-timeout".to_owned()).spawn(move || {
+        let result = super::hash_encoded_string(
+            params.t_cost,
+            params.m_cost,
+            params.p_cost,
+            &pwd,
+            &salt,
+            params.output_len,
+            variant,
+            Version::DEFAULT);
+        drop(pwd);
+        // The caller may have stopped waiting.
+        let _ = sender.send(result);
+    }).map_err(Error::TaskFailed)?;
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err(Error::TimedOut),
+        Err(RecvTimeoutError::Disconnected) => Err(Error::TaskFailed(std::io::Error::other("task panicked"))),
+    }
+}
+
+/// `hash_encoded_on` with `TokioExecutor`.
+#[cfg(feature = "tokio")]
+#[allow(clippy::too_many_arguments)]
+pub async fn hash_encoded_async(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    pwd: Vec<u8>,
+    salt: Vec<u8>,
+    hashlen: usize,
+    variant: Variant,
+    version: Version) -> Result<String, Error> {
+    hash_encoded_on(&TokioExecutor, t_cost, m_cost, parallelism, pwd, salt, hashlen, variant, version).await
+}
+
+/// `hash_raw_on` with `TokioExecutor`.
+#[cfg(feature = "tokio")]
+#[allow(clippy::too_many_arguments)]
+pub async fn hash_raw_async(
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    pwd: Vec<u8>,
+    salt: Vec<u8>,
+    hashlen: usize,
+    variant: Variant,
+    version: Version) -> Result<Vec<u8>, Error> {
+    hash_raw_on(&TokioExecutor, t_cost, m_cost, parallelism, pwd, salt, hashlen, variant, version).await
+}
+
+/// `verify_on` with `TokioExecutor`.
+#[cfg(feature = "tokio")]
+pub async fn verify_async(encoded: String, pwd: Vec<u8>, variant: Variant) -> Result<(), Error> {
+    verify_on(&TokioExecutor, encoded, pwd, variant).await
+}
@@ -0,0 +1,153 @@
+use super::types::Error;
+use std::convert::TryFrom;
+
+/// Costs and output length of a hash, as used by `PhcHasher` and the conversions to and from the
+/// parameters of other libraries and formats.
+///
+/// The defaults are the same as those of the pure Rust `argon2` crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhcParams {
+    /// Memory cost in kibibytes.
+    pub m_cost: u32,
+    /// Number of passes.
+    pub t_cost: u32,
+    /// Number of lanes and threads.
+    pub p_cost: u32,
+    /// Length of the hash in bytes.
+    pub output_len: usize,
+}
+
+impl PhcParams {
+    /// Default memory cost in kibibytes (19 MiB).
+    pub const DEFAULT_M_COST: u32 = 19 * 1024;
+    /// Default number of passes.
+    pub const DEFAULT_T_COST: u32 = 2;
+    /// Default number of lanes.
+    pub const DEFAULT_P_COST: u32 = 1;
+    /// Default length of the hash in bytes.
+    pub const DEFAULT_OUTPUT_LEN: usize = 32;
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for PhcParams {
+    /// Generates parameters that pass `validate`, with at most 4 lanes and 256 KiB of memory so
+    /// that hashing with them stays fast.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let p_cost = u.int_in_range(1..=4)?;
+        Ok(PhcParams {
+            m_cost: u.int_in_range(8 * p_cost..=256)?,
+            t_cost: u.int_in_range(1..=3)?,
+            p_cost,
+            output_len: u.int_in_range(4..=64)?,
+        })
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl PhcParams {
+    /// Generates parameters that fail `validate`, for negative tests: one of the costs or the
+    /// output length is out of range, with the others valid.
+    pub fn arbitrary_invalid(u: &mut arbitrary::Unstructured<'_>) -> arbitrary::Result<PhcParams> {
+        let mut params = <PhcParams as arbitrary::Arbitrary>::arbitrary(u)?;
+        match u.int_in_range(0..=4)? {
+            0 => params.t_cost = 0,
+            1 => params.m_cost = u.int_in_range(0..=8 * params.p_cost - 1)?,
+            2 => params.p_cost = 0,
+            3 => params.p_cost = u.int_in_range(0x1000000..=u32::MAX)?,
+            _ => params.output_len = u.int_in_range(0..=3)?,
+        }
+        Ok(params)
+    }
+}
+
+impl Default for PhcParams {
+    fn default() -> PhcParams {
+        PhcParams {
+            m_cost: PhcParams::DEFAULT_M_COST,
+            t_cost: PhcParams::DEFAULT_T_COST,
+            p_cost: PhcParams::DEFAULT_P_COST,
+            output_len: PhcParams::DEFAULT_OUTPUT_LEN,
+        }
+    }
+}
+
+// libsodium's `crypto_pwhash` with Argon2id, which always hashes with a single lane. `opslimit` is
+// the number of passes and `memlimit` the memory in bytes, which libsodium rounds down to whole
+// kibibytes.
+impl PhcParams {
+    /// `crypto_pwhash_argon2id_OPSLIMIT_MIN`.
+    pub const SODIUM_OPSLIMIT_MIN: u64 = 1;
+    /// `crypto_pwhash_argon2id_OPSLIMIT_MAX`.
+    pub const SODIUM_OPSLIMIT_MAX: u64 = u32::MAX as u64;
+    /// `crypto_pwhash_argon2id_MEMLIMIT_MIN`.
+    pub const SODIUM_MEMLIMIT_MIN: usize = 8192;
+    /// `crypto_pwhash_argon2id_MEMLIMIT_MAX`, 4 TiB - 1 MiB on 64 bit targets.
+    #[cfg(target_pointer_width = "64")]
+    pub const SODIUM_MEMLIMIT_MAX: usize = 4398046510080;
+    /// `crypto_pwhash_argon2id_MEMLIMIT_MAX`, 2 GiB on 32 bit targets.
+    #[cfg(not(target_pointer_width = "64"))]
+    pub const SODIUM_MEMLIMIT_MAX: usize = 2147483648;
+    /// `crypto_pwhash_argon2id_OPSLIMIT_INTERACTIVE`.
+    pub const SODIUM_OPSLIMIT_INTERACTIVE: u64 = 2;
+    /// `crypto_pwhash_argon2id_MEMLIMIT_INTERACTIVE` (64 MiB).
+    pub const SODIUM_MEMLIMIT_INTERACTIVE: usize = 64 << 20;
+    /// `crypto_pwhash_argon2id_OPSLIMIT_MODERATE`.
+    pub const SODIUM_OPSLIMIT_MODERATE: u64 = 3;
+    /// `crypto_pwhash_argon2id_MEMLIMIT_MODERATE` (256 MiB).
+    pub const SODIUM_MEMLIMIT_MODERATE: usize = 256 << 20;
+    /// `crypto_pwhash_argon2id_OPSLIMIT_SENSITIVE`.
+    pub const SODIUM_OPSLIMIT_SENSITIVE: u64 = 4;
+    /// `crypto_pwhash_argon2id_MEMLIMIT_SENSITIVE` (1 GiB).
+    pub const SODIUM_MEMLIMIT_SENSITIVE: usize = 1 << 30;
+
+    /// Returns the parameters `crypto_pwhash_str` hashes with for `opslimit` and `memlimit`: one
+    /// lane and a 32 byte hash.
+    ///
+    /// Returns `Error::InvalidParameter` for `"opslimit"` or `"memlimit"` if a limit is outside of
+    /// the range libsodium accepts.
+    pub fn from_sodium_limits(opslimit: u64, memlimit: usize) -> Result<PhcParams, Error> {
+        let t_cost = u32::try_from(opslimit)
+            .ok()
+            .filter(|_| opslimit >= PhcParams::SODIUM_OPSLIMIT_MIN)
+            .ok_or(Error::InvalidParameter {
+                name: "opslimit",
+                got: opslimit,
+                min: PhcParams::SODIUM_OPSLIMIT_MIN,
+                max: PhcParams::SODIUM_OPSLIMIT_MAX,
+            })?;
+        if !(PhcParams::SODIUM_MEMLIMIT_MIN..=PhcParams::SODIUM_MEMLIMIT_MAX).contains(&memlimit) {
+            return Err(Error::InvalidParameter {
+                name: "memlimit",
+                got: memlimit as u64,
+                min: PhcParams::SODIUM_MEMLIMIT_MIN as u64,
+                max: PhcParams::SODIUM_MEMLIMIT_MAX as u64,
+            });
+        }
+        Ok(PhcParams {
+            m_cost: (memlimit / 1024) as u32,
+            t_cost,
+            p_cost: 1,
+            output_len: 32,
+        })
+    }
+
+    /// Returns the `(opslimit, memlimit)` that `crypto_pwhash` hashes with the same costs for.
+    ///
+    /// Returns `Error::InvalidParameter` for `"p_cost"` unless `p_cost` is 1, and for `"m_cost"` if
+    /// the memory isn't within the limits of libsodium on this target.
+    pub fn to_sodium_limits(&self) -> Result<(u64, usize), Error> {
+        if self.p_cost != 1 {
+            return Err(Error::InvalidParameter { name: "p_cost", got: self.p_cost.into(), min: 1, max: 1 });
+        }
+        let memlimit = usize::try_from(u64::from(self.m_cost) * 1024)
+            .ok()
+            .filter(|memlimit| (PhcParams::SODIUM_MEMLIMIT_MIN..=PhcParams::SODIUM_MEMLIMIT_MAX).contains(memlimit))
+            .ok_or(Error::InvalidParameter {
+                name: "m_cost",
+                got: self.m_cost.into(),
+                min: (PhcParams::SODIUM_MEMLIMIT_MIN / 1024) as u64,
+                max: (PhcParams::SODIUM_MEMLIMIT_MAX / 1024) as u64,
+            })?;
+        Ok((self.t_cost.into(), memlimit))
+    }
+}
@@ -0,0 +1,529 @@
+use super::allocator::{MemoryAllocator, MemoryArena};
+use super::encoded::{decode_b64, EncodedHash};
+use super::memory::{check_memory_against_system, MemoryBudget};
+use super::params::PhcParams;
+use super::types::{check_tag, secure_wipe, Context, Error, ErrorCode, Flags, ThreadFallbackPolicy, Variant, Version};
+use super::{check_password_len, DEFAULT_MAX_PASSWORD_LEN};
+use password_hash::errors::InvalidValue;
+use password_hash::{Decimal, Ident, Output, ParamsString, PasswordHash, PasswordHasher, Salt};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+#[cfg(feature = "random-salt")]
+use super::salt::{OsSaltSource, SaltSource};
+#[cfg(feature = "random-salt")]
+use std::sync::{Mutex, PoisonError};
+#[cfg(feature = "tokio")]
+use super::nonblocking::{BlockingExecutor, TokioExecutor};
+#[cfg(feature = "tokio")]
+use super::types::Password;
+
+/// `password_hash::PasswordHasher` backed by the argon2 C library.
+///
+/// This is a drop-in replacement for `argon2::Argon2` of the pure Rust `argon2` crate in code
+/// that is written against the `password-hash` traits: it produces the same PHC strings for the
+/// same inputs, and verifies hashes produced by it. `PasswordVerifier` is implemented through the
+/// blanket implementation for every `PasswordHasher`.
+///
+/// The variant and version are used when hashing a new password; when verifying, the ones of the
+/// hash are used instead. The default hasher uses Argon2id version 0x13 and the default
+/// `PhcParams`, like the pure Rust crate.
+#[derive(Debug, Clone)]
+pub struct PhcHasher {
+    variant: Variant,
+    version: Version,
+    params: PhcParams,
+    max_password_len: Option<usize>,
+    max_memory_fraction: Option<f64>,
+    arena: Option<SharedArena>,
+    budget: Option<Arc<MemoryBudget>>,
+    thread_fallback: ThreadFallbackPolicy,
+    #[cfg(feature = "cross-check")]
+    cross_check: bool,
+    #[cfg(feature = "random-salt")]
+    salt_source: SharedSaltSource,
+}
+
+/// The `MemoryArena` of a `PhcHasher`, shared by its clones.
+#[derive(Clone)]
+struct SharedArena(Arc<dyn MemoryAllocator + Send + Sync>);
+
+impl fmt::Debug for SharedArena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MemoryArena")
+    }
+}
+
+/// The salt source of a `PhcHasher`, shared by its clones.
+#[cfg(feature = "random-salt")]
+#[derive(Clone)]
+struct SharedSaltSource(Arc<Mutex<dyn SaltSource + Send>>);
+
+#[cfg(feature = "random-salt")]
+impl fmt::Debug for SharedSaltSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SaltSource")
+    }
+}
+
+impl PhcHasher {
+    /// Creates a hasher that hashes new passwords with `variant`, `version` and `params`.
+    pub fn new(variant: Variant, version: Version, params: PhcParams) -> PhcHasher {
+        PhcHasher {
+            variant,
+            version,
+            params,
+            max_password_len: Some(DEFAULT_MAX_PASSWORD_LEN),
+            max_memory_fraction: None,
+            arena: None,
+            budget: None,
+            thread_fallback: ThreadFallbackPolicy::Fail,
+            #[cfg(feature = "cross-check")]
+            cross_check: false,
+            #[cfg(feature = "random-salt")]
+            salt_source: SharedSaltSource(Arc::new(Mutex::new(OsSaltSource))),
+        }
+    }
+
+    /// Creates an Argon2id hasher with `insecure::insecure_test_params`, for the fixtures of test
+    /// suites that hash many passwords. Its hashes verify like any other.
+    ///
+    /// **Never use it for real passwords**: the parameters offer no protection. Like
+    /// `insecure_test_params`, it panics in builds without debug assertions unless
+    /// `insecure::ALLOW_TEST_PARAMS_VAR` is set to `1`.
+    #[cfg(feature = "test-util")]
+    pub fn insecure_for_tests() -> PhcHasher {
+        PhcHasher::new(Variant::ID, Version::Version13, super::insecure::insecure_test_params())
+    }
+
+    /// Recomputes every hash with the pure Rust `argon2` crate, including the hashes computed to
+    /// verify a password, and fails with `password_hash::Error::Crypto` if it differs (see
+    /// `cross_check`). This doubles the time every hash and verification takes.
+    #[cfg(feature = "cross-check")]
+    pub fn with_cross_check(mut self, cross_check: bool) -> PhcHasher {
+        self.cross_check = cross_check;
+        self
+    }
+
+    /// Rejects passwords longer than `max_len` bytes, before hashing and before verifying, like
+    /// `check_password_len`. The default is `DEFAULT_MAX_PASSWORD_LEN`; `None` accepts passwords of
+    /// any length the C library accepts.
+    ///
+    /// The rejection is an `Error::PasswordTooLong`, or `password_hash::Error::Password` through
+    /// the `password-hash` traits.
+    pub fn with_max_password_len(mut self, max_len: Option<usize>) -> PhcHasher {
+        self.max_password_len = max_len;
+        self
+    }
+
+    /// Rejects hashes whose `m_cost` is more than `max_fraction` of the memory that is currently
+    /// available, like `check_memory_against_system`, before hashing and before verifying. `None`
+    /// (the default) turns the check off.
+    ///
+    /// The rejection is an `Error::InvalidParameter` for `"m_cost"`, or
+    /// `password_hash::Error::ParamValueInvalid` through the `password-hash` traits.
+    pub fn with_memory_check(mut self, max_fraction: Option<f64>) -> PhcHasher {
+        self.max_memory_fraction = max_fraction;
+        self
+    }
+
+    /// Allocates the memory of every hash and verification through `arena`, so that hashes with
+    /// the `m_cost` of the arena reuse its region instead of allocating their own (see
+    /// `MemoryArena`). Clones of the hasher share the arena.
+    pub fn with_arena<A>(mut self, arena: Arc<MemoryArena<A>>) -> PhcHasher
+    where
+        A: MemoryAllocator + Send + Sync + 'static,
+    {
+        self.arena = Some(SharedArena(arena));
+        self
+    }
+
+    /// Acquires a permit for the `m_cost` of every hash and verification from `budget` and holds
+    /// it while hashing, blocking until enough of the budget is free (see `MemoryBudget`). The
+    /// async methods wait on tokio's blocking thread pool. Clones of the hasher share the budget.
+    ///
+    /// Hashes whose `m_cost` exceeds the whole budget fail with `Error::InvalidParameter` for
+    /// `"m_cost"`, or `password_hash::Error::ParamValueInvalid` through the `password-hash` traits.
+    pub fn with_memory_budget(mut self, budget: Arc<MemoryBudget>) -> PhcHasher {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// What to do when the C library fails to spawn the threads of a hash or verification, like
+    /// `ctx_with_thread_fallback`. The default, `ThreadFallbackPolicy::Fail`, returns
+    /// `Error::ThreadFailed`; `ThreadFallbackPolicy::SingleThreaded` hashes again on the calling
+    /// thread, which gives the same hash.
+    pub fn with_thread_fallback(mut self, policy: ThreadFallbackPolicy) -> PhcHasher {
+        self.thread_fallback = policy;
+        self
+    }
+
+    /// Parameters used for new hashes.
+    pub fn params(&self) -> &PhcParams {
+        &self.params
+    }
+
+    /// Length in bytes of the salts that `hash_new_password` takes from the salt source.
+    #[cfg(feature = "random-salt")]
+    pub const NEW_SALT_LEN: usize = 16;
+
+    /// Takes the salts of `hash_new_password` from `source` instead of `OsSaltSource`. Clones of
+    /// the hasher share the source.
+    #[cfg(feature = "random-salt")]
+    pub fn with_salt_source(mut self, source: impl SaltSource + Send + 'static) -> PhcHasher {
+        self.salt_source = SharedSaltSource(Arc::new(Mutex::new(source)));
+        self
+    }
+
+    /// Takes the salts of `hash_new_password` from `rng` instead of the operating system, like
+    /// `with_salt_source(RngSaltSource::new(rng))`. The hasher owns the generator; to keep using
+    /// it elsewhere, pass a handle that shares its state.
+    #[cfg(all(feature = "random-salt", feature = "rand-core"))]
+    pub fn with_rng<R: rand_core::CryptoRngCore + Send + 'static>(self, rng: R) -> PhcHasher {
+        self.with_salt_source(super::salt::RngSaltSource::new(rng))
+    }
+
+    /// Hashes a password with a new salt of `NEW_SALT_LEN` bytes from the salt source, with the
+    /// variant, version and parameters of the hasher.
+    #[cfg(feature = "random-salt")]
+    pub fn hash_new_password(&self, password: &[u8]) -> Result<EncodedHash, Error> {
+        self.hash_new_password_timed(password).map(|(hash, _)| hash)
+    }
+
+    /// Like `hash_new_password`, but also returns the time spent hashing, like
+    /// `hash_encoded_timed`. The duration doesn't include waiting for the memory budget.
+    #[cfg(feature = "random-salt")]
+    pub fn hash_new_password_timed(&self, password: &[u8]) -> Result<(EncodedHash, Duration), Error> {
+        let mut salt = [0u8; PhcHasher::NEW_SALT_LEN];
+        self.salt_source.0.lock().unwrap_or_else(PoisonError::into_inner).fill(&mut salt)?;
+        self.check_input(password, self.params.m_cost)?;
+        let mut out = vec![0u8; self.params.output_len];
+        let elapsed = self.compute(password, &salt, &self.params, self.variant, self.version, &mut out)?;
+        let hash = EncodedHash::new(
+            self.variant,
+            self.version,
+            self.params.m_cost,
+            self.params.t_cost,
+            self.params.p_cost,
+            salt.to_vec(),
+            out);
+        Ok((hash, elapsed))
+    }
+
+    /// Verifies a password against `hash`, with the variant, version and parameters of the hash.
+    ///
+    /// This is `PasswordVerifier::verify_password` with the errors of this crate: the password
+    /// length limit and the memory check of the hasher apply, and a wrong password returns
+    /// `ErrorCode::VerifyMismatch`, like the `*verify` functions. Unlike `verify_password`, it is
+    /// reported to the `metrics` sink as a verification.
+    pub fn verify(&self, password: &[u8], hash: &EncodedHash) -> Result<(), Error> {
+        self.verify_timed(password, hash).0
+    }
+
+    /// Like `verify`, but also returns the time spent hashing, like the free `verify_timed`
+    /// function: the duration is returned for a mismatch as well, and is zero if the password was
+    /// rejected before hashing. It doesn't include waiting for the memory budget.
+    pub fn verify_timed(&self, password: &[u8], hash: &EncodedHash) -> (Result<(), Error>, Duration) {
+        let params = PhcParams {
+            m_cost: hash.m_cost(),
+            t_cost: hash.t_cost(),
+            p_cost: hash.parallelism(),
+            output_len: hash.hash().len(),
+        };
+        if let Err(err) = self.check_input(password, params.m_cost) {
+            return (Err(err), Duration::ZERO);
+        }
+        let mut elapsed = Duration::ZERO;
+        let version = hash.version();
+        let result = super::run_verify(hash.variant(), version.to_int(), params.t_cost, params.m_cost, params.p_cost, || {
+            let mut out = vec![0u8; params.output_len];
+            let result = self.compute(password, hash.salt(), &params, hash.variant(), version, &mut out)
+                .and_then(|hashed| {
+                    elapsed = hashed;
+                    check_tag(&out, hash.hash())
+                });
+            secure_wipe(&mut out);
+            result
+        });
+        (result, elapsed)
+    }
+
+    /// Like `hash_new_password`, but runs on tokio's blocking thread pool. Must be called from
+    /// within a tokio runtime.
+    ///
+    /// The password is owned so that nothing is borrowed across the await, and wiped when hashing
+    /// is done. Returns `Error::TaskFailed` if the task is cancelled or panics.
+    #[cfg(all(feature = "random-salt", feature = "tokio"))]
+    pub async fn hash_new_password_async(&self, password: Vec<u8>) -> Result<EncodedHash, Error> {
+        let hasher = self.clone();
+        let password = Password::new(password);
+        TokioExecutor.run(move || hasher.hash_new_password(&password)).await?
+    }
+
+    /// Like `verify`, but runs on tokio's blocking thread pool. Must be called from within a tokio
+    /// runtime.
+    ///
+    /// The inputs are owned so that nothing is borrowed across the await; the password is wiped
+    /// when verification is done. Returns `Error::TaskFailed` if the task is cancelled or panics.
+    #[cfg(feature = "tokio")]
+    pub async fn verify_async(&self, password: Vec<u8>, hash: EncodedHash) -> Result<(), Error> {
+        let hasher = self.clone();
+        let password = Password::new(password);
+        TokioExecutor.run(move || hasher.verify(&password, &hash)).await?
+    }
+
+    /// Hashes `password` into `out` through the arena if there is one and with the thread fallback
+    /// policy, and cross-checks the result if that is enabled, holding a permit of the memory
+    /// budget if there is one. `out` is wiped if anything fails. The caller applies `check_input`
+    /// first.
+    ///
+    /// Returns the time spent hashing, without waiting for the permit.
+    fn compute(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        params: &PhcParams,
+        variant: Variant,
+        version: Version,
+        out: &mut [u8]) -> Result<Duration, Error> {
+        let _permit = match &self.budget {
+            Some(budget) => Some(budget.acquire(params.m_cost)?),
+            None => None,
+        };
+        let start = Instant::now();
+        match (&self.arena, self.thread_fallback) {
+            (None, ThreadFallbackPolicy::Fail) => {
+                super::hash(params.t_cost, params.m_cost, params.p_cost, Some(password), Some(salt), Some(&mut *out), None, variant, version)?;
+            }
+            (arena, policy) => {
+                // `Context` takes mutable inputs; the copy of the password is wiped below.
+                let mut pwd = password.to_vec();
+                let mut salt = salt.to_vec();
+                let mut context = Context {
+                    out: &mut *out,
+                    pwd: Some(&mut pwd),
+                    salt: Some(&mut salt),
+                    secret: None,
+                    ad: None,
+                    t_cost: params.t_cost,
+                    m_cost: params.m_cost,
+                    lanes: params.p_cost,
+                    threads: params.p_cost,
+                    version,
+                    flags: Flags::DEFAULT,
+                };
+                let allocator = arena.as_ref().map(|arena| &*arena.0 as &dyn MemoryAllocator);
+                let result = super::ctx_with_options(&mut context, variant, allocator, policy);
+                secure_wipe(&mut pwd);
+                if result.is_err() {
+                    secure_wipe(out);
+                }
+                result?;
+            }
+        }
+        #[cfg(feature = "cross-check")]
+        if self.cross_check {
+            if let Err(err) = super::rustcrypto::cross_check(params, password, salt, out, variant, version) {
+                secure_wipe(out);
+                return Err(err);
+            }
+        }
+        Ok(start.elapsed())
+    }
+
+    /// Applies the password length limit and the memory check to the inputs of a hash.
+    fn check_input(&self, password: &[u8], m_cost: u32) -> Result<(), Error> {
+        check_password_len(Some(password), self.max_password_len)?;
+        match self.max_memory_fraction {
+            Some(max_fraction) => check_memory_against_system(m_cost, max_fraction),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for PhcHasher {
+    fn default() -> PhcHasher {
+        PhcHasher::new(Variant::ID, Version::Version13, PhcParams::default())
+    }
+}
+
+impl<'a> TryFrom<&'a PasswordHash<'a>> for PhcParams {
+    type Error = password_hash::Error;
+
+    /// Reads `m`, `t` and `p` from the hash, and the output length from the length of its output.
+    /// Parameters that are missing keep their default; unknown parameters are rejected.
+    fn try_from(hash: &'a PasswordHash<'a>) -> Result<PhcParams, password_hash::Error> {
+        let mut params = PhcParams::default();
+        for (ident, value) in hash.params.iter() {
+            match ident.as_str() {
+                "m" => params.m_cost = value.decimal()?,
+                "t" => params.t_cost = value.decimal()?,
+                "p" => params.p_cost = value.decimal()?,
+                _ => return Err(password_hash::Error::ParamNameInvalid),
+            }
+        }
+        if let Some(output) = &hash.hash {
+            params.output_len = output.len();
+        }
+        Ok(params)
+    }
+}
+
+impl TryFrom<PhcParams> for ParamsString {
+    type Error = password_hash::Error;
+
+    fn try_from(params: PhcParams) -> Result<ParamsString, password_hash::Error> {
+        let mut string = ParamsString::new();
+        string.add_decimal("m", params.m_cost)?;
+        string.add_decimal("t", params.t_cost)?;
+        string.add_decimal("p", params.p_cost)?;
+        Ok(string)
+    }
+}
+
+impl PasswordHasher for PhcHasher {
+    type Params = PhcParams;
+
+    /// Hashes with the variant, version and parameters of the hasher.
+    fn hash_password<'a>(&self, password: &[u8], salt: impl Into<Salt<'a>>) -> Result<PasswordHash<'a>, password_hash::Error> {
+        self.hash_password_customized(password, None, None, self.params, salt)
+    }
+
+    fn hash_password_customized<'a>(
+        &self,
+        password: &[u8],
+        algorithm: Option<Ident<'a>>,
+        version: Option<Decimal>,
+        params: PhcParams,
+        salt: impl Into<Salt<'a>>) -> Result<PasswordHash<'a>, password_hash::Error> {
+        let variant = match algorithm {
+            Some(algorithm) => variant_from_ident(algorithm)?,
+            None => self.variant,
+        };
+        let version = match version {
+            Some(version) => version_from_decimal(version)?,
+            None => self.version,
+        };
+        if params.output_len < Output::MIN_LENGTH {
+            return Err(password_hash::Error::OutputSize { provided: Ordering::Less, expected: Output::MIN_LENGTH });
+        }
+        if params.output_len > Output::MAX_LENGTH {
+            return Err(password_hash::Error::OutputSize { provided: Ordering::Greater, expected: Output::MAX_LENGTH });
+        }
+
+        let salt = salt.into();
+        let mut salt_buf = [0u8; Salt::MAX_LENGTH];
+        let salt_bytes = salt.decode_b64(&mut salt_buf)?;
+
+        let mut out = [0u8; Output::MAX_LENGTH];
+        let out = &mut out[..params.output_len];
+        self.check_input(password, params.m_cost)
+            .and_then(|()| self.compute(password, salt_bytes, &params, variant, version, out))
+            .map_err(to_password_hash_error)?;
+        let output = Output::new(out);
+        secure_wipe(out);
+
+        Ok(PasswordHash {
+            algorithm: Ident::new(variant.name())?,
+            version: Some(version.to_int()),
+            params: ParamsString::try_from(params)?,
+            salt: Some(salt),
+            hash: Some(output?),
+        })
+    }
+}
+
+impl<'a> TryFrom<&'a EncodedHash> for PasswordHash<'a> {
+    type Error = password_hash::Error;
+
+    /// Converts the fields one to one, so the `PasswordHash` is written as the same string as the
+    /// `EncodedHash`. Fails if the salt or hash is shorter or longer than a `PasswordHash` allows.
+    fn try_from(encoded: &'a EncodedHash) -> Result<PasswordHash<'a>, password_hash::Error> {
+        let mut params = ParamsString::new();
+        params.add_decimal("m", encoded.m_cost())?;
+        params.add_decimal("t", encoded.t_cost())?;
+        params.add_decimal("p", encoded.parallelism())?;
+        Ok(PasswordHash {
+            algorithm: Ident::new(encoded.variant().name())?,
+            version: encoded.version_field().map(Version::to_int),
+            params,
+            salt: Some(Salt::from_b64(encoded.salt_b64())?),
+            hash: Some(Output::new(encoded.hash())?),
+        })
+    }
+}
+
+impl TryFrom<&PasswordHash<'_>> for EncodedHash {
+    type Error = password_hash::Error;
+
+    /// Converts a PHC string of one of the Argon2 variants. `m`, `t` and `p` are required and no
+    /// other parameters are allowed, since the C library can't read them.
+    fn try_from(hash: &PasswordHash<'_>) -> Result<EncodedHash, password_hash::Error> {
+        let variant = variant_from_ident(hash.algorithm)?;
+        let version = hash.version.map(version_from_decimal).transpose()?;
+        let (mut m_cost, mut t_cost, mut p_cost) = (None, None, None);
+        for (ident, value) in hash.params.iter() {
+            match ident.as_str() {
+                "m" => m_cost = Some(value.decimal()?),
+                "t" => t_cost = Some(value.decimal()?),
+                "p" => p_cost = Some(value.decimal()?),
+                _ => return Err(password_hash::Error::ParamNameInvalid),
+            }
+        }
+        let missing = password_hash::Error::PhcStringField;
+        let salt = hash.salt.ok_or(missing)?;
+        let salt = decode_b64(salt.as_str()).ok_or(InvalidValue::Malformed.salt_error())?;
+        let output = hash.hash.ok_or(missing)?;
+        Ok(EncodedHash::new(
+            variant,
+            Version::Version10,
+            m_cost.ok_or(missing)?,
+            t_cost.ok_or(missing)?,
+            p_cost.ok_or(missing)?,
+            salt,
+            output.as_bytes().to_vec()).with_version_field(version))
+    }
+}
+
+/// Decodes a salt, e.g. a `password_hash::SaltString`, into the bytes that the hash functions of
+/// this crate take.
+///
+/// The pure Rust `argon2` crate hashes with the decoded bytes, so this is what to pass to get the
+/// same hash; hashing the base64 string itself gives a different one. Returns
+/// `Error::BadParam("salt")` if the salt isn't valid base64.
+pub fn decode_salt<'a>(salt: impl Into<Salt<'a>>) -> Result<Vec<u8>, Error> {
+    decode_b64(salt.into().as_str()).ok_or(Error::BadParam("salt"))
+}
+
+fn variant_from_ident(algorithm: Ident<'_>) -> Result<Variant, password_hash::Error> {
+    Variant::iter()
+        .find(|variant| variant.name() == algorithm.as_str())
+        .ok_or(password_hash::Error::Algorithm)
+}
+
+fn version_from_decimal(version: Decimal) -> Result<Version, password_hash::Error> {
+    Version::from_int(version).ok_or(password_hash::Error::Version)
+}
+
+/// Maps the errors that `hash` can return for the parameters of a PHC string.
+fn to_password_hash_error(err: Error) -> password_hash::Error {
+    match err {
+        Error::Code(ErrorCode::SaltTooShort) => InvalidValue::TooShort.salt_error(),
+        Error::Code(ErrorCode::SaltTooLong) => InvalidValue::TooLong.salt_error(),
+        Error::Code(ErrorCode::TimeTooSmall)
+        | Error::Code(ErrorCode::MemoryTooLittle)
+        | Error::Code(ErrorCode::LanesTooFew)
+        | Error::Code(ErrorCode::ThreadsTooFew) => InvalidValue::TooShort.param_error(),
+        Error::Code(ErrorCode::TimeTooLarge)
+        | Error::Code(ErrorCode::MemoryTooMuch)
+        | Error::Code(ErrorCode::LanesTooMany)
+        | Error::Code(ErrorCode::ThreadsTooMany)
+        | Error::ExceedsMemoryCap { .. } => InvalidValue::TooLong.param_error(),
+        Error::InvalidParameter { got, min, .. } if got < min => InvalidValue::TooShort.param_error(),
+        Error::InvalidParameter { .. } => InvalidValue::TooLong.param_error(),
+        Error::Code(ErrorCode::PwdTooLong) | Error::PasswordTooLong { .. } => password_hash::Error::Password,
+        _ => password_hash::Error::Crypto,
+    }
+}
@@ -0,0 +1,66 @@
+use super::encoded::EncodedHash;
+use super::params::PhcParams;
+use super::types::{Error, Variant, Version};
+
+/// The exact parameters that every hash of a table has to use, for `verify_pinned`.
+///
+/// Unlike a minimum, a pin also rejects hashes that are stronger than approved, e.g. hashes
+/// written by a misconfigured deployment.
+#[derive(Debug, Clone, Copy)]
+pub struct PinnedConfig {
+    /// The variant.
+    pub variant: Variant,
+    /// The version. Hashes without a version field are version 0x10.
+    pub version: Version,
+    /// Memory cost in kibibytes.
+    pub m_cost: u32,
+    /// Number of passes.
+    pub t_cost: u32,
+    /// Number of lanes.
+    pub p_cost: u32,
+    /// Length of the hash in bytes, or `None` to accept any length.
+    pub hash_len: Option<usize>,
+}
+
+impl PinnedConfig {
+    /// Pins the costs and output length of `params`.
+    pub fn new(variant: Variant, version: Version, params: &PhcParams) -> PinnedConfig {
+        PinnedConfig {
+            variant,
+            version,
+            m_cost: params.m_cost,
+            t_cost: params.t_cost,
+            p_cost: params.p_cost,
+            hash_len: Some(params.output_len),
+        }
+    }
+
+    /// Returns `Error::ParamsNotPinned` for the first field of `hash` that differs from the pin,
+    /// in the order variant, version, `m_cost`, `t_cost`, `p_cost` and hash length.
+    pub fn check(&self, hash: &EncodedHash) -> Result<(), Error> {
+        let fields = [
+            ("variant", self.variant.to_c() as u64, hash.variant().to_c() as u64),
+            ("version", self.version.to_int().into(), hash.version().to_int().into()),
+            ("m_cost", self.m_cost.into(), hash.m_cost().into()),
+            ("t_cost", self.t_cost.into(), hash.t_cost().into()),
+            ("p_cost", self.p_cost.into(), hash.parallelism().into()),
+        ];
+        let hash_len = self.hash_len.map(|len| ("hash_len", len as u64, hash.hash().len() as u64));
+        match fields.iter().copied().chain(hash_len).find(|&(_, expected, found)| expected != found) {
+            Some((field, expected, found)) => Err(Error::ParamsNotPinned { field, expected, found }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Verifies a password against an encoded hash that has to use exactly the parameters of
+/// `expected`.
+///
+/// The parameters are checked before any hashing work; the first deviation is returned as
+/// `Error::ParamsNotPinned`. Returns `ErrorCode::DecodingFail` if `encoded` isn't an encoded
+/// Argon2 hash, and the errors of `verify` otherwise.
+pub fn verify_pinned(encoded: &str, pwd: Option<&[u8]>, expected: &PinnedConfig) -> Result<(), Error> {
+    let hash = EncodedHash::parse(encoded)?;
+    expected.check(&hash)?;
+    hash.verify(pwd)
+}
@@ -0,0 +1,76 @@
+//! Records of a salt followed by a raw hash, for schemas that store a single binary column, e.g.
+//! `salt (16 bytes) || hash (32 bytes)`, instead of an encoded hash.
+//!
+//! Unlike an encoded hash, a record doesn't contain the parameters, so they have to be known to
+//! verify it, see `verify_raw_record`. The lengths of the parts are checked against the limits of
+//! the C library, and errors name the part that is wrong: `"salt_len"`, `"salt.len"` or
+//! `"hash.len"`.
+
+use super::params::PhcParams;
+use super::types::{check_tag, secure_wipe, Context, Error, Variant, Version};
+
+/// Concatenates `salt` and `hash` into a record.
+pub fn encode(salt: &[u8], hash: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(salt.len() + hash.len());
+    record.extend_from_slice(salt);
+    record.extend_from_slice(hash);
+    record
+}
+
+/// Splits a record into the salt of `salt_len` bytes and the hash, which is the rest.
+///
+/// Returns `Error::InvalidParameter` for `"salt_len"` if it is shorter than
+/// `Context::MIN_SALT_LENGTH`, for `"salt.len"` if the record is shorter than the salt, and for
+/// `"hash.len"` if the rest is shorter than `Context::MIN_OUTLEN` or too long for the C library.
+pub fn decode(record: &[u8], salt_len: usize) -> Result<(&[u8], &[u8]), Error> {
+    check_len("salt_len", salt_len, Context::MIN_SALT_LENGTH, Context::MAX_SALT_LENGTH)?;
+    if record.len() < salt_len {
+        return Err(Error::InvalidParameter {
+            name: "salt.len",
+            got: record.len() as u64,
+            min: salt_len as u64,
+            max: salt_len as u64,
+        });
+    }
+    let (salt, hash) = record.split_at(salt_len);
+    check_len("hash.len", hash.len(), Context::MIN_OUTLEN, Context::MAX_OUTLEN)?;
+    Ok((salt, hash))
+}
+
+/// Verifies a password against a record: splits it with `decode`, hashes the password with the
+/// salt and compares the result with the hash in constant time.
+///
+/// The hash has to be `params.output_len` bytes long, otherwise `Error::InvalidParameter` is
+/// returned for `"hash.len"`. A wrong password returns `ErrorCode::VerifyMismatch`, like the
+/// `*verify` functions.
+pub fn verify_raw_record(
+    params: &PhcParams,
+    variant: Variant,
+    version: Version,
+    pwd: Option<&[u8]>,
+    record: &[u8],
+    salt_len: usize) -> Result<(), Error> {
+    let (salt, expected) = decode(record, salt_len)?;
+    if expected.len() != params.output_len {
+        return Err(Error::InvalidParameter {
+            name: "hash.len",
+            got: expected.len() as u64,
+            min: params.output_len as u64,
+            max: params.output_len as u64,
+        });
+    }
+    super::run_verify(variant, version.to_int(), params.t_cost, params.m_cost, params.p_cost, || {
+        let mut out = vec![0u8; expected.len()];
+        let result = super::hash(params.t_cost, params.m_cost, params.p_cost, pwd, Some(salt), Some(&mut out), None, variant, version)
+            .and_then(|()| check_tag(&out, expected));
+        secure_wipe(&mut out);
+        result
+    })
+}
+
+fn check_len(name: &'static str, len: usize, min: u32, max: u32) -> Result<(), Error> {
+    if len < min as usize || len as u64 > u64::from(max) {
+        return Err(Error::InvalidParameter { name, got: len as u64, min: u64::from(min), max: u64::from(max) });
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! Hashing inputs that are read in chunks, e.g. the key file of a KeePass-style "password + key
+//! file" composite key, without holding the whole input in memory.
+//!
+//! The input is pre-hashed with the BLAKE2b of the C library and the digest is the Argon2
+//! password. Version 1 of the construction is
+//!
+//! ```text
+//! password = BLAKE2b-512(READER_PREHASH_V1 || input)
+//! ```
+//!
+//! with unkeyed BLAKE2b and `READER_PREHASH_V1 = "just-argon2 hash_raw_from_reader v1"` (35 ASCII
+//! bytes, without a terminator). The construction is fixed: hashes computed with it verify with
+//! every later release. A different construction would get a new domain string and new functions.
+
+use super::blake2::Blake2bState;
+use super::params::PhcParams;
+use super::types::{check_tag, secure_wipe, Error, Variant, Version};
+use std::io::{ErrorKind, Read};
+
+/// Domain string that version 1 of the pre-hash puts in front of the input.
+pub const READER_PREHASH_V1: &[u8] = b"just-argon2 hash_raw_from_reader v1";
+
+/// Length of the pre-hash, which is the length of the password passed to Argon2.
+const PREHASH_LEN: usize = 64;
+
+/// Size of the chunks that are read from the input.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Hashes everything `reader` returns into a raw hash of `out_len` bytes, with the time, memory
+/// and parallelism of `params` (its `output_len` is ignored). See the module documentation for
+/// the construction.
+///
+/// Reads until the end of the input and retries reads that fail with `ErrorKind::Interrupted`.
+/// Any other failure is returned as `Error::Read`.
+pub fn hash_raw_from_reader(
+    params: &PhcParams,
+    variant: Variant,
+    version: Version,
+    reader: impl Read,
+    salt: &[u8],
+    out_len: usize) -> Result<Vec<u8>, Error> {
+    let mut pwd = prehash(reader)?;
+    let mut out = vec![0u8; out_len];
+    let result = super::hash(params.t_cost, params.m_cost, params.p_cost, Some(&pwd), Some(salt), Some(&mut out), None, variant, version);
+    secure_wipe(&mut pwd);
+    result.map(|()| out)
+}
+
+/// Verifies a raw hash computed by `hash_raw_from_reader` with the same parameters and salt: hashes
+/// everything `reader` returns into a hash of the length of `expected` and compares them in
+/// constant time.
+///
+/// Returns `ErrorCode::VerifyMismatch` if they differ, like the `*verify` functions.
+pub fn verify_from_reader(
+    params: &PhcParams,
+    variant: Variant,
+    version: Version,
+    reader: impl Read,
+    salt: &[u8],
+    expected: &[u8]) -> Result<(), Error> {
+    // Reading isn't part of the verification that is reported to the `metrics` sink.
+    let mut pwd = prehash(reader)?;
+    let result = super::run_verify(variant, version.to_int(), params.t_cost, params.m_cost, params.p_cost, || {
+        let mut out = vec![0u8; expected.len()];
+        let result = super::hash(params.t_cost, params.m_cost, params.p_cost, Some(&pwd), Some(salt), Some(&mut out), None, variant, version)
+            .and_then(|()| check_tag(&out, expected));
+        secure_wipe(&mut out);
+        result
+    });
+    secure_wipe(&mut pwd);
+    result
+}
+
+/// Computes version 1 of the pre-hash of everything `reader` returns.
+fn prehash(mut reader: impl Read) -> Result<Vec<u8>, Error> {
+    let mut state = Blake2bState::new(PREHASH_LEN)?;
+    state.update(READER_PREHASH_V1)?;
+    let mut chunk = vec![0u8; CHUNK_LEN];
+    let result = loop {
+        match reader.read(&mut chunk) {
+            Ok(0) => break Ok(()),
+            Ok(len) => if let Err(err) = state.update(&chunk[..len]) {
+                break Err(err);
+            },
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => break Err(Error::Read(err)),
+        }
+    };
+    secure_wipe(&mut chunk);
+    result?;
+    state.finalize()
+}
@@ -0,0 +1,165 @@
+use super::params::PhcParams;
+#[cfg(feature = "cross-check")]
+use super::types::secure_wipe;
+use super::types::{Error, Variant, Version};
+use rustcrypto_argon2 as rc;
+use std::convert::TryFrom;
+
+#[cfg(not(feature = "no-argon2d"))]
+impl From<rc::Algorithm> for Variant {
+    fn from(algorithm: rc::Algorithm) -> Variant {
+        match algorithm {
+            rc::Algorithm::Argon2d => Variant::D,
+            rc::Algorithm::Argon2i => Variant::I,
+            rc::Algorithm::Argon2id => Variant::ID,
+        }
+    }
+}
+
+/// With the `no-argon2d` feature, `Argon2d` is rejected with `Error::UnknownAlgorithm`.
+#[cfg(feature = "no-argon2d")]
+impl TryFrom<rc::Algorithm> for Variant {
+    type Error = Error;
+
+    fn try_from(algorithm: rc::Algorithm) -> Result<Variant, Error> {
+        match algorithm {
+            rc::Algorithm::Argon2d => Err(Error::UnknownAlgorithm("argon2d")),
+            rc::Algorithm::Argon2i => Ok(Variant::I),
+            rc::Algorithm::Argon2id => Ok(Variant::ID),
+        }
+    }
+}
+
+impl From<Variant> for rc::Algorithm {
+    fn from(variant: Variant) -> rc::Algorithm {
+        match variant {
+            #[cfg(not(feature = "no-argon2d"))]
+            Variant::D => rc::Algorithm::Argon2d,
+            Variant::I => rc::Algorithm::Argon2i,
+            Variant::ID => rc::Algorithm::Argon2id,
+        }
+    }
+}
+
+impl From<rc::Version> for Version {
+    fn from(version: rc::Version) -> Version {
+        match version {
+            rc::Version::V0x10 => Version::Version10,
+            rc::Version::V0x13 => Version::Version13,
+        }
+    }
+}
+
+impl From<Version> for rc::Version {
+    fn from(version: Version) -> rc::Version {
+        match version {
+            Version::Version10 => rc::Version::V0x10,
+            Version::Version13 => rc::Version::V0x13,
+        }
+    }
+}
+
+impl TryFrom<&rc::Params> for PhcParams {
+    type Error = Error;
+
+    /// Converts the costs and the output length. Parameters without an output length map to
+    /// `PhcParams::DEFAULT_OUTPUT_LEN`, the length the pure Rust crate's `PasswordHasher` uses for
+    /// them.
+    ///
+    /// Returns `Error::BadParam` for `"keyid"` or `"data"` if the parameters carry a key id or
+    /// associated data, which PHC strings of this crate can't carry.
+    fn try_from(params: &rc::Params) -> Result<PhcParams, Error> {
+        if !params.keyid().is_empty() {
+            return Err(Error::BadParam("keyid"));
+        }
+        if !params.data().is_empty() {
+            return Err(Error::BadParam("data"));
+        }
+        Ok(PhcParams {
+            m_cost: params.m_cost(),
+            t_cost: params.t_cost(),
+            p_cost: params.p_cost(),
+            output_len: params.output_len().unwrap_or(PhcParams::DEFAULT_OUTPUT_LEN),
+        })
+    }
+}
+
+impl TryFrom<rc::Params> for PhcParams {
+    type Error = Error;
+
+    fn try_from(params: rc::Params) -> Result<PhcParams, Error> {
+        PhcParams::try_from(&params)
+    }
+}
+
+impl TryFrom<PhcParams> for rc::Params {
+    type Error = Error;
+
+    /// Converts the costs and sets the output length explicitly. Returns `Error::InvalidParameter`
+    /// for parameters that `argon2::Params` rejects.
+    fn try_from(params: PhcParams) -> Result<rc::Params, Error> {
+        let PhcParams { m_cost, t_cost, p_cost, output_len } = params;
+        rc::Params::new(m_cost, t_cost, p_cost, Some(output_len)).map_err(|err| match err {
+            rc::Error::MemoryTooLittle => Error::InvalidParameter {
+                name: "m_cost",
+                got: m_cost.into(),
+                min: u64::from(rc::Params::MIN_M_COST).max(u64::from(p_cost) * 8),
+                max: rc::Params::MAX_M_COST.into(),
+            },
+            rc::Error::TimeTooSmall => Error::InvalidParameter {
+                name: "t_cost",
+                got: t_cost.into(),
+                min: rc::Params::MIN_T_COST.into(),
+                max: rc::Params::MAX_T_COST.into(),
+            },
+            rc::Error::ThreadsTooFew | rc::Error::ThreadsTooMany => Error::InvalidParameter {
+                name: "p_cost",
+                got: p_cost.into(),
+                min: rc::Params::MIN_P_COST.into(),
+                max: rc::Params::MAX_P_COST.into(),
+            },
+            rc::Error::OutputTooShort | rc::Error::OutputTooLong => Error::InvalidParameter {
+                name: "output_len",
+                got: output_len as u64,
+                min: rc::Params::MIN_OUTPUT_LEN as u64,
+                max: rc::Params::MAX_OUTPUT_LEN as u64,
+            },
+            _ => Error::BadParam("params"),
+        })
+    }
+}
+
+/// Recomputes `hash` with the pure Rust `argon2` crate and returns `Error::CrossCheckMismatch` if
+/// it gets a different hash, or rejects the inputs. `params.output_len` is ignored, the length of
+/// `hash` is used instead.
+///
+/// This is the check of `PhcHasher::with_cross_check`, for hashes of the other functions. It
+/// takes as long as hashing again, and allocates the memory of the hash once more.
+#[cfg(feature = "cross-check")]
+pub fn cross_check(
+    params: &PhcParams,
+    pwd: &[u8],
+    salt: &[u8],
+    hash: &[u8],
+    variant: Variant,
+    version: Version) -> Result<(), Error> {
+    let params = rc::Params::try_from(PhcParams { output_len: hash.len(), ..*params })?;
+    let mut memory = vec![rc::Block::new(); params.block_count()];
+    let argon2 = rc::Argon2::new(variant.into(), version.into(), params);
+    cross_check_with(hash, |other| {
+        argon2.hash_password_into_with_memory(pwd, salt, other, &mut memory).map_err(|_| Error::CrossCheckMismatch)
+    })
+}
+
+/// Compares `hash` to the hash that `recompute` writes.
+#[cfg(feature = "cross-check")]
+pub(crate) fn cross_check_with(
+    hash: &[u8],
+    recompute: impl FnOnce(&mut [u8]) -> Result<(), Error>) -> Result<(), Error> {
+    let mut other = vec![0u8; hash.len()];
+    let result = recompute(&mut other).and_then(|()| {
+        if other == hash { Ok(()) } else { Err(Error::CrossCheckMismatch) }
+    });
+    secure_wipe(&mut other);
+    result
+}
@@ -0,0 +1,63 @@
+use super::types::Error;
+#[cfg(feature = "rand-core")]
+use rand_core::CryptoRngCore;
+
+/// Where `PhcHasher::hash_new_password` gets the salts of new hashes from.
+///
+/// The default is `OsSaltSource`. Tests that need the same hash on every run can use
+/// `insecure::FixedSaltSource` instead.
+#[cfg(feature = "random-salt")]
+pub trait SaltSource {
+    /// Fills `buf` with a new salt.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), Error>;
+}
+
+/// Salts from the operating system's random number generator.
+#[cfg(feature = "random-salt")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OsSaltSource;
+
+#[cfg(feature = "random-salt")]
+impl SaltSource for OsSaltSource {
+    /// Returns `Error::Random` if the random number generator fails.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        getrandom::getrandom(buf).map_err(|err| Error::Random(err.into()))
+    }
+}
+
+/// Salts from a random number generator of the caller, see `PhcHasher::with_rng`.
+#[cfg(all(feature = "random-salt", feature = "rand-core"))]
+#[derive(Debug, Clone)]
+pub struct RngSaltSource<R>(R);
+
+#[cfg(all(feature = "random-salt", feature = "rand-core"))]
+impl<R: CryptoRngCore> RngSaltSource<R> {
+    /// Creates a source that fills the salts with `rng`.
+    pub fn new(rng: R) -> RngSaltSource<R> {
+        RngSaltSource(rng)
+    }
+}
+
+#[cfg(all(feature = "random-salt", feature = "rand-core"))]
+impl<R: CryptoRngCore> SaltSource for RngSaltSource<R> {
+    /// Returns `Error::Random` if the random number generator fails.
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        fill_with_rng(&mut self.0, buf)
+    }
+}
+
+/// Generates a salt of `len` bytes with `rng`, e.g. a DRBG that all randomness of an application
+/// has to come from, instead of the operating system's random number generator.
+///
+/// Returns `Error::Random` if the random number generator fails.
+#[cfg(feature = "rand-core")]
+pub fn generate_salt_with_rng(rng: &mut impl CryptoRngCore, len: usize) -> Result<Vec<u8>, Error> {
+    let mut salt = vec![0u8; len];
+    fill_with_rng(rng, &mut salt)?;
+    Ok(salt)
+}
+
+#[cfg(feature = "rand-core")]
+fn fill_with_rng(rng: &mut impl CryptoRngCore, buf: &mut [u8]) -> Result<(), Error> {
+    rng.try_fill_bytes(buf).map_err(|err| Error::Random(err.into()))
+}
@@ -0,0 +1,128 @@
+use super::test_vectors::{self, TestVector};
+use super::types::{Context, Error, Flags, Variant, Version};
+use std::fmt;
+
+/// The vectors that `self_test` hashes with `hash` and checks with `verify`: the single-lane ones
+/// with 256 KiB of memory.
+const ENCODED_VECTORS: [&TestVector; 3] = [
+    &test_vectors::ARGON2I_V10[3],
+    &test_vectors::ARGON2I_V13[3],
+    &test_vectors::ARGON2ID_V13[2],
+];
+
+/// What went wrong in `self_test`.
+#[derive(Debug)]
+pub enum SelfTestFailure {
+    /// Hashing or verifying returned an error.
+    Error(Error),
+    /// The raw hash differs from the expected one.
+    HashMismatch,
+}
+
+/// A test vector that failed in `self_test`.
+#[derive(Debug)]
+pub struct SelfTestError {
+    pub variant: Variant,
+    pub version: Version,
+    /// The expected raw hash in hex, which identifies the vector among those in `test_vectors`.
+    pub expected: &'static str,
+    pub failure: SelfTestFailure,
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Self test of {} version 0x{:02x} failed for {}: ", self.variant, self.version.to_int(), self.expected)?;
+        match &self.failure {
+            SelfTestFailure::Error(err) => write!(f, "{}", err),
+            SelfTestFailure::HashMismatch => f.write_str("hash mismatch"),
+        }
+    }
+}
+
+impl std::error::Error for SelfTestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.failure {
+            SelfTestFailure::Error(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Checks the C library against known answers: every variant in both versions with a secret and
+/// associated data (`test_vectors::KATS`), and Argon2i 0x10, Argon2i 0x13 and Argon2id 0x13
+/// through `hash` and `verify`. The vectors use at most 256 KiB of memory and two passes, so this
+/// takes a few milliseconds.
+///
+/// Returns the first vector that fails. See the crate documentation for running it at startup.
+pub fn self_test() -> Result<(), SelfTestError> {
+    test_vectors::KATS.iter().chain(ENCODED_VECTORS).try_for_each(check)
+}
+
+/// Checks a single vector, with `hash` and `verify` if it has an encoded form and with `ctx`
+/// otherwise.
+pub(crate) fn check(vector: &TestVector) -> Result<(), SelfTestError> {
+    if vector.encoded.is_some() { check_hash_verify(vector) } else { check_ctx(vector) }
+}
+
+fn failed(vector: &TestVector, failure: SelfTestFailure) -> SelfTestError {
+    SelfTestError { variant: vector.variant, version: vector.version, expected: vector.hash, failure }
+}
+
+fn hex_eq(bytes: &[u8], hex: &str) -> bool {
+    const DIGITS: &[u8] = b"0123456789abcdef";
+    hex.len() == bytes.len() * 2 && bytes.iter().zip(hex.as_bytes().chunks(2)).all(|(&byte, digits)| {
+        digits == [DIGITS[usize::from(byte >> 4)], DIGITS[usize::from(byte & 0xF)]]
+    })
+}
+
+fn check_ctx(vector: &TestVector) -> Result<(), SelfTestError> {
+    let mut out = vec![0u8; vector.hash_len()];
+    super::ctx(&mut Context {
+        out: &mut out,
+        pwd: Some(&mut vector.password.to_vec()),
+        salt: Some(&mut vector.salt.to_vec()),
+        secret: Some(&mut vector.secret.to_vec()),
+        ad: Some(&mut vector.ad.to_vec()),
+        t_cost: vector.t_cost,
+        m_cost: vector.m_cost,
+        lanes: vector.parallelism,
+        // One thread is enough for 32 KiB, and works on targets without threads.
+        threads: 1,
+        version: vector.version,
+        flags: Flags::DEFAULT,
+    }, vector.variant).map_err(|err| failed(vector, SelfTestFailure::Error(err)))?;
+    if !hex_eq(&out, vector.hash) {
+        return Err(failed(vector, SelfTestFailure::HashMismatch));
+    }
+    Ok(())
+}
+
+fn check_hash_verify(vector: &TestVector) -> Result<(), SelfTestError> {
+    let error = |err| failed(vector, SelfTestFailure::Error(err));
+    let expected_encoded = vector.encoded.unwrap_or_default();
+    let mut out = vec![0u8; vector.hash_len()];
+    let mut encoded = vec![0u8; super::encodedlen(
+        vector.t_cost,
+        vector.m_cost,
+        vector.parallelism,
+        vector.salt.len() as u32,
+        vector.hash_len() as u32,
+        vector.variant)];
+    super::hash(
+        vector.t_cost,
+        vector.m_cost,
+        vector.parallelism,
+        Some(vector.password),
+        Some(vector.salt),
+        Some(&mut out),
+        Some(&mut encoded),
+        vector.variant,
+        vector.version).map_err(error)?;
+    if !hex_eq(&out, vector.hash) {
+        return Err(failed(vector, SelfTestFailure::HashMismatch));
+    }
+    // Like the reference tests, verify both encodings instead of comparing them: for version 0x10
+    // the C library writes a `v=16` field that the reference encodings don't have.
+    super::verify_bytes(&encoded, Some(vector.password), vector.variant).map_err(error)?;
+    super::verify_bytes(expected_encoded.as_bytes(), Some(vector.password), vector.variant).map_err(error)
+}
@@ -0,0 +1,100 @@
+//! "Server relief": the client does the expensive hashing, the server only a cheap final pass.
+//!
+//! Password hashing with high costs lets every login cost the server a lot of memory and time.
+//! With this scheme the client pays the costs instead, and the server stores a hash of the
+//! client's result so that a leaked database doesn't directly contain what clients send.
+//!
+//! # Protocol (version 1)
+//!
+//! 1. The server tells the client the `domain_tag`, a string that is unique to the service (e.g.
+//!    its host name), and the parameters of the client hash.
+//! 2. The client computes `client_prehash(params, username, domain_tag, pwd)`: the 32 byte raw
+//!    Argon2id (version 0x13) hash of the password with the costs of `params` and the salt
+//!
+//!    ```text
+//!    "just-argon2 server relief v1" || LE32(len(domain_tag)) || domain_tag || username
+//!    ```
+//!
+//!    where `LE32` is the length as 32 bit little endian. The salt is deterministic so that the
+//!    client needs no state, and includes the domain tag so that the same password of the same
+//!    user gives different pre-hashes for different services. Usernames are taken as bytes; the
+//!    client and the server have to agree on their normalization (e.g. case).
+//! 3. The client sends the pre-hash instead of the password, over a confidential channel.
+//! 4. At registration, the server stores `server_hash(prehash, salt)` with a new random salt of
+//!    its own: an encoded Argon2id hash with the light `SERVER_PARAMS`. At login, it checks the
+//!    pre-hash with `server_verify`.
+//!
+//! The pre-hash is a password equivalent: whoever knows it can log in. The server pass makes sure
+//! that the stored hash isn't one. Changing the client parameters changes every pre-hash, so
+//! stored hashes have to be migrated when users next log in with the old ones.
+//!
+//! Everything that goes into the pre-hash and the server hash is fixed for version 1 and checked
+//! by test vectors, so that clients and servers built with different releases interoperate.
+
+use super::params::PhcParams;
+use super::types::{Error, Variant, Version};
+use std::convert::TryFrom;
+
+/// Prefix of the client salt, which names the protocol and its version.
+pub const CLIENT_SALT_PREFIX: &[u8] = b"just-argon2 server relief v1";
+
+/// Length of the pre-hash in bytes.
+pub const PREHASH_LEN: usize = 32;
+
+/// Parameters of the server pass of `server_hash`: a single pass over 1 MiB with one lane, with a
+/// 32 byte hash. The client pre-hash is already expensive to guess, so this only has to keep the
+/// stored hashes from being password equivalents.
+pub const SERVER_PARAMS: PhcParams = PhcParams { m_cost: 1024, t_cost: 1, p_cost: 1, output_len: 32 };
+
+/// Computes the pre-hash of `pwd` on the client, with the costs of `params`. `params.output_len`
+/// is ignored, the pre-hash is always `PREHASH_LEN` bytes.
+///
+/// Returns `Error::InvalidParameter` for `"domain_tag.len"` if the domain tag is longer than
+/// `u32::MAX` bytes, and the errors of `hash` otherwise, e.g. `ErrorCode::SaltTooLong` if the salt
+/// doesn't fit.
+pub fn client_prehash(params: &PhcParams, username: &[u8], domain_tag: &[u8], pwd: &[u8]) -> Result<[u8; PREHASH_LEN], Error> {
+    let salt = client_salt(username, domain_tag)?;
+    let mut out = [0u8; PREHASH_LEN];
+    super::hash(params.t_cost, params.m_cost, params.p_cost, Some(pwd), Some(&salt), Some(&mut out), None, Variant::ID, Version::Version13)?;
+    Ok(out)
+}
+
+/// Hashes a pre-hash for storage on the server, with `SERVER_PARAMS` and `salt`, which should be
+/// random and unique to the user (e.g. 16 bytes from the operating system). Returns the encoded
+/// Argon2id hash.
+pub fn server_hash(prehash: &[u8; PREHASH_LEN], salt: &[u8]) -> Result<String, Error> {
+    super::hash_encoded_string(
+        SERVER_PARAMS.t_cost,
+        SERVER_PARAMS.m_cost,
+        SERVER_PARAMS.p_cost,
+        prehash,
+        salt,
+        SERVER_PARAMS.output_len,
+        Variant::ID,
+        Version::Version13)
+}
+
+/// Verifies a pre-hash sent by a client against an encoded hash of `server_hash`. The parameters
+/// are taken from `encoded`, so hashes stored with older parameters still verify.
+///
+/// Returns `ErrorCode::VerifyMismatch` if the pre-hash doesn't match, like the `*verify`
+/// functions, and `ErrorCode::DecodingFail` if `encoded` isn't an Argon2id hash.
+pub fn server_verify(encoded: &str, prehash: &[u8; PREHASH_LEN]) -> Result<(), Error> {
+    super::verify_bytes(encoded.as_bytes(), Some(prehash), Variant::ID)
+}
+
+/// Builds the salt of step 2 of the protocol.
+fn client_salt(username: &[u8], domain_tag: &[u8]) -> Result<Vec<u8>, Error> {
+    let tag_len = u32::try_from(domain_tag.len()).map_err(|_| Error::InvalidParameter {
+        name: "domain_tag.len",
+        got: domain_tag.len() as u64,
+        min: 0,
+        max: u64::from(u32::MAX),
+    })?;
+    let mut salt = Vec::with_capacity(CLIENT_SALT_PREFIX.len() + 4 + domain_tag.len() + username.len());
+    salt.extend_from_slice(CLIENT_SALT_PREFIX);
+    salt.extend_from_slice(&tag_len.to_le_bytes());
+    salt.extend_from_slice(domain_tag);
+    salt.extend_from_slice(username);
+    Ok(salt)
+}
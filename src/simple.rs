@@ -0,0 +1,45 @@
+use super::encoded::EncodedHash;
+use super::params::PhcParams;
+use super::salt::{OsSaltSource, SaltSource};
+use super::types::{Error, Variant, Version};
+
+/// Length in bytes of the salts of `hash_password`.
+const SALT_LEN: usize = 16;
+
+/// Hashes a password for storage. This and `verify_password` are the recommended entry points
+/// when there is nothing to configure.
+///
+/// Hashes with Argon2id, `Version::DEFAULT` and the default `PhcParams` (19 MiB, two passes, one
+/// lane, a 32 byte hash), with a new 16 byte salt from the operating system's random number
+/// generator, and returns the encoded hash. Store it as it is; it contains everything
+/// `verify_password` needs.
+///
+/// Returns `Error::Random` if the random number generator fails.
+pub fn hash_password(pwd: &str) -> Result<String, Error> {
+    let params = PhcParams::default();
+    let mut salt = [0u8; SALT_LEN];
+    OsSaltSource.fill(&mut salt)?;
+    super::hash_encoded_string(
+        params.t_cost,
+        params.m_cost,
+        params.p_cost,
+        pwd.as_bytes(),
+        &salt,
+        params.output_len,
+        Variant::ID,
+        Version::DEFAULT)
+}
+
+/// Verifies a password against an encoded hash, e.g. one of `hash_password`. The variant and
+/// parameters are taken from the hash, so hashes of other functions and libraries verify as well.
+///
+/// Returns `Ok(false)` if the password doesn't match, `ErrorCode::DecodingFail` if `encoded`
+/// isn't an encoded Argon2 hash, and the errors of `verify` otherwise.
+pub fn verify_password(encoded: &str, pwd: &str) -> Result<bool, Error> {
+    let variant = EncodedHash::parse(encoded)?.variant();
+    match super::verify_bytes(encoded.as_bytes(), Some(pwd.as_bytes()), variant) {
+        Ok(()) => Ok(true),
+        Err(err) if err.is_verify_mismatch() => Ok(false),
+        Err(err) => Err(err),
+    }
+}
@@ -0,0 +1,70 @@
+use super::encoded::EncodedHash;
+
+// `EncodedHash` is stored as its string, so it works with every database that has a text type.
+
+#[cfg(feature = "sqlx")]
+use sqlx::{encode::IsNull, error::BoxDynError, Database, Decode, Encode, Type};
+#[cfg(feature = "diesel")]
+use diesel::{backend::Backend, deserialize::{self, FromSql}, serialize::{self, Output, ToSql}, sql_types::Text};
+
+#[cfg(feature = "sqlx")]
+impl<DB: Database> Type<DB> for EncodedHash
+where
+    str: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo {
+        <str as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool {
+        <str as Type<DB>>::compatible(ty)
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl<'q, DB: Database> Encode<'q, DB> for EncodedHash
+where
+    String: Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut <DB as Database>::ArgumentBuffer<'q>) -> Result<IsNull, BoxDynError> {
+        self.as_str().to_owned().encode(buf)
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl<'r, DB: Database> Decode<'r, DB> for EncodedHash
+where
+    &'r str: Decode<'r, DB>,
+{
+    /// Fails with the `Error` of `EncodedHash::parse` as the source if the column doesn't hold an
+    /// encoded hash.
+    fn decode(value: <DB as Database>::ValueRef<'r>) -> Result<EncodedHash, BoxDynError> {
+        let encoded = <&'r str as Decode<'r, DB>>::decode(value)?;
+        Ok(EncodedHash::parse(encoded)?)
+    }
+}
+
+#[cfg(feature = "diesel")]
+impl<DB> ToSql<Text, DB> for EncodedHash
+where
+    DB: Backend,
+    str: ToSql<Text, DB>,
+{
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, DB>) -> serialize::Result {
+        self.as_str().to_sql(out)
+    }
+}
+
+#[cfg(feature = "diesel")]
+impl<DB> FromSql<Text, DB> for EncodedHash
+where
+    DB: Backend,
+    String: FromSql<Text, DB>,
+{
+    /// Fails with the `Error` of `EncodedHash::parse` as the source of the
+    /// `DeserializationError` if the column doesn't hold an encoded hash.
+    fn from_sql(bytes: DB::RawValue<'_>) -> deserialize::Result<EncodedHash> {
+        let encoded = <String as FromSql<Text, DB>>::from_sql(bytes)?;
+        Ok(EncodedHash::parse(&encoded)?)
+    }
+}
@@ -0,0 +1,189 @@
+//! The test vectors of the reference implementation, for checking other Argon2 implementations (or
+//! a build of this crate) against it.
+//!
+//! `ARGON2I_V10`, `ARGON2I_V13` and `ARGON2ID_V13` are the vectors of the reference `test.c`: 32
+//! byte hashes of ASCII passwords and salts, with their encoded form. `KATS` are the known answers
+//! of the reference `kats` directory, which also use a secret and associated data and so have no
+//! encoded form; the version 0x13 ones are the test vectors of RFC 9106. `all` iterates over every
+//! vector. Two vectors use 1 GiB of memory (`m_cost` of 2^20), tests may want to skip those.
+
+use super::types::{Variant, Version};
+
+/// An Argon2 hash of known inputs.
+#[derive(Debug, Clone, Copy)]
+pub struct TestVector {
+    pub variant: Variant,
+    pub version: Version,
+    pub t_cost: u32,
+    /// Memory in KiB.
+    pub m_cost: u32,
+    pub parallelism: u32,
+    pub password: &'static [u8],
+    pub salt: &'static [u8],
+    /// Empty if there is none.
+    pub secret: &'static [u8],
+    /// Empty if there is none.
+    pub ad: &'static [u8],
+    /// The raw hash in lowercase hex; its length is the output length.
+    pub hash: &'static str,
+    /// The encoded hash, unless the vector has a secret or associated data.
+    pub encoded: Option<&'static str>,
+}
+
+impl TestVector {
+    /// Length of the raw hash in bytes.
+    pub fn hash_len(&self) -> usize {
+        self.hash.len() / 2
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+const fn simple(
+    variant: Variant,
+    version: Version,
+    t_cost: u32,
+    m_cost: u32,
+    parallelism: u32,
+    password: &'static str,
+    salt: &'static str,
+    hash: &'static str,
+    encoded: &'static str) -> TestVector {
+    TestVector {
+        variant,
+        version,
+        t_cost,
+        m_cost,
+        parallelism,
+        password: password.as_bytes(),
+        salt: salt.as_bytes(),
+        secret: &[],
+        ad: &[],
+        hash,
+        encoded: Some(encoded),
+    }
+}
+
+const fn kat(variant: Variant, version: Version, hash: &'static str) -> TestVector {
+    TestVector {
+        variant,
+        version,
+        t_cost: 3,
+        m_cost: 32,
+        parallelism: 4,
+        password: &[0x01; 32],
+        salt: &[0x02; 16],
+        secret: &[0x03; 8],
+        ad: &[0x04; 12],
+        hash,
+        encoded: None,
+    }
+}
+
+/// Argon2i version 0x10. The second vector uses 1 GiB of memory.
+pub const ARGON2I_V10: &[TestVector] = &[
+    simple(Variant::I, Version::Version10, 2, 1 << 16, 1, "password", "somesalt",
+           "f6c4db4a54e2a370627aff3db6176b94a2a209a62c8e36152711802f7b30c694",
+           "$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ$9sTbSlTio3Biev89thdrlKKiCaYsjjYVJxGAL3swxpQ"),
+    simple(Variant::I, Version::Version10, 2, 1 << 20, 1, "password", "somesalt",
+           "9690ec55d28d3ed32562f2e73ea62b02b018757643a2ae6e79528459de8106e9",
+           "$argon2i$m=1048576,t=2,p=1$c29tZXNhbHQ$lpDsVdKNPtMlYvLnPqYrArAYdXZDoq5ueVKEWd6BBuk"),
+    simple(Variant::I, Version::Version10, 2, 1 << 18, 1, "password", "somesalt",
+           "3e689aaa3d28a77cf2bc72a51ac53166761751182f1ee292e3f677a7da4c2467",
+           "$argon2i$m=262144,t=2,p=1$c29tZXNhbHQ$Pmiaqj0op3zyvHKlGsUxZnYXURgvHuKS4/Z3p9pMJGc"),
+    simple(Variant::I, Version::Version10, 2, 1 << 8, 1, "password", "somesalt",
+           "fd4dd83d762c49bdeaf57c47bdcd0c2f1babf863fdeb490df63ede9975fccf06",
+           "$argon2i$m=256,t=2,p=1$c29tZXNhbHQ$/U3YPXYsSb3q9XxHvc0MLxur+GP960kN9j7emXX8zwY"),
+    simple(Variant::I, Version::Version10, 2, 1 << 8, 2, "password", "somesalt",
+           "b6c11560a6a9d61eac706b79a2f97d68b4463aa3ad87e00c07e2b01e90c564fb",
+           "$argon2i$m=256,t=2,p=2$c29tZXNhbHQ$tsEVYKap1h6scGt5ovl9aLRGOqOth+AMB+KwHpDFZPs"),
+    simple(Variant::I, Version::Version10, 1, 1 << 16, 1, "password", "somesalt",
+           "81630552b8f3b1f48cdb1992c4c678643d490b2b5eb4ff6c4b3438b5621724b2",
+           "$argon2i$m=65536,t=1,p=1$c29tZXNhbHQ$gWMFUrjzsfSM2xmSxMZ4ZD1JCytetP9sSzQ4tWIXJLI"),
+    simple(Variant::I, Version::Version10, 4, 1 << 16, 1, "password", "somesalt",
+           "f212f01615e6eb5d74734dc3ef40ade2d51d052468d8c69440a3a1f2c1c2847b",
+           "$argon2i$m=65536,t=4,p=1$c29tZXNhbHQ$8hLwFhXm6110c03D70Ct4tUdBSRo2MaUQKOh8sHChHs"),
+    simple(Variant::I, Version::Version10, 2, 1 << 16, 1, "differentpassword", "somesalt",
+           "e9c902074b6754531a3a0be519e5baf404b30ce69b3f01ac3bf21229960109a3",
+           "$argon2i$m=65536,t=2,p=1$c29tZXNhbHQ$6ckCB0tnVFMaOgvlGeW69ASzDOabPwGsO/ISKZYBCaM"),
+    simple(Variant::I, Version::Version10, 2, 1 << 16, 1, "password", "diffsalt",
+           "79a103b90fe8aef8570cb31fc8b22259778916f8336b7bdac3892569d4f1c497",
+           "$argon2i$m=65536,t=2,p=1$ZGlmZnNhbHQ$eaEDuQ/orvhXDLMfyLIiWXeJFvgza3vaw4kladTxxJc"),
+];
+
+/// Argon2i version 0x13. The second vector uses 1 GiB of memory.
+pub const ARGON2I_V13: &[TestVector] = &[
+    simple(Variant::I, Version::Version13, 2, 1 << 16, 1, "password", "somesalt",
+           "c1628832147d9720c5bd1cfd61367078729f6dfb6f8fea9ff98158e0d7816ed0",
+           "$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweHKfbftvj+qf+YFY4NeBbtA"),
+    simple(Variant::I, Version::Version13, 2, 1 << 20, 1, "password", "somesalt",
+           "d1587aca0922c3b5d6a83edab31bee3c4ebaef342ed6127a55d19b2351ad1f41",
+           "$argon2i$v=19$m=1048576,t=2,p=1$c29tZXNhbHQ$0Vh6ygkiw7XWqD7asxvuPE667zQu1hJ6VdGbI1GtH0E"),
+    simple(Variant::I, Version::Version13, 2, 1 << 18, 1, "password", "somesalt",
+           "296dbae80b807cdceaad44ae741b506f14db0959267b183b118f9b24229bc7cb",
+           "$argon2i$v=19$m=262144,t=2,p=1$c29tZXNhbHQ$KW266AuAfNzqrUSudBtQbxTbCVkmexg7EY+bJCKbx8s"),
+    simple(Variant::I, Version::Version13, 2, 1 << 8, 1, "password", "somesalt",
+           "89e9029f4637b295beb027056a7336c414fadd43f6b208645281cb214a56452f",
+           "$argon2i$v=19$m=256,t=2,p=1$c29tZXNhbHQ$iekCn0Y3spW+sCcFanM2xBT63UP2sghkUoHLIUpWRS8"),
+    simple(Variant::I, Version::Version13, 2, 1 << 8, 2, "password", "somesalt",
+           "4ff5ce2769a1d7f4c8a491df09d41a9fbe90e5eb02155a13e4c01e20cd4eab61",
+           "$argon2i$v=19$m=256,t=2,p=2$c29tZXNhbHQ$T/XOJ2mh1/TIpJHfCdQan76Q5esCFVoT5MAeIM1Oq2E"),
+    simple(Variant::I, Version::Version13, 1, 1 << 16, 1, "password", "somesalt",
+           "d168075c4d985e13ebeae560cf8b94c3b5d8a16c51916b6f4ac2da3ac11bbecf",
+           "$argon2i$v=19$m=65536,t=1,p=1$c29tZXNhbHQ$0WgHXE2YXhPr6uVgz4uUw7XYoWxRkWtvSsLaOsEbvs8"),
+    simple(Variant::I, Version::Version13, 4, 1 << 16, 1, "password", "somesalt",
+           "aaa953d58af3706ce3df1aefd4a64a84e31d7f54175231f1285259f88174ce5b",
+           "$argon2i$v=19$m=65536,t=4,p=1$c29tZXNhbHQ$qqlT1YrzcGzj3xrv1KZKhOMdf1QXUjHxKFJZ+IF0zls"),
+    simple(Variant::I, Version::Version13, 2, 1 << 16, 1, "differentpassword", "somesalt",
+           "14ae8da01afea8700c2358dcef7c5358d9021282bd88663a4562f59fb74d22ee",
+           "$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$FK6NoBr+qHAMI1jc73xTWNkCEoK9iGY6RWL1n7dNIu4"),
+    simple(Variant::I, Version::Version13, 2, 1 << 16, 1, "password", "diffsalt",
+           "b0357cccfbef91f3860b0dba447b2348cbefecadaf990abfe9cc40726c521271",
+           "$argon2i$v=19$m=65536,t=2,p=1$ZGlmZnNhbHQ$sDV8zPvvkfOGCw26RHsjSMvv7K2vmQq/6cxAcmxSEnE"),
+];
+
+/// Argon2id version 0x13.
+pub const ARGON2ID_V13: &[TestVector] = &[
+    simple(Variant::ID, Version::Version13, 2, 1 << 16, 1, "password", "somesalt",
+           "09316115d5cf24ed5a15a31a3ba326e5cf32edc24702987c02b6566f61913cf7",
+           "$argon2id$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$CTFhFdXPJO1aFaMaO6Mm5c8y7cJHAph8ArZWb2GRPPc"),
+    simple(Variant::ID, Version::Version13, 2, 1 << 18, 1, "password", "somesalt",
+           "78fe1ec91fb3aa5657d72e710854e4c3d9b9198c742f9616c2f085bed95b2e8c",
+           "$argon2id$v=19$m=262144,t=2,p=1$c29tZXNhbHQ$eP4eyR+zqlZX1y5xCFTkw9m5GYx0L5YWwvCFvtlbLow"),
+    simple(Variant::ID, Version::Version13, 2, 1 << 8, 1, "password", "somesalt",
+           "9dfeb910e80bad0311fee20f9c0e2b12c17987b4cac90c2ef54d5b3021c68bfe",
+           "$argon2id$v=19$m=256,t=2,p=1$c29tZXNhbHQ$nf65EOgLrQMR/uIPnA4rEsF5h7TKyQwu9U1bMCHGi/4"),
+    simple(Variant::ID, Version::Version13, 2, 1 << 8, 2, "password", "somesalt",
+           "6d093c501fd5999645e0ea3bf620d7b8be7fd2db59c20d9fff9539da2bf57037",
+           "$argon2id$v=19$m=256,t=2,p=2$c29tZXNhbHQ$bQk8UB/VmZZF4Oo79iDXuL5/0ttZwg2f/5U52iv1cDc"),
+    simple(Variant::ID, Version::Version13, 1, 1 << 16, 1, "password", "somesalt",
+           "f6a5adc1ba723dddef9b5ac1d464e180fcd9dffc9d1cbf76cca2fed795d9ca98",
+           "$argon2id$v=19$m=65536,t=1,p=1$c29tZXNhbHQ$9qWtwbpyPd3vm1rB1GThgPzZ3/ydHL92zKL+15XZypg"),
+    simple(Variant::ID, Version::Version13, 4, 1 << 16, 1, "password", "somesalt",
+           "9025d48e68ef7395cca9079da4c4ec3affb3c8911fe4f86d1a2520856f63172c",
+           "$argon2id$v=19$m=65536,t=4,p=1$c29tZXNhbHQ$kCXUjmjvc5XMqQedpMTsOv+zyJEf5PhtGiUghW9jFyw"),
+    simple(Variant::ID, Version::Version13, 2, 1 << 16, 1, "differentpassword", "somesalt",
+           "0b84d652cf6b0c4beaef0dfe278ba6a80df6696281d7e0d2891b817d8c458fde",
+           "$argon2id$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$C4TWUs9rDEvq7w3+J4umqA32aWKB1+DSiRuBfYxFj94"),
+    simple(Variant::ID, Version::Version13, 2, 1 << 16, 1, "password", "diffsalt",
+           "bdf32b05ccc42eb15d58fd19b1f856b113da1e9a5874fdcc544308565aa8141c",
+           "$argon2id$v=19$m=65536,t=2,p=1$ZGlmZnNhbHQ$vfMrBczELrFdWP0ZsfhWsRPaHppYdP3MVEMIVlqoFBw"),
+];
+
+/// The known answers of every variant in both versions, with t = 3, m = 32 KiB, p = 4, a 32 byte
+/// password of 0x01, a 16 byte salt of 0x02, an 8 byte secret of 0x03 and 12 bytes of associated
+/// data of 0x04. With the `no-argon2d` feature, the Argon2d ones are left out.
+pub const KATS: &[TestVector] = &[
+    #[cfg(not(feature = "no-argon2d"))]
+    kat(Variant::D, Version::Version10, "96a9d4e5a1734092c85e29f410a45914a5dd1f5cbf08b2670da68a0285abf32b"),
+    kat(Variant::I, Version::Version10, "87aeedd6517ab830cd9765cd8231abb2e647a5dee08f7c05e02fcb763335d0fd"),
+    kat(Variant::ID, Version::Version10, "b64615f07789b66b645b67ee9ed3b377ae350b6bfcbb0fc95141ea8f322613c0"),
+    #[cfg(not(feature = "no-argon2d"))]
+    kat(Variant::D, Version::Version13, "512b391b6f1162975371d30919734294f868e3be3984f3c1a13a4db9fabe4acb"),
+    kat(Variant::I, Version::Version13, "c814d9d1dc7f37aa13f0d77f2494bda1c8de6b016dd388d29952a4c4672b6ce8"),
+    kat(Variant::ID, Version::Version13, "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659"),
+];
+
+/// Iterates over `ARGON2I_V10`, `ARGON2I_V13`, `ARGON2ID_V13` and `KATS`, in that order.
+pub fn all() -> impl Iterator<Item = &'static TestVector> {
+    ARGON2I_V10.iter().chain(ARGON2I_V13).chain(ARGON2ID_V13).chain(KATS)
+}
@@ -0,0 +1,177 @@
+#[cfg(feature = "metrics")]
+use super::metrics;
+use super::types::{Error, Variant};
+use std::ffi::CStr;
+
+/// Runs a hash function in an `argon2.hash` span.
+///
+/// Every hash and verification passes through one of these functions, so they also report to the
+/// `metrics` sink. With the `tracing` feature disabled, that is all they do besides calling `f`.
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub(crate) fn hash<T, F>(variant: Variant, version: u32, t_cost: u32, m_cost: u32, parallelism: u32, f: F)
+    -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    hash_metrics(variant, version, t_cost, m_cost, parallelism, f)
+}
+
+/// Runs a verify function with known parameters in an `argon2.verify` span.
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub(crate) fn verify<T, F>(_variant: Variant, _version: u32, _t_cost: u32, _m_cost: u32, _parallelism: u32, f: F)
+    -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    verify_metrics(f)
+}
+
+/// Runs a verify function in an `argon2.verify` span, with the parameters parsed from `encoded`.
+#[cfg(not(feature = "tracing"))]
+#[inline]
+pub(crate) fn verify_encoded<T, F>(_variant: Variant, _encoded: &CStr, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    verify_metrics(f)
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn hash<T, F>(variant: Variant, version: u32, t_cost: u32, m_cost: u32, parallelism: u32, f: F)
+    -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    let span = tracing::info_span!(
+        "argon2.hash",
+        variant = %variant,
+        version,
+        t_cost,
+        m_cost,
+        parallelism,
+        outcome = tracing::field::Empty,
+    );
+    run(span, || hash_metrics(variant, version, t_cost, m_cost, parallelism, f))
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn verify<T, F>(variant: Variant, version: u32, t_cost: u32, m_cost: u32, parallelism: u32, f: F)
+    -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    let span = tracing::info_span!(
+        "argon2.verify",
+        variant = %variant,
+        version,
+        t_cost,
+        m_cost,
+        parallelism,
+        outcome = tracing::field::Empty,
+    );
+    run(span, || verify_metrics(f))
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn verify_encoded<T, F>(variant: Variant, encoded: &CStr, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    use super::memory::encoded_param;
+
+    // Parameters that can't be parsed are left empty; the C library reports the malformed hash.
+    let encoded = encoded.to_bytes();
+    let span = tracing::info_span!(
+        "argon2.verify",
+        variant = %variant,
+        version = encoded_param(encoded, b"v").unwrap_or(0x10),
+        t_cost = encoded_param(encoded, b"t"),
+        m_cost = encoded_param(encoded, b"m"),
+        parallelism = encoded_param(encoded, b"p"),
+        outcome = tracing::field::Empty,
+    );
+    run(span, || verify_metrics(f))
+}
+
+/// Reports a hash to the sink of the `metrics` module. Without the `metrics` feature, this just
+/// calls `f`.
+#[cfg(feature = "metrics")]
+#[inline]
+fn hash_metrics<T, F>(variant: Variant, version: u32, t_cost: u32, m_cost: u32, parallelism: u32, f: F)
+    -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    metrics::hash(metrics::HashParams { variant, version, t_cost, m_cost, parallelism }, f)
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline]
+fn hash_metrics<T, F>(_variant: Variant, _version: u32, _t_cost: u32, _m_cost: u32, _parallelism: u32, f: F)
+    -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    f()
+}
+
+/// Reports a verification to the sink of the `metrics` module. Without the `metrics` feature,
+/// this just calls `f`.
+#[cfg(feature = "metrics")]
+#[inline]
+fn verify_metrics<T, F>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    metrics::verify(f)
+}
+
+#[cfg(not(feature = "metrics"))]
+#[inline]
+fn verify_metrics<T, F>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    f()
+}
+
+/// Runs `f` in `span`, records the outcome and an event with the error code if `f` fails.
+#[cfg(feature = "tracing")]
+fn run<T, F>(span: tracing::Span, f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error>,
+{
+    let _entered = span.enter();
+    let result = f();
+    match &result {
+        Ok(_) => {
+            span.record("outcome", "ok");
+        }
+        Err(err) if err.is_verify_mismatch() => {
+            span.record("outcome", "mismatch");
+            tracing::debug!(error_code = error_code(err), "password does not match");
+        }
+        Err(err) => {
+            span.record("outcome", "error");
+            tracing::warn!(error_code = error_code(err), error = %err, "argon2 failed");
+        }
+    }
+    result
+}
+
+/// The C error code that corresponds to `err`, if any.
+#[cfg(feature = "tracing")]
+fn error_code(err: &Error) -> Option<i64> {
+    use super::types::ErrorCode;
+
+    let code = match err {
+        Error::Code(code) => code.to_c(),
+        Error::AllocationFailed { .. } => ErrorCode::MemoryAllocationError.to_c(),
+        Error::ThreadFailed(_) => ErrorCode::ThreadFail.to_c(),
+        Error::UnknownCode(code) => *code,
+        _ => return None,
+    };
+    Some(code.into())
+}
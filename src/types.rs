@@ -1,38 +1,365 @@
+use super::memory::{check_m_cost_cap, encoded_param};
 use super::sys;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::fmt;
+use std::os::raw::c_int;
 
 /// Error type returned by all Rust wrappers of Argon2 functions.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug)]
 pub enum Error {
     /// This error is returned whenever a bad parameter is passed in but doesn't make it past the
     /// wrapper layer. e.g. a parameter that cannot be converted to the type required by the argon2
     /// C library.
+    ///
+    /// Parameters that are merely out of range are reported as `InvalidParameter` instead, use
+    /// `Error::param_name` to get the parameter name regardless of the variant.
+    ///
+    /// Before `InvalidParameter` was added, a hash length that doesn't match the encoded hash in
+    /// the `*_verify_ctx` functions was reported as `BadParam("hash.len")`, and a `Context` slice
+    /// that is too long for the C library as `BadParam("context.<field>.len")`. Code that matches
+    /// on these names should match on `param_name()` instead, which returns them for both
+    /// variants, and code that matches on the variant alone should use `is_bad_param()`.
     BadParam(&'static str),
 
+    /// A parameter passed to a Rust wrapper lies outside of the range that is accepted.
+    /// `min` and `max` are inclusive.
+    InvalidParameter {
+        /// Name of the parameter.
+        name: &'static str,
+        /// The value that was passed in.
+        got: u64,
+        /// Smallest accepted value.
+        min: u64,
+        /// Largest accepted value.
+        max: u64,
+    },
+
+    /// The buffer for an encoded hash is too small. Both sizes are in bytes and include the
+    /// terminating null byte.
+    BufferTooSmall {
+        /// Size the buffer needs to have.
+        required: usize,
+        /// Size of the buffer that was passed in.
+        provided: usize,
+    },
+
+    /// A byte string that should have been converted to a C string contains no terminating NUL
+    /// byte.
+    MissingNul,
+
+    /// A password is longer than the limit passed to `check_password_len`.
+    PasswordTooLong {
+        /// Length of the password in bytes.
+        len: usize,
+        /// Largest accepted length in bytes.
+        max: usize,
+    },
+
+    /// A function of the argon2 C library unexpectedly returned a null pointer.
+    /// Contains the name of the C function.
+    NullPointer(&'static str),
+
+    /// A string returned by the argon2 C library is not valid UTF-8.
+    InvalidUtf8(std::str::Utf8Error),
+
     /// An error returned from the argon2 C library in the form of an error code.
     Code(ErrorCode),
 
-    /// An error occurred an argon2 but it has no Rust wrapper.
-    /// These are bugs in the library itself.
+    /// The argon2 C library couldn't allocate its working memory (`ARGON2_MEMORY_ALLOCATION_ERROR`).
+    ///
+    /// Returned by the functions that know the requested memory cost; `requested_bytes` is the size
+    /// of the blocks the C library allocates, i.e. `m_cost` KiB rounded down to a multiple of
+    /// `4 * lanes` KiB. Verifying against an encoded hash still reports
+    /// `ErrorCode::MemoryAllocationError`, since the memory cost is only known to the C library.
+    AllocationFailed {
+        /// Amount of memory requested in bytes.
+        requested_bytes: u64,
+    },
+
+    /// The memory cost exceeds the cap set with `set_max_m_cost`. Both values are in kibibytes.
+    ExceedsMemoryCap {
+        /// The memory cost that was requested, or parsed from an encoded hash.
+        m_cost: u32,
+        /// The cap.
+        cap: u32,
+    },
+
+    /// The argon2 C library failed to create or join its worker threads (`ARGON2_THREAD_FAIL`).
+    ///
+    /// Contains the error that `pthread_create` returned, e.g. `EAGAIN` when hitting a thread or
+    /// pid limit, or the error of `CreateThread` on Windows. The C library drops it, so it is kept
+    /// by a wrapper around its thread creation, which only the bundled sources have. With a
+    /// prebuilt or system library, this is `std::io::Error::last_os_error()` as captured right
+    /// after the failing call, as a best effort.
+    ThreadFailed(std::io::Error),
+
+    /// The operating system's random number generator failed.
+    Random(std::io::Error),
+
+    /// The blocking task of an `*_on` or `*_async` function couldn't be started, was cancelled or
+    /// panicked.
+    TaskFailed(std::io::Error),
+
+    /// Reading the input of `hash_raw_from_reader` or `verify_from_reader` failed.
+    Read(std::io::Error),
+
+    /// `hash_encoded_with_timeout` gave up waiting for its worker thread, which keeps hashing in
+    /// the background.
+    TimedOut,
+
+    /// An encoded hash passed to `verify_pinned` doesn't use exactly the pinned parameters.
+    /// `field` is `"variant"` (compared as the `argon2_type` of the C library, 0 for Argon2d, 1 for
+    /// Argon2i and 2 for Argon2id), `"version"`, `"m_cost"`, `"t_cost"`, `"p_cost"` or
+    /// `"hash_len"`.
+    ParamsNotPinned {
+        /// Name of the field that differs.
+        field: &'static str,
+        /// The pinned value.
+        expected: u64,
+        /// The value of the hash.
+        found: u64,
+    },
+
+    /// More than one thread was requested on a target without threads (wasm), where the C library
+    /// is built with `ARGON2_NO_THREADS`. Contains the number of threads that was requested, the
+    /// parallelism of the hash functions or the `p` of an encoded hash.
+    ThreadsUnavailable {
+        /// Number of threads that was requested.
+        threads: u32,
+    },
+
+    /// The pure Rust `argon2` crate computed a different hash than the C library, or rejected
+    /// inputs that the C library accepted. Only returned with the `cross-check` feature.
+    CrossCheckMismatch,
+
+    /// Every hash passed to `verify_any_of` is malformed. Contains the error of each hash, in the
+    /// same order; these are decoding errors or `UnknownAlgorithm`.
+    AllMalformed(Vec<Error>),
+
+    /// An encoded hash or a KDF names an Argon2 variant that is compiled out, i.e. Argon2d with the
+    /// `no-argon2d` feature. Contains the name of the variant.
+    UnknownAlgorithm(&'static str),
+
+    /// The argon2 C library returned an error code that has no `ErrorCode` counterpart.
+    /// The raw code is kept so that it can still be reported.
+    UnknownCode(i32),
+
+    /// An error occurred in argon2 but it has no Rust wrapper.
+    ///
+    /// No longer returned: error codes without an `ErrorCode` counterpart are reported as
+    /// `UnknownCode`, which keeps the raw code. Kept so that existing matches still compile.
+    #[deprecated(note = "unknown error codes are reported as `Error::UnknownCode`")]
     Unknown,
 }
 
 impl Error {
-    pub(crate) fn check_code(code: sys::Argon2_ErrorCodes) -> Result<(), Error> {
-        if code == 0 {
-            Ok(())
-        } else {
-            if let Some(err_code) = ErrorCode::from_c(code as sys::Argon2_ErrorCodes) {
-                Err(Error::Code(err_code))
-            } else {
-                Err(Error::Unknown)
+    /// Returns the name of the offending parameter for `BadParam` and `InvalidParameter` errors,
+    /// and `"pwd"` for `PasswordTooLong`.
+    ///
+    /// A parameter keeps its name when its checks move from `BadParam` to `InvalidParameter`, so
+    /// matching on the name works with either.
+    pub fn param_name(&self) -> Option<&'static str> {
+        match self {
+            Error::BadParam(name) | Error::InvalidParameter { name, .. } => Some(name),
+            Error::PasswordTooLong { .. } => Some("pwd"),
+            _ => None,
+        }
+    }
+
+    /// Returns true for `BadParam` and `InvalidParameter`, i.e. if a parameter was rejected by a
+    /// Rust wrapper before calling into the C library.
+    ///
+    /// Use this instead of matching on `Error::BadParam(_)`: parameters whose checks moved to
+    /// `InvalidParameter` are still covered.
+    pub fn is_bad_param(&self) -> bool {
+        matches!(self, Error::BadParam(_) | Error::InvalidParameter { .. })
+    }
+
+    /// Returns true if the password didn't match the hash (`ErrorCode::VerifyMismatch`).
+    pub fn is_verify_mismatch(&self) -> bool {
+        matches!(self, Error::Code(ErrorCode::VerifyMismatch))
+    }
+
+    /// Returns true if an encoded hash couldn't be decoded, i.e. it is malformed
+    /// (`ErrorCode::DecodingFail`, `ErrorCode::DecodingLengthFail` and `Error::AllMalformed`).
+    pub fn is_decoding_error(&self) -> bool {
+        matches!(self,
+                 Error::Code(ErrorCode::DecodingFail) |
+                 Error::Code(ErrorCode::DecodingLengthFail) |
+                 Error::AllMalformed(_))
+    }
+
+    /// Returns true if the requested amount of memory is out of range or couldn't be allocated
+    /// (`ErrorCode::MemoryTooLittle`, `ErrorCode::MemoryTooMuch`,
+    /// `ErrorCode::MemoryAllocationError`, `Error::AllocationFailed` and
+    /// `Error::ExceedsMemoryCap`).
+    pub fn is_memory_error(&self) -> bool {
+        matches!(self,
+                 Error::Code(ErrorCode::MemoryTooLittle) |
+                 Error::Code(ErrorCode::MemoryTooMuch) |
+                 Error::Code(ErrorCode::MemoryAllocationError) |
+                 Error::AllocationFailed { .. } |
+                 Error::ExceedsMemoryCap { .. })
+    }
+
+    /// Returns true if a parameter was rejected, either by the Rust wrapper (`Error::BadParam`,
+    /// `Error::InvalidParameter`, `Error::BufferTooSmall`, `Error::MissingNul` and
+    /// `Error::PasswordTooLong`) or by the C
+    /// library. The latter covers the `*TooShort`,
+    /// `*TooLong`, `*TooSmall`, `*TooLarge`, `*TooFew`, `*TooMany` and `*PtrMismatch` codes as
+    /// well as `OutputPtrNull`, `IncorrectParameter`, `IncorrectType` and `MissingArgs`.
+    ///
+    /// Note that `MemoryTooLittle` and `MemoryTooMuch` are also reported by `is_memory_error`.
+    pub fn is_invalid_parameter(&self) -> bool {
+        match self {
+            Error::BadParam(_) |
+            Error::InvalidParameter { .. } |
+            Error::BufferTooSmall { .. } |
+            Error::MissingNul |
+            Error::PasswordTooLong { .. } => true,
+            Error::Code(code) => matches!(code,
+                ErrorCode::OutputPtrNull |
+                ErrorCode::OutputTooShort |
+                ErrorCode::OutputTooLong |
+                ErrorCode::PwdTooShort |
+                ErrorCode::PwdTooLong |
+                ErrorCode::SaltTooShort |
+                ErrorCode::SaltTooLong |
+                ErrorCode::AdTooShort |
+                ErrorCode::AdTooLong |
+                ErrorCode::SecretTooShort |
+                ErrorCode::SecretTooLong |
+                ErrorCode::TimeTooSmall |
+                ErrorCode::TimeTooLarge |
+                ErrorCode::MemoryTooLittle |
+                ErrorCode::MemoryTooMuch |
+                ErrorCode::LanesTooFew |
+                ErrorCode::LanesTooMany |
+                ErrorCode::PwdPtrMismatch |
+                ErrorCode::SaltPtrMismatch |
+                ErrorCode::SecretPtrMismatch |
+                ErrorCode::AdPtrMismatch |
+                ErrorCode::IncorrectParameter |
+                ErrorCode::IncorrectType |
+                ErrorCode::OutPtrMismatch |
+                ErrorCode::ThreadsTooFew |
+                ErrorCode::ThreadsTooMany |
+                ErrorCode::MissingArgs),
+            _ => false,
+        }
+    }
+}
+
+#[allow(deprecated)]
+impl PartialEq for Error {
+    /// `ThreadFailed`, `Random`, `TaskFailed` and `Read` errors compare equal if their `io::Error`s
+    /// have the same kind and raw OS error code.
+    fn eq(&self, other: &Error) -> bool {
+        match (self, other) {
+            (Error::BadParam(a), Error::BadParam(b)) => a == b,
+            (Error::InvalidParameter { name: a_name, got: a_got, min: a_min, max: a_max },
+             Error::InvalidParameter { name: b_name, got: b_got, min: b_min, max: b_max }) =>
+                (a_name, a_got, a_min, a_max) == (b_name, b_got, b_min, b_max),
+            (Error::BufferTooSmall { required: a_required, provided: a_provided },
+             Error::BufferTooSmall { required: b_required, provided: b_provided }) =>
+                (a_required, a_provided) == (b_required, b_provided),
+            (Error::MissingNul, Error::MissingNul) => true,
+            (Error::PasswordTooLong { len: a_len, max: a_max }, Error::PasswordTooLong { len: b_len, max: b_max }) =>
+                (a_len, a_max) == (b_len, b_max),
+            (Error::NullPointer(a), Error::NullPointer(b)) => a == b,
+            (Error::InvalidUtf8(a), Error::InvalidUtf8(b)) => a == b,
+            (Error::Code(a), Error::Code(b)) => a == b,
+            (Error::AllocationFailed { requested_bytes: a }, Error::AllocationFailed { requested_bytes: b }) =>
+                a == b,
+            (Error::ExceedsMemoryCap { m_cost: a_m_cost, cap: a_cap },
+             Error::ExceedsMemoryCap { m_cost: b_m_cost, cap: b_cap }) =>
+                (a_m_cost, a_cap) == (b_m_cost, b_cap),
+            (Error::ThreadFailed(a), Error::ThreadFailed(b)) |
+            (Error::Random(a), Error::Random(b)) |
+            (Error::TaskFailed(a), Error::TaskFailed(b)) |
+            (Error::Read(a), Error::Read(b)) =>
+                a.kind() == b.kind() && a.raw_os_error() == b.raw_os_error(),
+            (Error::ThreadsUnavailable { threads: a }, Error::ThreadsUnavailable { threads: b }) => a == b,
+            (Error::TimedOut, Error::TimedOut) => true,
+            (Error::ParamsNotPinned { field: a_field, expected: a_expected, found: a_found },
+             Error::ParamsNotPinned { field: b_field, expected: b_expected, found: b_found }) =>
+                (a_field, a_expected, a_found) == (b_field, b_expected, b_found),
+            (Error::CrossCheckMismatch, Error::CrossCheckMismatch) => true,
+            (Error::AllMalformed(a), Error::AllMalformed(b)) => a == b,
+            (Error::UnknownAlgorithm(a), Error::UnknownAlgorithm(b)) => a == b,
+            (Error::UnknownCode(a), Error::UnknownCode(b)) => a == b,
+            (Error::Unknown, Error::Unknown) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Error {}
+
+#[allow(deprecated)]
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BadParam(param) => write!(f, "Bad parameter: {}", param),
+            Error::InvalidParameter { name, got, min, max } if min == max =>
+                write!(f, "Invalid parameter {}: got {}, expected {}", name, got, min),
+            Error::InvalidParameter { name, got, min, max } =>
+                write!(f, "Invalid parameter {}: got {}, expected {}..={}", name, got, min, max),
+            Error::Code(code) => write!(f, "{} (code {})", code, code.to_c()),
+            Error::BufferTooSmall { required, provided } =>
+                write!(f, "Buffer too small: {} bytes provided, {} required", provided, required),
+            Error::MissingNul => f.write_str("Byte string has no terminating NUL byte"),
+            Error::PasswordTooLong { len, max } =>
+                write!(f, "Password too long: {} bytes, at most {} allowed", len, max),
+            Error::NullPointer(function) => write!(f, "{} returned a null pointer", function),
+            Error::InvalidUtf8(err) => write!(f, "String is not valid UTF-8: {}", err),
+            Error::AllocationFailed { requested_bytes } =>
+                write!(f, "{} ({} bytes requested)", ErrorCode::MemoryAllocationError, requested_bytes),
+            Error::ExceedsMemoryCap { m_cost, cap } =>
+                write!(f, "Memory cost of {} KiB exceeds the cap of {} KiB", m_cost, cap),
+            Error::ThreadFailed(err) =>
+                write!(f, "{} (code {}): {}", ErrorCode::ThreadFail, ErrorCode::ThreadFail.to_c(), err),
+            Error::Random(err) => write!(f, "Random number generator failed: {}", err),
+            Error::TaskFailed(err) => write!(f, "Hashing task failed: {}", err),
+            Error::Read(err) => write!(f, "Reading the input failed: {}", err),
+            Error::TimedOut => f.write_str("Hashing didn't finish in time"),
+            Error::ParamsNotPinned { field, expected, found } =>
+                write!(f, "Hash doesn't use the pinned parameters: {} is {}, expected {}", field, found, expected),
+            Error::ThreadsUnavailable { threads } =>
+                write!(f, "{} threads requested, but threads are unavailable on this target", threads),
+            Error::CrossCheckMismatch => f.write_str("Hash differs from the one of the cross-check implementation"),
+            Error::AllMalformed(errors) => {
+                write!(f, "All {} encoded hashes are malformed", errors.len())?;
+                errors.iter().enumerate().try_for_each(|(i, err)| write!(f, "{} {}: {}", if i == 0 { ":" } else { ";" }, i, err))
             }
+            Error::UnknownAlgorithm(name) => write!(f, "Unsupported algorithm {}", name),
+            Error::UnknownCode(code) => write!(f, "Unknown error code {}", code),
+            Error::Unknown => f.write_str("Unknown error"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    /// `Error::Code` has no source: its `Display` already includes the message of the
+    /// `ErrorCode`, so error reporters would print it twice.
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidUtf8(err) => Some(err),
+            Error::ThreadFailed(err) | Error::Random(err) | Error::TaskFailed(err) | Error::Read(err) => Some(err),
+            Error::AllMalformed(errors) => errors.first().map(|err| err as _),
+            _ => None,
         }
     }
 }
 
 /// Error code returned by failed Argon2 C functions.
+///
+/// Covers every error constant of the C library's `Argon2_ErrorCodes`. New codes may be added if
+/// the C library grows them, so this enum is marked `#[non_exhaustive]`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(i32)]
+#[non_exhaustive]
 pub enum ErrorCode {
     OutputPtrNull = sys::Argon2_ErrorCodes_ARGON2_OUTPUT_PTR_NULL,
     OutputTooShort = sys::Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_SHORT,
@@ -73,8 +400,9 @@ pub enum ErrorCode {
 
 impl ErrorCode {
     /// Get the associated message for this error code.
+    /// Returns `"UNKNOWN_ERROR_CODE"` if the C library doesn't provide one.
     pub fn message(self) -> &'static str {
-        super::error_message(self)
+        super::error_message_or_unknown(self)
     }
 
     /// Converts an Argon2 error code into a Rust representation.
@@ -121,7 +449,7 @@ impl ErrorCode {
     }
 
     /// Converts a Rust representation of an Argon2 error code into the C error code.
-    pub(crate) fn to_c(self: ErrorCode) -> sys::Argon2_ErrorCodes {
+    pub fn to_c(self: ErrorCode) -> c_int {
         match self {
             ErrorCode::OutputPtrNull => sys::Argon2_ErrorCodes_ARGON2_OUTPUT_PTR_NULL,
             ErrorCode::OutputTooShort => sys::Argon2_ErrorCodes_ARGON2_OUTPUT_TOO_SHORT,
@@ -162,24 +490,70 @@ impl ErrorCode {
     }
 }
 
+impl fmt::Display for ErrorCode {
+    /// Writes the message the argon2 C library associates with this error code.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for ErrorCode {}
+
+impl TryFrom<c_int> for ErrorCode {
+    type Error = Error;
+
+    /// Converts a raw C error code. Codes without an `ErrorCode` counterpart (including
+    /// `ARGON2_OK`) are returned as `Error::UnknownCode`.
+    fn try_from(code: c_int) -> Result<ErrorCode, Error> {
+        ErrorCode::from_c(code).ok_or(Error::UnknownCode(code))
+    }
+}
+
 /// Argon2 primitive type.
+///
+/// With the `no-argon2d` feature, `Variant::D` doesn't exist.
 #[derive(Debug, Clone, Copy)]
 pub enum Variant {
+    #[cfg(not(feature = "no-argon2d"))]
     D   = 0,
     I   = 1,
     ID  = 2,
 }
 
 impl Variant {
+    /// Every supported variant, in a stable order (d, i, id).
+    #[cfg(not(feature = "no-argon2d"))]
+    pub const ALL: [Variant; 3] = [Variant::D, Variant::I, Variant::ID];
+
+    /// Every supported variant, in a stable order (i, id).
+    #[cfg(feature = "no-argon2d")]
+    pub const ALL: [Variant; 2] = [Variant::I, Variant::ID];
+
+    /// Returns an iterator over every supported variant in the same order as `Variant::ALL`.
+    pub fn iter() -> impl Iterator<Item = Variant> {
+        Variant::ALL.iter().copied()
+    }
+
+    /// Lowercase name of the variant as used in encoded hashes.
+    pub(crate) const fn name(self) -> &'static str {
+        match self {
+            #[cfg(not(feature = "no-argon2d"))]
+            Variant::D  => "argon2d",
+            Variant::I  => "argon2i",
+            Variant::ID => "argon2id",
+        }
+    }
+
     /// Converts from the C Variant type to the Rust Variant Type.
     #[inline]
     #[allow(dead_code)]
-    fn from_c(c_variant: sys::Argon2_type) -> Variant {
+    pub(crate) fn from_c(c_variant: sys::Argon2_type) -> Option<Variant> {
         match c_variant {
-            sys::Argon2_type_Argon2_d   => Variant::D,
-            sys::Argon2_type_Argon2_i   => Variant::I,
-            sys::Argon2_type_Argon2_id  => Variant::ID,
-            _ => panic!("Unimplemented version {}", c_variant),
+            #[cfg(not(feature = "no-argon2d"))]
+            sys::Argon2_type_Argon2_d   => Some(Variant::D),
+            sys::Argon2_type_Argon2_i   => Some(Variant::I),
+            sys::Argon2_type_Argon2_id  => Some(Variant::ID),
+            _ => None,
         }
     }
 
@@ -187,6 +561,7 @@ impl Variant {
     #[inline]
     pub(crate) fn to_c(self) -> sys::Argon2_type {
         match self {
+            #[cfg(not(feature = "no-argon2d"))]
             Variant::D  => sys::Argon2_type_Argon2_d,
             Variant::I  => sys::Argon2_type_Argon2_i,
             Variant::ID => sys::Argon2_type_Argon2_id,
@@ -194,6 +569,13 @@ impl Variant {
     }
 }
 
+impl fmt::Display for Variant {
+    /// Writes the lowercase name of the variant, the same as `type2string(variant, false)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 /// Version of the algorithm.
 #[derive(Debug, Clone, Copy)]
 pub enum Version {
@@ -205,8 +587,24 @@ pub enum Version {
 }
 
 impl Version {
+    /// Every supported version, from oldest to newest.
+    pub const ALL: [Version; 2] = [Version::Version10, Version::Version13];
+
+    /// The version used by the C library whenever one isn't given explicitly
+    /// (`ARGON2_VERSION_NUMBER`). This is the latest version of the algorithm.
+    pub const DEFAULT: Version = match sys::Argon2_version_ARGON2_VERSION_NUMBER {
+        sys::Argon2_version_ARGON2_VERSION_10 => Version::Version10,
+        sys::Argon2_version_ARGON2_VERSION_13 => Version::Version13,
+        _ => panic!("Unimplemented ARGON2_VERSION_NUMBER"),
+    };
+
+    /// Returns an iterator over every supported version in the same order as `Version::ALL`.
+    pub fn iter() -> impl Iterator<Item = Version> {
+        Version::ALL.iter().copied()
+    }
+
     /// Converts the version to an integer.
-    pub fn to_int(self) -> u32 {
+    pub const fn to_int(self) -> u32 {
         match self {
             Version::Version10 => 0x10,
             Version::Version13 => 0x13,
@@ -225,16 +623,17 @@ impl Version {
     /// Converts the C version type to the Rust version type.
     #[inline]
     #[allow(dead_code)]
-    pub(crate) fn from_c(c_version: sys::Argon2_version) -> Version {
+    pub(crate) fn from_c(c_version: sys::Argon2_version) -> Option<Version> {
         match c_version {
-            sys::Argon2_version_ARGON2_VERSION_10 => Version::Version10,
-            sys::Argon2_version_ARGON2_VERSION_13 => Version::Version13,
-            _ => panic!("Unimplemented version 0x{:X}", c_version),
+            sys::Argon2_version_ARGON2_VERSION_10 => Some(Version::Version10),
+            sys::Argon2_version_ARGON2_VERSION_13 => Some(Version::Version13),
+            _ => None,
         }
     }
 
     /// Converts the Rust version type to the C version type.
     #[inline]
+    #[allow(dead_code)]
     pub(crate) fn to_c(self) -> sys::Argon2_version {
         match self {
             Version::Version10 => sys::Argon2_version_ARGON2_VERSION_10,
@@ -244,9 +643,156 @@ impl Version {
 }
 
 impl Default for Version {
-    /// Returns the latest version of the algorithm.
+    /// Returns the version the C library uses by default (`Version::DEFAULT`).
     fn default() -> Version {
-        Version::Version13
+        Version::DEFAULT
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Variant {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(*u.choose(&Variant::ALL)?)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Version {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(*u.choose(&Version::ALL)?)
+    }
+}
+
+#[cfg(feature = "clap")]
+impl clap::ValueEnum for Variant {
+    fn value_variants<'a>() -> &'a [Self] {
+        &Variant::ALL
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        Some(clap::builder::PossibleValue::new(self.name()))
+    }
+}
+
+#[cfg(feature = "clap")]
+impl clap::ValueEnum for Version {
+    fn value_variants<'a>() -> &'a [Self] {
+        &Version::ALL
+    }
+
+    /// Versions are named by their hexadecimal number, the same way the reference CLI does it
+    /// (`10` and `13`).
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        let name = match self {
+            Version::Version10 => "10",
+            Version::Version13 => "13",
+        };
+        Some(clap::builder::PossibleValue::new(name))
+    }
+}
+
+/// Overwrites `buf` with zeroes using volatile writes followed by a compiler fence, so that the
+/// compiler can't elide the writes even if `buf` is never read again.
+///
+/// This is best-effort: copies that were made earlier, e.g. by moving a value or growing a `Vec`,
+/// and copies in registers or swap are not reached.
+pub fn secure_wipe(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Compares two byte slices in constant time.
+///
+/// The time taken depends only on the lengths of the slices, not on their contents or on the
+/// position of the first difference. Slices of different lengths are unequal, but their common
+/// prefix is still compared in full. Use this instead of `==` to compare raw hashes or tokens
+/// derived from secrets.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let mut diff = (a.len() != b.len()) as u8;
+    for (x, y) in a.iter().zip(b) {
+        diff |= x ^ y;
+    }
+    // Keep the compiler from turning the loop into an early return.
+    std::hint::black_box(diff) == 0
+}
+
+/// Compares a computed hash with the expected one in constant time and returns
+/// `ErrorCode::VerifyMismatch` if they differ, like the `*verify` functions.
+pub(crate) fn check_tag(out: &[u8], expected: &[u8]) -> Result<(), Error> {
+    if constant_time_eq(out, expected) { Ok(()) } else { Err(Error::Code(ErrorCode::VerifyMismatch)) }
+}
+
+/// What `ctx_with_thread_fallback` does when the C library fails to spawn its threads.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadFallbackPolicy {
+    /// Return `Error::ThreadFailed`, like `ctx`.
+    #[default]
+    Fail,
+    /// Hash again on the calling thread only. The number of lanes is kept, so the hash is the
+    /// same as with the requested threads.
+    SingleThreaded,
+}
+
+/// An owned password that is wiped from memory when dropped.
+///
+/// Dereferences to `&[u8]`, so `Some(&*password)` can be passed to every hash and verify function.
+/// `Debug` only shows the length of the password. `Clone` is deliberately not implemented, so
+/// that no unwiped copies are made by accident.
+pub struct Password {
+    bytes: Vec<u8>,
+}
+
+impl Password {
+    /// Takes ownership of the password bytes. A `Vec<u8>` or `String` is moved without copying;
+    /// any other input is copied into a new buffer first.
+    pub fn new<P: Into<Vec<u8>>>(password: P) -> Password {
+        Password { bytes: password.into() }
+    }
+
+    /// Overwrites the whole buffer, including unused capacity, with zeroes using volatile writes
+    /// so that the compiler can't elide them.
+    pub(crate) fn wipe(&mut self) {
+        let ptr = self.bytes.as_mut_ptr();
+        for idx in 0..self.bytes.capacity() {
+            unsafe { std::ptr::write_volatile(ptr.add(idx), 0) };
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+impl Drop for Password {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+impl std::ops::Deref for Password {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl AsRef<[u8]> for Password {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl std::str::FromStr for Password {
+    type Err = std::convert::Infallible;
+
+    fn from_str(password: &str) -> Result<Password, Self::Err> {
+        Ok(Password::new(password))
+    }
+}
+
+impl fmt::Debug for Password {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Password").field("len", &self.bytes.len()).finish()
     }
 }
 
@@ -336,22 +882,24 @@ impl<'o, 'p, 'sa, 'se, 'ad> Context<'o, 'p, 'sa, 'se, 'ad> {
     pub const MAX_SECRET_LENGTH: u32 = 0xFFFFFFFF;
 
     pub(crate) fn try_to_c(&mut self) -> Result<sys::Argon2_Context, Error> {
+        check_m_cost_cap(self.m_cost)?;
+        check_threads(self.threads)?;
         Ok(sys::Argon2_Context {
             out: self.out.as_mut_ptr(),
-            outlen: try_conv("context.out.len", self.out.len())?,
+            outlen: checked_len_u32("context.out.len", self.out.len(), Context::MAX_OUTLEN)?,
             pwd: opt_slice_ptr_mut(&mut self.pwd),
-            pwdlen: opt_slice_len_u32("context.pwd.len", &self.pwd)?,
+            pwdlen: opt_slice_len_u32("context.pwd.len", &self.pwd, Context::MAX_PWD_LENGTH)?,
             salt: opt_slice_ptr_mut(&mut self.salt),
-            saltlen: opt_slice_len_u32("context.salt.len", &self.salt)?,
+            saltlen: opt_slice_len_u32("context.salt.len", &self.salt, Context::MAX_SALT_LENGTH)?,
             secret: opt_slice_ptr_mut(&mut self.secret),
-            secretlen: opt_slice_len_u32("context.secret.len", &self.secret)?,
+            secretlen: opt_slice_len_u32("context.secret.len", &self.secret, Context::MAX_SECRET_LENGTH)?,
             ad: opt_slice_ptr_mut(&mut self.ad),
-            adlen: opt_slice_len_u32("context.ad.len", &self.ad)?,
+            adlen: opt_slice_len_u32("context.ad.len", &self.ad, Context::MAX_AD_LENGTH)?,
             t_cost: self.t_cost,
             m_cost: self.m_cost,
             lanes: self.lanes,
             threads: self.threads,
-            version: self.version.to_c() as _,
+            version: self.version.to_int(),
             allocate_cbk: None,
             free_cbk: None,
             flags: self.flags.bits(),
@@ -406,22 +954,24 @@ impl OwnedContext {
     }
 
     pub(crate) fn try_to_c(&mut self) -> Result<sys::Argon2_Context, Error> {
+        check_m_cost_cap(self.m_cost)?;
+        check_threads(self.threads)?;
         Ok(sys::Argon2_Context {
             out: self.out.as_mut_ptr(),
-            outlen: try_conv("context.out.len", self.out.len())?,
+            outlen: checked_len_u32("context.out.len", self.out.len(), Context::MAX_OUTLEN)?,
             pwd: opt_slice_ptr_mut(&mut self.pwd),
-            pwdlen: opt_slice_len_u32("context.pwd.len", &self.pwd)?,
+            pwdlen: opt_slice_len_u32("context.pwd.len", &self.pwd, Context::MAX_PWD_LENGTH)?,
             salt: opt_slice_ptr_mut(&mut self.salt),
-            saltlen: opt_slice_len_u32("context.salt.len", &self.salt)?,
+            saltlen: opt_slice_len_u32("context.salt.len", &self.salt, Context::MAX_SALT_LENGTH)?,
             secret: opt_slice_ptr_mut(&mut self.secret),
-            secretlen: opt_slice_len_u32("context.secret.len", &self.secret)?,
+            secretlen: opt_slice_len_u32("context.secret.len", &self.secret, Context::MAX_SECRET_LENGTH)?,
             ad: opt_slice_ptr_mut(&mut self.ad),
-            adlen: opt_slice_len_u32("context.ad.len", &self.ad)?,
+            adlen: opt_slice_len_u32("context.ad.len", &self.ad, Context::MAX_AD_LENGTH)?,
             t_cost: self.t_cost,
             m_cost: self.m_cost,
             lanes: self.lanes,
             threads: self.threads,
-            version: self.version.to_c() as _,
+            version: self.version.to_int(),
             allocate_cbk: None,
             free_cbk: None,
             flags: self.flags.bits(),
@@ -429,26 +979,183 @@ impl OwnedContext {
     }
 }
 
-impl<'o, 'p, 'sa, 'se, 'ad> std::convert::TryFrom<&mut Context<'o, 'p, 'sa, 'se, 'ad>> for sys::Argon2_Context {
-    type Error = self::Error;
+/// Wipes the output, password, salt, secret and associated data buffers. Wrap the context in
+/// `zeroize::Zeroizing` to have this happen when it is dropped.
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for OwnedContext {
+    fn zeroize(&mut self) {
+        self.out.zeroize();
+        self.pwd.zeroize();
+        self.salt.zeroize();
+        self.secret.zeroize();
+        self.ad.zeroize();
+    }
+}
+
+/// Argon2 inputs that can be passed to the `*_ctx` functions, i.e. `Context` and `OwnedContext`.
+///
+/// The raw C context, which only holds pointers into the input buffers, is created inside those
+/// functions while the inputs are mutably borrowed, so the buffers can't be freed or moved while
+/// the C library uses them. This trait is sealed and cannot be implemented outside of this crate.
+///
+/// The inputs are borrowed for the whole call:
+///
+/// ```
+/// # use std::convert::TryInto;
+/// # let mut context = argon2::OwnedContext {
+/// #     out: vec![0; 32],
+/// #     pwd: Some(b"password".to_vec()),
+/// #     salt: Some(b"somesalt".to_vec()),
+/// #     secret: None,
+/// #     ad: None,
+/// #     t_cost: 2,
+/// #     m_cost: 16,
+/// #     lanes: 1,
+/// #     threads: 1,
+/// #     version: argon2::Version::DEFAULT,
+/// #     flags: argon2::Flags::DEFAULT,
+/// # };
+/// argon2::id_ctx(&mut context).unwrap();
+/// ```
+///
+/// There is no way to get at the raw C context, so it can't outlive its buffers. This example has
+/// the same setup as the one above, so the conversion is the only thing that can fail to compile:
+///
+/// ```compile_fail,E0277
+/// # use std::convert::TryInto;
+/// # let mut context = argon2::OwnedContext {
+/// #     out: vec![0; 32],
+/// #     pwd: Some(b"password".to_vec()),
+/// #     salt: Some(b"somesalt".to_vec()),
+/// #     secret: None,
+/// #     ad: None,
+/// #     t_cost: 2,
+/// #     m_cost: 16,
+/// #     lanes: 1,
+/// #     threads: 1,
+/// #     version: argon2::Version::DEFAULT,
+/// #     flags: argon2::Flags::DEFAULT,
+/// # };
+/// let raw: argon2::sys::Argon2_Context = (&mut context).try_into().unwrap();
+/// ```
+pub trait AsContext: sealed::Sealed {}
+
+impl<'o, 'p, 'sa, 'se, 'ad> AsContext for Context<'o, 'p, 'sa, 'se, 'ad> {}
+
+impl AsContext for OwnedContext {}
+
+pub(crate) mod sealed {
+    use super::{sys, Context, Error, OwnedContext};
+
+    pub trait Sealed {
+        /// Creates the raw C context. The result must not outlive the borrow of `self`.
+        fn try_to_c(&mut self) -> Result<sys::Argon2_Context, Error>;
+    }
+
+    impl<'o, 'p, 'sa, 'se, 'ad> Sealed for Context<'o, 'p, 'sa, 'se, 'ad> {
+        fn try_to_c(&mut self) -> Result<sys::Argon2_Context, Error> {
+            Context::try_to_c(self)
+        }
+    }
+
+    impl Sealed for OwnedContext {
+        fn try_to_c(&mut self) -> Result<sys::Argon2_Context, Error> {
+            OwnedContext::try_to_c(self)
+        }
+    }
+}
+
+/// Converts a length to a u32, returning an InvalidParameter error if it is larger than `max`.
+/// This keeps lengths that don't fit the C library's `uint32_t` fields from being truncated.
+#[inline]
+pub(crate) fn checked_len_u32(param: &'static str, len: usize, max: u32) -> Result<u32, Error> {
+    match u32::try_from(len) {
+        Ok(len) if len <= max => Ok(len),
+        _ => Err(Error::InvalidParameter {
+            name: param,
+            got: len as u64,
+            min: 0,
+            max: max.into(),
+        }),
+    }
+}
+
+/// Converts the return value of an argon2 C function into a Result. The code is passed on
+/// unchanged, so codes without an `ErrorCode` counterpart end up in `Error::UnknownCode`.
+/// `ARGON2_THREAD_FAIL` becomes `Error::ThreadFailed`, so this must be called right after the C
+/// function returns, on the same thread, before anything else can overwrite `errno`.
+#[inline]
+pub(crate) fn check(code: c_int) -> Result<(), Error> {
+    match code {
+        sys::Argon2_ErrorCodes_ARGON2_OK => Ok(()),
+        sys::Argon2_ErrorCodes_ARGON2_THREAD_FAIL => Err(Error::ThreadFailed(thread_error())),
+        _ => Err(ErrorCode::try_from(code).map_or_else(|err| err, Error::Code)),
+    }
+}
+
+/// The error of the failed thread creation of the last call on this thread.
+fn thread_error() -> std::io::Error {
+    let last_os_error = std::io::Error::last_os_error();
+    match unsafe { sys::argon2_sys_take_thread_error() } {
+        0 => last_os_error,
+        code => std::io::Error::from_raw_os_error(code),
+    }
+}
 
-    fn try_from(context: &mut Context<'o, 'p, 'sa, 'se, 'ad>) -> Result<Self, Self::Error> {
-        context.try_to_c()
+/// Like `check`, but turns `ARGON2_MEMORY_ALLOCATION_ERROR` into `Error::AllocationFailed` for the
+/// memory that the C library allocates for `m_cost` KiB and `lanes`.
+#[inline]
+pub(crate) fn check_alloc(code: c_int, m_cost: u32, lanes: u32) -> Result<(), Error> {
+    match check(code) {
+        Err(Error::Code(ErrorCode::MemoryAllocationError)) =>
+            Err(Error::AllocationFailed { requested_bytes: memory_blocks(m_cost, lanes) * 1024 }),
+        result => result,
     }
 }
 
-impl std::convert::TryFrom<&mut OwnedContext> for sys::Argon2_Context {
-    type Error = self::Error;
+/// Number of 1 KiB blocks that the C library allocates for `m_cost` and `lanes`: `m_cost` rounded
+/// down to a multiple of `4 * lanes` (`ARGON2_SYNC_POINTS` segments per lane), but at least 8 per
+/// lane, as in `argon2_ctx`.
+pub(crate) fn memory_blocks(m_cost: u32, lanes: u32) -> u64 {
+    let segments = u64::from(Context::SYNC_POINTS) * u64::from(lanes.max(1));
+    u64::from(m_cost).max(2 * segments) / segments * segments
+}
+
+/// Whether the C library can spawn threads, as its build script reports. It is built with
+/// `ARGON2_NO_THREADS` for wasm.
+pub(crate) const THREADS_AVAILABLE: bool = sys::THREADS;
 
-    fn try_from(context: &mut OwnedContext) -> Result<Self, Self::Error> {
-        context.try_to_c()
+/// Returns `Error::ThreadsUnavailable` for more than one thread on targets without threads,
+/// instead of letting the C library compute the lanes one after the other.
+#[inline]
+pub(crate) fn check_threads(threads: u32) -> Result<(), Error> {
+    if threads > 1 && !THREADS_AVAILABLE {
+        Err(Error::ThreadsUnavailable { threads })
+    } else {
+        Ok(())
     }
 }
 
-/// Tries to convert between two types and returns a BadParam error on failure.
+/// Like `check_threads`, for the parallelism of an encoded hash. Hashes that can't be parsed are
+/// left for the C library to reject.
 #[inline]
-pub(crate) fn try_conv<T, U: std::convert::TryFrom<T>>(param: &'static str, input: T) -> Result<U, Error> {
-    U::try_from(input).map_err(|_| Error::BadParam(param))
+pub(crate) fn check_encoded_threads(encoded: &CStr) -> Result<(), Error> {
+    encoded_param(encoded.to_bytes(), b"p").map_or(Ok(()), check_threads)
+}
+
+/// Checks that a hash passed to one of the `*_verify_ctx` functions is exactly `outlen` bytes
+/// long, returning an InvalidParameter error otherwise.
+#[inline]
+pub(crate) fn check_hash_len(len: usize, outlen: u32) -> Result<(), Error> {
+    match u32::try_from(len) {
+        Ok(len) if len == outlen => Ok(()),
+        _ => Err(Error::InvalidParameter {
+            name: "hash.len",
+            got: len as u64,
+            min: outlen.into(),
+            max: outlen.into(),
+        }),
+    }
 }
 
 /// Gets the length of a slice contained an in option (0 if none).
@@ -458,10 +1165,10 @@ pub(crate) fn opt_slice_len<T, S: AsRef<[T]>>(opt: &Option<S>) -> usize {
 }
 
 /// Gets the length of a slice contained in an option (0 if none) and tries to convert the size to
-/// a u32, returning an error on failure.
+/// a u32 no larger than `max`, returning an error on failure.
 #[inline]
-pub(crate) fn opt_slice_len_u32<T, S: AsRef<[T]>>(param: &'static str, opt: &Option<S>) -> Result<u32, Error> {
-    try_conv(param, opt.as_ref().map(|s| s.as_ref().len()).unwrap_or(0))
+pub(crate) fn opt_slice_len_u32<T, S: AsRef<[T]>>(param: &'static str, opt: &Option<S>, max: u32) -> Result<u32, Error> {
+    checked_len_u32(param, opt_slice_len(opt), max)
 }
 
 /// Converts an option containing a slice into a mutable pointer that is null if the option is
@@ -0,0 +1,39 @@
+//! A small API for JavaScript, exported with `wasm-bindgen`, e.g. for web pages and Electron apps
+//! that hash passwords in WebAssembly.
+//!
+//! Errors are thrown as JavaScript `Error`s with the message of the `Error` of this crate. Like on
+//! every wasm target, the C library is built without threads, so a parallelism of more than one
+//! fails with `Error::ThreadsUnavailable`. Building the C sources for `wasm32-unknown-unknown`
+//! needs a clang with a wasm libc, such as the one of wasi-sdk (`CC_wasm32_unknown_unknown` and
+//! `CFLAGS_wasm32_unknown_unknown=--sysroot=...`).
+
+use super::encoded::EncodedHash;
+use super::types::{Error, Variant, Version};
+use wasm_bindgen::prelude::*;
+
+/// Length of the hashes of `hash_encoded` in bytes.
+pub const HASH_LEN: usize = 32;
+
+/// Hashes a password with Argon2id and `Version::DEFAULT` into a 32 byte hash and returns its
+/// encoded form.
+#[wasm_bindgen]
+pub fn hash_encoded(password: &[u8], salt: &[u8], m_cost: u32, t_cost: u32, p: u32) -> Result<String, JsValue> {
+    super::hash_encoded_string(t_cost, m_cost, p, password, salt, HASH_LEN, Variant::ID, Version::DEFAULT)
+        .map_err(to_js)
+}
+
+/// Verifies a password against an encoded hash of any variant. Returns `false` if the password
+/// doesn't match, and throws if the hash is malformed or can't be verified.
+#[wasm_bindgen]
+pub fn verify(encoded: &str, password: &[u8]) -> Result<bool, JsValue> {
+    let variant = EncodedHash::parse(encoded).map_err(to_js)?.variant();
+    match super::verify_bytes(encoded.as_bytes(), Some(password), variant) {
+        Ok(()) => Ok(true),
+        Err(err) if err.is_verify_mismatch() => Ok(false),
+        Err(err) => Err(to_js(err)),
+    }
+}
+
+fn to_js(err: Error) -> JsValue {
+    JsError::new(&err.to_string()).into()
+}
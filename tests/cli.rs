@@ -0,0 +1,86 @@
+//! The `argon2` binary, compared against the output of the reference implementation's utility.
+
+use assert_cmd::assert::Assert;
+use assert_cmd::Command;
+
+fn argon2(pwd: &str, args: &[&str]) -> Assert {
+    Command::new(env!("CARGO_BIN_EXE_argon2")).args(args).write_stdin(pwd).assert()
+}
+
+fn stdout(assert: &Assert) -> String {
+    String::from_utf8(assert.get_output().stdout.clone()).unwrap()
+}
+
+fn fails_with(args: &[&str], code: i32, error: &str) {
+    argon2("password", args).code(code).stderr(format!("Error: {}\n", error));
+}
+
+#[test]
+fn test_cli_output() {
+    let assert = argon2("password", &["somesalt", "-t", "2", "-m", "16", "-p", "1"]).success().stderr("");
+    let stdout = stdout(&assert);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(8, lines.len(), "{}", stdout);
+    assert_eq!(&[
+        "Type:\t\tArgon2i",
+        "Iterations:\t2",
+        "Memory:\t\t65536 KiB",
+        "Parallelism:\t1",
+        "Hash:\t\tc1628832147d9720c5bd1cfd61367078729f6dfb6f8fea9ff98158e0d7816ed0",
+        "Encoded:\t$argon2i$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$wWKIMhR9lyDFvRz9YTZweHKfbftvj+qf+YFY4NeBbtA",
+    ], &lines[..6]);
+    assert!(lines[6].ends_with(" seconds"), "{}", lines[6]);
+    assert_eq!("Verification ok", lines[7]);
+}
+
+#[test]
+fn test_cli_vectors() {
+    argon2("password", &["somesalt", "-id", "-t", "2", "-m", "16", "-e"]).success()
+        .stdout("$argon2id$v=19$m=65536,t=2,p=1$c29tZXNhbHQ$CTFhFdXPJO1aFaMaO6Mm5c8y7cJHAph8ArZWb2GRPPc\n");
+    argon2("password", &["somesalt", "-id", "-t", "2", "-m", "16", "-r"]).success()
+        .stdout("09316115d5cf24ed5a15a31a3ba326e5cf32edc24702987c02b6566f61913cf7\n");
+    argon2("password", &["somesalt", "-t", "2", "-k", "256", "-p", "2", "-id", "-e"]).success()
+        .stdout("$argon2id$v=19$m=256,t=2,p=2$c29tZXNhbHQ$bQk8UB/VmZZF4Oo79iDXuL5/0ttZwg2f/5U52iv1cDc\n");
+    argon2("password", &["diffsalt", "-i", "-t", "2", "-m", "16", "-v", "10", "-e"]).success()
+        .stdout("$argon2i$v=16$m=65536,t=2,p=1$ZGlmZnNhbHQ$eaEDuQ/orvhXDLMfyLIiWXeJFvgza3vaw4kladTxxJc\n");
+    argon2("differentpassword", &["somesalt", "-t", "2", "-m", "16", "-v", "13", "-r"]).success()
+        .stdout("14ae8da01afea8700c2358dcef7c5358d9021282bd88663a4562f59fb74d22ee\n");
+
+    // The defaults are Argon2i version 0x13 with t = 3, m = 2^12 and p = 1.
+    let defaults = stdout(&argon2("password", &["somesalt", "-e"]).success());
+    let explicit = stdout(&argon2("password", &["somesalt", "-i", "-v", "13", "-t", "3", "-k", "4096", "-p", "1", "-l", "32", "-e"]).success());
+    assert_eq!(explicit, defaults);
+    assert!(defaults.starts_with("$argon2i$v=19$m=4096,t=3,p=1$"), "{}", defaults);
+}
+
+#[test]
+fn test_cli_errors() {
+    assert!(stdout(&argon2("password", &[]).code(226)).starts_with("Usage:  "));
+    assert!(stdout(&argon2("password", &["-h"]).code(1)).starts_with("Usage:  "));
+
+    fails_with(&["somesalt", "-m"], 1, "missing -m argument");
+    fails_with(&["somesalt", "-m", "0"], 1, "bad numeric input for -m");
+    fails_with(&["somesalt", "-m", "33"], 1, "bad numeric input for -m");
+    fails_with(&["somesalt", "-m", "x"], 1, "bad numeric input for -m");
+    fails_with(&["somesalt", "-m", "8", "-k", "256"], 1, "-m or -k can only be used once");
+    fails_with(&["somesalt", "-k", "-1"], 1, "bad numeric input for -k");
+    fails_with(&["somesalt", "-t", "0"], 1, "bad numeric input for -t");
+    fails_with(&["somesalt", "-p", "16777216"], 1, "bad numeric input for -p");
+    fails_with(&["somesalt", "-v", "12"], 1, "invalid Argon2 version");
+    fails_with(&["somesalt", "-v"], 1, "missing -v argument");
+    fails_with(&["somesalt", "-x"], 1, "unknown argument");
+    fails_with(&["somesalt", "-i", "-id"], 1, "cannot specify multiple Argon2 types");
+    if cfg!(feature = "no-argon2d") {
+        fails_with(&["somesalt", "-d"], 1, "unknown argument");
+    } else {
+        fails_with(&["somesalt", "-i", "-d"], 1, "cannot specify multiple Argon2 types");
+    }
+    fails_with(&["somesalt", "-e", "-r"], 1, "cannot provide both -e and -r");
+    fails_with(&["short", "-e"], 1, "Salt is too short");
+    fails_with(&["somesalt", "-l", "3", "-e"], 1, "Output is too short");
+
+    argon2("", &["somesalt"]).code(1).stdout("").stderr("Error: no password read\n");
+    argon2(&"p".repeat(128), &["somesalt"]).code(1)
+        .stderr("Error: Provided password longer than supported in command line utility\n");
+    argon2(&"p".repeat(127), &["somesalt", "-m", "8", "-e"]).success();
+}
@@ -0,0 +1,73 @@
+//! The memory cap is process-wide and can only be set once, so it is tested in its own binary.
+
+use argon2::{Context, Error, Flags, Variant, Version};
+
+const CAP: u32 = 1 << 5;
+
+fn exceeds(m_cost: u32) -> Error {
+    Error::ExceedsMemoryCap { m_cost, cap: CAP }
+}
+
+fn encode(m_cost: u32) -> Vec<u8> {
+    let mut encoded = vec![0u8; 128];
+    argon2::id_hash_encoded(2, m_cost, 1, Some(b"password"), Some(b"somesalt"), 32, &mut encoded).unwrap();
+    encoded
+}
+
+#[test]
+fn test_memory_cap() {
+    // Stored before the cap was introduced.
+    let above_cap = encode(CAP * 2);
+
+    assert_eq!(None, argon2::max_m_cost());
+    argon2::set_max_m_cost(CAP).unwrap();
+    assert_eq!(Err(CAP), argon2::set_max_m_cost(1 << 20));
+    assert_eq!(Some(CAP), argon2::max_m_cost());
+
+    // Hashing.
+    let mut out = [0xAAu8; 32];
+    argon2::id_hash_raw(2, CAP, 1, Some(b"password"), Some(b"somesalt"), &mut out).unwrap();
+    let mut out = [0xAAu8; 32];
+    assert_eq!(Err(exceeds(CAP + 1)),
+               argon2::id_hash_raw(2, CAP + 1, 1, Some(b"password"), Some(b"somesalt"), &mut out));
+    assert_eq!([0u8; 32], out);
+
+    let mut encoded = [0u8; 128];
+    let err = argon2::i_hash_encoded(2, 1 << 16, 1, Some(b"password"), Some(b"somesalt"), 32, &mut encoded)
+        .unwrap_err();
+    assert_eq!(exceeds(1 << 16), err);
+    assert!(err.is_memory_error());
+    assert_eq!("Memory cost of 65536 KiB exceeds the cap of 32 KiB", err.to_string());
+
+    assert_eq!(Err(exceeds(1 << 16)),
+               argon2::hash(2, 1 << 16, 1, Some(b"password"), Some(b"somesalt"), Some(&mut out), None,
+                            Variant::ID, Version::DEFAULT));
+
+    let mut pwd = b"password".to_vec();
+    let mut salt = b"somesalt".to_vec();
+    let mut out = [0u8; 32];
+    let mut context = Context {
+        out: &mut out,
+        pwd: Some(&mut pwd),
+        salt: Some(&mut salt),
+        secret: None,
+        ad: None,
+        t_cost: 2,
+        m_cost: CAP + 1,
+        lanes: 1,
+        threads: 1,
+        version: Version::DEFAULT,
+        flags: Flags::DEFAULT,
+    };
+    assert_eq!(Err(exceeds(CAP + 1)), argon2::id_ctx(&mut context));
+
+    // Verification, with the memory cost taken from the context or parsed from the encoded hash.
+    assert_eq!(Err(exceeds(CAP + 1)), argon2::id_verify_ctx(&mut context, &[0u8; 32]));
+
+    let at_cap = encode(CAP);
+    let at_cap = argon2::c_str(&at_cap).unwrap();
+    let above_cap = argon2::c_str(&above_cap).unwrap();
+    argon2::id_verify(at_cap, Some(b"password")).unwrap();
+    assert_eq!(Err(exceeds(CAP * 2)), argon2::id_verify(above_cap, Some(b"password")));
+    assert_eq!(Err(exceeds(CAP * 2)), argon2::verify(above_cap, Some(b"password"), Variant::ID));
+}
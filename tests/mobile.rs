@@ -0,0 +1,30 @@
+//! Smoke test for Android and iOS, where the C library is built with threads like on other Unix
+//! targets.
+//!
+//! CI only builds it, e.g. with `cargo test --no-run --target aarch64-linux-android --test mobile`
+//! and `ANDROID_NDK_HOME` set, or `--target aarch64-apple-ios` on macOS. To run it, push the test
+//! binary to a device or emulator, or use a runner such as `cargo dinghy`.
+#![cfg(any(target_os = "android", target_os = "ios"))]
+
+use argon2::{Variant, Version};
+
+const HASH: &str = "9dfeb910e80bad0311fee20f9c0e2b12c17987b4cac90c2ef54d5b3021c68bfe";
+const ENCODED: &[u8] = b"$argon2id$v=19$m=256,t=2,p=1$c29tZXNhbHQ$nf65EOgLrQMR/uIPnA4rEsF5h7TKyQwu9U1bMCHGi/4\0";
+
+#[test]
+fn test_mobile_smoke() {
+    let mut out = [0u8; 32];
+    let mut encoded = [0u8; 128];
+    argon2::hash(2, 1 << 8, 1, Some(b"password"), Some(b"somesalt"), Some(&mut out), Some(&mut encoded),
+                 Variant::ID, Version::Version13).unwrap();
+    let hex: String = out.iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(HASH, hex);
+    assert_eq!(argon2::c_str(ENCODED).unwrap(), argon2::c_str(&encoded).unwrap());
+    argon2::id_verify(argon2::c_str(ENCODED).unwrap(), Some(b"password")).unwrap();
+
+    // Both have pthreads.
+    assert!(argon2::capabilities().threads_enabled);
+    argon2::id_hash_raw(2, 1 << 8, 4, Some(b"password"), Some(b"somesalt"), &mut out).unwrap();
+
+    assert_eq!(Some(argon2::MOBILE_MAX_M_COST), argon2::PLATFORM_MAX_M_COST);
+}
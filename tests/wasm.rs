@@ -0,0 +1,29 @@
+//! Smoke test for wasm targets, where the C library is built without threads.
+//!
+//! Runs under a wasm runtime, e.g. with
+//! `CARGO_TARGET_WASM32_WASIP1_RUNNER=wasmtime cargo test --target wasm32-wasip1 --test wasm`.
+//! Building the C sources for wasm needs a C compiler with a wasm libc, such as the clang of
+//! wasi-sdk (`CC_wasm32_wasip1`).
+#![cfg(target_family = "wasm")]
+
+use argon2::{Error, Variant, Version};
+
+const HASH: &str = "9dfeb910e80bad0311fee20f9c0e2b12c17987b4cac90c2ef54d5b3021c68bfe";
+const ENCODED: &[u8] = b"$argon2id$v=19$m=256,t=2,p=1$c29tZXNhbHQ$nf65EOgLrQMR/uIPnA4rEsF5h7TKyQwu9U1bMCHGi/4\0";
+
+#[test]
+fn test_wasm_smoke() {
+    let mut out = [0u8; 32];
+    let mut encoded = [0u8; 128];
+    argon2::hash(2, 1 << 8, 1, Some(b"password"), Some(b"somesalt"), Some(&mut out), Some(&mut encoded),
+                 Variant::ID, Version::Version13).unwrap();
+    let hex: String = out.iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(HASH, hex);
+    assert_eq!(argon2::c_str(ENCODED).unwrap(), argon2::c_str(&encoded).unwrap());
+
+    argon2::id_verify(argon2::c_str(ENCODED).unwrap(), Some(b"password")).unwrap();
+    assert!(argon2::id_verify(argon2::c_str(ENCODED).unwrap(), Some(b"wrong")).unwrap_err().is_verify_mismatch());
+
+    assert_eq!(Err(Error::ThreadsUnavailable { threads: 2 }),
+               argon2::id_hash_raw(2, 1 << 8, 2, Some(b"password"), Some(b"somesalt"), &mut out));
+}
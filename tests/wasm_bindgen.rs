@@ -0,0 +1,44 @@
+//! Tests of the `wasm` module against the native test vectors, run in Node with
+//! `CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUNNER=wasm-bindgen-test-runner cargo test
+//! --target wasm32-unknown-unknown --features wasm-bindgen,test-vectors --test wasm_bindgen`.
+#![cfg(all(target_family = "wasm", feature = "wasm-bindgen", feature = "test-vectors"))]
+
+use argon2::test_vectors::{self, TestVector};
+use argon2::wasm;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// The single-lane vectors with at most 64 MiB; the others need threads or too much memory.
+fn vectors(vectors: &'static [TestVector]) -> impl Iterator<Item = &'static TestVector> {
+    vectors.iter().filter(|vector| vector.parallelism == 1 && vector.m_cost <= 1 << 16)
+}
+
+fn message(err: JsValue) -> String {
+    js_sys::Error::from(err).message().into()
+}
+
+#[wasm_bindgen_test]
+fn test_hash_encoded() {
+    let mut count = 0;
+    for vector in vectors(test_vectors::ARGON2ID_V13).filter(|vector| vector.hash_len() == wasm::HASH_LEN) {
+        let encoded = wasm::hash_encoded(vector.password, vector.salt, vector.m_cost, vector.t_cost, 1).unwrap();
+        assert_eq!(vector.encoded, Some(encoded.as_str()));
+        count += 1;
+    }
+    assert!(count > 0);
+
+    assert_eq!("Salt is too short (code -6)", message(wasm::hash_encoded(b"password", b"salt", 256, 2, 1).unwrap_err()));
+    assert_eq!("2 threads requested, but threads are unavailable on this target",
+               message(wasm::hash_encoded(b"password", b"somesalt", 256, 2, 2).unwrap_err()));
+}
+
+#[wasm_bindgen_test]
+fn test_verify() {
+    for vector in vectors(test_vectors::ARGON2I_V13).chain(vectors(test_vectors::ARGON2ID_V13)) {
+        let encoded = vector.encoded.unwrap();
+        assert_eq!(Ok(true), wasm::verify(encoded, vector.password));
+        assert_eq!(Ok(false), wasm::verify(encoded, b"wrong password"));
+    }
+
+    assert_eq!("Decoding failed (code -32)", message(wasm::verify("$argon2id$m=256", b"password").unwrap_err()));
+}